@@ -16,15 +16,12 @@ impl<const N: usize> BloomFilter<N> {
     pub fn new(expected_elements: usize) -> Self {
         // Optimal hash function count: k = (m/n) * ln(2)
         // where m = N*8 bits, n = expected_elements
-        let optimal_k = if expected_elements > 0 {
-            // Calculate optimal hash count: k = (m/n) * ln(2)
-            // Using integer approximation: ln(2) ≈ 0.693 ≈ 693/1000
-            let m = N * 8; // Total bits
-            let k_times_1000 = (m * 693) / expected_elements; // k * 1000
-            let k = k_times_1000.div_ceil(1000); // Ceiling division
-            k as u8
-        } else {
-            3
+        // Using integer approximation: ln(2) ≈ 0.693 ≈ 693/1000
+        let m = N * 8; // Total bits
+        let optimal_k = match (m * 693).checked_div(expected_elements) {
+            // k * 1000, rounded up
+            Some(k_times_1000) => k_times_1000.div_ceil(1000) as u8,
+            None => 3,
         };
 
         // Clamp to reasonable range
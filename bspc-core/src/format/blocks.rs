@@ -0,0 +1,71 @@
+//! Block table definitions for compressed BSPC files
+//!
+//! Compressed files split the sorted COO arrays into blocks of
+//! [`BspcHeader::block_len`](super::BspcHeader::block_len) elements. Each block
+//! is encoded independently and located through a fixed-size entry in the block
+//! table, so readers can decode only the blocks a query touches.
+
+use super::metadata::{read_u64, write_u64};
+use crate::{BspcError, Result};
+
+/// Block table entry (64 bytes)
+///
+/// Offsets are absolute file offsets. The row range lets readers find the
+/// blocks holding a row without decoding any indices.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlockTableEntry {
+    /// Row of the first element in the block
+    pub first_row: u64,
+    /// Row of the last element in the block
+    pub last_row: u64,
+    /// Offset to the encoded values
+    pub values_offset: u64,
+    /// Size of the encoded values in bytes
+    pub values_size: u64,
+    /// Offset to the encoded row indices
+    pub rows_offset: u64,
+    /// Size of the encoded row indices in bytes
+    pub rows_size: u64,
+    /// Offset to the encoded column indices
+    pub cols_offset: u64,
+    /// Size of the encoded column indices in bytes
+    pub cols_size: u64,
+}
+
+impl BlockTableEntry {
+    /// Size of an entry in bytes
+    pub const SIZE: usize = 64;
+
+    /// Parse from bytes
+    pub const fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < Self::SIZE {
+            return Err(BspcError::InsufficientBuffer);
+        }
+
+        Ok(Self {
+            first_row: read_u64(bytes, 0),
+            last_row: read_u64(bytes, 8),
+            values_offset: read_u64(bytes, 16),
+            values_size: read_u64(bytes, 24),
+            rows_offset: read_u64(bytes, 32),
+            rows_size: read_u64(bytes, 40),
+            cols_offset: read_u64(bytes, 48),
+            cols_size: read_u64(bytes, 56),
+        })
+    }
+
+    /// Convert to bytes
+    pub const fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        write_u64(&mut bytes, 0, self.first_row);
+        write_u64(&mut bytes, 8, self.last_row);
+        write_u64(&mut bytes, 16, self.values_offset);
+        write_u64(&mut bytes, 24, self.values_size);
+        write_u64(&mut bytes, 32, self.rows_offset);
+        write_u64(&mut bytes, 40, self.rows_size);
+        write_u64(&mut bytes, 48, self.cols_offset);
+        write_u64(&mut bytes, 56, self.cols_size);
+        bytes
+    }
+}
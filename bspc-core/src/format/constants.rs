@@ -15,10 +15,34 @@ pub mod metadata {
     pub const MAGIC: [u8; 4] = *b"META";
 
     /// Current metadata format version
-    pub const VERSION: u8 = 1;
+    pub const VERSION: u8 = 5;
+
+    /// First metadata version that allows variable-length label arrays
+    pub const VARIABLE_LABELS_VERSION: u8 = 3;
 
     /// Fixed size of metadata header
-    pub const HEADER_SIZE: usize = 40;
+    pub const HEADER_SIZE: usize = 120;
+
+    /// Size of the version 1 metadata header (no label index fields)
+    pub const HEADER_SIZE_V1: usize = 40;
+
+    /// Size of the version 2 and 3 metadata header (no annotation fields)
+    pub const HEADER_SIZE_V2: usize = 72;
+
+    /// Size of the version 4 metadata header (no attribute fields)
+    pub const HEADER_SIZE_V4: usize = 104;
+
+    /// Fixed size of an annotation table header
+    pub const ANNOTATION_TABLE_HEADER_SIZE: usize = 8;
+
+    /// Fixed size of an annotation column descriptor
+    pub const ANNOTATION_COLUMN_HEADER_SIZE: usize = 32;
+
+    /// Fixed size of the attribute block header
+    pub const ATTRIBUTE_BLOCK_HEADER_SIZE: usize = 8;
+
+    /// Fixed size of an attribute entry header
+    pub const ATTRIBUTE_ENTRY_HEADER_SIZE: usize = 16;
 
     /// Fixed size of label array header
     pub const LABEL_ARRAY_HEADER_SIZE: usize = 8;
@@ -52,8 +52,22 @@ pub struct BspcHeader {
     pub bloom_filter_offset: u64,
     /// Size of chunk bloom filter data in bytes
     pub bloom_filter_size: u64,
+    /// Width of the stored row and column indices (u32=0, u64=1)
+    pub index_width: u8,
+    /// Encoding of compressed index blocks (raw=0, delta bit-packed=1)
+    pub index_codec: u8,
+    /// Encoding of compressed value blocks (raw=0, dictionary=1, zstd=2, lz4=3)
+    pub value_codec: u8,
+    /// Elements per compressed block as a power of two
+    pub block_size_log2: u8,
+    /// Array storage (raw arrays=0, compressed blocks=1)
+    pub compression: u8,
     /// Reserved space for future extensions
-    pub reserved: [u8; 32],
+    pub reserved: [u8; 11],
+    /// Offset to the block table (zero for uncompressed files)
+    pub block_table_offset: u64,
+    /// Size of the block table in bytes
+    pub block_table_size: u64,
 }
 
 impl BspcHeader {
@@ -61,7 +75,24 @@ impl BspcHeader {
     pub const MAGIC: [u8; 4] = *b"BSPC";
 
     /// Current format version
-    pub const VERSION: u8 = 1;
+    ///
+    /// Version 1 files store 32-bit indices and keep bytes 128..160 zeroed.
+    /// Version 2 adds `index_width` and version 3 adds block compression.
+    /// Writers only raise the version when a file uses a newer feature, so
+    /// older readers reject such files instead of misreading them.
+    pub const VERSION: u8 = 3;
+
+    /// Version of files that use no feature newer than the original format
+    pub const BASE_VERSION: u8 = 1;
+
+    /// First version with a meaningful `index_width` byte
+    pub const INDEX_WIDTH_VERSION: u8 = 2;
+
+    /// First version with compressed blocks
+    pub const COMPRESSION_VERSION: u8 = 3;
+
+    /// `compression` value of files stored as compressed blocks
+    pub const BLOCK_COMPRESSION: u8 = 1;
 
     /// Size of the header in bytes
     pub const SIZE: usize = size_of::<Self>();
@@ -70,7 +101,7 @@ impl BspcHeader {
     pub const fn new() -> Self {
         Self {
             magic: Self::MAGIC,
-            version: Self::VERSION,
+            version: Self::BASE_VERSION,
             format_type: 0,
             data_type: 0,
             structure_flags: 0,
@@ -89,7 +120,14 @@ impl BspcHeader {
             metadata_size: 0,
             bloom_filter_offset: 0,
             bloom_filter_size: 0,
-            reserved: [0; 32],
+            index_width: 0,
+            index_codec: 0,
+            value_codec: 0,
+            block_size_log2: 0,
+            compression: 0,
+            reserved: [0; 11],
+            block_table_offset: 0,
+            block_table_size: 0,
         }
     }
 
@@ -111,6 +149,91 @@ impl BspcHeader {
         }
     }
 
+    /// Get block table region offset and size
+    ///
+    /// Only compressed files have a block table; when present the values and
+    /// index regions hold encoded blocks rather than raw arrays.
+    pub fn block_table_region(&self) -> Option<(u64, u64)> {
+        if self.block_table_offset == 0 || self.block_table_size == 0 {
+            None
+        } else {
+            Some((self.block_table_offset, self.block_table_size))
+        }
+    }
+
+    /// Check whether the arrays are stored as compressed blocks
+    ///
+    /// A compressed matrix without elements has no block table, so this reads
+    /// the explicit `compression` marker rather than the block table region.
+    pub fn is_compressed(&self) -> bool {
+        self.compression == Self::BLOCK_COMPRESSION
+    }
+
+    /// Mark the arrays as compressed blocks with the given codecs
+    ///
+    /// This raises the version to [`Self::COMPRESSION_VERSION`].
+    pub fn set_compression(
+        &mut self,
+        index_codec: IndexCodec,
+        value_codec: ValueCodec,
+        block_size_log2: u8,
+    ) {
+        self.compression = Self::BLOCK_COMPRESSION;
+        self.index_codec = index_codec.to_u8();
+        self.value_codec = value_codec.to_u8();
+        self.block_size_log2 = block_size_log2;
+        self.version = self.version.max(Self::COMPRESSION_VERSION);
+    }
+
+    /// Get the codec of the compressed index blocks
+    pub fn index_codec(&self) -> Option<IndexCodec> {
+        IndexCodec::from_u8(self.index_codec)
+    }
+
+    /// Get the codec of the compressed value blocks
+    pub fn value_codec(&self) -> Option<ValueCodec> {
+        ValueCodec::from_u8(self.value_codec)
+    }
+
+    /// Number of elements per compressed block
+    pub fn block_len(&self) -> usize {
+        1usize << self.block_size_log2
+    }
+
+    /// End of the data sections, excluding the metadata region
+    ///
+    /// This is the first byte after the values, indices, pointers, bloom
+    /// filter and block table, whichever comes last.
+    pub fn data_end(&self) -> u64 {
+        [
+            self.values_offset + self.values_size,
+            self.indices_0_offset + self.indices_0_size,
+            self.indices_1_offset + self.indices_1_size,
+            self.pointers_offset + self.pointers_size,
+            self.bloom_filter_offset + self.bloom_filter_size,
+            self.block_table_offset + self.block_table_size,
+            Self::SIZE as u64,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(Self::SIZE as u64)
+    }
+
+    /// Get the width of the stored row and column indices
+    pub fn index_width(&self) -> Option<IndexWidth> {
+        IndexWidth::from_u8(self.index_width)
+    }
+
+    /// Set the width of the stored indices
+    ///
+    /// Widths other than `U32` raise the version to [`Self::INDEX_WIDTH_VERSION`].
+    pub fn set_index_width(&mut self, width: IndexWidth) {
+        self.index_width = width.to_u8();
+        if width != IndexWidth::U32 {
+            self.version = self.version.max(Self::INDEX_WIDTH_VERSION);
+        }
+    }
+
     /// Set metadata region offset and size
     pub fn set_metadata_region(&mut self, offset: u64, size: u64) {
         self.metadata_offset = offset;
@@ -125,7 +248,28 @@ impl BspcHeader {
 
     /// Validate the header structure
     pub fn is_valid(&self) -> bool {
-        self.magic == Self::MAGIC && self.version <= Self::VERSION
+        self.magic == Self::MAGIC
+            && self.version <= Self::VERSION
+            && Self::index_width_known(self.version, self.index_width)
+            && Self::compression_known(self.version, self.compression)
+    }
+
+    /// Check that an index width byte is defined for a format version
+    fn index_width_known(version: u8, index_width: u8) -> bool {
+        match IndexWidth::from_u8(index_width) {
+            Some(IndexWidth::U32) => true,
+            Some(_) => version >= Self::INDEX_WIDTH_VERSION,
+            None => false,
+        }
+    }
+
+    /// Check that a compression marker is defined for a format version
+    fn compression_known(version: u8, compression: u8) -> bool {
+        match compression {
+            0 => true,
+            Self::BLOCK_COMPRESSION => version >= Self::COMPRESSION_VERSION,
+            _ => false,
+        }
     }
 
     /// Parse header from bytes
@@ -141,6 +285,9 @@ impl BspcHeader {
 
         // Parse header fields (assuming little-endian)
         let version = bytes[4];
+        if version > Self::VERSION {
+            return Err(crate::BspcError::UnsupportedFormat);
+        }
         let format_type = bytes[5];
         let data_type = bytes[6];
         let structure_flags = bytes[7];
@@ -198,8 +345,29 @@ impl BspcHeader {
             bytes[127],
         ]);
 
-        let mut reserved = [0u8; 32];
-        reserved.copy_from_slice(&bytes[128..160]);
+        // Files written before these fields have zeros here, meaning u32
+        // indices and no compression
+        let index_width = bytes[128];
+        if !Self::index_width_known(version, index_width) {
+            return Err(crate::BspcError::InvalidHeader);
+        }
+        let index_codec = bytes[129];
+        let value_codec = bytes[130];
+        let block_size_log2 = bytes[131];
+        let compression = bytes[132];
+        if !Self::compression_known(version, compression) {
+            return Err(crate::BspcError::InvalidHeader);
+        }
+        let mut reserved = [0u8; 11];
+        reserved.copy_from_slice(&bytes[133..144]);
+        let block_table_offset = u64::from_le_bytes([
+            bytes[144], bytes[145], bytes[146], bytes[147], bytes[148], bytes[149], bytes[150],
+            bytes[151],
+        ]);
+        let block_table_size = u64::from_le_bytes([
+            bytes[152], bytes[153], bytes[154], bytes[155], bytes[156], bytes[157], bytes[158],
+            bytes[159],
+        ]);
 
         Ok(Self {
             magic: Self::MAGIC,
@@ -222,7 +390,14 @@ impl BspcHeader {
             metadata_size,
             bloom_filter_offset,
             bloom_filter_size,
+            index_width,
+            index_codec,
+            value_codec,
+            block_size_log2,
+            compression,
             reserved,
+            block_table_offset,
+            block_table_size,
         })
     }
 
@@ -254,7 +429,14 @@ impl BspcHeader {
         bytes.extend_from_slice(&self.metadata_size.to_le_bytes());
         bytes.extend_from_slice(&self.bloom_filter_offset.to_le_bytes());
         bytes.extend_from_slice(&self.bloom_filter_size.to_le_bytes());
+        bytes.push(self.index_width);
+        bytes.push(self.index_codec);
+        bytes.push(self.value_codec);
+        bytes.push(self.block_size_log2);
+        bytes.push(self.compression);
         bytes.extend_from_slice(&self.reserved);
+        bytes.extend_from_slice(&self.block_table_offset.to_le_bytes());
+        bytes.extend_from_slice(&self.block_table_size.to_le_bytes());
 
         bytes
     }
@@ -426,10 +608,25 @@ impl BspcHeader {
         bytes[126] = bloom_filter_size_bytes[6];
         bytes[127] = bloom_filter_size_bytes[7];
 
+        bytes[128] = self.index_width;
+        bytes[129] = self.index_codec;
+        bytes[130] = self.value_codec;
+        bytes[131] = self.block_size_log2;
+        bytes[132] = self.compression;
+
         // Reserved bytes (already initialized to 0)
         let mut i = 0;
-        while i < 32 {
-            bytes[128 + i] = self.reserved[i];
+        while i < 11 {
+            bytes[133 + i] = self.reserved[i];
+            i += 1;
+        }
+
+        let block_table_offset_bytes = self.block_table_offset.to_le_bytes();
+        let block_table_size_bytes = self.block_table_size.to_le_bytes();
+        let mut i = 0;
+        while i < 8 {
+            bytes[144 + i] = block_table_offset_bytes[i];
+            bytes[152 + i] = block_table_size_bytes[i];
             i += 1;
         }
 
@@ -482,6 +679,137 @@ impl core::fmt::Display for MatrixFormat {
     }
 }
 
+/// Width of the row and column indices stored in a BSPC file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum IndexWidth {
+    /// 32-bit unsigned indices, for dimensions up to 2^32
+    U32 = 0,
+    /// 64-bit unsigned indices
+    U64 = 1,
+}
+
+impl IndexWidth {
+    /// Convert from u8 representation
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(IndexWidth::U32),
+            1 => Some(IndexWidth::U64),
+            _ => None,
+        }
+    }
+
+    /// Convert to u8 representation
+    pub const fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Get the size in bytes of one index
+    pub const fn size_bytes(self) -> usize {
+        match self {
+            IndexWidth::U32 => 4,
+            IndexWidth::U64 => 8,
+        }
+    }
+
+    /// Narrowest width that can index every row and column of a matrix
+    pub const fn for_dims(nrows: u64, ncols: u64) -> Self {
+        let max = if nrows > ncols { nrows } else { ncols };
+        if max > u32::MAX as u64 + 1 {
+            IndexWidth::U64
+        } else {
+            IndexWidth::U32
+        }
+    }
+}
+
+impl core::fmt::Display for IndexWidth {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IndexWidth::U32 => write!(f, "u32"),
+            IndexWidth::U64 => write!(f, "u64"),
+        }
+    }
+}
+
+/// Encodings for compressed row and column index blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum IndexCodec {
+    /// Little-endian indices at the file's index width
+    Raw = 0,
+    /// First index followed by bit-packed zigzag deltas
+    DeltaBitPack = 1,
+}
+
+impl IndexCodec {
+    /// Convert from u8 representation
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(IndexCodec::Raw),
+            1 => Some(IndexCodec::DeltaBitPack),
+            _ => None,
+        }
+    }
+
+    /// Convert to u8 representation
+    pub const fn to_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl core::fmt::Display for IndexCodec {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IndexCodec::Raw => write!(f, "raw"),
+            IndexCodec::DeltaBitPack => write!(f, "delta"),
+        }
+    }
+}
+
+/// Encodings for compressed value blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ValueCodec {
+    /// Little-endian values
+    Raw = 0,
+    /// Per-block table of distinct values followed by bit-packed codes
+    Dictionary = 1,
+    /// Zstandard-compressed little-endian values
+    Zstd = 2,
+    /// LZ4-compressed little-endian values
+    Lz4 = 3,
+}
+
+impl ValueCodec {
+    /// Convert from u8 representation
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ValueCodec::Raw),
+            1 => Some(ValueCodec::Dictionary),
+            2 => Some(ValueCodec::Zstd),
+            3 => Some(ValueCodec::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Convert to u8 representation
+    pub const fn to_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl core::fmt::Display for ValueCodec {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ValueCodec::Raw => write!(f, "raw"),
+            ValueCodec::Dictionary => write!(f, "dictionary"),
+            ValueCodec::Zstd => write!(f, "zstd"),
+            ValueCodec::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+
 /// Data types supported by BSPC format
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -498,6 +826,24 @@ pub enum DataType {
     U32 = 4,
     /// 64-bit unsigned integer
     U64 = 5,
+    /// Pattern (boolean) matrix with no values array; every stored element is `true`
+    Pattern = 6,
+    /// 8-bit signed integer
+    I8 = 7,
+    /// 16-bit signed integer
+    I16 = 8,
+    /// 8-bit unsigned integer
+    U8 = 9,
+    /// 16-bit unsigned integer
+    U16 = 10,
+    /// 16-bit IEEE 754 half precision floating point
+    F16 = 11,
+    /// 16-bit bfloat16 floating point
+    BF16 = 12,
+    /// 64-bit complex, stored as interleaved little-endian f32 real/imaginary pairs
+    C64 = 13,
+    /// 128-bit complex, stored as interleaved little-endian f64 real/imaginary pairs
+    C128 = 14,
 }
 
 impl DataType {
@@ -510,6 +856,15 @@ impl DataType {
             3 => Some(DataType::I64),
             4 => Some(DataType::U32),
             5 => Some(DataType::U64),
+            6 => Some(DataType::Pattern),
+            7 => Some(DataType::I8),
+            8 => Some(DataType::I16),
+            9 => Some(DataType::U8),
+            10 => Some(DataType::U16),
+            11 => Some(DataType::F16),
+            12 => Some(DataType::BF16),
+            13 => Some(DataType::C64),
+            14 => Some(DataType::C128),
             _ => None,
         }
     }
@@ -519,11 +874,22 @@ impl DataType {
         self as u8
     }
 
+    /// Check whether elements of this type are complex numbers
+    pub const fn is_complex(self) -> bool {
+        matches!(self, DataType::C64 | DataType::C128)
+    }
+
     /// Get the size in bytes for this data type
+    ///
+    /// Pattern matrices store no values, so their element size is 0.
     pub const fn size_bytes(self) -> usize {
         match self {
+            DataType::Pattern => 0,
+            DataType::I8 | DataType::U8 => 1,
+            DataType::I16 | DataType::U16 | DataType::F16 | DataType::BF16 => 2,
             DataType::F32 | DataType::I32 | DataType::U32 => 4,
-            DataType::F64 | DataType::I64 | DataType::U64 => 8,
+            DataType::F64 | DataType::I64 | DataType::U64 | DataType::C64 => 8,
+            DataType::C128 => 16,
         }
     }
 }
@@ -537,6 +903,133 @@ impl core::fmt::Display for DataType {
             DataType::I64 => write!(f, "i64"),
             DataType::U32 => write!(f, "u32"),
             DataType::U64 => write!(f, "u64"),
+            DataType::Pattern => write!(f, "pattern"),
+            DataType::I8 => write!(f, "i8"),
+            DataType::I16 => write!(f, "i16"),
+            DataType::U8 => write!(f, "u8"),
+            DataType::U16 => write!(f, "u16"),
+            DataType::F16 => write!(f, "f16"),
+            DataType::BF16 => write!(f, "bf16"),
+            DataType::C64 => write!(f, "c64"),
+            DataType::C128 => write!(f, "c128"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_type_codes() {
+        for code in 0..=14u8 {
+            let data_type = DataType::from_u8(code).unwrap();
+            assert_eq!(data_type.to_u8(), code);
+        }
+        assert_eq!(DataType::from_u8(15), None);
+
+        assert_eq!(DataType::Pattern.size_bytes(), 0);
+        assert_eq!(DataType::F16.size_bytes(), 2);
+        assert_eq!(DataType::BF16.size_bytes(), 2);
+        assert_eq!(DataType::C64.size_bytes(), 8);
+        assert_eq!(DataType::C128.size_bytes(), 16);
+        assert!(DataType::C64.is_complex());
+        assert!(!DataType::F64.is_complex());
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let mut header = BspcHeader::new();
+        header.nrows = 10;
+        header.ncols = 20;
+        header.nnz = 3;
+        header.data_type = DataType::C64.to_u8();
+        header.set_metadata_region(1000, 24);
+        header.set_chunk_bloom_filter_region(500, 64);
+
+        let bytes = header.to_bytes_array();
+        assert_eq!(bytes.len(), BspcHeader::SIZE);
+        assert_eq!(BspcHeader::from_bytes(&bytes), Ok(header));
+        #[cfg(feature = "alloc")]
+        assert_eq!(header.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_index_width_sets_version() {
+        let mut header = BspcHeader::new();
+        header.set_index_width(IndexWidth::U32);
+        assert_eq!(header.version, BspcHeader::BASE_VERSION);
+
+        header.set_index_width(IndexWidth::U64);
+        assert_eq!(header.version, BspcHeader::INDEX_WIDTH_VERSION);
+        assert_eq!(header.index_width(), Some(IndexWidth::U64));
+        let parsed = BspcHeader::from_bytes(&header.to_bytes_array()).unwrap();
+        assert_eq!(parsed.index_width(), Some(IndexWidth::U64));
+        assert!(parsed.is_valid());
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_unknown_fields() {
+        let mut header = BspcHeader::new();
+        header.set_index_width(IndexWidth::U64);
+
+        // A 64-bit index width in a version 1 header
+        let mut bytes = header.to_bytes_array();
+        bytes[4] = 1;
+        assert_eq!(
+            BspcHeader::from_bytes(&bytes),
+            Err(crate::BspcError::InvalidHeader)
+        );
+
+        let mut bytes = header.to_bytes_array();
+        bytes[128] = 7;
+        assert_eq!(
+            BspcHeader::from_bytes(&bytes),
+            Err(crate::BspcError::InvalidHeader)
+        );
+
+        let mut bytes = header.to_bytes_array();
+        bytes[4] = BspcHeader::VERSION + 1;
+        assert_eq!(
+            BspcHeader::from_bytes(&bytes),
+            Err(crate::BspcError::UnsupportedFormat)
+        );
+
+        assert_eq!(
+            BspcHeader::from_bytes(&bytes[..BspcHeader::SIZE - 1]),
+            Err(crate::BspcError::InsufficientBuffer)
+        );
+    }
+
+    #[test]
+    fn test_compression_marker() {
+        let mut header = BspcHeader::new();
+        assert!(!header.is_compressed());
+
+        header.set_compression(IndexCodec::DeltaBitPack, ValueCodec::Zstd, 12);
+        assert!(header.is_compressed());
+        assert_eq!(header.version, BspcHeader::COMPRESSION_VERSION);
+        assert!(header.block_table_region().is_none());
+
+        let parsed = BspcHeader::from_bytes(&header.to_bytes_array()).unwrap();
+        assert!(parsed.is_compressed());
+        assert_eq!(parsed.index_codec(), Some(IndexCodec::DeltaBitPack));
+        assert_eq!(parsed.value_codec(), Some(ValueCodec::Zstd));
+        assert_eq!(parsed.block_len(), 4096);
+
+        // Older versions cannot carry compressed blocks
+        let mut bytes = header.to_bytes_array();
+        bytes[4] = BspcHeader::INDEX_WIDTH_VERSION;
+        assert_eq!(
+            BspcHeader::from_bytes(&bytes),
+            Err(crate::BspcError::InvalidHeader)
+        );
+
+        let mut bytes = header.to_bytes_array();
+        bytes[132] = 2;
+        assert_eq!(
+            BspcHeader::from_bytes(&bytes),
+            Err(crate::BspcError::InvalidHeader)
+        );
+    }
+}
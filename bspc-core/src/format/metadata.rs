@@ -6,13 +6,20 @@
 use super::constants::metadata::*;
 use crate::{BspcError, Result};
 
-/// Fixed-size metadata header (40 bytes, 8-byte aligned)
+/// Fixed-size metadata header (120 bytes, 8-byte aligned)
+///
+/// Version 1 headers are 40 bytes and end after the column label fields, and
+/// versions 2 and 3 are 72 bytes and end after the label index fields; missing
+/// fields are read as zero. Version 3 adds the variable-length label encoding
+/// (see [`LabelArrayHeader`]) with no header changes. Version 4 adds per-axis
+/// annotation tables (see [`AnnotationTableHeader`]) and version 5 adds the
+/// key/value attribute block (see [`AttributeEntryHeader`]).
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BspcMetadataHeader {
     /// Magic bytes: "META"
     pub magic: [u8; 4],
-    /// Version number (1)
+    /// Version number (1 to 5)
     pub version: u8,
     /// Padding for alignment
     pub _padding: [u8; 3],
@@ -24,6 +31,26 @@ pub struct BspcMetadataHeader {
     pub col_labels_offset: u64,
     /// Size of column labels array in bytes
     pub col_labels_size: u64,
+    /// Offset to sorted row label index from metadata start (version 2)
+    pub row_index_offset: u64,
+    /// Size of row label index in bytes (0 if absent)
+    pub row_index_size: u64,
+    /// Offset to sorted column label index from metadata start (version 2)
+    pub col_index_offset: u64,
+    /// Size of column label index in bytes (0 if absent)
+    pub col_index_size: u64,
+    /// Offset to row annotation table from metadata start (version 4)
+    pub row_annotations_offset: u64,
+    /// Size of row annotation table in bytes (0 if absent)
+    pub row_annotations_size: u64,
+    /// Offset to column annotation table from metadata start (version 4)
+    pub col_annotations_offset: u64,
+    /// Size of column annotation table in bytes (0 if absent)
+    pub col_annotations_size: u64,
+    /// Offset to attribute block from metadata start (version 5)
+    pub attributes_offset: u64,
+    /// Size of attribute block in bytes (0 if absent)
+    pub attributes_size: u64,
 }
 
 impl Default for BspcMetadataHeader {
@@ -43,6 +70,34 @@ impl BspcMetadataHeader {
             row_labels_size: 0,
             col_labels_offset: 0,
             col_labels_size: 0,
+            row_index_offset: 0,
+            row_index_size: 0,
+            col_index_offset: 0,
+            col_index_size: 0,
+            row_annotations_offset: 0,
+            row_annotations_size: 0,
+            col_annotations_offset: 0,
+            col_annotations_size: 0,
+            attributes_offset: 0,
+            attributes_size: 0,
+        }
+    }
+
+    /// Size of the header as stored for its version
+    pub const fn stored_size(&self) -> usize {
+        Self::size_for_version(self.version)
+    }
+
+    /// Size of the header as stored for a given version
+    const fn size_for_version(version: u8) -> usize {
+        if version >= 5 {
+            HEADER_SIZE
+        } else if version >= 4 {
+            HEADER_SIZE_V4
+        } else if version >= 2 {
+            HEADER_SIZE_V2
+        } else {
+            HEADER_SIZE_V1
         }
     }
 
@@ -62,7 +117,7 @@ impl BspcMetadataHeader {
 
     /// Parse metadata header from bytes
     pub const fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < HEADER_SIZE {
+        if bytes.len() < HEADER_SIZE_V1 {
             return Err(BspcError::InsufficientBuffer);
         }
 
@@ -79,6 +134,9 @@ impl BspcMetadataHeader {
         if version > VERSION {
             return Err(BspcError::UnsupportedFormat);
         }
+        if bytes.len() < Self::size_for_version(version) {
+            return Err(BspcError::InsufficientBuffer);
+        }
 
         // Parse fields using const-friendly approach
         let row_labels_offset = u64::from_le_bytes([
@@ -94,6 +152,42 @@ impl BspcMetadataHeader {
             bytes[32], bytes[33], bytes[34], bytes[35], bytes[36], bytes[37], bytes[38], bytes[39],
         ]);
 
+        // Label index fields only exist from version 2
+        let (row_index_offset, row_index_size, col_index_offset, col_index_size) = if version >= 2 {
+            (
+                read_u64(bytes, 40),
+                read_u64(bytes, 48),
+                read_u64(bytes, 56),
+                read_u64(bytes, 64),
+            )
+        } else {
+            (0, 0, 0, 0)
+        };
+
+        // Annotation fields only exist from version 4
+        let (
+            row_annotations_offset,
+            row_annotations_size,
+            col_annotations_offset,
+            col_annotations_size,
+        ) = if version >= 4 {
+            (
+                read_u64(bytes, 72),
+                read_u64(bytes, 80),
+                read_u64(bytes, 88),
+                read_u64(bytes, 96),
+            )
+        } else {
+            (0, 0, 0, 0)
+        };
+
+        // Attribute fields only exist from version 5
+        let (attributes_offset, attributes_size) = if version >= 5 {
+            (read_u64(bytes, 104), read_u64(bytes, 112))
+        } else {
+            (0, 0)
+        };
+
         Ok(Self {
             magic: MAGIC,
             version,
@@ -102,6 +196,16 @@ impl BspcMetadataHeader {
             row_labels_size,
             col_labels_offset,
             col_labels_size,
+            row_index_offset,
+            row_index_size,
+            col_index_offset,
+            col_index_size,
+            row_annotations_offset,
+            row_annotations_size,
+            col_annotations_offset,
+            col_annotations_size,
+            attributes_offset,
+            attributes_size,
         })
     }
 
@@ -163,28 +267,121 @@ impl BspcMetadataHeader {
         bytes[38] = col_size_bytes[6];
         bytes[39] = col_size_bytes[7];
 
+        // Label index fields
+        write_u64(&mut bytes, 40, self.row_index_offset);
+        write_u64(&mut bytes, 48, self.row_index_size);
+        write_u64(&mut bytes, 56, self.col_index_offset);
+        write_u64(&mut bytes, 64, self.col_index_size);
+
+        // Annotation fields
+        write_u64(&mut bytes, 72, self.row_annotations_offset);
+        write_u64(&mut bytes, 80, self.row_annotations_size);
+        write_u64(&mut bytes, 88, self.col_annotations_offset);
+        write_u64(&mut bytes, 96, self.col_annotations_size);
+
+        // Attribute fields
+        write_u64(&mut bytes, 104, self.attributes_offset);
+        write_u64(&mut bytes, 112, self.attributes_size);
+
         bytes
     }
 }
 
+/// Read a little-endian u64 at `offset` (const-friendly)
+pub(super) const fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+        bytes[offset + 4],
+        bytes[offset + 5],
+        bytes[offset + 6],
+        bytes[offset + 7],
+    ])
+}
+
+/// Read a little-endian u32 at `offset` (const-friendly)
+const fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Write a little-endian u64 at `offset` (const-friendly)
+pub(super) const fn write_u64(bytes: &mut [u8], offset: usize, value: u64) {
+    let value_bytes = value.to_le_bytes();
+    let mut i = 0;
+    while i < 8 {
+        bytes[offset + i] = value_bytes[i];
+        i += 1;
+    }
+}
+
+/// Encoding of a label array, as recorded by the stride field of its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LabelEncoding {
+    /// Offset table plus a blob of label bytes, with no padding
+    #[default]
+    Variable,
+    /// Every label NUL padded to the given stride
+    ///
+    /// Labels must not end with NUL bytes as padding is stripped on read.
+    Fixed(u32),
+}
+
 /// Label array header (8 bytes)
+///
+/// A non-zero `stride` means `count` labels of `stride` bytes each, NUL
+/// padded. A stride of zero marks the variable-length encoding (metadata
+/// version 3 and later): `count + 1` little-endian `u64` offsets into a blob of
+/// label bytes that follows them, with label `i` spanning
+/// `offsets[i]..offsets[i + 1]`.
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LabelArrayHeader {
     /// Number of labels in the array
     pub count: u32,
-    /// Fixed stride (width) of each label in bytes
+    /// Fixed stride (width) of each label in bytes, or 0 for variable-length
     pub stride: u32,
 }
 
 impl LabelArrayHeader {
-    /// Create a new label array header
+    /// Create a new fixed-stride label array header
     pub const fn new(count: u32, stride: u32) -> Self {
         Self { count, stride }
     }
 
+    /// Create a new variable-length label array header
+    pub const fn variable(count: u32) -> Self {
+        Self { count, stride: 0 }
+    }
+
+    /// Label encoding described by this header
+    pub const fn encoding(&self) -> LabelEncoding {
+        if self.stride == 0 {
+            LabelEncoding::Variable
+        } else {
+            LabelEncoding::Fixed(self.stride)
+        }
+    }
+
     /// Parse from bytes
+    ///
+    /// Only fixed-stride headers are accepted; use [`Self::from_bytes_for_version`]
+    /// to also accept the variable-length encoding.
     pub const fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes_for_version(bytes, 1)
+    }
+
+    /// Parse from bytes stored in metadata of the given version
+    ///
+    /// A zero stride is accepted as variable-length only from
+    /// [`VARIABLE_LABELS_VERSION`] on.
+    pub const fn from_bytes_for_version(bytes: &[u8], version: u8) -> Result<Self> {
         if bytes.len() < LABEL_ARRAY_HEADER_SIZE {
             return Err(BspcError::InsufficientBuffer);
         }
@@ -193,7 +390,10 @@ impl LabelArrayHeader {
         let stride = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
 
         // Validate stride
-        if stride == 0 || stride > crate::format::constants::MAX_LABEL_STRIDE {
+        if stride == 0 && version < VARIABLE_LABELS_VERSION {
+            return Err(BspcError::InvalidMetadata);
+        }
+        if stride > crate::format::constants::MAX_LABEL_STRIDE {
             return Err(BspcError::InvalidMetadata);
         }
 
@@ -219,8 +419,305 @@ impl LabelArrayHeader {
         bytes
     }
 
-    /// Calculate total size of the label data
+    /// Size of the fixed part following the header: the padded labels, or the
+    /// offset table for variable-length labels (the blob is not included)
     pub const fn total_size(&self) -> usize {
-        self.count as usize * self.stride as usize
+        if self.stride == 0 {
+            (self.count as usize + 1) * 8
+        } else {
+            self.count as usize * self.stride as usize
+        }
+    }
+}
+
+/// Value type of an annotation column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AnnotationKind {
+    /// UTF-8 strings: `len + 1` u64 offsets followed by a blob
+    String = 0,
+    /// Categories: `u32` category count, `u32` reserved, a string array of
+    /// category names, padding to 4 bytes, then one `u32` code per entry
+    Categorical = 1,
+    /// One little-endian `i64` per entry
+    I64 = 2,
+    /// One little-endian `f64` per entry
+    F64 = 3,
+}
+
+impl AnnotationKind {
+    /// Convert from u8 representation
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::String),
+            1 => Some(Self::Categorical),
+            2 => Some(Self::I64),
+            3 => Some(Self::F64),
+            _ => None,
+        }
+    }
+}
+
+/// Annotation table header (8 bytes)
+///
+/// A table holds named columns with one entry per row (or column) of the
+/// matrix. The header is followed by `column_count` [`AnnotationColumnHeader`]
+/// descriptors, then column names and column data at the offsets they give.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnnotationTableHeader {
+    /// Number of annotation columns
+    pub column_count: u32,
+    /// Number of entries in every column
+    pub len: u32,
+}
+
+impl AnnotationTableHeader {
+    /// Create a new annotation table header
+    pub const fn new(column_count: u32, len: u32) -> Self {
+        Self { column_count, len }
+    }
+
+    /// Parse from bytes
+    pub const fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < ANNOTATION_TABLE_HEADER_SIZE {
+            return Err(BspcError::InsufficientBuffer);
+        }
+
+        Ok(Self {
+            column_count: read_u32(bytes, 0),
+            len: read_u32(bytes, 4),
+        })
+    }
+
+    /// Convert to bytes
+    pub const fn to_bytes(&self) -> [u8; ANNOTATION_TABLE_HEADER_SIZE] {
+        let mut bytes = [0u8; ANNOTATION_TABLE_HEADER_SIZE];
+        let count_bytes = self.column_count.to_le_bytes();
+        let len_bytes = self.len.to_le_bytes();
+        let mut i = 0;
+        while i < 4 {
+            bytes[i] = count_bytes[i];
+            bytes[4 + i] = len_bytes[i];
+            i += 1;
+        }
+        bytes
+    }
+}
+
+/// Annotation column descriptor (32 bytes)
+///
+/// Offsets are relative to the start of the annotation table.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnnotationColumnHeader {
+    /// Column value type
+    pub kind: AnnotationKind,
+    /// Length of the UTF-8 column name in bytes
+    pub name_len: u32,
+    /// Offset to the column name
+    pub name_offset: u64,
+    /// Offset to the column data
+    pub data_offset: u64,
+    /// Size of the column data in bytes
+    pub data_size: u64,
+}
+
+impl AnnotationColumnHeader {
+    /// Parse from bytes
+    pub const fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < ANNOTATION_COLUMN_HEADER_SIZE {
+            return Err(BspcError::InsufficientBuffer);
+        }
+
+        let kind = match AnnotationKind::from_u8(bytes[0]) {
+            Some(kind) => kind,
+            None => return Err(BspcError::InvalidMetadata),
+        };
+
+        Ok(Self {
+            kind,
+            name_len: read_u32(bytes, 4),
+            name_offset: read_u64(bytes, 8),
+            data_offset: read_u64(bytes, 16),
+            data_size: read_u64(bytes, 24),
+        })
+    }
+
+    /// Convert to bytes
+    pub const fn to_bytes(&self) -> [u8; ANNOTATION_COLUMN_HEADER_SIZE] {
+        let mut bytes = [0u8; ANNOTATION_COLUMN_HEADER_SIZE];
+        bytes[0] = self.kind as u8;
+        // Bytes 1-3 reserved
+        let name_len_bytes = self.name_len.to_le_bytes();
+        let mut i = 0;
+        while i < 4 {
+            bytes[4 + i] = name_len_bytes[i];
+            i += 1;
+        }
+        write_u64(&mut bytes, 8, self.name_offset);
+        write_u64(&mut bytes, 16, self.data_offset);
+        write_u64(&mut bytes, 24, self.data_size);
+        bytes
+    }
+}
+
+/// Value type of a file attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AttributeKind {
+    /// UTF-8 string
+    String = 0,
+    /// Little-endian `i64`
+    I64 = 1,
+    /// Little-endian `f64`
+    F64 = 2,
+    /// Single byte, 0 or 1
+    Bool = 3,
+    /// UTF-8 JSON text
+    Json = 4,
+}
+
+impl AttributeKind {
+    /// Convert from u8 representation
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::String),
+            1 => Some(Self::I64),
+            2 => Some(Self::F64),
+            3 => Some(Self::Bool),
+            4 => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Attribute entry header (16 bytes)
+///
+/// The attribute block starts with a `u32` entry count and 4 reserved bytes.
+/// Each entry is this header followed by the UTF-8 key and the value bytes,
+/// padded to 8 bytes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttributeEntryHeader {
+    /// Value type
+    pub kind: AttributeKind,
+    /// Length of the key in bytes
+    pub key_len: u32,
+    /// Length of the value in bytes
+    pub value_len: u64,
+}
+
+impl AttributeEntryHeader {
+    /// Total entry size including header, key, value and padding
+    pub const fn entry_size(&self) -> usize {
+        // Saturates so corrupt lengths fail bounds checks instead of overflowing
+        let size = (ATTRIBUTE_ENTRY_HEADER_SIZE + self.key_len as usize)
+            .saturating_add(self.value_len as usize);
+        size.saturating_add(7) & !7
+    }
+
+    /// Parse from bytes
+    pub const fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < ATTRIBUTE_ENTRY_HEADER_SIZE {
+            return Err(BspcError::InsufficientBuffer);
+        }
+
+        let kind = match AttributeKind::from_u8(bytes[0]) {
+            Some(kind) => kind,
+            None => return Err(BspcError::InvalidMetadata),
+        };
+
+        Ok(Self {
+            kind,
+            key_len: read_u32(bytes, 4),
+            value_len: read_u64(bytes, 8),
+        })
+    }
+
+    /// Convert to bytes
+    pub const fn to_bytes(&self) -> [u8; ATTRIBUTE_ENTRY_HEADER_SIZE] {
+        let mut bytes = [0u8; ATTRIBUTE_ENTRY_HEADER_SIZE];
+        bytes[0] = self.kind as u8;
+        // Bytes 1-3 reserved
+        let key_len_bytes = self.key_len.to_le_bytes();
+        let mut i = 0;
+        while i < 4 {
+            bytes[4 + i] = key_len_bytes[i];
+            i += 1;
+        }
+        write_u64(&mut bytes, 8, self.value_len);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_array_header_roundtrip() {
+        let fixed = LabelArrayHeader::new(3, 16);
+        assert_eq!(LabelArrayHeader::from_bytes(&fixed.to_bytes()), Ok(fixed));
+        assert_eq!(fixed.encoding(), LabelEncoding::Fixed(16));
+        assert_eq!(fixed.total_size(), 48);
+
+        let variable = LabelArrayHeader::variable(3);
+        assert_eq!(variable.to_bytes()[4..], [0; 4]);
+        assert_eq!(variable.encoding(), LabelEncoding::Variable);
+        assert_eq!(variable.total_size(), 32);
+        assert_eq!(
+            LabelArrayHeader::from_bytes_for_version(&variable.to_bytes(), VERSION),
+            Ok(variable)
+        );
+    }
+
+    #[test]
+    fn test_label_array_zero_stride_needs_version_3() {
+        let bytes = LabelArrayHeader::variable(2).to_bytes();
+        assert_eq!(
+            LabelArrayHeader::from_bytes(&bytes),
+            Err(BspcError::InvalidMetadata)
+        );
+        assert_eq!(
+            LabelArrayHeader::from_bytes_for_version(&bytes, VARIABLE_LABELS_VERSION - 1),
+            Err(BspcError::InvalidMetadata)
+        );
+        assert!(LabelArrayHeader::from_bytes_for_version(&bytes, VARIABLE_LABELS_VERSION).is_ok());
+
+        let too_wide = LabelArrayHeader::new(1, crate::format::constants::MAX_LABEL_STRIDE + 1);
+        assert_eq!(
+            LabelArrayHeader::from_bytes(&too_wide.to_bytes()),
+            Err(BspcError::InvalidMetadata)
+        );
+        assert_eq!(
+            LabelArrayHeader::from_bytes(&[0; 4]),
+            Err(BspcError::InsufficientBuffer)
+        );
+    }
+
+    #[test]
+    fn test_annotation_headers_roundtrip() {
+        let table = AnnotationTableHeader::new(2, 10);
+        assert_eq!(
+            AnnotationTableHeader::from_bytes(&table.to_bytes()),
+            Ok(table)
+        );
+
+        let column = AnnotationColumnHeader {
+            kind: AnnotationKind::Categorical,
+            name_len: 4,
+            name_offset: 72,
+            data_offset: 80,
+            data_size: 123,
+        };
+        let mut bytes = column.to_bytes();
+        assert_eq!(AnnotationColumnHeader::from_bytes(&bytes), Ok(column));
+
+        bytes[0] = 4;
+        assert_eq!(
+            AnnotationColumnHeader::from_bytes(&bytes),
+            Err(BspcError::InvalidMetadata)
+        );
     }
 }
@@ -3,10 +3,15 @@
 //! This module contains pure data structure definitions for the BSPC wire format.
 //! No I/O operations or concrete implementations - only format specifications.
 
+pub mod blocks;
 pub mod constants;
 pub mod header;
 pub mod metadata;
 
 // Re-export format definitions
-pub use header::{BspcHeader, DataType, MatrixFormat};
-pub use metadata::{BspcMetadataHeader, LabelArrayHeader};
+pub use blocks::BlockTableEntry;
+pub use header::{BspcHeader, DataType, IndexCodec, IndexWidth, MatrixFormat, ValueCodec};
+pub use metadata::{
+    AnnotationColumnHeader, AnnotationKind, AnnotationTableHeader, AttributeEntryHeader,
+    AttributeKind, BspcMetadataHeader, LabelArrayHeader, LabelEncoding,
+};
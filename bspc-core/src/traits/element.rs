@@ -4,6 +4,7 @@
 //! stored as matrix elements in BSPC format.
 
 use crate::format::DataType;
+use num_complex::{Complex32, Complex64};
 
 /// Trait for types that can be stored as matrix elements
 ///
@@ -31,8 +32,23 @@ pub trait MatrixElement: Copy + Clone + PartialEq + Sized {
     /// Convert to f64 for generic operations
     ///
     /// This is used for generic operations where a common numeric
-    /// type is needed.
+    /// type is needed. Complex types return their real part; use
+    /// [`MatrixElement::to_complex`] to keep the imaginary part.
     fn to_f64(self) -> f64;
+
+    /// Convert to a complex number
+    ///
+    /// Real types become the real part with a zero imaginary part.
+    fn to_complex(self) -> Complex64 {
+        Complex64::new(self.to_f64(), 0.0)
+    }
+
+    /// Convert from a complex number
+    ///
+    /// Real types keep only the real part.
+    fn from_complex(value: Complex64) -> Self {
+        Self::from_f64(value.re)
+    }
 }
 
 // Implement MatrixElement for standard numeric types
@@ -121,5 +137,156 @@ impl MatrixElement for u64 {
     }
 }
 
+impl MatrixElement for i8 {
+    fn data_type() -> DataType {
+        DataType::I8
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as i8
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl MatrixElement for i16 {
+    fn data_type() -> DataType {
+        DataType::I16
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as i16
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl MatrixElement for u8 {
+    fn data_type() -> DataType {
+        DataType::U8
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as u8
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl MatrixElement for u16 {
+    fn data_type() -> DataType {
+        DataType::U16
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as u16
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl MatrixElement for half::f16 {
+    fn data_type() -> DataType {
+        DataType::F16
+    }
+
+    fn from_f64(value: f64) -> Self {
+        half::f16::from_f64(value)
+    }
+
+    fn to_f64(self) -> f64 {
+        half::f16::to_f64(self)
+    }
+}
+
+impl MatrixElement for half::bf16 {
+    fn data_type() -> DataType {
+        DataType::BF16
+    }
+
+    fn from_f64(value: f64) -> Self {
+        half::bf16::from_f64(value)
+    }
+
+    fn to_f64(self) -> f64 {
+        half::bf16::to_f64(self)
+    }
+}
+
+impl MatrixElement for Complex32 {
+    fn data_type() -> DataType {
+        DataType::C64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        Complex32::new(value as f32, 0.0)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.re as f64
+    }
+
+    fn to_complex(self) -> Complex64 {
+        Complex64::new(self.re as f64, self.im as f64)
+    }
+
+    fn from_complex(value: Complex64) -> Self {
+        Complex32::new(value.re as f32, value.im as f32)
+    }
+}
+
+impl MatrixElement for Complex64 {
+    fn data_type() -> DataType {
+        DataType::C128
+    }
+
+    fn from_f64(value: f64) -> Self {
+        Complex64::new(value, 0.0)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.re
+    }
+
+    fn to_complex(self) -> Complex64 {
+        self
+    }
+
+    fn from_complex(value: Complex64) -> Self {
+        value
+    }
+}
+
+/// Element type of pattern matrices
+///
+/// A pattern matrix only records which positions are present; every stored
+/// element is `true`. `Pattern` is zero-sized, so slices of it need no backing
+/// memory and no values array is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pattern;
+
+impl MatrixElement for Pattern {
+    fn data_type() -> DataType {
+        DataType::Pattern
+    }
+
+    fn from_f64(_value: f64) -> Self {
+        Pattern
+    }
+
+    fn to_f64(self) -> f64 {
+        1.0
+    }
+}
+
 // Note: ArrayValue from binsparse_rs doesn't implement Copy, so it can't
 // directly implement MatrixElement. Use wrapper types or specific conversions instead.
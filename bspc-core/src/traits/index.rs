@@ -0,0 +1,74 @@
+//! Matrix index type constraints for BSPC specification
+//!
+//! This module defines the trait for the integer types used to store row and
+//! column indices. The width in use is recorded in the file header.
+
+use crate::format::IndexWidth;
+
+/// Trait for types that can be stored as row and column indices
+///
+/// Index types are fixed-width unsigned integers stored little-endian. Every
+/// index type must be:
+/// - Copy and Ord: Indices are compared while searching sorted COO data
+/// - Send and Sync: Index arrays are shared between threads
+pub trait MatrixIndex: Copy + Ord + Default + core::fmt::Debug + Send + Sync + 'static {
+    /// Header representation of this index type
+    const WIDTH: IndexWidth;
+
+    /// Little-endian byte representation
+    type Bytes: AsRef<[u8]>;
+
+    /// Widen to usize
+    fn to_usize(self) -> usize;
+
+    /// Narrow from usize, or `None` if the value does not fit
+    fn from_usize(value: usize) -> Option<Self>;
+
+    /// Convert to little-endian bytes
+    fn to_le_bytes(self) -> Self::Bytes;
+
+    /// Read from little-endian bytes, or `None` if there are too few
+    fn from_le_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl MatrixIndex for u32 {
+    const WIDTH: IndexWidth = IndexWidth::U32;
+    type Bytes = [u8; 4];
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    fn from_usize(value: usize) -> Option<Self> {
+        u32::try_from(value).ok()
+    }
+
+    fn to_le_bytes(self) -> Self::Bytes {
+        u32::to_le_bytes(self)
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?))
+    }
+}
+
+impl MatrixIndex for u64 {
+    const WIDTH: IndexWidth = IndexWidth::U64;
+    type Bytes = [u8; 8];
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    fn from_usize(value: usize) -> Option<Self> {
+        u64::try_from(value).ok()
+    }
+
+    fn to_le_bytes(self) -> Self::Bytes {
+        u64::to_le_bytes(self)
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?))
+    }
+}
@@ -5,10 +5,12 @@
 
 pub mod backend;
 pub mod element;
+pub mod index;
 pub mod matrix;
 
 pub use backend::{ChunkProcessor, Chunkable, StorageBackend};
-pub use element::MatrixElement;
+pub use element::{MatrixElement, Pattern};
+pub use index::MatrixIndex;
 #[cfg(feature = "alloc")]
 pub use matrix::MatrixOperations;
 pub use matrix::SparseMatrix;
@@ -171,6 +171,12 @@ async fn handle_http_query(
         println!("Use --row X --col-range start:end to query specific row with column range");
     }
 
+    let stats = matrix.cache_stats();
+    println!(
+        "Cache: {} hits, {} misses, {} bytes cached",
+        stats.hits, stats.misses, stats.cached_bytes
+    );
+
     Ok(())
 }
 
@@ -183,6 +189,7 @@ async fn handle_http_info(url: &str) -> Result<(), Box<dyn std::error::Error>> {
     println!("  Dimensions: {} x {}", matrix.nrows(), matrix.ncols());
     println!("  Non-zero elements: {}", matrix.nnz());
     println!("  Format: {} ({})", matrix.format(), matrix.data_type());
+    println!("  Index width: {}", matrix.index_width());
 
     // Check server capabilities
     let supports_ranges = matrix
@@ -202,5 +209,13 @@ async fn handle_http_info(url: &str) -> Result<(), Box<dyn std::error::Error>> {
         println!("  File size: {size} bytes");
     }
 
+    let attributes = matrix.attributes().await.map_err(|e| format!("{e:?}"))?;
+    if !attributes.is_empty() {
+        println!("  Attributes:");
+        for (key, value) in attributes {
+            println!("    {key}: {value}");
+        }
+    }
+
     Ok(())
 }
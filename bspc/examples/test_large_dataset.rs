@@ -38,6 +38,7 @@ async fn main() -> Result<(), binsparse_rs::Error> {
         memory_limit_mb: 2048,
         bloom_hash_count: 3,
         chunk_size: 100_000,
+        compression: None,
     };
 
     println!("Writing with V2 format (u64 support)...");
@@ -0,0 +1,252 @@
+use bspc::{slice_file, BspcFile, MetadataView, Selection};
+use bspc_core::{DataType, MatrixFormat};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(about = "BSPC CLI - Work with binary sparse matrix files")]
+struct Cli {
+    /// Print the elapsed time to stderr when the command finishes
+    #[arg(long, short, global = true)]
+    verbose: bool,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Extract a subset of rows and columns into a new file
+    ///
+    /// Lists are comma separated, or `@path` to read one entry per line from a file.
+    /// Omitted axes are kept whole.
+    Slice {
+        /// Input BSPC matrix file
+        input: PathBuf,
+
+        /// Output BSPC matrix file
+        output: PathBuf,
+
+        /// Row indices to keep, in output order
+        #[arg(long, conflicts_with = "row_labels")]
+        rows: Option<String>,
+
+        /// Column indices to keep, in output order
+        #[arg(long, conflicts_with = "col_labels")]
+        cols: Option<String>,
+
+        /// Row labels to keep, in output order
+        #[arg(long)]
+        row_labels: Option<String>,
+
+        /// Column labels to keep, in output order
+        #[arg(long)]
+        col_labels: Option<String>,
+    },
+
+    /// Show the header, labels, annotations and attributes of a matrix file
+    Info {
+        /// BSPC matrix file
+        input: PathBuf,
+    },
+
+    /// Serve files over HTTP
+    ///
+    /// By default serves one directory with range request support, so remote
+    /// matrices can be opened from `http://<addr>/<file>`. With `--api`,
+    /// memory-maps the given files, or the `.bspc` files in the given
+    /// directories, and answers row, column and element queries as JSON or Arrow.
+    #[cfg(feature = "http")]
+    Serve {
+        /// Directory to serve, or matrix files and directories with `--api`
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: std::net::SocketAddr,
+
+        /// Serve the JSON query API instead of the files themselves
+        #[cfg(feature = "api")]
+        #[arg(long)]
+        api: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let start_time = std::time::Instant::now();
+
+    match &cli.command {
+        Commands::Slice {
+            input,
+            output,
+            rows,
+            cols,
+            row_labels,
+            col_labels,
+        } => {
+            handle_slice(input, output, rows, cols, row_labels, col_labels).await?;
+        }
+        Commands::Info { input } => {
+            handle_info(input)?;
+        }
+        #[cfg(feature = "http")]
+        Commands::Serve {
+            paths,
+            addr,
+            #[cfg(feature = "api")]
+            api,
+        } => {
+            #[cfg(feature = "api")]
+            if *api {
+                println!("Serving query API at http://{addr}/matrices");
+                bspc::serve_api(paths, *addr)
+                    .await
+                    .map_err(|e| format!("{e:?}"))?;
+                return Ok(());
+            }
+
+            let [dir] = paths.as_slice() else {
+                return Err("Serving files takes a single directory".into());
+            };
+            println!("Serving {} at http://{addr}/", dir.display());
+            bspc::serve_dir(dir, *addr)
+                .await
+                .map_err(|e| format!("{e:?}"))?;
+        }
+    }
+
+    if cli.verbose {
+        eprintln!("Completed in {:.2?}", start_time.elapsed());
+    }
+
+    Ok(())
+}
+
+/// Expand a comma separated list, or read one entry per line from `@path`
+fn read_list(arg: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let entries: Vec<String> = match arg.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path)?
+            .lines()
+            .map(str::to_owned)
+            .collect(),
+        None => arg.split(',').map(str::to_owned).collect(),
+    };
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| entry.trim().to_owned())
+        .filter(|entry| !entry.is_empty())
+        .collect())
+}
+
+/// Build a selection from optional index and label arguments
+fn parse_selection(
+    indices: &Option<String>,
+    labels: &Option<String>,
+) -> Result<Selection, Box<dyn std::error::Error>> {
+    if let Some(indices) = indices {
+        let indices = read_list(indices)?
+            .iter()
+            .map(|entry| entry.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Selection::Indices(indices))
+    } else if let Some(labels) = labels {
+        let labels = read_list(labels)?
+            .into_iter()
+            .map(String::into_bytes)
+            .collect();
+        Ok(Selection::Labels(labels))
+    } else {
+        Ok(Selection::All)
+    }
+}
+
+async fn handle_slice(
+    input: &Path,
+    output: &Path,
+    rows: &Option<String>,
+    cols: &Option<String>,
+    row_labels: &Option<String>,
+    col_labels: &Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rows = parse_selection(rows, row_labels)?;
+    let cols = parse_selection(cols, col_labels)?;
+
+    slice_file(input, &rows, &cols, output)
+        .await
+        .map_err(|e| format!("{e:?}"))?;
+
+    println!("Wrote {}", output.display());
+    Ok(())
+}
+
+fn handle_info(input: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = BspcFile::open(input).map_err(|e| format!("{e:?}"))?;
+    let header = &file.header;
+
+    println!("Matrix Info:");
+    println!("  Path: {}", input.display());
+    println!("  Dimensions: {} x {}", header.nrows, header.ncols);
+    println!("  Non-zero elements: {}", header.nnz);
+    match (
+        MatrixFormat::from_u8(header.format_type),
+        DataType::from_u8(header.data_type),
+    ) {
+        (Some(format), Some(data_type)) => println!("  Format: {format} ({data_type})"),
+        _ => println!("  Format: unknown"),
+    }
+    match header.index_width() {
+        Some(width) => println!("  Index width: {width}"),
+        None => println!("  Index width: unknown"),
+    }
+    if header.is_compressed() {
+        match (header.index_codec(), header.value_codec()) {
+            (Some(index_codec), Some(value_codec)) => println!(
+                "  Compression: {index_codec} indices, {value_codec} values, {} elements per block",
+                header.block_len()
+            ),
+            _ => println!("  Compression: unknown"),
+        }
+    }
+
+    let Some(metadata) = file.read_metadata().map_err(|e| format!("{e:?}"))? else {
+        return Ok(());
+    };
+    let view = MetadataView::new(&metadata).map_err(|e| format!("{e:?}"))?;
+
+    for (axis, labels) in [
+        ("Row", view.row_labels().map_err(|e| format!("{e:?}"))?),
+        ("Column", view.col_labels().map_err(|e| format!("{e:?}"))?),
+    ] {
+        if let Some(labels) = labels {
+            println!("  {axis} labels: {}", labels.count());
+        }
+    }
+
+    for (axis, table) in [
+        ("Row", view.row_annotations().map_err(|e| format!("{e:?}"))?),
+        (
+            "Column",
+            view.col_annotations().map_err(|e| format!("{e:?}"))?,
+        ),
+    ] {
+        if let Some(table) = table {
+            let names = table.column_names().map_err(|e| format!("{e:?}"))?;
+            println!("  {axis} annotations: {}", names.join(", "));
+        }
+    }
+
+    let attributes = view.attributes().map_err(|e| format!("{e:?}"))?;
+    if !attributes.is_empty() {
+        println!("  Attributes:");
+        for (key, value) in attributes {
+            println!("    {key}: {value}");
+        }
+    }
+
+    Ok(())
+}
@@ -4,6 +4,7 @@
 //! of sparse matrix data when they don't contain elements in the requested range.
 
 use bspc_core::bloom_filter::BloomFilter64;
+use bspc_core::MatrixIndex;
 use rayon::prelude::*;
 use std::sync::Mutex;
 use std::vec::Vec;
@@ -147,12 +148,20 @@ impl ChunkBloomFilter {
 
     /// Get the serialized size in bytes
     pub fn serialized_size(&self) -> usize {
-        // 4 bytes for chunk_size + 4 bytes for total_rows + 4 bytes for num_chunks
+        self.serialized_size_with::<u32>()
+    }
+
+    /// Get the serialized size in bytes with fields of index type `I`
+    pub fn serialized_size_with<I: MatrixIndex>(&self) -> usize {
+        // chunk_size, total_rows and num_chunks as I
         // + 1 byte for hash_count per chunk + 8 bytes per chunk filter
-        12 + self.chunk_filters.len() * 9
+        3 * I::WIDTH.size_bytes() + self.chunk_filters.len() * 9
     }
 
     /// Serialize the chunk bloom filter
+    ///
+    /// Sizes are stored as u32; matrices with 64-bit indices use
+    /// [`ChunkBloomFilter::serialize_with`].
     pub fn serialize(&self) -> Vec<u8> {
         let buffer = Mutex::new(Vec::with_capacity(self.serialized_size()));
 
@@ -164,6 +173,26 @@ impl ChunkBloomFilter {
             buf.extend_from_slice(&(self.chunk_filters.len() as u32).to_le_bytes());
         }
 
+        let mut buf = buffer.into_inner().unwrap();
+        self.serialize_chunks(&mut buf);
+        buf
+    }
+
+    /// Serialize the chunk bloom filter with sizes stored as index type `I`
+    pub fn serialize_with<I: MatrixIndex>(&self) -> Result<Vec<u8>, &'static str> {
+        let mut buf = Vec::with_capacity(self.serialized_size_with::<I>());
+        for value in [self.chunk_size, self.total_rows, self.chunk_filters.len()] {
+            let value =
+                I::from_usize(value).ok_or("Chunk bloom filter size exceeds index width")?;
+            buf.extend_from_slice(value.to_le_bytes().as_ref());
+        }
+
+        self.serialize_chunks(&mut buf);
+        Ok(buf)
+    }
+
+    /// Append the per-chunk filters
+    fn serialize_chunks(&self, buf: &mut Vec<u8>) {
         // Parallel serialization of chunk filters
         let chunk_data: Vec<_> = self
             .chunk_filters
@@ -177,26 +206,37 @@ impl ChunkBloomFilter {
             .collect();
 
         // Sequential append to maintain order
-        let mut buf = buffer.into_inner().unwrap();
         for chunk_bytes in chunk_data {
             buf.extend_from_slice(&chunk_bytes);
         }
-
-        buf
     }
 
     /// Deserialize a chunk bloom filter
     pub fn deserialize(data: &[u8]) -> Result<Self, &'static str> {
-        if data.len() < 12 {
+        Self::deserialize_with::<u32>(data)
+    }
+
+    /// Deserialize a chunk bloom filter whose sizes are stored as index type `I`
+    pub fn deserialize_with<I: MatrixIndex>(data: &[u8]) -> Result<Self, &'static str> {
+        let width = I::WIDTH.size_bytes();
+        if data.len() < 3 * width {
             return Err("Invalid chunk bloom filter data");
         }
 
-        let chunk_size = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
-        let total_rows = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
-        let num_chunks = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
+        let field = |i: usize| {
+            I::from_le_bytes(&data[i * width..])
+                .map(I::to_usize)
+                .ok_or("Invalid chunk bloom filter data")
+        };
+        let chunk_size = field(0)?;
+        let total_rows = field(1)?;
+        let num_chunks = field(2)?;
+        if chunk_size == 0 {
+            return Err("Invalid chunk bloom filter chunk size");
+        }
 
-        let mut chunk_filters = Vec::with_capacity(num_chunks);
-        let mut offset = 12;
+        let mut chunk_filters = Vec::with_capacity(num_chunks.min(data.len() / 9));
+        let mut offset = 3 * width;
 
         for _ in 0..num_chunks {
             if offset + 9 > data.len() {
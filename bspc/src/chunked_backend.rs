@@ -18,6 +18,8 @@ pub struct ChunkConfig {
     pub bloom_hash_count: u8,
     /// Size of each chunk in rows for bloom filtering
     pub chunk_size: usize,
+    /// Block compression for written files (`None` writes raw arrays)
+    pub compression: Option<crate::compression::CompressionConfig>,
 }
 
 impl ChunkConfig {
@@ -27,6 +29,7 @@ impl ChunkConfig {
             memory_limit_mb,
             bloom_hash_count: 3,
             chunk_size: 100_000, // Default 100K rows per chunk
+            compression: None,
         }
     }
 
@@ -42,6 +45,17 @@ impl ChunkConfig {
         self
     }
 
+    /// Write files as compressed blocks
+    pub fn with_compression(mut self, compression: crate::compression::CompressionConfig) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Get the block compression settings for written files
+    pub fn compression(&self) -> Option<&crate::compression::CompressionConfig> {
+        self.compression.as_ref()
+    }
+
     /// Get chunk size in rows
     pub fn chunk_size(&self) -> usize {
         self.chunk_size
@@ -75,6 +89,7 @@ impl Default for ChunkConfig {
             memory_limit_mb: 128,
             bloom_hash_count: 3,
             chunk_size: 100_000,
+            compression: None,
         }
     }
 }
@@ -0,0 +1,417 @@
+//! Block codecs for compressed .bspc files
+//!
+//! Compressed files store the sorted COO arrays as independently encoded blocks
+//! (see [`bspc_core::BlockTableEntry`]). Row and column indices use delta +
+//! bit-packing; values use a per-block dictionary, zstd or LZ4. The zstd and LZ4
+//! codecs are only available with the `zstd` and `lz4` features.
+
+#[cfg(feature = "mmap")]
+use crate::mmap_backend::MatrixElement;
+use binsparse_rs::{Error, Result};
+use bspc_core::{IndexWidth, MatrixIndex};
+#[cfg(feature = "mmap")]
+use std::collections::HashMap;
+
+pub use bspc_core::{IndexCodec, ValueCodec};
+
+/// Settings for writing compressed files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Codec for row and column index blocks
+    pub index_codec: IndexCodec,
+    /// Codec for value blocks
+    pub value_codec: ValueCodec,
+    /// Elements per block as a power of two
+    pub block_size_log2: u8,
+    /// Compression level for zstd (ignored by other codecs)
+    pub level: i32,
+}
+
+impl CompressionConfig {
+    /// Create a config with 4096-element blocks
+    pub fn new(index_codec: IndexCodec, value_codec: ValueCodec) -> Self {
+        Self {
+            index_codec,
+            value_codec,
+            block_size_log2: 12,
+            level: 3,
+        }
+    }
+
+    /// Set the number of elements per block as a power of two
+    pub fn with_block_size_log2(mut self, block_size_log2: u8) -> Self {
+        self.block_size_log2 = block_size_log2;
+        self
+    }
+
+    /// Set the zstd compression level
+    pub fn with_level(mut self, level: i32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Number of elements per block
+    pub fn block_len(&self) -> usize {
+        1usize << self.block_size_log2
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self::new(IndexCodec::DeltaBitPack, ValueCodec::Raw)
+    }
+}
+
+/// Append `values` to `out` using `width` bits each, least significant bit first
+fn pack_bits(out: &mut Vec<u8>, values: impl Iterator<Item = u64>, width: u32) {
+    let mut buffer = 0u128;
+    let mut bits = 0u32;
+    for value in values {
+        buffer |= (value as u128) << bits;
+        bits += width;
+        while bits >= 8 {
+            out.push(buffer as u8);
+            buffer >>= 8;
+            bits -= 8;
+        }
+    }
+    if bits > 0 {
+        out.push(buffer as u8);
+    }
+}
+
+/// Read `count` values of `width` bits each written by [`pack_bits`]
+fn unpack_bits(bytes: &[u8], count: usize, width: u32) -> Result<Vec<u64>> {
+    let needed = (count as u64 * width as u64).div_ceil(8);
+    if (bytes.len() as u64) < needed {
+        return Err(Error::InvalidState("Bit-packed block is truncated"));
+    }
+
+    let mask = if width == 64 {
+        u64::MAX
+    } else {
+        (1u64 << width) - 1
+    };
+    let mut values = Vec::with_capacity(count);
+    let mut buffer = 0u128;
+    let mut bits = 0u32;
+    let mut bytes = bytes.iter();
+    for _ in 0..count {
+        while bits < width {
+            buffer |= (*bytes.next().unwrap_or(&0) as u128) << bits;
+            bits += 8;
+        }
+        values.push(buffer as u64 & mask);
+        buffer >>= width;
+        bits -= width;
+    }
+    Ok(values)
+}
+
+/// Bits needed to store `value`
+fn bit_width(value: u64) -> u32 {
+    u64::BITS - value.leading_zeros()
+}
+
+/// Encode one block of indices
+///
+/// `width` is the file's index width, used by the raw codec.
+pub fn encode_indices(indices: &[u64], width: IndexWidth, codec: IndexCodec) -> Result<Vec<u8>> {
+    match codec {
+        IndexCodec::Raw => {
+            let mut out = Vec::with_capacity(indices.len() * width.size_bytes());
+            for &index in indices {
+                match width {
+                    IndexWidth::U32 => out.extend_from_slice(
+                        &u32::try_from(index)
+                            .map_err(|_| Error::InvalidState("Index does not fit in index width"))?
+                            .to_le_bytes(),
+                    ),
+                    IndexWidth::U64 => out.extend_from_slice(&index.to_le_bytes()),
+                }
+            }
+            Ok(out)
+        }
+        IndexCodec::DeltaBitPack => {
+            // First index, bit width, then zigzag deltas so that column indices
+            // restarting at each row stay small
+            let Some((&first, _)) = indices.split_first() else {
+                return Ok(Vec::new());
+            };
+            let deltas: Vec<u64> = indices
+                .windows(2)
+                .map(|w| {
+                    let delta = w[1].wrapping_sub(w[0]) as i64;
+                    ((delta << 1) ^ (delta >> 63)) as u64
+                })
+                .collect();
+            let width = deltas.iter().copied().map(bit_width).max().unwrap_or(0);
+
+            let mut out = Vec::with_capacity(9 + (deltas.len() * width as usize).div_ceil(8));
+            out.extend_from_slice(&first.to_le_bytes());
+            out.push(width as u8);
+            pack_bits(&mut out, deltas.into_iter(), width);
+            Ok(out)
+        }
+    }
+}
+
+/// Decode one block of `count` indices
+pub fn decode_indices<I: MatrixIndex>(
+    bytes: &[u8],
+    count: usize,
+    codec: IndexCodec,
+) -> Result<Vec<I>> {
+    let invalid = || Error::InvalidState("Invalid compressed index block");
+    match codec {
+        IndexCodec::Raw => {
+            if bytes.len() != count * I::WIDTH.size_bytes() {
+                return Err(invalid());
+            }
+            Ok(bytes
+                .chunks_exact(I::WIDTH.size_bytes())
+                .filter_map(I::from_le_bytes)
+                .collect())
+        }
+        IndexCodec::DeltaBitPack => {
+            if count == 0 {
+                return Ok(Vec::new());
+            }
+            if bytes.len() < 9 || bytes[8] > 64 {
+                return Err(invalid());
+            }
+
+            let first = u64::from_le_bytes(bytes[..8].try_into().map_err(|_| invalid())?);
+            let deltas = unpack_bits(&bytes[9..], count - 1, bytes[8] as u32)?;
+
+            let mut indices = Vec::with_capacity(count);
+            let mut current = first;
+            indices.push(I::from_usize(current as usize).ok_or_else(invalid)?);
+            for zigzag in deltas {
+                let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+                current = current.wrapping_add(delta as u64);
+                indices.push(I::from_usize(current as usize).ok_or_else(invalid)?);
+            }
+            Ok(indices)
+        }
+    }
+}
+
+/// Encode one block of values
+#[cfg(feature = "mmap")]
+pub fn encode_values<T: MatrixElement>(
+    values: &[T],
+    codec: ValueCodec,
+    level: i32,
+) -> Result<Vec<u8>> {
+    // Pattern matrices have no value bytes whatever the codec
+    if T::size_bytes() == 0 {
+        return Ok(Vec::new());
+    }
+
+    let raw = || -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+
+    match codec {
+        ValueCodec::Raw => Ok(raw()),
+        ValueCodec::Dictionary => {
+            // Distinct values in first-seen order, then one code per element
+            let mut dictionary: HashMap<Vec<u8>, u64> = HashMap::new();
+            let mut entries = Vec::new();
+            let codes: Vec<u64> = values
+                .iter()
+                .map(|value| {
+                    let bytes = value.to_le_bytes();
+                    let next = dictionary.len() as u64;
+                    *dictionary.entry(bytes).or_insert_with_key(|bytes| {
+                        entries.extend_from_slice(bytes);
+                        next
+                    })
+                })
+                .collect();
+            let width = bit_width(dictionary.len().saturating_sub(1) as u64);
+
+            let mut out = Vec::with_capacity(5 + entries.len());
+            out.extend_from_slice(&(dictionary.len() as u32).to_le_bytes());
+            out.extend_from_slice(&entries);
+            out.push(width as u8);
+            pack_bits(&mut out, codes.into_iter(), width);
+            Ok(out)
+        }
+        ValueCodec::Zstd => compress_zstd(&raw(), level),
+        ValueCodec::Lz4 => compress_lz4(&raw()),
+    }
+}
+
+/// Decode one block of `count` values
+#[cfg(feature = "mmap")]
+pub fn decode_values<T: MatrixElement>(
+    bytes: &[u8],
+    count: usize,
+    codec: ValueCodec,
+) -> Result<Vec<T>> {
+    let size = T::size_bytes();
+    if size == 0 {
+        return (0..count).map(|_| T::from_le_bytes(&[])).collect();
+    }
+
+    let invalid = || Error::InvalidState("Invalid compressed value block");
+    let from_raw = |raw: &[u8]| -> Result<Vec<T>> {
+        if raw.len() != count * size {
+            return Err(invalid());
+        }
+        raw.chunks_exact(size).map(T::from_le_bytes).collect()
+    };
+
+    match codec {
+        ValueCodec::Raw => from_raw(bytes),
+        ValueCodec::Dictionary => {
+            if bytes.len() < 4 {
+                return Err(invalid());
+            }
+            let len = u32::from_le_bytes(bytes[..4].try_into().map_err(|_| invalid())?) as usize;
+            let entries_end = len
+                .checked_mul(size)
+                .and_then(|n| n.checked_add(4))
+                .ok_or_else(invalid)?;
+            if bytes.len() <= entries_end || bytes[entries_end] > 64 {
+                return Err(invalid());
+            }
+
+            let entries = bytes[4..entries_end]
+                .chunks_exact(size)
+                .map(T::from_le_bytes)
+                .collect::<Result<Vec<T>>>()?;
+            let codes = unpack_bits(&bytes[entries_end + 1..], count, bytes[entries_end] as u32)?;
+            codes
+                .into_iter()
+                .map(|code| entries.get(code as usize).copied().ok_or_else(invalid))
+                .collect()
+        }
+        ValueCodec::Zstd => from_raw(&decompress_zstd(bytes, count * size)?),
+        ValueCodec::Lz4 => from_raw(&decompress_lz4(bytes, count * size)?),
+    }
+}
+
+#[cfg(all(feature = "mmap", feature = "zstd"))]
+fn compress_zstd(raw: &[u8], level: i32) -> Result<Vec<u8>> {
+    zstd::bulk::compress(raw, level).map_err(|_| Error::ConversionError("zstd compression failed"))
+}
+
+#[cfg(all(feature = "mmap", feature = "zstd"))]
+fn decompress_zstd(bytes: &[u8], capacity: usize) -> Result<Vec<u8>> {
+    zstd::bulk::decompress(bytes, capacity)
+        .map_err(|_| Error::ConversionError("zstd decompression failed"))
+}
+
+#[cfg(all(feature = "mmap", not(feature = "zstd")))]
+fn compress_zstd(_raw: &[u8], _level: i32) -> Result<Vec<u8>> {
+    Err(Error::InvalidState(
+        "zstd support not enabled. Build with --features zstd",
+    ))
+}
+
+#[cfg(all(feature = "mmap", not(feature = "zstd")))]
+fn decompress_zstd(_bytes: &[u8], _capacity: usize) -> Result<Vec<u8>> {
+    compress_zstd(&[], 0)
+}
+
+#[cfg(all(feature = "mmap", feature = "lz4"))]
+fn compress_lz4(raw: &[u8]) -> Result<Vec<u8>> {
+    Ok(lz4_flex::block::compress(raw))
+}
+
+#[cfg(all(feature = "mmap", feature = "lz4"))]
+fn decompress_lz4(bytes: &[u8], capacity: usize) -> Result<Vec<u8>> {
+    lz4_flex::block::decompress(bytes, capacity)
+        .map_err(|_| Error::ConversionError("LZ4 decompression failed"))
+}
+
+#[cfg(all(feature = "mmap", not(feature = "lz4")))]
+fn compress_lz4(_raw: &[u8]) -> Result<Vec<u8>> {
+    Err(Error::InvalidState(
+        "LZ4 support not enabled. Build with --features lz4",
+    ))
+}
+
+#[cfg(all(feature = "mmap", not(feature = "lz4")))]
+fn decompress_lz4(_bytes: &[u8], _capacity: usize) -> Result<Vec<u8>> {
+    compress_lz4(&[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_codecs_roundtrip() {
+        let indices = [7u64, 7, 8, 3, 1 << 20, 0, u32::MAX as u64];
+        for codec in [IndexCodec::Raw, IndexCodec::DeltaBitPack] {
+            let bytes = encode_indices(&indices, IndexWidth::U32, codec).unwrap();
+            let decoded = decode_indices::<u32>(&bytes, indices.len(), codec).unwrap();
+            assert!(decoded.iter().map(|&i| i as u64).eq(indices));
+
+            let bytes = encode_indices(&indices, IndexWidth::U64, codec).unwrap();
+            let decoded = decode_indices::<u64>(&bytes, indices.len(), codec).unwrap();
+            assert_eq!(decoded, indices);
+
+            assert!(decode_indices::<u32>(&[], 0, codec).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_raw_indices_reject_narrow_width() {
+        assert!(encode_indices(&[1 << 40], IndexWidth::U32, IndexCodec::Raw).is_err());
+        let bytes = encode_indices(&[1, 2], IndexWidth::U32, IndexCodec::Raw).unwrap();
+        assert!(decode_indices::<u32>(&bytes, 3, IndexCodec::Raw).is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    fn value_codecs() -> Vec<ValueCodec> {
+        let mut codecs = vec![ValueCodec::Raw, ValueCodec::Dictionary];
+        if cfg!(feature = "zstd") {
+            codecs.push(ValueCodec::Zstd);
+        }
+        if cfg!(feature = "lz4") {
+            codecs.push(ValueCodec::Lz4);
+        }
+        codecs
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_value_codecs_roundtrip() {
+        let values = [1.5f64, -2.0, 1.5, 0.0, 1e300, -2.0];
+        let ints = [3i32, 3, 3, i32::MIN, 3];
+        for codec in value_codecs() {
+            let bytes = encode_values(&values, codec, 3).unwrap();
+            assert_eq!(
+                decode_values::<f64>(&bytes, values.len(), codec).unwrap(),
+                values
+            );
+
+            let bytes = encode_values(&ints, codec, 3).unwrap();
+            assert_eq!(
+                decode_values::<i32>(&bytes, ints.len(), codec).unwrap(),
+                ints
+            );
+
+            // Byte-oriented codecs know their exact length, so a wrong element
+            // count is an error rather than a padded block
+            if codec != ValueCodec::Dictionary {
+                assert!(decode_values::<i32>(&bytes, ints.len() + 1, codec).is_err());
+            }
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_dictionary_rejects_bad_codes() {
+        let mut bytes = encode_values(&[1u32, 2, 1], ValueCodec::Dictionary, 0).unwrap();
+        // Two entries need one bit per code; code 1 is the last valid one
+        let last = bytes.len() - 1;
+        bytes[last] = 0xff;
+        let width_pos = 4 + 2 * 4;
+        bytes[width_pos] = 2;
+        assert!(decode_values::<u32>(&bytes, 3, ValueCodec::Dictionary).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! Data type dispatch shared by the typed entry points
+//!
+//! Every `DataType` maps to one element type and one `DynamicMatrix` variant.
+//! The table lives in [`with_data_types`] so adding a data type only touches
+//! this file and the enums themselves.
+
+/// Pass the `(variant, element type)` table to another macro
+macro_rules! with_data_types {
+    ($callback:ident! { $($args:tt)* }) => {
+        $callback! { $($args)* [
+            (F32, f32),
+            (F64, f64),
+            (I32, i32),
+            (I64, i64),
+            (U32, u32),
+            (U64, u64),
+            (Pattern, $crate::Pattern),
+            (I8, i8),
+            (I16, i16),
+            (U8, u8),
+            (U16, u16),
+            (F16, $crate::f16),
+            (BF16, $crate::bf16),
+            (C64, $crate::Complex32),
+            (C128, $crate::Complex64)
+        ] }
+    };
+}
+
+/// Run typed code for a runtime data type
+///
+/// - `dispatch_data_type!(data_type, |T| body)` evaluates `body` with `T`
+///   bound to the element type of a `DataType`.
+/// - `dispatch_data_type!(dynamic data_type, |T| body)` does the same and wraps
+///   the resulting `MmapMatrix<T>` in the matching `DynamicMatrix` variant.
+/// - `dispatch_data_type!(match matrix, |m| body)` evaluates `body` with `m`
+///   bound to the typed matrix inside a `DynamicMatrix`.
+macro_rules! dispatch_data_type {
+    (@type ($data_type:expr) ($T:ident) ($body:expr) [$(($variant:ident, $ty:ty)),*]) => {
+        match $data_type {
+            $($crate::DataType::$variant => {
+                type $T = $ty;
+                $body
+            })*
+        }
+    };
+    (@dynamic ($data_type:expr) ($T:ident) ($body:expr) [$(($variant:ident, $ty:ty)),*]) => {
+        match $data_type {
+            $($crate::DataType::$variant => {
+                type $T = $ty;
+                $crate::mmap_backend::DynamicMatrix::$variant($body)
+            })*
+        }
+    };
+    (@matrix ($matrix:expr) ($m:ident) ($body:expr) [$(($variant:ident, $ty:ty)),*]) => {
+        match $matrix {
+            $($crate::mmap_backend::DynamicMatrix::$variant($m) => $body,)*
+        }
+    };
+
+    (match $matrix:expr, |$m:ident| $body:expr) => {
+        with_data_types! { dispatch_data_type! { @matrix ($matrix) ($m) ($body) } }
+    };
+    (dynamic $data_type:expr, |$T:ident| $body:expr) => {
+        with_data_types! { dispatch_data_type! { @dynamic ($data_type) ($T) ($body) } }
+    };
+    ($data_type:expr, |$T:ident| $body:expr) => {
+        with_data_types! { dispatch_data_type! { @type ($data_type) ($T) ($body) } }
+    };
+}
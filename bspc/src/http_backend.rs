@@ -2,89 +2,257 @@
 //!
 //! This module provides HTTP-based access to remote BSPC files using range requests
 //! for efficient partial downloads. Only available when the "http" feature is enabled.
+//! The matrix reads through a [`RangeReader`] (`range_reader`), so the same code
+//! also serves local files and in-memory buffers.
+//! Downloaded bytes are kept in a byte-budgeted block cache (`block_cache`),
+//! optionally backed by a persistent cache directory (`disk_cache`). Scattered
+//! reads are merged into few requests by the range planner (`range_plan`), and
+//! requests are retried and checked by the transport (`transport`). Custom
+//! clients and credentials (`auth`) are configured through the `builder`.
+//! Sequential row scans download ahead of the caller (`prefetch`).
+
+#[cfg(feature = "http")]
+mod auth;
+#[cfg(feature = "http")]
+mod block_cache;
+#[cfg(feature = "http")]
+mod builder;
+#[cfg(feature = "http")]
+mod disk_cache;
+#[cfg(feature = "http")]
+mod prefetch;
+#[cfg(feature = "http")]
+mod range_plan;
+#[cfg(feature = "http")]
+mod range_reader;
+#[cfg(feature = "http")]
+mod transport;
+
+#[cfg(feature = "http")]
+pub use block_cache::{CacheConfig, CacheStats};
+#[cfg(feature = "http")]
+pub use builder::HttpMatrixBuilder;
+#[cfg(feature = "http")]
+pub use prefetch::{PrefetchConfig, RowStream};
+#[cfg(feature = "http")]
+pub use range_plan::{coalesce_ranges, RangePlan};
+#[cfg(feature = "http")]
+pub use range_reader::{ConditionalRead, FileReader, MemoryReader, RangeReader, ReadFuture};
+#[cfg(feature = "http")]
+pub use transport::{HttpConfig, HttpError, HttpReader};
+
+#[cfg(feature = "http")]
+pub(crate) use range_reader::file_etag;
 
 #[cfg(feature = "http")]
 pub mod http_impl {
     use binsparse_rs::{array::ArrayValue, Error, Result};
-    use bspc_core::{BspcHeader, DataType, MatrixFormat};
-    use reqwest::Client;
-    use std::collections::HashMap;
+    use bspc_core::{BspcHeader, DataType, IndexWidth, MatrixFormat, MatrixIndex};
+    use num_complex::Complex64;
     use std::ops::Range;
-    use tokio::sync::RwLock;
-
-    /// HTTP client for efficient range-based access to remote BSPC files
-    pub struct HttpMatrix {
-        client: Client,
-        url: String,
+    use tokio::sync::OnceCell;
+
+    use super::block_cache::{BlockCache, CacheConfig, CacheStats};
+    use super::builder::HttpMatrixBuilder;
+    use super::disk_cache::DiskCache;
+    use super::range_plan::RangePlan;
+    use super::range_reader::{ConditionalRead, RangeReader};
+    use super::transport::{HttpConfig, HttpError, HttpReader};
+    use crate::chunk_bloom_filter::ChunkBloomFilter;
+
+    /// Range-based access to a BSPC file behind a [`RangeReader`]
+    ///
+    /// Only the byte spans a query needs are read, through a block cache.
+    /// [`HttpMatrix`] reads over HTTP; [`super::FileReader`] and
+    /// [`super::MemoryReader`] run the same code against local data.
+    ///
+    /// Values come back as [`ArrayValue`], which has no half precision
+    /// variants: `F16` and `BF16` values widen to `Float32`, so use
+    /// [`RemoteMatrix::data_type`] for the stored type. It has no complex
+    /// variants either, so the `ArrayValue` accessors fail for complex
+    /// matrices; use [`RemoteMatrix::get_element_complex`] for them.
+    pub struct RemoteMatrix<R: RangeReader> {
+        reader: R,
         header: BspcHeader,
-        cache: RwLock<HashMap<Range<usize>, Vec<u8>>>,
+        cache: BlockCache,
+        bloom_filter: OnceCell<Option<ChunkBloomFilter>>,
+        metadata: OnceCell<Option<Vec<u8>>>,
     }
 
+    /// HTTP client for efficient range-based access to remote BSPC files
+    pub type HttpMatrix = RemoteMatrix<HttpReader>;
+
     impl HttpMatrix {
-        /// Create a new HTTP matrix client
+        /// Create a new HTTP matrix client with the default block cache
         pub async fn new(url: &str) -> Result<Self> {
-            let client = Client::new();
+            Self::with_cache_config(url, CacheConfig::default()).await
+        }
+
+        /// Create a new HTTP matrix client with the given block cache settings
+        ///
+        /// With a disk cache directory configured, blocks cached by earlier runs
+        /// are reused as long as the server reports the same ETag or
+        /// Last-Modified value.
+        pub async fn with_cache_config(url: &str, cache_config: CacheConfig) -> Result<Self> {
+            Self::with_config(url, cache_config, HttpConfig::default()).await
+        }
+
+        /// Create a new HTTP matrix client with block cache, timeout and retry settings
+        pub async fn with_config(
+            url: &str,
+            cache_config: CacheConfig,
+            http_config: HttpConfig,
+        ) -> Result<Self> {
+            Self::builder(url)
+                .cache_config(cache_config)
+                .http_config(http_config)
+                .build()
+                .await
+        }
+
+        /// Start building a client with custom headers, credentials or client
+        pub fn builder(url: &str) -> HttpMatrixBuilder {
+            HttpMatrixBuilder::new(url)
+        }
+
+        /// Set how scattered reads are turned into range requests
+        ///
+        /// Also available as [`HttpMatrixBuilder::range_plan`].
+        pub fn with_range_plan(mut self, range_plan: RangePlan) -> Self {
+            self.reader.set_range_plan(range_plan);
+            self
+        }
+
+        /// Get the range request settings
+        pub fn range_plan(&self) -> &RangePlan {
+            self.reader.range_plan()
+        }
+
+        /// Get the most recent request failure, with its status code
+        ///
+        /// Methods report failures as [`Error`], which cannot carry the status
+        /// code; this keeps the full detail of the last one.
+        pub fn last_error(&self) -> Option<HttpError> {
+            self.reader.last_error()
+        }
 
-            // Read and validate header using range request
-            let header_bytes = Self::fetch_range(&client, url, 0, BspcHeader::SIZE).await?;
+        /// Check if the server supports range requests
+        pub async fn supports_range_requests(&self) -> Result<bool> {
+            self.reader.supports_range_requests().await
+        }
+    }
+
+    impl<R: RangeReader> RemoteMatrix<R> {
+        /// Open the matrix behind `reader`
+        ///
+        /// The disk cache is only used when the reader has a cache key and
+        /// reports a version tag. A header cached on disk is revalidated with a
+        /// conditional read, so an unchanged file costs one request that
+        /// transfers no body.
+        pub async fn open(reader: R, cache_config: CacheConfig) -> Result<Self> {
+            let disk_root = match (&cache_config.disk_dir, reader.cache_key()) {
+                (Some(dir), Some(key)) => Some((dir.clone(), key.to_owned())),
+                _ => None,
+            };
+
+            let header_range = 0..BspcHeader::SIZE;
+            let (header_bytes, tag) = match &disk_root {
+                Some((dir, key)) => {
+                    let stored = DiskCache::stored_header(dir, key).await;
+                    let known_tag = stored.as_ref().map(|(tag, _)| tag.as_str());
+                    match reader
+                        .read_range_if_modified(header_range, known_tag)
+                        .await?
+                    {
+                        ConditionalRead::Modified(header_bytes, tag) => (header_bytes, tag),
+                        ConditionalRead::NotModified => match stored {
+                            Some((tag, header_bytes)) => (header_bytes, Some(tag)),
+                            None => {
+                                return Err(Error::InvalidState(
+                                    "Unconditional read reported no modification",
+                                ))
+                            }
+                        },
+                    }
+                }
+                None => (reader.read_range(header_range).await?, None),
+            };
 
             let header = BspcHeader::from_bytes(&header_bytes)
                 .map_err(|_| Error::InvalidState("Invalid BSPC header format"))?;
+            if header.index_width().is_none() {
+                return Err(Error::InvalidState("Unsupported index width"));
+            }
+            if header.is_compressed() {
+                return Err(Error::InvalidState(
+                    "Compressed files are not supported for range access",
+                ));
+            }
+
+            // The metadata region is the last section written
+            let file_len = header
+                .metadata_region()
+                .map_or(0, |(offset, size)| offset + size)
+                .max(header.data_end());
+
+            // Without a version tag the disk cache could serve stale blocks
+            let disk = match (disk_root, tag) {
+                (Some((dir, key)), Some(tag)) => Some(
+                    DiskCache::open(
+                        &dir,
+                        &key,
+                        &tag,
+                        &header_bytes,
+                        cache_config.block_size.max(1),
+                        cache_config.disk_max_bytes,
+                    )
+                    .await?,
+                ),
+                _ => None,
+            };
 
             Ok(Self {
-                client,
-                url: url.to_string(),
+                reader,
                 header,
-                cache: RwLock::new(HashMap::new()),
+                cache: BlockCache::new(cache_config, file_len as usize, disk),
+                bloom_filter: OnceCell::new(),
+                metadata: OnceCell::new(),
             })
         }
 
-        /// Fetch a byte range from the remote file
-        async fn fetch_range(
-            client: &Client,
-            url: &str,
-            start: usize,
-            len: usize,
-        ) -> Result<Vec<u8>> {
-            let end = start + len - 1;
-            let range_header = format!("bytes={start}-{end}");
-
-            let response = client
-                .get(url)
-                .header("Range", range_header)
-                .send()
-                .await
-                .map_err(|_| Error::IoError("Failed to fetch range"))?;
-
-            if !response.status().is_success() && response.status().as_u16() != 206 {
-                return Err(Error::IoError("HTTP request failed"));
-            }
-
-            let bytes = response
-                .bytes()
-                .await
-                .map_err(|_| Error::IoError("Failed to read response bytes"))?;
-
-            Ok(bytes.to_vec())
+        /// Get the reader the matrix reads through
+        pub fn reader(&self) -> &R {
+            &self.reader
         }
 
         /// Get cached data or fetch from server
+        ///
+        /// Empty sections, such as the values of a pattern matrix, need no request.
         async fn get_cached_range(&self, range: Range<usize>) -> Result<Vec<u8>> {
-            {
-                let cache = self.cache.read().await;
-                if let Some(data) = cache.get(&range) {
-                    return Ok(data.clone());
-                }
-            }
+            self.cache
+                .read(range, move |block_ranges| {
+                    self.reader.read_ranges(block_ranges)
+                })
+                .await
+        }
 
-            let data = Self::fetch_range(&self.client, &self.url, range.start, range.len()).await?;
+        /// Get several cached ranges, fetching the missing blocks together
+        async fn get_cached_ranges(&self, ranges: &[Range<usize>]) -> Result<Vec<Vec<u8>>> {
+            self.cache
+                .read_many(ranges, move |block_ranges| {
+                    self.reader.read_ranges(block_ranges)
+                })
+                .await
+        }
 
-            {
-                let mut cache = self.cache.write().await;
-                cache.insert(range, data.clone());
-            }
+        /// Get the block cache settings
+        pub fn cache_config(&self) -> &CacheConfig {
+            self.cache.config()
+        }
 
-            Ok(data)
+        /// Get the block cache hit, miss and eviction counters
+        pub fn cache_stats(&self) -> CacheStats {
+            self.cache.stats()
         }
 
         /// Get matrix dimensions
@@ -105,25 +273,106 @@ pub mod http_impl {
             MatrixFormat::from_u8(self.header.format_type).unwrap_or(MatrixFormat::Coo)
         }
 
+        /// Get the width of the stored row and column indices
+        pub fn index_width(&self) -> IndexWidth {
+            self.header.index_width().unwrap_or(IndexWidth::U32)
+        }
+
         /// Get data type
         pub fn data_type(&self) -> DataType {
             DataType::from_u8(self.header.data_type).unwrap_or(DataType::F64)
         }
 
+        /// Fail for complex matrices, which have no `ArrayValue` representation
+        fn ensure_real(&self) -> Result<()> {
+            if self.data_type().is_complex() {
+                return Err(Error::InvalidState(
+                    "Complex values require get_element_complex",
+                ));
+            }
+            Ok(())
+        }
+
         /// Get a specific element with efficient range queries
+        ///
+        /// The bloom filter rules out empty rows without touching the arrays;
+        /// otherwise only the byte spans holding the row are downloaded.
         pub async fn get_element(&self, row: usize, col: usize) -> Result<Option<ArrayValue>> {
+            self.ensure_real()?;
             if row >= self.nrows() || col >= self.ncols() {
                 return Err(Error::InvalidState("Index out of bounds"));
             }
 
-            // For now, implement a simple COO search - in production you'd optimize this
-            // by reading chunks and building indices
-            let values_range = self.header.values_offset as usize
-                ..self.header.values_offset as usize + self.header.values_size as usize;
-            let row_indices_range = self.header.indices_0_offset as usize
-                ..self.header.indices_0_offset as usize + self.header.indices_0_size as usize;
-            let col_indices_range = self.header.indices_1_offset as usize
-                ..self.header.indices_1_offset as usize + self.header.indices_1_size as usize;
+            let Some((values_bytes, _, col_indices)) = self.fetch_rows(row, row + 1).await? else {
+                return Ok(None);
+            };
+
+            // Columns are sorted within a row
+            let data_type = self.data_type();
+            match col_indices.binary_search(&(col as u64)) {
+                Ok(i) => Ok(Some(self.extract_value_at_index(
+                    &values_bytes,
+                    i,
+                    data_type,
+                )?)),
+                Err(_) => Ok(None),
+            }
+        }
+
+        /// Get a specific element as a complex number
+        ///
+        /// Unlike [`HttpMatrix::get_element`], which rejects complex matrices,
+        /// this keeps both parts. Real matrices return a zero imaginary part.
+        pub async fn get_element_complex(
+            &self,
+            row: usize,
+            col: usize,
+        ) -> Result<Option<Complex64>> {
+            if row >= self.nrows() || col >= self.ncols() {
+                return Err(Error::InvalidState("Index out of bounds"));
+            }
+
+            let Some((values_bytes, _, col_indices)) = self.fetch_rows(row, row + 1).await? else {
+                return Ok(None);
+            };
+            let data_type = self.data_type();
+
+            col_indices
+                .binary_search(&(col as u64))
+                .ok()
+                .map(|i| self.extract_complex_at_index(&values_bytes, i, data_type))
+                .transpose()
+        }
+
+        /// Get a specific row as complex numbers
+        pub async fn get_row_complex(&self, row: usize) -> Result<Vec<(usize, Complex64)>> {
+            if row >= self.nrows() {
+                return Err(Error::InvalidState("Row index out of bounds"));
+            }
+
+            let Some((values_bytes, _, col_indices)) = self.fetch_rows(row, row + 1).await? else {
+                return Ok(Vec::new());
+            };
+            let data_type = self.data_type();
+
+            col_indices
+                .iter()
+                .enumerate()
+                .map(|(i, &col)| {
+                    let value = self.extract_complex_at_index(&values_bytes, i, data_type)?;
+                    Ok((col as usize, value))
+                })
+                .collect()
+        }
+
+        /// Fetch the values and both index arrays for elements in `span`
+        ///
+        /// The returned values start at the first element of the span.
+        pub(super) async fn fetch_span(
+            &self,
+            span: Range<usize>,
+        ) -> Result<(Vec<u8>, Vec<u64>, Vec<u64>)> {
+            let (values_range, row_indices_range, col_indices_range) = self.span_ranges(&span);
 
             let (values_bytes, row_indices_bytes, col_indices_bytes) = tokio::try_join!(
                 self.get_cached_range(values_range),
@@ -131,25 +380,117 @@ pub mod http_impl {
                 self.get_cached_range(col_indices_range)
             )?;
 
-            let data_type = DataType::from_u8(self.header.data_type).unwrap_or(DataType::F64);
-            let nnz = self.header.nnz as usize;
-
-            // Convert row and column indices to u32 slices
-            let row_indices = self.bytes_to_u32_slice(&row_indices_bytes)?;
-            let col_indices = self.bytes_to_u32_slice(&col_indices_bytes)?;
-
-            // Search for the element
-            for i in 0..nnz {
-                if row_indices[i] as usize == row && col_indices[i] as usize == col {
-                    return Ok(Some(self.extract_value_at_index(
-                        &values_bytes,
-                        i,
-                        data_type,
-                    )?));
+            Ok((
+                values_bytes,
+                self.bytes_to_indices(&row_indices_bytes)?,
+                self.bytes_to_indices(&col_indices_bytes)?,
+            ))
+        }
+
+        /// Byte ranges of the values, row indices and column indices of `span`
+        fn span_ranges(&self, span: &Range<usize>) -> (Range<usize>, Range<usize>, Range<usize>) {
+            let element_size = self.data_type().size_bytes();
+            let index_size = self.index_width().size_bytes();
+            let byte_span = |offset: u64, size: usize| {
+                offset as usize + span.start * size..offset as usize + span.end * size
+            };
+
+            (
+                byte_span(self.header.values_offset, element_size),
+                byte_span(self.header.indices_0_offset, index_size),
+                byte_span(self.header.indices_1_offset, index_size),
+            )
+        }
+
+        /// Fetch the elements of rows `start_row..end_row`
+        ///
+        /// Returns `None` when the bloom filter rules the rows out or they hold
+        /// no elements, so callers skip downloading anything.
+        async fn fetch_rows(
+            &self,
+            start_row: usize,
+            end_row: usize,
+        ) -> Result<Option<(Vec<u8>, Vec<u64>, Vec<u64>)>> {
+            if let Some(bloom_filter) = self.chunk_bloom_filter().await? {
+                if bloom_filter
+                    .may_contain_range(start_row, end_row)
+                    .is_empty()
+                {
+                    return Ok(None);
+                }
+            }
+
+            let span = self.row_span(start_row, end_row).await?;
+            if span.is_empty() {
+                return Ok(None);
+            }
+
+            self.fetch_span(span).await.map(Some)
+        }
+
+        /// Get the chunk bloom filter, fetching it on first use
+        ///
+        /// Returns `None` when the file has no bloom filter or it cannot be
+        /// decoded; row lookups then fall back to searching the row indices.
+        pub async fn chunk_bloom_filter(&self) -> Result<Option<&ChunkBloomFilter>> {
+            let bloom_filter = self
+                .bloom_filter
+                .get_or_try_init(|| async {
+                    let Some((offset, size)) = self.header.chunk_bloom_filter_region() else {
+                        return Ok(None);
+                    };
+
+                    let start = offset as usize;
+                    let data = self.get_cached_range(start..start + size as usize).await?;
+                    Ok::<_, Error>(match self.index_width() {
+                        IndexWidth::U32 => ChunkBloomFilter::deserialize_with::<u32>(&data).ok(),
+                        IndexWidth::U64 => ChunkBloomFilter::deserialize_with::<u64>(&data).ok(),
+                    })
+                })
+                .await?;
+
+            Ok(bloom_filter.as_ref())
+        }
+
+        /// Range of element positions holding rows `start_row..end_row`
+        async fn row_span(&self, start_row: usize, end_row: usize) -> Result<Range<usize>> {
+            let start = self.lower_bound_row(start_row, 0).await?;
+            let end = self.lower_bound_row(end_row, start).await?;
+            Ok(start..end)
+        }
+
+        /// First element position at or after `from` whose row is not less than `row`
+        ///
+        /// The sorted row indices are binary searched, probing one
+        /// cache block at a time so neighbouring probes share a request.
+        pub(super) async fn lower_bound_row(&self, row: usize, from: usize) -> Result<usize> {
+            if row >= self.nrows() {
+                return Ok(self.nnz());
+            }
+
+            let (mut lo, mut hi) = (from, self.nnz());
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if (self.probe_row_index(mid).await? as usize) < row {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
                 }
             }
 
-            Ok(None)
+            Ok(lo)
+        }
+
+        /// Read the row index of element `position`
+        async fn probe_row_index(&self, position: usize) -> Result<u64> {
+            let index_size = self.index_width().size_bytes();
+            let start = self.header.indices_0_offset as usize + position * index_size;
+            let bytes = self.get_cached_range(start..start + index_size).await?;
+
+            self.bytes_to_indices(&bytes)?
+                .first()
+                .copied()
+                .ok_or(Error::InvalidState("Row index probe out of bounds"))
         }
 
         /// Get a range of rows efficiently
@@ -158,41 +499,23 @@ pub mod http_impl {
             start_row: usize,
             end_row: usize,
         ) -> Result<Vec<(usize, usize, ArrayValue)>> {
+            self.ensure_real()?;
             if start_row >= self.nrows() || end_row > self.nrows() || start_row >= end_row {
                 return Err(Error::InvalidState("Invalid row range"));
             }
 
-            // Fetch all required data in parallel
-            let values_range = self.header.values_offset as usize
-                ..self.header.values_offset as usize + self.header.values_size as usize;
-            let row_indices_range = self.header.indices_0_offset as usize
-                ..self.header.indices_0_offset as usize + self.header.indices_0_size as usize;
-            let col_indices_range = self.header.indices_1_offset as usize
-                ..self.header.indices_1_offset as usize + self.header.indices_1_size as usize;
-
-            let (values_bytes, row_indices_bytes, col_indices_bytes) = tokio::try_join!(
-                self.get_cached_range(values_range),
-                self.get_cached_range(row_indices_range),
-                self.get_cached_range(col_indices_range)
-            )?;
-
-            let data_type = DataType::from_u8(self.header.data_type).unwrap_or(DataType::F64);
-            let nnz = self.header.nnz as usize;
-
-            // Convert indices to u32 slices
-            let row_indices = self.bytes_to_u32_slice(&row_indices_bytes)?;
-            let col_indices = self.bytes_to_u32_slice(&col_indices_bytes)?;
+            let Some((values_bytes, row_indices, col_indices)) =
+                self.fetch_rows(start_row, end_row).await?
+            else {
+                return Ok(Vec::new());
+            };
+            let data_type = self.data_type();
 
-            let mut results = Vec::new();
-
-            // Filter elements in the row range
-            for i in 0..nnz {
-                let file_row = row_indices[i] as usize;
-                let file_col = col_indices[i] as usize;
-
-                if file_row >= start_row && file_row < end_row && file_col < self.ncols() {
+            let mut results = Vec::with_capacity(row_indices.len());
+            for (i, (&file_row, &file_col)) in row_indices.iter().zip(&col_indices).enumerate() {
+                if (file_col as usize) < self.ncols() {
                     let value = self.extract_value_at_index(&values_bytes, i, data_type)?;
-                    results.push((file_row, file_col, value));
+                    results.push((file_row as usize, file_col as usize, value));
                 }
             }
 
@@ -208,6 +531,51 @@ pub mod http_impl {
                 .collect())
         }
 
+        /// Get several rows, fetching the scattered spans together
+        ///
+        /// Rows are returned in the order requested. Spans of nearby rows are
+        /// merged by the range planner instead of issuing one request per row.
+        pub async fn get_rows(&self, rows: &[usize]) -> Result<Vec<Vec<(usize, ArrayValue)>>> {
+            self.ensure_real()?;
+            if rows.iter().any(|&row| row >= self.nrows()) {
+                return Err(Error::InvalidState("Row index out of bounds"));
+            }
+
+            let bloom_filter = self.chunk_bloom_filter().await?;
+            let mut spans = Vec::with_capacity(rows.len());
+            for &row in rows {
+                if bloom_filter.is_some_and(|filter| !filter.may_contain_row(row)) {
+                    spans.push(0..0);
+                } else {
+                    spans.push(self.row_span(row, row + 1).await?);
+                }
+            }
+
+            let mut ranges = Vec::with_capacity(2 * spans.len());
+            for span in &spans {
+                let (values_range, _, col_indices_range) = self.span_ranges(span);
+                ranges.push(values_range);
+                ranges.push(col_indices_range);
+            }
+            let bytes = self.get_cached_ranges(&ranges).await?;
+            let data_type = self.data_type();
+
+            bytes
+                .chunks_exact(2)
+                .map(|parts| {
+                    let col_indices = self.bytes_to_indices(&parts[1])?;
+                    col_indices
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &col)| {
+                            let value = self.extract_value_at_index(&parts[0], i, data_type)?;
+                            Ok((col as usize, value))
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+
         /// Get a specific row with column range filter
         pub async fn get_row_with_col_range(
             &self,
@@ -215,6 +583,7 @@ pub mod http_impl {
             start_col: usize,
             end_col: usize,
         ) -> Result<Vec<(usize, ArrayValue)>> {
+            self.ensure_real()?;
             if row >= self.nrows() {
                 return Err(Error::InvalidState("Row index out of bounds"));
             }
@@ -222,149 +591,110 @@ pub mod http_impl {
                 return Err(Error::InvalidState("Invalid column range"));
             }
 
-            // Fetch all required data in parallel
-            let values_range = self.header.values_offset as usize
-                ..self.header.values_offset as usize + self.header.values_size as usize;
-            let row_indices_range = self.header.indices_0_offset as usize
-                ..self.header.indices_0_offset as usize + self.header.indices_0_size as usize;
-            let col_indices_range = self.header.indices_1_offset as usize
-                ..self.header.indices_1_offset as usize + self.header.indices_1_size as usize;
-
-            let (values_bytes, row_indices_bytes, col_indices_bytes) = tokio::try_join!(
-                self.get_cached_range(values_range),
-                self.get_cached_range(row_indices_range),
-                self.get_cached_range(col_indices_range)
-            )?;
-
-            let data_type = DataType::from_u8(self.header.data_type).unwrap_or(DataType::F64);
-            let nnz = self.header.nnz as usize;
-
-            // Convert indices to u32 slices
-            let row_indices = self.bytes_to_u32_slice(&row_indices_bytes)?;
-            let col_indices = self.bytes_to_u32_slice(&col_indices_bytes)?;
-
-            let mut results = Vec::new();
+            let Some((values_bytes, _, col_indices)) = self.fetch_rows(row, row + 1).await? else {
+                return Ok(Vec::new());
+            };
+            let data_type = self.data_type();
 
-            // Filter elements in the specific row and column range
-            for i in 0..nnz {
-                let file_row = row_indices[i] as usize;
-                let file_col = col_indices[i] as usize;
+            // Columns are sorted within a row
+            let first = col_indices.partition_point(|&c| (c as usize) < start_col);
+            let last = col_indices.partition_point(|&c| (c as usize) < end_col);
 
-                if file_row == row && file_col >= start_col && file_col < end_col {
+            (first..last)
+                .map(|i| {
                     let value = self.extract_value_at_index(&values_bytes, i, data_type)?;
-                    results.push((file_col, value));
-                }
-            }
-
-            Ok(results)
+                    Ok((col_indices[i] as usize, value))
+                })
+                .collect()
         }
 
-        /// Get a column range efficiently
+        /// Get a column range
+        ///
+        /// Elements are sorted by row, so column queries download the whole
+        /// column index array: O(nnz) bytes whatever the number of matches. The
+        /// chunk bloom filter and row spans only narrow row queries. Values and
+        /// row indices are fetched only for the matches. Prefer row queries, or
+        /// a transposed copy of the file, when columns are read often.
         pub async fn get_col_range(
             &self,
             start_col: usize,
             end_col: usize,
         ) -> Result<Vec<(usize, usize, ArrayValue)>> {
+            self.ensure_real()?;
             if start_col >= self.ncols() || end_col > self.ncols() || start_col >= end_col {
                 return Err(Error::InvalidState("Invalid column range"));
             }
 
-            // Fetch all required data in parallel
-            let values_range = self.header.values_offset as usize
-                ..self.header.values_offset as usize + self.header.values_size as usize;
-            let row_indices_range = self.header.indices_0_offset as usize
-                ..self.header.indices_0_offset as usize + self.header.indices_0_size as usize;
-            let col_indices_range = self.header.indices_1_offset as usize
-                ..self.header.indices_1_offset as usize + self.header.indices_1_size as usize;
-
-            let (values_bytes, row_indices_bytes, col_indices_bytes) = tokio::try_join!(
-                self.get_cached_range(values_range),
-                self.get_cached_range(row_indices_range),
-                self.get_cached_range(col_indices_range)
-            )?;
-
-            let data_type = DataType::from_u8(self.header.data_type).unwrap_or(DataType::F64);
-            let nnz = self.header.nnz as usize;
-
-            // Convert indices to u32 slices
-            let row_indices = self.bytes_to_u32_slice(&row_indices_bytes)?;
-            let col_indices = self.bytes_to_u32_slice(&col_indices_bytes)?;
-
-            let mut results = Vec::new();
-
-            // Filter elements in the column range
-            for i in 0..nnz {
-                let file_row = row_indices[i] as usize;
-                let file_col = col_indices[i] as usize;
-
-                if file_col >= start_col && file_col < end_col && file_row < self.nrows() {
-                    let value = self.extract_value_at_index(&values_bytes, i, data_type)?;
-                    results.push((file_row, file_col, value));
-                }
-            }
-
-            Ok(results)
+            self.get_cols_matching(|col| col >= start_col && col < end_col)
+                .await
         }
 
         /// Get a specific column
+        ///
+        /// Like [`RemoteMatrix::get_col_range`], this downloads the whole column
+        /// index array.
         pub async fn get_col(&self, col: usize) -> Result<Vec<(usize, ArrayValue)>> {
+            self.ensure_real()?;
             if col >= self.ncols() {
                 return Err(Error::InvalidState("Column index out of bounds"));
             }
 
-            // Fetch all required data
-            let values_range = self.header.values_offset as usize
-                ..self.header.values_offset as usize + self.header.values_size as usize;
-            let row_indices_range = self.header.indices_0_offset as usize
-                ..self.header.indices_0_offset as usize + self.header.indices_0_size as usize;
+            let elements = self.get_cols_matching(|c| c == col).await?;
+            Ok(elements
+                .into_iter()
+                .map(|(row, _, value)| (row, value))
+                .collect())
+        }
+
+        /// Collect the elements whose column satisfies `matches`
+        async fn get_cols_matching(
+            &self,
+            matches: impl Fn(usize) -> bool,
+        ) -> Result<Vec<(usize, usize, ArrayValue)>> {
             let col_indices_range = self.header.indices_1_offset as usize
                 ..self.header.indices_1_offset as usize + self.header.indices_1_size as usize;
-
-            let (values_bytes, row_indices_bytes, col_indices_bytes) = tokio::try_join!(
-                self.get_cached_range(values_range),
-                self.get_cached_range(row_indices_range),
-                self.get_cached_range(col_indices_range)
-            )?;
-
-            let data_type = DataType::from_u8(self.header.data_type).unwrap_or(DataType::F64);
-            let nnz = self.header.nnz as usize;
-
-            // Convert indices to u32 slices
-            let row_indices = self.bytes_to_u32_slice(&row_indices_bytes)?;
-            let col_indices = self.bytes_to_u32_slice(&col_indices_bytes)?;
-
-            let mut results = Vec::new();
-
-            // Filter elements in the column
-            for i in 0..nnz {
-                let file_row = row_indices[i] as usize;
-                let file_col = col_indices[i] as usize;
-
-                if file_col == col && file_row < self.nrows() {
-                    let value = self.extract_value_at_index(&values_bytes, i, data_type)?;
-                    results.push((file_row, value));
+            let col_indices =
+                self.bytes_to_indices(&self.get_cached_range(col_indices_range).await?)?;
+
+            let positions: Vec<usize> = (0..col_indices.len())
+                .filter(|&i| matches(col_indices[i] as usize))
+                .collect();
+
+            // Matches are scattered; the range planner merges nearby ones
+            let mut ranges = Vec::with_capacity(2 * positions.len());
+            for &i in &positions {
+                let (values_range, row_indices_range, _) = self.span_ranges(&(i..i + 1));
+                ranges.push(values_range);
+                ranges.push(row_indices_range);
+            }
+            let bytes = self.get_cached_ranges(&ranges).await?;
+            let data_type = self.data_type();
+
+            let mut results = Vec::with_capacity(positions.len());
+            for (i, parts) in positions.into_iter().zip(bytes.chunks_exact(2)) {
+                let file_row = self.bytes_to_indices(&parts[1])?.first().copied();
+                let Some(file_row) = file_row.map(|row| row as usize) else {
+                    return Err(Error::InvalidState("Index out of bounds"));
+                };
+                if file_row < self.nrows() {
+                    let value = self.extract_value_at_index(&parts[0], 0, data_type)?;
+                    results.push((file_row, col_indices[i] as usize, value));
                 }
             }
 
             Ok(results)
         }
 
-        /// Convert bytes to u32 slice
-        fn bytes_to_u32_slice(&self, bytes: &[u8]) -> Result<Vec<u32>> {
-            if bytes.len() % 4 != 0 {
-                return Err(Error::InvalidState("Invalid u32 array size"));
-            }
-
-            let mut result = Vec::new();
-            for chunk in bytes.chunks_exact(4) {
-                let array: [u8; 4] = chunk.try_into().unwrap();
-                result.push(u32::from_le_bytes(array));
+        /// Convert bytes to indices, widening to u64 whatever the stored width
+        fn bytes_to_indices(&self, bytes: &[u8]) -> Result<Vec<u64>> {
+            match self.index_width() {
+                IndexWidth::U32 => decode_indices::<u32>(bytes),
+                IndexWidth::U64 => decode_indices::<u64>(bytes),
             }
-            Ok(result)
         }
 
         /// Extract value at specific index based on data type
-        fn extract_value_at_index(
+        pub(super) fn extract_value_at_index(
             &self,
             bytes: &[u8],
             index: usize,
@@ -405,47 +735,177 @@ pub mod http_impl {
                     let array: [u8; 8] = value_bytes.try_into().unwrap();
                     Ok(ArrayValue::UInt64(u64::from_le_bytes(array)))
                 }
+                DataType::Pattern => Ok(ArrayValue::BInt8(1)),
+                DataType::I8 => Ok(ArrayValue::Int8(value_bytes[0] as i8)),
+                DataType::I16 => {
+                    let array: [u8; 2] = value_bytes.try_into().unwrap();
+                    Ok(ArrayValue::Int16(i16::from_le_bytes(array)))
+                }
+                DataType::U8 => Ok(ArrayValue::UInt8(value_bytes[0])),
+                DataType::U16 => {
+                    let array: [u8; 2] = value_bytes.try_into().unwrap();
+                    Ok(ArrayValue::UInt16(u16::from_le_bytes(array)))
+                }
+                DataType::F16 => {
+                    let array: [u8; 2] = value_bytes.try_into().unwrap();
+                    Ok(ArrayValue::Float32(
+                        half::f16::from_le_bytes(array).to_f32(),
+                    ))
+                }
+                DataType::BF16 => {
+                    let array: [u8; 2] = value_bytes.try_into().unwrap();
+                    Ok(ArrayValue::Float32(
+                        half::bf16::from_le_bytes(array).to_f32(),
+                    ))
+                }
+                // ArrayValue has no complex variant; dropping the imaginary
+                // part would silently give wrong values
+                DataType::C64 | DataType::C128 => Err(Error::InvalidState(
+                    "Complex values require get_element_complex",
+                )),
             }
         }
 
-        /// Get file size from server
-        pub async fn get_file_size(&self) -> Result<u64> {
-            let response = self
-                .client
-                .head(&self.url)
-                .send()
-                .await
-                .map_err(|_| Error::IoError("Failed to get file info"))?;
-
-            if let Some(content_length) = response.headers().get("content-length") {
-                let size_str = content_length
-                    .to_str()
-                    .map_err(|_| Error::InvalidState("Invalid content-length header"))?;
-                let size = size_str
-                    .parse::<u64>()
-                    .map_err(|_| Error::InvalidState("Invalid content-length value"))?;
-                Ok(size)
-            } else {
-                Err(Error::InvalidState("No content-length header"))
+        /// Extract value at specific index as a complex number
+        ///
+        /// Complex types keep both parts; real types become the real part.
+        fn extract_complex_at_index(
+            &self,
+            bytes: &[u8],
+            index: usize,
+            data_type: DataType,
+        ) -> Result<Complex64> {
+            let element_size = data_type.size_bytes();
+            let start = index * element_size;
+            let end = start + element_size;
+
+            if end > bytes.len() {
+                return Err(Error::InvalidState("Index out of bounds"));
+            }
+
+            let value_bytes = &bytes[start..end];
+
+            match data_type {
+                DataType::C64 => {
+                    let re: [u8; 4] = value_bytes[..4].try_into().unwrap();
+                    let im: [u8; 4] = value_bytes[4..].try_into().unwrap();
+                    Ok(Complex64::new(
+                        f32::from_le_bytes(re) as f64,
+                        f32::from_le_bytes(im) as f64,
+                    ))
+                }
+                DataType::C128 => {
+                    let re: [u8; 8] = value_bytes[..8].try_into().unwrap();
+                    let im: [u8; 8] = value_bytes[8..].try_into().unwrap();
+                    Ok(Complex64::new(
+                        f64::from_le_bytes(re),
+                        f64::from_le_bytes(im),
+                    ))
+                }
+                _ => {
+                    let re = match self.extract_value_at_index(bytes, index, data_type)? {
+                        ArrayValue::Float32(v) => v as f64,
+                        ArrayValue::Float64(v) => v,
+                        ArrayValue::Int32(v) => v as f64,
+                        ArrayValue::Int64(v) => v as f64,
+                        ArrayValue::UInt32(v) => v as f64,
+                        ArrayValue::UInt64(v) => v as f64,
+                        ArrayValue::UInt8(v) => v as f64,
+                        ArrayValue::UInt16(v) => v as f64,
+                        ArrayValue::Int8(v) => v as f64,
+                        ArrayValue::Int16(v) => v as f64,
+                        ArrayValue::BInt8(v) => v as f64,
+                    };
+                    Ok(Complex64::new(re, 0.0))
+                }
             }
         }
 
-        /// Check if the server supports range requests
-        pub async fn supports_range_requests(&self) -> Result<bool> {
-            let response = self
-                .client
-                .head(&self.url)
-                .send()
-                .await
-                .map_err(|_| Error::IoError("Failed to check range support"))?;
+        /// Fetch the metadata section, if the file has one
+        pub async fn metadata_bytes(&self) -> Result<Option<Vec<u8>>> {
+            Ok(self.metadata().await?.map(<[u8]>::to_vec))
+        }
+
+        /// Get the metadata section, fetching and validating it on first use
+        ///
+        /// The section is kept outside the block cache, so label lookups never
+        /// fetch it twice however small the cache budget is.
+        async fn metadata(&self) -> Result<Option<&[u8]>> {
+            let metadata = self
+                .metadata
+                .get_or_try_init(|| async {
+                    let Some((offset, size)) = self.header.metadata_region() else {
+                        return Ok(None);
+                    };
+
+                    let start = offset as usize;
+                    let data = self.get_cached_range(start..start + size as usize).await?;
+                    crate::metadata::MetadataView::new(&data)?;
+                    Ok::<_, Error>(Some(data))
+                })
+                .await?;
+
+            Ok(metadata.as_deref())
+        }
+
+        /// Find the row with the given label
+        ///
+        /// The metadata section is fetched once and kept in memory; lookups
+        /// then use the sorted label index when the file has one.
+        pub async fn row_index_of(&self, label: &[u8]) -> Result<Option<usize>> {
+            let Some(metadata) = self.metadata().await? else {
+                return Ok(None);
+            };
+            let view = crate::metadata::MetadataView::new(metadata)?;
+            Ok(view.row_index_of(label)?.map(|i| i as usize))
+        }
+
+        /// Find the column with the given label
+        ///
+        /// See [`RemoteMatrix::row_index_of`].
+        pub async fn col_index_of(&self, label: &[u8]) -> Result<Option<usize>> {
+            let Some(metadata) = self.metadata().await? else {
+                return Ok(None);
+            };
+            let view = crate::metadata::MetadataView::new(metadata)?;
+            Ok(view.col_index_of(label)?.map(|i| i as usize))
+        }
+
+        /// Get the file attributes stored in the metadata section
+        ///
+        /// Returns an empty list when the file has no metadata or no attributes.
+        pub async fn attributes(&self) -> Result<Vec<(String, crate::metadata::AttributeValue)>> {
+            let Some(metadata) = self.metadata().await? else {
+                return Ok(Vec::new());
+            };
+            let view = crate::metadata::MetadataView::new(metadata)?;
+            Ok(view
+                .attributes()?
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect())
+        }
 
-            Ok(response
-                .headers()
-                .get("accept-ranges")
-                .is_some_and(|v| v.as_bytes() == b"bytes"))
+        /// Get the size of the file in bytes
+        pub async fn get_file_size(&self) -> Result<u64> {
+            self.reader.size().await
         }
     }
 
+    /// Decode a little-endian index array stored as `I`
+    fn decode_indices<I: MatrixIndex>(bytes: &[u8]) -> Result<Vec<u64>> {
+        let width = I::WIDTH.size_bytes();
+        if bytes.len() % width != 0 {
+            return Err(Error::InvalidState("Invalid index array size"));
+        }
+
+        Ok(bytes
+            .chunks_exact(width)
+            .filter_map(I::from_le_bytes)
+            .map(|index| index.to_usize() as u64)
+            .collect())
+    }
+
     /// Parse range string (e.g., "10:20") to range
     pub fn parse_range(range_str: &str) -> Result<std::ops::Range<usize>> {
         let parts: Vec<&str> = range_str.split(':').collect();
@@ -494,3 +954,314 @@ pub mod http_stub {
 
 #[cfg(not(feature = "http"))]
 pub use http_stub::*;
+
+#[cfg(all(test, feature = "http", feature = "mmap"))]
+mod tests {
+    use super::*;
+    use crate::chunked_backend::ChunkConfig;
+    use crate::mmap_backend::BspcFile;
+    use crate::test_support::TempDir;
+    use binsparse_rs::array::ArrayValue;
+    use num_complex::Complex64;
+    use std::ops::Range;
+
+    /// Write a small labelled matrix and load its bytes
+    async fn labelled_matrix() -> Vec<u8> {
+        let dir = TempDir::new();
+        let path = dir.file("matrix.bspc");
+        let elements = [(0, 0, 1.0f64), (1, 2, 2.0), (2, 1, 3.0)];
+        let rows: [&[u8]; 3] = [b"r0", b"r1", b"r2"];
+        let cols: [&[u8]; 3] = [b"c0", b"c1", b"c2"];
+        BspcFile::write_sparse_matrix_with_labels(
+            3,
+            3,
+            &elements,
+            &rows,
+            &cols,
+            0,
+            ChunkConfig::default(),
+            &path,
+        )
+        .await
+        .unwrap();
+        std::fs::read(&path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_label_lookups_fetch_metadata_once() {
+        let reader = MemoryReader::new(labelled_matrix().await);
+        // No cache budget: every cached read would go back to the reader
+        let matrix = RemoteMatrix::open(reader, CacheConfig::with_max_bytes(0))
+            .await
+            .unwrap();
+
+        assert_eq!(matrix.row_index_of(b"r2").await.unwrap(), Some(2));
+        let misses = matrix.cache_stats().misses;
+
+        assert_eq!(matrix.col_index_of(b"c1").await.unwrap(), Some(1));
+        assert_eq!(matrix.row_index_of(b"missing").await.unwrap(), None);
+        assert!(matrix.attributes().await.unwrap().is_empty());
+        assert_eq!(matrix.cache_stats().misses, misses);
+    }
+
+    #[tokio::test]
+    async fn test_column_queries() {
+        let reader = MemoryReader::new(labelled_matrix().await);
+        let matrix = RemoteMatrix::open(reader, CacheConfig::default())
+            .await
+            .unwrap();
+
+        let col = matrix.get_col(2).await.unwrap();
+        assert_eq!(col.len(), 1);
+        assert!(matches!(col[0], (1, ArrayValue::Float64(v)) if v == 2.0));
+        assert_eq!(matrix.get_col(1).await.unwrap().len(), 1);
+
+        let cols = matrix.get_col_range(0, 2).await.unwrap();
+        let positions: Vec<(usize, usize)> = cols.iter().map(|&(r, c, _)| (r, c)).collect();
+        assert_eq!(positions, [(0, 0), (2, 1)]);
+
+        assert!(matrix.get_col(3).await.is_err());
+        assert!(matrix.get_col_range(2, 2).await.is_err());
+    }
+
+    /// In-memory reader with a version tag and a count of range reads
+    struct VersionedReader {
+        inner: MemoryReader,
+        tag: &'static str,
+        reads: std::sync::atomic::AtomicUsize,
+    }
+
+    impl VersionedReader {
+        fn new(bytes: Vec<u8>, tag: &'static str) -> Self {
+            Self {
+                inner: MemoryReader::new(bytes),
+                tag,
+                reads: Default::default(),
+            }
+        }
+
+        fn reads(&self) -> usize {
+            self.reads.load(std::sync::atomic::Ordering::Relaxed)
+        }
+    }
+
+    impl RangeReader for VersionedReader {
+        fn read_range(&self, range: Range<usize>) -> ReadFuture<'_, Vec<u8>> {
+            self.reads
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.inner.read_range(range)
+        }
+
+        fn size(&self) -> ReadFuture<'_, u64> {
+            self.inner.size()
+        }
+
+        fn etag(&self) -> ReadFuture<'_, Option<String>> {
+            let tag = self.tag.to_owned();
+            Box::pin(async move { Ok(Some(tag)) })
+        }
+
+        fn cache_key(&self) -> Option<&str> {
+            Some("matrix")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disk_cache_revalidates_header() {
+        let dir = TempDir::new();
+        let config = CacheConfig::default().with_disk_cache(dir.file("cache"), 1 << 20);
+        let bytes = labelled_matrix().await;
+
+        let first = RemoteMatrix::open(
+            VersionedReader::new(bytes.clone(), "\"v1\""),
+            config.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(first.get_row(1).await.unwrap().len(), 1);
+        assert!(first.reader().reads() > 0);
+
+        // Same version: the header and blocks come from disk
+        let second = RemoteMatrix::open(
+            VersionedReader::new(bytes.clone(), "\"v1\""),
+            config.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(second.get_row(1).await.unwrap().len(), 1);
+        assert_eq!(second.reader().reads(), 0);
+        assert!(second.cache_stats().disk_hits > 0);
+
+        // A new version is read afresh
+        let mut changed = bytes;
+        changed[8..16].copy_from_slice(&4u64.to_le_bytes());
+        let third = RemoteMatrix::open(VersionedReader::new(changed, "\"v2\""), config)
+            .await
+            .unwrap();
+        assert_eq!(third.nrows(), 4);
+        assert!(third.reader().reads() > 0);
+        assert_eq!(third.cache_stats().disk_hits, 0);
+    }
+
+    /// Elements of a 40 x 30 matrix whose rows 10..30 are empty
+    fn sparse_elements() -> Vec<(usize, usize, f64)> {
+        (0..40)
+            .filter(|row| !(10..30).contains(row))
+            .flat_map(|row| {
+                [
+                    (row, row % 7, row as f64),
+                    (row, 20 + row % 5, -(row as f64)),
+                ]
+            })
+            .collect()
+    }
+
+    async fn sparse_matrix_bytes() -> Vec<u8> {
+        let dir = TempDir::new();
+        let path = dir.file("sparse.bspc");
+        let config = ChunkConfig::default().with_chunk_size(5);
+        BspcFile::write_sparse_matrix(40, 30, &sparse_elements(), config, &path)
+            .await
+            .unwrap();
+        std::fs::read(&path).unwrap()
+    }
+
+    fn as_f64(value: &ArrayValue) -> f64 {
+        match value {
+            ArrayValue::Float64(v) => *v,
+            _ => panic!("expected f64 values"),
+        }
+    }
+
+    fn expected_row(row: usize) -> Vec<(usize, f64)> {
+        sparse_elements()
+            .into_iter()
+            .filter(|&(r, _, _)| r == row)
+            .map(|(_, col, value)| (col, value))
+            .collect()
+    }
+
+    async fn assert_row_queries<R: RangeReader>(matrix: &RemoteMatrix<R>) {
+        for row in [0, 9, 15, 30, 39] {
+            let got: Vec<(usize, f64)> = matrix
+                .get_row(row)
+                .await
+                .unwrap()
+                .iter()
+                .map(|(col, value)| (*col, as_f64(value)))
+                .collect();
+            assert_eq!(got, expected_row(row), "row {row}");
+        }
+
+        let rows = matrix.get_rows(&[39, 12, 3]).await.unwrap();
+        assert_eq!(rows[0].len(), 2);
+        assert!(rows[1].is_empty());
+        assert_eq!(rows[2][0].0, 3);
+
+        let range = matrix.get_row_range(8, 31).await.unwrap();
+        let positions: Vec<(usize, usize)> = range.iter().map(|&(r, c, _)| (r, c)).collect();
+        assert_eq!(
+            positions,
+            [(8, 1), (8, 23), (9, 2), (9, 24), (30, 2), (30, 20)]
+        );
+
+        let filtered = matrix.get_row_with_col_range(4, 0, 20).await.unwrap();
+        assert_eq!(filtered.len(), 1);
+        assert!(
+            matches!(matrix.get_element(4, 24).await.unwrap(), Some(ArrayValue::Float64(v)) if v == -4.0)
+        );
+        assert!(matrix.get_element(4, 5).await.unwrap().is_none());
+        assert!(matrix.get_element(40, 0).await.is_err());
+        assert!(matrix.get_row_range(5, 5).await.is_err());
+        assert!(matrix.get_rows(&[40]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_reader_row_queries() {
+        let reader = MemoryReader::new(sparse_matrix_bytes().await);
+        let matrix = RemoteMatrix::open(reader, CacheConfig::default().with_block_size(64))
+            .await
+            .unwrap();
+        assert_eq!((matrix.nrows(), matrix.ncols()), (40, 30));
+        assert_eq!(matrix.nnz(), sparse_elements().len());
+        assert_row_queries(&matrix).await;
+    }
+
+    #[tokio::test]
+    async fn test_bloom_filter_skips_empty_rows() {
+        let reader = MemoryReader::new(sparse_matrix_bytes().await);
+        let matrix = RemoteMatrix::open(reader, CacheConfig::with_max_bytes(0))
+            .await
+            .unwrap();
+        assert!(matrix.chunk_bloom_filter().await.unwrap().is_some());
+
+        // Rows 10..30 fill whole chunks, so the filter answers without reads
+        let misses = matrix.cache_stats().misses;
+        assert!(matrix.get_row(17).await.unwrap().is_empty());
+        assert!(matrix.get_element(22, 3).await.unwrap().is_none());
+        assert_eq!(matrix.cache_stats().misses, misses);
+    }
+
+    #[tokio::test]
+    async fn test_file_and_boxed_readers() {
+        let dir = TempDir::new();
+        let path = dir.file("sparse.bspc");
+        std::fs::write(&path, sparse_matrix_bytes().await).unwrap();
+
+        let reader = FileReader::open(&path).await.unwrap();
+        assert!(reader.etag().await.unwrap().is_some());
+        let matrix = RemoteMatrix::open(reader, CacheConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            matrix.get_file_size().await.unwrap(),
+            std::fs::metadata(&path).unwrap().len()
+        );
+        assert_row_queries(&matrix).await;
+
+        let boxed: Box<dyn RangeReader> =
+            Box::new(MemoryReader::new(std::fs::read(&path).unwrap()));
+        let matrix = RemoteMatrix::open(boxed, CacheConfig::default())
+            .await
+            .unwrap();
+        assert_row_queries(&matrix).await;
+    }
+
+    #[tokio::test]
+    async fn test_complex_matrices_need_complex_accessors() {
+        let dir = TempDir::new();
+        let path = dir.file("complex.bspc");
+        let value = Complex64::new(1.5, -2.0);
+        BspcFile::write_sparse_matrix(2, 2, &[(1, 0, value)], ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        let reader = MemoryReader::new(std::fs::read(&path).unwrap());
+        let matrix = RemoteMatrix::open(reader, CacheConfig::default())
+            .await
+            .unwrap();
+
+        assert!(matrix.get_row(1).await.is_err());
+        assert!(matrix.get_element(1, 0).await.is_err());
+        assert_eq!(matrix.get_element_complex(1, 0).await.unwrap(), Some(value));
+        assert_eq!(matrix.get_row_complex(1).await.unwrap(), [(0, value)]);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_compressed_and_truncated_files() {
+        let dir = TempDir::new();
+        let path = dir.file("compressed.bspc");
+        let config = ChunkConfig::default().with_compression(Default::default());
+        BspcFile::write_sparse_matrix(2, 2, &[(0, 0, 1.0f64)], config, &path)
+            .await
+            .unwrap();
+        let reader = MemoryReader::new(std::fs::read(&path).unwrap());
+        assert!(RemoteMatrix::open(reader, CacheConfig::default())
+            .await
+            .is_err());
+
+        let reader = MemoryReader::new(sparse_matrix_bytes().await[..100].to_vec());
+        assert!(RemoteMatrix::open(reader, CacheConfig::default())
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Credentials attached to remote requests
+//!
+//! Static headers, basic auth and bearer tokens are added to every request.
+//! Token and URL providers are callbacks that produce fresh credentials: they
+//! run when the matrix is opened and again whenever the server answers `401`
+//! or `403`, which covers expiring bearer tokens and presigned URLs. A
+//! generation counter makes concurrent requests that fail with the same stale
+//! credentials share a single refresh.
+
+use binsparse_rs::Result;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Method, RequestBuilder};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+/// Future returned by a credential provider
+pub(crate) type ProviderFuture<T> = Pin<Box<dyn Future<Output = Result<T>> + Send>>;
+
+/// Callback producing fresh credentials
+pub(crate) type Provider<T> = Arc<dyn Fn() -> ProviderFuture<T> + Send + Sync>;
+
+/// Box an async callback as a [`Provider`]
+pub(crate) fn provider<T, F, Fut>(callback: F) -> Provider<T>
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<T>> + Send + 'static,
+{
+    Arc::new(move || Box::pin(callback()))
+}
+
+/// Credentials currently in use
+struct Current {
+    url: String,
+    token: Option<String>,
+    /// Bumped on every refresh
+    generation: u64,
+}
+
+/// Credentials for one remote file
+pub(crate) struct Auth {
+    headers: HeaderMap,
+    basic: Option<(String, Option<String>)>,
+    token_provider: Option<Provider<String>>,
+    url_provider: Option<Provider<String>>,
+    current: RwLock<Current>,
+    refresh_lock: tokio::sync::Mutex<()>,
+}
+
+impl Auth {
+    /// Create credentials for `url`
+    pub(crate) fn new(
+        url: &str,
+        headers: HeaderMap,
+        basic: Option<(String, Option<String>)>,
+        token: Option<String>,
+        token_provider: Option<Provider<String>>,
+        url_provider: Option<Provider<String>>,
+    ) -> Self {
+        Self {
+            headers,
+            basic,
+            token_provider,
+            url_provider,
+            current: RwLock::new(Current {
+                url: url.to_string(),
+                token,
+                generation: 0,
+            }),
+            refresh_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Check whether credentials can be refreshed
+    pub(crate) fn can_refresh(&self) -> bool {
+        self.token_provider.is_some() || self.url_provider.is_some()
+    }
+
+    /// Get the generation of the current credentials
+    pub(crate) fn generation(&self) -> u64 {
+        self.current.read().unwrap().generation
+    }
+
+    /// Build a request with the current URL and credentials
+    ///
+    /// Also returns the generation of the credentials used, for [`Auth::refresh`].
+    pub(crate) fn request(&self, client: &Client, method: Method) -> (RequestBuilder, u64) {
+        let current = self.current.read().unwrap();
+        let mut request = client
+            .request(method, &current.url)
+            .headers(self.headers.clone());
+        if let Some((username, password)) = &self.basic {
+            request = request.basic_auth(username, password.as_deref());
+        }
+        if let Some(token) = &current.token {
+            request = request.bearer_auth(token);
+        }
+        (request, current.generation)
+    }
+
+    /// Fetch fresh credentials unless another request already replaced `seen`
+    pub(crate) async fn refresh(&self, seen: u64) -> Result<()> {
+        let _refreshing = self.refresh_lock.lock().await;
+        if self.generation() != seen {
+            return Ok(());
+        }
+
+        let token = match &self.token_provider {
+            Some(provider) => Some(provider().await?),
+            None => None,
+        };
+        let url = match &self.url_provider {
+            Some(provider) => Some(provider().await?),
+            None => None,
+        };
+
+        let mut current = self.current.write().unwrap();
+        if token.is_some() {
+            current.token = token;
+        }
+        if let Some(url) = url {
+            current.url = url;
+        }
+        current.generation += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,531 @@
+//! Byte-budgeted block cache for remote reads
+//!
+//! Reads are split into fixed-size blocks aligned to the start of the file.
+//! Missing blocks are fetched in contiguous runs, so overlapping reads share
+//! blocks and a request spanning many blocks becomes one range request.
+//! Blocks are evicted least recently used first once the byte budget is
+//! exceeded, and concurrent reads of a block being fetched wait for that
+//! fetch instead of issuing their own. With a disk cache configured, blocks
+//! missing from memory are looked up on disk before going to the server.
+
+use super::disk_cache::DiskCache;
+use binsparse_rs::{Error, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// Block cache settings for remote matrices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Size of each cached block in bytes
+    pub block_size: usize,
+    /// Maximum number of cached bytes before blocks are evicted
+    pub max_bytes: usize,
+    /// Directory for the persistent block cache (`None` keeps blocks in memory only)
+    pub disk_dir: Option<PathBuf>,
+    /// Maximum size of the persistent block cache in bytes
+    pub disk_max_bytes: u64,
+}
+
+impl CacheConfig {
+    /// Create config with a byte budget
+    pub fn with_max_bytes(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            ..Self::default()
+        }
+    }
+
+    /// Set the block size in bytes
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Persist fetched blocks under `dir`, keeping at most `max_bytes` on disk
+    ///
+    /// The directory can be shared between processes. On open the cached
+    /// version is revalidated with a conditional request carrying its ETag
+    /// (`If-None-Match`) or Last-Modified date (`If-Modified-Since`).
+    pub fn with_disk_cache(mut self, dir: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        self.disk_dir = Some(dir.into());
+        self.disk_max_bytes = max_bytes;
+        self
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            block_size: 64 * 1024,       // 64 KiB blocks
+            max_bytes: 64 * 1024 * 1024, // 64 MiB budget
+            disk_dir: None,
+            disk_max_bytes: 1024 * 1024 * 1024, // 1 GiB on disk
+        }
+    }
+}
+
+/// Block cache counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Blocks served from the cache
+    pub hits: u64,
+    /// Blocks read from the persistent disk cache
+    pub disk_hits: u64,
+    /// Blocks fetched from the server
+    pub misses: u64,
+    /// Blocks evicted to stay within the byte budget
+    pub evictions: u64,
+    /// Bytes currently cached
+    pub cached_bytes: usize,
+}
+
+/// Blocks needed by a read, filled in as they are found or fetched
+type Found = BTreeMap<usize, Option<Arc<[u8]>>>;
+
+/// A cached block and its position in the eviction order
+struct Entry {
+    data: Arc<[u8]>,
+    tick: u64,
+}
+
+/// Cached blocks and fetches in progress
+#[derive(Default)]
+struct CacheState {
+    blocks: HashMap<usize, Entry>,
+    /// Blocks ordered by last use, oldest first
+    lru: BTreeMap<u64, usize>,
+    /// Blocks being fetched; the receiver wakes when the fetch ends
+    in_flight: HashMap<usize, watch::Receiver<()>>,
+    tick: u64,
+    bytes: usize,
+}
+
+impl CacheState {
+    /// Get a cached block and mark it as most recently used
+    fn touch(&mut self, block: usize) -> Option<Arc<[u8]>> {
+        self.tick += 1;
+        let entry = self.blocks.get_mut(&block)?;
+        self.lru.remove(&entry.tick);
+        self.lru.insert(self.tick, block);
+        entry.tick = self.tick;
+        Some(Arc::clone(&entry.data))
+    }
+
+    /// Insert a block, evicting old blocks beyond `max_bytes`
+    ///
+    /// Returns the number of evicted blocks.
+    fn insert(&mut self, block: usize, data: Arc<[u8]>, max_bytes: usize) -> u64 {
+        self.tick += 1;
+        self.bytes += data.len();
+        self.lru.insert(self.tick, block);
+        if let Some(old) = self.blocks.insert(
+            block,
+            Entry {
+                data,
+                tick: self.tick,
+            },
+        ) {
+            self.lru.remove(&old.tick);
+            self.bytes -= old.data.len();
+        }
+
+        let mut evicted = 0;
+        while self.bytes > max_bytes {
+            let Some((_, oldest)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.blocks.remove(&oldest) {
+                self.bytes -= entry.data.len();
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+}
+
+/// Clears in-flight markers when a fetch finishes or fails
+///
+/// Dropping the sender afterwards wakes every reader waiting on the blocks.
+struct InFlight<'a> {
+    state: &'a Mutex<CacheState>,
+    blocks: Vec<usize>,
+    _done: watch::Sender<()>,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        for block in &self.blocks {
+            state.in_flight.remove(block);
+        }
+    }
+}
+
+/// Block cache over a remote file of known length
+pub(crate) struct BlockCache {
+    config: CacheConfig,
+    file_len: usize,
+    state: Mutex<CacheState>,
+    disk: Option<DiskCache>,
+    hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl BlockCache {
+    /// Create an empty cache for a file of `file_len` bytes
+    pub(crate) fn new(config: CacheConfig, file_len: usize, disk: Option<DiskCache>) -> Self {
+        let block_size = config.block_size;
+        Self {
+            config: config.with_block_size(block_size),
+            file_len,
+            state: Mutex::new(CacheState::default()),
+            disk,
+            hits: AtomicU64::new(0),
+            disk_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the cache settings
+    pub(crate) fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    /// Get the cache counters
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            disk_hits: self.disk_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            cached_bytes: self.state.lock().unwrap().bytes,
+        }
+    }
+
+    /// Insert a block into memory and the blocks gathered for a read
+    fn insert(&self, block: usize, data: Arc<[u8]>, found: &mut Found) {
+        let evicted =
+            self.state
+                .lock()
+                .unwrap()
+                .insert(block, Arc::clone(&data), self.config.max_bytes);
+        self.evictions.fetch_add(evicted, Ordering::Relaxed);
+        found.insert(block, Some(data));
+    }
+
+    /// Load blocks of `runs` from disk, returning the runs still missing
+    async fn load_from_disk(
+        &self,
+        runs: Vec<Range<usize>>,
+        found: &mut Found,
+    ) -> Vec<Range<usize>> {
+        let Some(disk) = &self.disk else {
+            return runs;
+        };
+
+        let mut missing: Vec<Range<usize>> = Vec::new();
+        for block in runs.into_iter().flatten() {
+            let expected = self
+                .config
+                .block_size
+                .min(self.file_len - block * self.config.block_size);
+            match disk.load(block).await {
+                Some(data) if data.len() == expected => {
+                    self.disk_hits.fetch_add(1, Ordering::Relaxed);
+                    self.insert(block, Arc::from(data), found);
+                }
+                _ => push_block(&mut missing, block),
+            }
+        }
+        missing
+    }
+
+    /// Read `range`, fetching missing blocks with `fetch`
+    ///
+    /// See [`BlockCache::read_many`].
+    pub(crate) async fn read<F, Fut>(&self, range: Range<usize>, fetch: F) -> Result<Vec<u8>>
+    where
+        F: Fn(Vec<Range<usize>>) -> Fut,
+        Fut: Future<Output = Result<Vec<Vec<u8>>>>,
+    {
+        let mut bytes = self.read_many(std::slice::from_ref(&range), fetch).await?;
+        Ok(bytes.pop().unwrap_or_default())
+    }
+
+    /// Read each of `ranges`, fetching missing blocks with `fetch`
+    ///
+    /// Missing blocks of all ranges are gathered into contiguous runs and
+    /// handed to `fetch` in a single call, which receives block-aligned byte
+    /// ranges clipped to the file length and returns their bytes in order.
+    pub(crate) async fn read_many<F, Fut>(
+        &self,
+        ranges: &[Range<usize>],
+        fetch: F,
+    ) -> Result<Vec<Vec<u8>>>
+    where
+        F: Fn(Vec<Range<usize>>) -> Fut,
+        Fut: Future<Output = Result<Vec<Vec<u8>>>>,
+    {
+        if ranges.iter().any(|range| range.end > self.file_len) {
+            return Err(Error::InvalidState("Range extends past end of file"));
+        }
+
+        let block_size = self.config.block_size;
+        let mut found: Found = ranges
+            .iter()
+            .filter(|range| !range.is_empty())
+            .flat_map(|range| range.start / block_size..(range.end - 1) / block_size + 1)
+            .map(|block| (block, None))
+            .collect();
+
+        loop {
+            let mut waits = Vec::new();
+            let mut runs: Vec<Range<usize>> = Vec::new();
+            let in_flight = {
+                let mut state = self.state.lock().unwrap();
+                for (&block, slot) in found.iter_mut() {
+                    if slot.is_some() {
+                        continue;
+                    }
+
+                    if let Some(data) = state.touch(block) {
+                        self.hits.fetch_add(1, Ordering::Relaxed);
+                        *slot = Some(data);
+                    } else if let Some(done) = state.in_flight.get(&block) {
+                        waits.push(done.clone());
+                    } else {
+                        push_block(&mut runs, block);
+                    }
+                }
+
+                if runs.is_empty() {
+                    None
+                } else {
+                    let (done, receiver) = watch::channel(());
+                    let blocks: Vec<usize> = runs.iter().flat_map(Clone::clone).collect();
+                    for &block in &blocks {
+                        state.in_flight.insert(block, receiver.clone());
+                    }
+                    Some(InFlight {
+                        state: &self.state,
+                        blocks,
+                        _done: done,
+                    })
+                }
+            };
+
+            let runs = self.load_from_disk(runs, &mut found).await;
+            if !runs.is_empty() {
+                let byte_ranges: Vec<Range<usize>> = runs
+                    .iter()
+                    .map(|run| run.start * block_size..(run.end * block_size).min(self.file_len))
+                    .collect();
+                let fetched = fetch(byte_ranges.clone()).await?;
+                if fetched.len() != runs.len() {
+                    return Err(Error::IoError("Short read from server"));
+                }
+
+                for ((run, byte_range), data) in runs.into_iter().zip(byte_ranges).zip(fetched) {
+                    if data.len() != byte_range.len() {
+                        return Err(Error::IoError("Short read from server"));
+                    }
+                    self.misses.fetch_add(run.len() as u64, Ordering::Relaxed);
+
+                    for (block, chunk) in run.zip(data.chunks(block_size)) {
+                        if let Some(disk) = &self.disk {
+                            disk.store(block, chunk).await;
+                        }
+                        self.insert(block, Arc::from(chunk), &mut found);
+                    }
+                }
+            }
+            drop(in_flight);
+
+            if waits.is_empty() {
+                break;
+            }
+            // Woken by success or failure alike; either way the blocks are re-checked
+            for mut done in waits {
+                let _ = done.changed().await;
+            }
+        }
+
+        ranges
+            .iter()
+            .map(|range| {
+                let mut bytes = Vec::with_capacity(range.len());
+                if range.is_empty() {
+                    return Ok(bytes);
+                }
+
+                for block in range.start / block_size..(range.end - 1) / block_size + 1 {
+                    let data = found
+                        .get(&block)
+                        .and_then(Option::as_ref)
+                        .ok_or(Error::InvalidState("Missing cached block"))?;
+                    let block_start = block * block_size;
+                    let start = range.start.max(block_start) - block_start;
+                    let end = range.end.min(block_start + data.len()) - block_start;
+                    bytes.extend_from_slice(&data[start..end]);
+                }
+
+                if bytes.len() != range.len() {
+                    return Err(Error::IoError("Short read from server"));
+                }
+                Ok(bytes)
+            })
+            .collect()
+    }
+}
+
+/// Append `block` to `runs`, extending the last run when contiguous
+fn push_block(runs: &mut Vec<Range<usize>>, block: usize) {
+    match runs.last_mut() {
+        Some(run) if run.end == block => run.end += 1,
+        _ => runs.push(block..block + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Calls = Mutex<Vec<Vec<Range<usize>>>>;
+    type Fetch<'a> = std::pin::Pin<Box<dyn Future<Output = Result<Vec<Vec<u8>>>> + 'a>>;
+
+    /// Serve ranges of `data`, recording each call and yielding once
+    fn fetcher<'a>(data: &'a [u8], calls: &'a Calls) -> impl Fn(Vec<Range<usize>>) -> Fetch<'a> {
+        move |ranges| {
+            calls.lock().unwrap().push(ranges.clone());
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                Ok(ranges.into_iter().map(|r| data[r].to_vec()).collect())
+            })
+        }
+    }
+
+    fn file(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    fn cache(block_size: usize, max_bytes: usize, file_len: usize) -> BlockCache {
+        let config = CacheConfig::with_max_bytes(max_bytes).with_block_size(block_size);
+        BlockCache::new(config, file_len, None)
+    }
+
+    #[tokio::test]
+    async fn test_read_fetches_missing_blocks_once() {
+        let data = file(100);
+        let calls = Calls::default();
+        let cache = cache(16, 1024, data.len());
+
+        let bytes = cache.read(10..40, fetcher(&data, &calls)).await.unwrap();
+        assert_eq!(bytes, &data[10..40]);
+        // Blocks 0..3 are fetched as one contiguous run
+        assert_eq!(*calls.lock().unwrap(), [vec![Range { start: 0, end: 48 }]]);
+
+        let bytes = cache.read(20..30, fetcher(&data, &calls)).await.unwrap();
+        assert_eq!(bytes, &data[20..30]);
+        assert_eq!(calls.lock().unwrap().len(), 1);
+
+        // The last block is clipped to the file length
+        let bytes = cache.read(90..100, fetcher(&data, &calls)).await.unwrap();
+        assert_eq!(bytes, &data[90..100]);
+        assert_eq!(
+            calls.lock().unwrap()[1],
+            [Range {
+                start: 80,
+                end: 100
+            }]
+        );
+
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 5);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.cached_bytes, 68);
+    }
+
+    #[tokio::test]
+    async fn test_read_many_merges_runs() {
+        let data = file(128);
+        let calls = Calls::default();
+        let cache = cache(16, 1024, data.len());
+
+        let ranges = [0..4, 100..120, 5..20, 40..40];
+        let bytes = cache
+            .read_many(&ranges, fetcher(&data, &calls))
+            .await
+            .unwrap();
+        for (range, bytes) in ranges.iter().zip(&bytes) {
+            assert_eq!(bytes, &data[range.clone()]);
+        }
+        assert_eq!(*calls.lock().unwrap(), [vec![0..32, 96..128]]);
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let data = file(64);
+        let calls = Calls::default();
+        let cache = cache(16, 32, data.len());
+
+        cache.read(0..1, fetcher(&data, &calls)).await.unwrap();
+        cache.read(16..17, fetcher(&data, &calls)).await.unwrap();
+        // Touch block 0 so block 1 is the oldest
+        cache.read(0..1, fetcher(&data, &calls)).await.unwrap();
+        cache.read(32..33, fetcher(&data, &calls)).await.unwrap();
+
+        let stats = cache.stats();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.cached_bytes, 32);
+
+        calls.lock().unwrap().clear();
+        cache.read(0..1, fetcher(&data, &calls)).await.unwrap();
+        assert!(calls.lock().unwrap().is_empty());
+        cache.read(16..17, fetcher(&data, &calls)).await.unwrap();
+        assert_eq!(*calls.lock().unwrap(), [vec![Range { start: 16, end: 32 }]]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_reads_share_fetch() {
+        let data = file(64);
+        let calls = Calls::default();
+        let cache = cache(16, 1024, data.len());
+
+        let (a, b) = tokio::join!(
+            cache.read(0..20, fetcher(&data, &calls)),
+            cache.read(10..30, fetcher(&data, &calls))
+        );
+        assert_eq!(a.unwrap(), &data[0..20]);
+        assert_eq!(b.unwrap(), &data[10..30]);
+        assert_eq!(calls.lock().unwrap().len(), 1);
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_read_errors() {
+        let data = file(64);
+        let calls = Calls::default();
+        let cache = cache(16, 1024, data.len());
+
+        assert!(cache.read(60..70, fetcher(&data, &calls)).await.is_err());
+        assert!(calls.lock().unwrap().is_empty());
+
+        let short = |ranges: Vec<Range<usize>>| async move {
+            Ok(ranges.into_iter().map(|r| vec![0; r.len() - 1]).collect())
+        };
+        assert!(cache.read(0..8, short).await.is_err());
+        assert_eq!(cache.stats().cached_bytes, 0);
+
+        // A failed fetch leaves nothing in flight, so later reads succeed
+        let bytes = cache.read(0..8, fetcher(&data, &calls)).await.unwrap();
+        assert_eq!(bytes, &data[0..8]);
+    }
+}
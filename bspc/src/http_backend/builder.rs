@@ -0,0 +1,292 @@
+//! Builder for remote matrices
+//!
+//! Collects the client, credentials and cache settings for an [`HttpMatrix`]
+//! before the header is fetched.
+
+use super::auth::{self, Auth, Provider};
+use super::block_cache::CacheConfig;
+use super::http_impl::HttpMatrix;
+use super::range_plan::RangePlan;
+use super::transport::{HttpConfig, HttpReader, HttpTransport};
+use binsparse_rs::{Error, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+use std::future::Future;
+
+/// Builder for [`HttpMatrix`] with custom headers, credentials and clients
+///
+/// ```rust,no_run
+/// use bspc::HttpMatrix;
+///
+/// async fn example() -> Result<(), binsparse_rs::Error> {
+///     let matrix = HttpMatrix::builder("https://example.com/matrix.bspc")
+///         .header("X-Api-Key", "secret")
+///         .token_provider(|| async { Ok("fresh-token".to_string()) })
+///         .build()
+///         .await?;
+///     println!("{} x {}", matrix.nrows(), matrix.ncols());
+///     Ok(())
+/// }
+/// ```
+pub struct HttpMatrixBuilder {
+    url: String,
+    client: Option<Client>,
+    headers: Vec<(String, String)>,
+    basic_auth: Option<(String, Option<String>)>,
+    bearer_token: Option<String>,
+    token_provider: Option<Provider<String>>,
+    url_provider: Option<Provider<String>>,
+    cache_key: Option<String>,
+    cache_config: CacheConfig,
+    http_config: HttpConfig,
+    range_plan: RangePlan,
+}
+
+impl HttpMatrixBuilder {
+    /// Create a builder for the file at `url`
+    pub(crate) fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            client: None,
+            headers: Vec::new(),
+            basic_auth: None,
+            bearer_token: None,
+            token_provider: None,
+            url_provider: None,
+            cache_key: None,
+            cache_config: CacheConfig::default(),
+            http_config: HttpConfig::default(),
+            range_plan: RangePlan::default(),
+        }
+    }
+
+    /// Add a header sent with every request
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Authenticate with HTTP basic auth
+    pub fn basic_auth(mut self, username: impl Into<String>, password: Option<&str>) -> Self {
+        self.basic_auth = Some((username.into(), password.map(str::to_owned)));
+        self
+    }
+
+    /// Authenticate with a fixed bearer token
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    /// Fetch bearer tokens from a callback
+    ///
+    /// The callback runs when the matrix is built and again whenever the
+    /// server answers `401` or `403`.
+    pub fn token_provider<F, Fut>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        self.token_provider = Some(auth::provider(provider));
+        self
+    }
+
+    /// Fetch presigned URLs from a callback
+    ///
+    /// The callback runs when the matrix is built and again whenever the
+    /// server answers `401` or `403`, such as after a presigned URL expires.
+    /// Unless [`HttpMatrixBuilder::cache_key`] is set, the disk cache is keyed
+    /// by the URL without its query string, which is where the signature lives.
+    pub fn url_provider<F, Fut>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        self.url_provider = Some(auth::provider(provider));
+        self
+    }
+
+    /// Send requests through a custom client
+    ///
+    /// The client's own settings apply; only the request timeout of
+    /// [`HttpConfig`] is added to each request.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Set the key identifying the file in the disk cache
+    pub fn cache_key(mut self, key: impl Into<String>) -> Self {
+        self.cache_key = Some(key.into());
+        self
+    }
+
+    /// Set the block cache settings
+    pub fn cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
+    }
+
+    /// Set the timeout and retry settings
+    pub fn http_config(mut self, http_config: HttpConfig) -> Self {
+        self.http_config = http_config;
+        self
+    }
+
+    /// Set how scattered reads are turned into range requests
+    pub fn range_plan(mut self, range_plan: RangePlan) -> Self {
+        self.range_plan = range_plan;
+        self
+    }
+
+    /// Fetch the header and open the matrix
+    pub async fn build(self) -> Result<HttpMatrix> {
+        let mut headers = HeaderMap::with_capacity(self.headers.len());
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| Error::InvalidState("Invalid HTTP header name"))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| Error::InvalidState("Invalid HTTP header value"))?;
+            headers.append(name, value);
+        }
+
+        let cache_key = match (self.cache_key, &self.url_provider) {
+            (Some(key), _) => key,
+            (None, Some(_)) => self.url.split('?').next().unwrap_or_default().to_string(),
+            (None, None) => self.url.clone(),
+        };
+
+        let auth = Auth::new(
+            &self.url,
+            headers,
+            self.basic_auth,
+            self.bearer_token,
+            self.token_provider,
+            self.url_provider,
+        );
+        let transport = HttpTransport::new(self.client, auth, self.http_config)?;
+        transport.refresh_credentials().await?;
+
+        let reader = HttpReader::new(transport, cache_key, self.range_plan);
+        HttpMatrix::open(reader, self.cache_config).await
+    }
+}
+
+#[cfg(all(test, feature = "mmap"))]
+mod tests {
+    use super::*;
+    use crate::chunked_backend::ChunkConfig;
+    use crate::http_backend::RangeReader;
+    use crate::mmap_backend::BspcFile;
+    use crate::test_support::{MockResponse, MockServer, TempDir};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    async fn matrix_bytes() -> Arc<[u8]> {
+        let dir = TempDir::new();
+        let path = dir.file("matrix.bspc");
+        let elements = [(0, 1, 1.0f64), (2, 0, 2.0)];
+        BspcFile::write_sparse_matrix(3, 2, &elements, ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        std::fs::read(&path).unwrap().into()
+    }
+
+    /// Serve the matrix to requests that pass `authorized`, 401 otherwise
+    async fn serve_matrix(
+        authorized: impl Fn(&crate::test_support::MockRequest) -> bool + Send + Sync + 'static,
+    ) -> MockServer {
+        let file = matrix_bytes().await;
+        MockServer::start(move |request| {
+            if authorized(request) {
+                request.serve_range(&file)
+            } else {
+                MockResponse::new(401, "unauthorized")
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_sends_headers_and_credentials() {
+        let server = serve_matrix(|request| {
+            request.header("x-api-key") == Some("secret")
+                && request.header("authorization") == Some("Bearer token")
+        })
+        .await;
+
+        let matrix = HttpMatrix::builder(server.url())
+            .header("X-Api-Key", "secret")
+            .bearer_token("token")
+            .build()
+            .await
+            .unwrap();
+        assert_eq!((matrix.nrows(), matrix.ncols(), matrix.nnz()), (3, 2, 2));
+        assert_eq!(matrix.get_row(2).await.unwrap().len(), 1);
+
+        // "user:pass" in base64
+        let server =
+            serve_matrix(|request| request.header("authorization") == Some("Basic dXNlcjpwYXNz"))
+                .await;
+        let matrix = HttpMatrix::builder(server.url())
+            .basic_auth("user", Some("pass"))
+            .build()
+            .await;
+        assert!(matrix.is_ok());
+
+        let missing = HttpMatrix::builder(server.url()).build().await;
+        assert!(missing.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_provider_refreshes_on_unauthorized() {
+        let server =
+            serve_matrix(|request| request.header("authorization") == Some("Bearer t2")).await;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+
+        let matrix = HttpMatrix::builder(server.url())
+            .token_provider(move || {
+                let n = counter.fetch_add(1, Ordering::Relaxed) + 1;
+                async move { Ok::<_, Error>(format!("t{n}")) }
+            })
+            .build()
+            .await
+            .unwrap();
+        // Once when building, once more after the first 401
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(matrix.get_row(0).await.unwrap().len(), 1);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_url_provider_replaces_expired_urls() {
+        let server = serve_matrix(|request| request.target.ends_with("?sig=2")).await;
+        let base = server.url().to_owned();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+
+        let matrix = HttpMatrix::builder(&format!("{base}?sig=0"))
+            .url_provider(move || {
+                let n = counter.fetch_add(1, Ordering::Relaxed) + 1;
+                let url = format!("{base}?sig={n}");
+                async move { Ok::<_, Error>(url) }
+            })
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(matrix.reader().cache_key(), Some(server.url()));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_headers() {
+        let server = serve_matrix(|_| true).await;
+        let result = HttpMatrix::builder(server.url())
+            .header("bad header", "value")
+            .build()
+            .await;
+        assert!(result.is_err());
+        assert_eq!(server.requests(), 0);
+    }
+}
@@ -0,0 +1,295 @@
+//! Persistent on-disk block cache for remote files
+//!
+//! Blocks are stored under `<root>/<key hash>/<version hash>/`, one file per
+//! block, so a file that changes at its source gets a fresh directory and
+//! stale blocks are never read back. The version tag (such as an ETag) of the
+//! most recent version is recorded in `<root>/<key hash>/current` together
+//! with the file header; the next open revalidates that tag with a
+//! conditional request and reuses the header when the file is unchanged.
+//!
+//! Every file is written to a unique temporary name and renamed into place,
+//! so processes sharing a directory only ever see complete files. Directories
+//! of older versions are left in place, since another process may still be
+//! reading them. They stop being used, so their blocks are the first to go
+//! when the size cap is enforced by deleting the least recently used blocks,
+//! and emptied directories are removed then. Files removed by another process
+//! are skipped, and a removed directory is recreated by the next store.
+
+use binsparse_rs::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Name of the file recording the current version tag and header
+const CURRENT_FILE: &str = "current";
+
+/// Suffix of block files
+const BLOCK_SUFFIX: &str = "blk";
+
+/// Fraction of the size cap to shrink to when evicting, in percent
+const EVICT_TARGET_PERCENT: u64 = 90;
+
+/// Counter making temporary file names unique within the process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// 64-bit FNV-1a hash, stable across builds and platforms
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Write `data` to `path` through a temporary file and an atomic rename
+async fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let temp = path.with_extension(format!(
+        "tmp-{}-{}",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    tokio::fs::write(&temp, data).await?;
+    if let Err(error) = tokio::fs::rename(&temp, path).await {
+        let _ = tokio::fs::remove_file(&temp).await;
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Disk cache for the blocks of one file version
+pub(crate) struct DiskCache {
+    root: PathBuf,
+    version_dir: PathBuf,
+    block_size: usize,
+    max_bytes: u64,
+    /// Bytes written since the size cap was last enforced
+    written: AtomicU64,
+}
+
+impl DiskCache {
+    /// Directory holding every version of the file identified by `key`
+    fn key_dir(root: &Path, key: &str) -> PathBuf {
+        root.join(format!("{:016x}", fnv1a(key.as_bytes())))
+    }
+
+    /// Read the version tag and header most recently stored for `key`, if any
+    pub(crate) async fn stored_header(root: &Path, key: &str) -> Option<(String, Vec<u8>)> {
+        let current = tokio::fs::read(Self::key_dir(root, key).join(CURRENT_FILE))
+            .await
+            .ok()?;
+        let split = current.iter().position(|&b| b == 0)?;
+        let tag = std::str::from_utf8(&current[..split]).ok()?;
+        Some((tag.to_owned(), current[split + 1..].to_vec()))
+    }
+
+    /// Open the cache for version `tag` of the file identified by `key`
+    ///
+    /// Records `tag` and `header` as the current version when they differ
+    /// from what is stored.
+    pub(crate) async fn open(
+        root: &Path,
+        key: &str,
+        tag: &str,
+        header: &[u8],
+        block_size: usize,
+        max_bytes: u64,
+    ) -> Result<Self> {
+        let key_dir = Self::key_dir(root, key);
+        let version = format!("{:016x}", fnv1a(tag.as_bytes()));
+        let version_dir = key_dir.join(&version);
+        tokio::fs::create_dir_all(&version_dir)
+            .await
+            .map_err(|_| Error::IoError("Failed to create disk cache directory"))?;
+
+        let stored = Self::stored_header(root, key).await;
+        if stored.as_ref().map(|(t, h)| (t.as_str(), h.as_slice())) != Some((tag, header)) {
+            let mut current = tag.as_bytes().to_vec();
+            current.push(0);
+            current.extend_from_slice(header);
+            write_atomic(&key_dir.join(CURRENT_FILE), &current)
+                .await
+                .map_err(|_| Error::IoError("Failed to write disk cache entry"))?;
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            version_dir,
+            block_size,
+            max_bytes,
+            written: AtomicU64::new(0),
+        })
+    }
+
+    /// Path of a block file; the block size is part of the name
+    fn block_path(&self, block: usize) -> PathBuf {
+        self.version_dir
+            .join(format!("{}-{block}.{BLOCK_SUFFIX}", self.block_size))
+    }
+
+    /// Read a cached block and mark it as recently used
+    pub(crate) async fn load(&self, block: usize) -> Option<Vec<u8>> {
+        let path = self.block_path(block);
+        let data = tokio::fs::read(&path).await.ok()?;
+
+        // Best effort: eviction order falls back to the write time
+        let _ = tokio::task::spawn_blocking(move || {
+            std::fs::File::options()
+                .write(true)
+                .open(&path)?
+                .set_modified(SystemTime::now())
+        })
+        .await;
+        Some(data)
+    }
+
+    /// Store a block, evicting old blocks once the size cap is exceeded
+    ///
+    /// Failures are ignored; the block is simply fetched again next time.
+    pub(crate) async fn store(&self, block: usize, data: &[u8]) {
+        let path = self.block_path(block);
+        if write_atomic(&path, data).await.is_err() {
+            // Eviction may have removed the emptied directory
+            if tokio::fs::create_dir_all(&self.version_dir).await.is_err()
+                || write_atomic(&path, data).await.is_err()
+            {
+                return;
+            }
+        }
+
+        let written = self.written.fetch_add(data.len() as u64, Ordering::Relaxed);
+        if written + data.len() as u64 > self.max_bytes / 10 {
+            self.written.store(0, Ordering::Relaxed);
+            let root = self.root.clone();
+            let max_bytes = self.max_bytes;
+            let _ = tokio::task::spawn_blocking(move || evict(&root, max_bytes)).await;
+        }
+    }
+}
+
+/// Delete least recently used blocks under `root` until it fits `max_bytes`
+///
+/// Only runs after a tenth of the cap has been written, so the directory is
+/// scanned rarely. Version directories left empty are removed.
+fn evict(root: &Path, max_bytes: u64) {
+    let mut blocks = Vec::new();
+    let mut total = 0;
+
+    let dirs = std::fs::read_dir(root).into_iter().flatten().flatten();
+    let version_dirs: Vec<PathBuf> = dirs
+        .flat_map(|key_dir| {
+            std::fs::read_dir(key_dir.path())
+                .into_iter()
+                .flatten()
+                .flatten()
+        })
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    for version_dir in &version_dirs {
+        for entry in std::fs::read_dir(version_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != BLOCK_SUFFIX) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            total += metadata.len();
+            blocks.push((used, metadata.len(), path));
+        }
+    }
+
+    if total > max_bytes {
+        blocks.sort_unstable_by_key(|(used, _, _)| *used);
+        let target = max_bytes - max_bytes / 100 * (100 - EVICT_TARGET_PERCENT);
+        for (_, len, path) in blocks {
+            if total <= target {
+                break;
+            }
+            // Another process may have removed it already
+            if std::fs::remove_file(&path).is_ok() {
+                total -= len;
+            }
+        }
+    }
+
+    // Only succeeds for empty directories; a store recreates its own
+    for version_dir in version_dirs {
+        let _ = std::fs::remove_dir(version_dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    async fn open(root: &Path, tag: &str, max_bytes: u64) -> DiskCache {
+        DiskCache::open(root, "file", tag, b"header", 4, max_bytes)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_store_and_load() {
+        let dir = TempDir::new();
+        let root = dir.file("cache");
+        let cache = open(&root, "\"v1\"", 1 << 20).await;
+
+        assert_eq!(cache.load(0).await, None);
+        cache.store(0, b"abcd").await;
+        cache.store(3, b"ef").await;
+        assert_eq!(cache.load(0).await.as_deref(), Some(&b"abcd"[..]));
+        assert_eq!(cache.load(3).await.as_deref(), Some(&b"ef"[..]));
+
+        let stored = DiskCache::stored_header(&root, "file").await;
+        assert_eq!(stored, Some(("\"v1\"".to_owned(), b"header".to_vec())));
+        assert_eq!(DiskCache::stored_header(&root, "other").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_new_version_keeps_old_blocks_readable() {
+        let dir = TempDir::new();
+        let root = dir.file("cache");
+        let old = open(&root, "\"v1\"", 1 << 20).await;
+        old.store(0, b"old!").await;
+
+        // A second process opens a newer version while the first still reads
+        let new = open(&root, "\"v2\"", 1 << 20).await;
+        assert_eq!(new.load(0).await, None);
+        assert_eq!(old.load(0).await.as_deref(), Some(&b"old!"[..]));
+
+        let (tag, _) = DiskCache::stored_header(&root, "file").await.unwrap();
+        assert_eq!(tag, "\"v2\"");
+    }
+
+    #[tokio::test]
+    async fn test_eviction_prefers_unused_versions() {
+        let dir = TempDir::new();
+        let root = dir.file("cache");
+        let old = open(&root, "\"v1\"", 1 << 20).await;
+        old.store(0, b"0000").await;
+        old.store(1, b"1111").await;
+        // Keep the two versions' modification times apart
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        let new = open(&root, "\"v2\"", 1 << 20).await;
+        new.store(0, b"aaaa").await;
+        new.store(1, b"bbbb").await;
+        evict(&root, 8);
+
+        // The old version's blocks were least recently used, and its
+        // emptied directory is removed
+        assert_eq!(old.load(0).await, None);
+        assert!(!old.version_dir.exists());
+        assert_eq!(new.load(0).await.as_deref(), Some(&b"aaaa"[..]));
+        assert_eq!(new.load(1).await.as_deref(), Some(&b"bbbb"[..]));
+
+        // Stores recreate a removed directory
+        old.store(2, b"2222").await;
+        assert_eq!(old.load(2).await.as_deref(), Some(&b"2222"[..]));
+    }
+}
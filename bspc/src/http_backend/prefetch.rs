@@ -0,0 +1,406 @@
+//! Read-ahead for sequential row scans
+//!
+//! Elements are sorted by row, so scanning rows in order reads the arrays
+//! front to back. [`RowStream`] splits the scanned elements into windows of
+//! about one cache block per array and keeps the next windows downloading on
+//! background tasks while the caller consumes the current one. The number of
+//! windows in flight is capped both by the configured concurrency and by a
+//! byte budget, which defaults to half the block cache so read-ahead cannot
+//! evict the blocks it is about to use.
+
+use super::http_impl::RemoteMatrix;
+use super::range_reader::RangeReader;
+use binsparse_rs::{array::ArrayValue, Error, Result};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// Read-ahead settings for [`RowStream`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefetchConfig {
+    /// Most windows downloading at once
+    pub concurrency: usize,
+    /// Most bytes downloading or waiting to be consumed
+    ///
+    /// `None` uses half the block cache budget. Larger values are capped at
+    /// the whole budget.
+    pub max_bytes: Option<usize>,
+}
+
+impl PrefetchConfig {
+    /// Set the most windows downloading at once
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set the most bytes downloading or waiting to be consumed
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            max_bytes: None,
+        }
+    }
+}
+
+/// Values and both index arrays of one window of elements
+type Window = (Vec<u8>, Vec<u64>, Vec<u64>);
+
+/// Stream of the non-empty rows of a remote matrix, in order
+///
+/// Created by [`RemoteMatrix::stream_rows`]. Dropping the stream cancels
+/// the downloads still in flight.
+pub struct RowStream<R: RangeReader + 'static> {
+    matrix: Arc<RemoteMatrix<R>>,
+    /// Next element position to schedule
+    next_position: usize,
+    end_position: usize,
+    /// Elements per window
+    window_len: usize,
+    /// Most windows in flight at once
+    max_pending: usize,
+    pending: VecDeque<JoinHandle<Result<Window>>>,
+    window: Window,
+    /// Position within `window` of the next element to consume
+    cursor: usize,
+    /// Row being assembled, emitted once an element of another row appears
+    row: Option<(usize, Vec<(usize, ArrayValue)>)>,
+}
+
+impl<R: RangeReader + 'static> RemoteMatrix<R> {
+    /// Stream the non-empty rows in `rows`, downloading ahead of the caller
+    ///
+    /// Rows are yielded in order as `(row, [(col, value)])`; rows without
+    /// elements are skipped.
+    ///
+    /// ```rust,no_run
+    /// use bspc::{HttpMatrix, PrefetchConfig};
+    /// use std::sync::Arc;
+    ///
+    /// async fn example() -> Result<(), binsparse_rs::Error> {
+    ///     let matrix = Arc::new(HttpMatrix::new("https://example.com/matrix.bspc").await?);
+    ///     let config = PrefetchConfig::default().with_concurrency(8);
+    ///     let mut rows = matrix.stream_rows(0..matrix.nrows(), config).await?;
+    ///     while let Some(row) = rows.next().await {
+    ///         let (row, elements) = row?;
+    ///         println!("row {row}: {} elements", elements.len());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn stream_rows(
+        self: &Arc<Self>,
+        rows: Range<usize>,
+        config: PrefetchConfig,
+    ) -> Result<RowStream<R>> {
+        if rows.start > rows.end || rows.end > self.nrows() {
+            return Err(Error::InvalidState("Invalid row range"));
+        }
+
+        let start = self.lower_bound_row(rows.start, 0).await?;
+        let end = self.lower_bound_row(rows.end, start).await?;
+
+        // About one cache block of the widest array per window
+        let element_size = self.data_type().size_bytes();
+        let index_size = self.index_width().size_bytes();
+        let cache_config = self.cache_config();
+        let window_len = (cache_config.block_size / element_size.max(index_size)).max(1);
+        let window_bytes = window_len * (element_size + 2 * index_size);
+
+        let budget = config
+            .max_bytes
+            .unwrap_or(cache_config.max_bytes / 2)
+            .min(cache_config.max_bytes);
+        // The window being consumed counts against the budget too
+        let max_pending = (budget / window_bytes)
+            .saturating_sub(1)
+            .min(config.concurrency)
+            .max(1);
+
+        let mut stream = RowStream {
+            matrix: Arc::clone(self),
+            next_position: start,
+            end_position: end,
+            window_len,
+            max_pending,
+            pending: VecDeque::new(),
+            window: Window::default(),
+            cursor: 0,
+            row: None,
+        };
+        stream.schedule();
+        Ok(stream)
+    }
+}
+
+impl<R: RangeReader + 'static> RowStream<R> {
+    /// Start downloading windows until the concurrency or byte budget is reached
+    fn schedule(&mut self) {
+        while self.pending.len() < self.max_pending && self.next_position < self.end_position {
+            let end = (self.next_position + self.window_len).min(self.end_position);
+            let span = self.next_position..end;
+            self.next_position = end;
+
+            let matrix = Arc::clone(&self.matrix);
+            self.pending
+                .push_back(tokio::spawn(async move { matrix.fetch_span(span).await }));
+        }
+    }
+
+    /// Stop downloading after a failure, so the stream ends
+    fn cancel(&mut self) {
+        for task in self.pending.drain(..) {
+            task.abort();
+        }
+        self.next_position = self.end_position;
+        self.window = Window::default();
+        self.cursor = 0;
+        self.row = None;
+    }
+
+    /// Get the next non-empty row, or `None` at the end of the range
+    pub async fn next(&mut self) -> Option<Result<(usize, Vec<(usize, ArrayValue)>)>> {
+        loop {
+            let (values, rows, cols) = &self.window;
+            if self.cursor >= rows.len() {
+                let task = match self.pending.pop_front() {
+                    Some(task) => task,
+                    None => return self.row.take().map(Ok),
+                };
+                match task.await {
+                    Ok(Ok(window)) => {
+                        self.window = window;
+                        self.cursor = 0;
+                        self.schedule();
+                        continue;
+                    }
+                    Ok(Err(error)) => {
+                        self.cancel();
+                        return Some(Err(error));
+                    }
+                    Err(_) => {
+                        self.cancel();
+                        return Some(Err(Error::InvalidState("Prefetch task failed")));
+                    }
+                }
+            }
+
+            let i = self.cursor;
+            let row = rows[i] as usize;
+            if self
+                .row
+                .as_ref()
+                .is_some_and(|(current, _)| *current != row)
+            {
+                return self.row.take().map(Ok);
+            }
+            self.cursor += 1;
+
+            let col = cols[i] as usize;
+            if row >= self.matrix.nrows() || col >= self.matrix.ncols() {
+                continue;
+            }
+            let value = match self
+                .matrix
+                .extract_value_at_index(values, i, self.matrix.data_type())
+            {
+                Ok(value) => value,
+                Err(error) => {
+                    self.cancel();
+                    return Some(Err(error));
+                }
+            };
+            self.row
+                .get_or_insert_with(|| (row, Vec::new()))
+                .1
+                .push((col, value));
+        }
+    }
+}
+
+impl<R: RangeReader + 'static> Drop for RowStream<R> {
+    fn drop(&mut self) {
+        for task in &self.pending {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunked_backend::ChunkConfig;
+    use crate::http_backend::{CacheConfig, MemoryReader, ReadFuture};
+    use crate::mmap_backend::BspcFile;
+    use crate::test_support::TempDir;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Elements of a 60 x 8 matrix whose rows 20..35 are empty
+    fn elements() -> Vec<(usize, usize, f64)> {
+        (0..60)
+            .filter(|row| !(20..35).contains(row))
+            .flat_map(|row| [(row, row % 4, row as f64), (row, 7, -(row as f64))])
+            .collect()
+    }
+
+    async fn matrix_bytes() -> Vec<u8> {
+        let dir = TempDir::new();
+        let path = dir.file("matrix.bspc");
+        BspcFile::write_sparse_matrix(60, 8, &elements(), ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        std::fs::read(&path).unwrap()
+    }
+
+    /// 64 byte blocks hold 8 values, so a window is 8 elements
+    async fn open(cache_bytes: usize) -> Arc<RemoteMatrix<MemoryReader>> {
+        let reader = MemoryReader::new(matrix_bytes().await);
+        let config = CacheConfig::with_max_bytes(cache_bytes).with_block_size(64);
+        Arc::new(RemoteMatrix::open(reader, config).await.unwrap())
+    }
+
+    async fn collect<R: RangeReader>(mut stream: RowStream<R>) -> Vec<(usize, Vec<(usize, f64)>)> {
+        let mut rows = Vec::new();
+        while let Some(row) = stream.next().await {
+            let (row, elements) = row.unwrap();
+            let elements = elements
+                .into_iter()
+                .map(|(col, value)| match value {
+                    ArrayValue::Float64(v) => (col, v),
+                    _ => panic!("expected f64 values"),
+                })
+                .collect();
+            rows.push((row, elements));
+        }
+        rows
+    }
+
+    fn expected(rows: Range<usize>) -> Vec<(usize, Vec<(usize, f64)>)> {
+        rows.filter(|row| !(20..35).contains(row))
+            .map(|row| (row, vec![(row % 4, row as f64), (7, -(row as f64))]))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stream_matches_row_queries() {
+        let matrix = open(1 << 20).await;
+
+        let stream = matrix
+            .stream_rows(0..60, PrefetchConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(stream.window_len, 8);
+        assert_eq!(collect(stream).await, expected(0..60));
+
+        // Rows split across windows and ranges starting in the empty rows
+        for rows in [3..4, 5..21, 22..40, 35..60, 59..60] {
+            let config = PrefetchConfig::default().with_concurrency(2);
+            let stream = matrix.stream_rows(rows.clone(), config).await.unwrap();
+            assert_eq!(collect(stream).await, expected(rows.clone()), "{rows:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_and_invalid_ranges() {
+        let matrix = open(1 << 20).await;
+        let config = PrefetchConfig::default();
+
+        let mut stream = matrix.stream_rows(20..35, config).await.unwrap();
+        assert!(stream.pending.is_empty());
+        assert!(stream.next().await.is_none());
+        let mut stream = matrix.stream_rows(60..60, config).await.unwrap();
+        assert!(stream.next().await.is_none());
+
+        assert!(matrix.stream_rows(0..61, config).await.is_err());
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 10..5;
+        assert!(matrix.stream_rows(reversed, config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pending_windows_respect_budget() {
+        // A window of 8 f64 values and two u32 index arrays is 128 bytes
+        let max_pending = |matrix: &Arc<RemoteMatrix<MemoryReader>>, config| {
+            let matrix = Arc::clone(matrix);
+            async move { matrix.stream_rows(0..60, config).await.unwrap().max_pending }
+        };
+
+        let matrix = open(1 << 20).await;
+        let config = PrefetchConfig::default();
+        assert_eq!(max_pending(&matrix, config).await, 4);
+        assert_eq!(max_pending(&matrix, config.with_concurrency(0)).await, 1);
+        assert_eq!(
+            max_pending(&matrix, config.with_max_bytes(3 * 128)).await,
+            2
+        );
+        assert_eq!(max_pending(&matrix, config.with_max_bytes(0)).await, 1);
+
+        // Half the cache by default, and never more than all of it
+        let matrix = open(8 * 128).await;
+        assert_eq!(max_pending(&matrix, config).await, 3);
+        let config = config.with_concurrency(100).with_max_bytes(1 << 20);
+        assert_eq!(max_pending(&matrix, config).await, 7);
+        let stream = matrix.stream_rows(0..60, config).await.unwrap();
+        assert_eq!(stream.pending.len(), 7);
+        assert_eq!(collect(stream).await, expected(0..60));
+    }
+
+    /// In-memory reader whose reads fail once `failing` is set
+    struct FailingReader {
+        inner: MemoryReader,
+        failing: AtomicBool,
+    }
+
+    impl RangeReader for FailingReader {
+        fn read_range(&self, range: Range<usize>) -> ReadFuture<'_, Vec<u8>> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Box::pin(async { Err(Error::IoError("Read failed")) });
+            }
+            self.inner.read_range(range)
+        }
+
+        fn size(&self) -> ReadFuture<'_, u64> {
+            self.inner.size()
+        }
+
+        fn etag(&self) -> ReadFuture<'_, Option<String>> {
+            self.inner.etag()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_after_error() {
+        let reader = FailingReader {
+            inner: MemoryReader::new(matrix_bytes().await),
+            failing: AtomicBool::new(false),
+        };
+        let config = CacheConfig::default().with_block_size(64);
+        let matrix = Arc::new(RemoteMatrix::open(reader, config).await.unwrap());
+
+        let mut stream = matrix
+            .stream_rows(0..60, PrefetchConfig::default())
+            .await
+            .unwrap();
+        // The windows have not been downloaded yet
+        matrix.reader().failing.store(true, Ordering::Relaxed);
+
+        let mut failed = false;
+        while let Some(row) = stream.next().await {
+            if row.is_err() {
+                failed = true;
+                break;
+            }
+        }
+        assert!(failed);
+        assert!(stream.next().await.is_none());
+        assert!(stream.pending.is_empty());
+    }
+}
@@ -0,0 +1,304 @@
+//! Planning and parsing of HTTP range requests
+//!
+//! Reads that touch many small, scattered spans are merged into fewer
+//! requests: spans separated by at most `max_gap` bytes are fetched as one
+//! range, and the remaining ranges can be sent together as a single
+//! `Range: bytes=a-b,c-d` request whose `multipart/byteranges` response is
+//! split back into its parts.
+
+use binsparse_rs::{Error, Result};
+use std::ops::Range;
+
+/// Settings for turning byte spans into HTTP range requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangePlan {
+    /// Largest gap in bytes between two spans that are fetched as one range
+    pub max_gap: usize,
+    /// Send several ranges in one multi-range request
+    pub multi_range: bool,
+    /// Most ranges sent in one multi-range request
+    pub max_ranges_per_request: usize,
+}
+
+impl RangePlan {
+    /// Set the largest gap between spans that are merged
+    pub fn with_max_gap(mut self, max_gap: usize) -> Self {
+        self.max_gap = max_gap;
+        self
+    }
+
+    /// Enable or disable multi-range requests
+    pub fn with_multi_range(mut self, multi_range: bool) -> Self {
+        self.multi_range = multi_range;
+        self
+    }
+
+    /// Set the most ranges sent in one multi-range request
+    pub fn with_max_ranges_per_request(mut self, max_ranges: usize) -> Self {
+        self.max_ranges_per_request = max_ranges.max(1);
+        self
+    }
+}
+
+impl Default for RangePlan {
+    fn default() -> Self {
+        Self {
+            max_gap: 64 * 1024, // one default cache block
+            // Many object stores and CDNs answer multi-range requests with the full body
+            multi_range: false,
+            max_ranges_per_request: 32,
+        }
+    }
+}
+
+/// Merge ranges that overlap or are separated by at most `max_gap` bytes
+///
+/// The result is sorted and covers every input range.
+pub fn coalesce_ranges(ranges: &[Range<usize>], max_gap: usize) -> Vec<Range<usize>> {
+    let mut sorted: Vec<Range<usize>> = ranges.iter().filter(|r| !r.is_empty()).cloned().collect();
+    sorted.sort_unstable_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(sorted.len());
+    for range in sorted {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(max_gap) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Format a `Range` header value covering `ranges`
+pub(crate) fn range_header(ranges: &[Range<usize>]) -> String {
+    let specs: Vec<String> = ranges
+        .iter()
+        .map(|range| format!("{}-{}", range.start, range.end - 1))
+        .collect();
+    format!("bytes={}", specs.join(","))
+}
+
+/// Parse a `Content-Range` value such as `bytes 0-99/1234`
+pub(crate) fn parse_content_range(value: &str) -> Option<Range<usize>> {
+    let spec = value.trim().strip_prefix("bytes")?.trim_start();
+    let (span, _total) = spec.split_once('/')?;
+    let (start, end) = span.split_once('-')?;
+    let start = start.trim().parse::<usize>().ok()?;
+    let end = end.trim().parse::<usize>().ok()?;
+    (start <= end).then_some(start..end.checked_add(1)?)
+}
+
+/// Get the boundary of a `multipart/byteranges` content type
+pub(crate) fn multipart_boundary(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';');
+    let mime = params.next()?.trim();
+    if !mime.eq_ignore_ascii_case("multipart/byteranges") {
+        return None;
+    }
+
+    params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Find `needle` in `haystack` starting at `from`
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| from + i)
+}
+
+/// Split a `multipart/byteranges` body into its ranges and bytes
+///
+/// Part bodies are sized by their `Content-Range` header, so bodies that
+/// happen to contain the boundary are still read correctly.
+pub(crate) fn parse_multipart_byteranges(
+    body: &[u8],
+    boundary: &str,
+) -> Result<Vec<(Range<usize>, Vec<u8>)>> {
+    const INVALID: Error = Error::InvalidState("Invalid multipart/byteranges response");
+
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut position = find(body, delimiter.as_bytes(), 0).ok_or(INVALID)?;
+
+    loop {
+        position += delimiter.len();
+        if body.get(position..position + 2) == Some(&b"--"[..]) {
+            return Ok(parts);
+        }
+
+        let headers_end = find(body, b"\r\n\r\n", position).ok_or(INVALID)?;
+        let headers = std::str::from_utf8(&body[position..headers_end]).map_err(|_| INVALID)?;
+        let range = headers
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-range"))
+            .and_then(|(_, value)| parse_content_range(value))
+            .ok_or(INVALID)?;
+
+        let start = headers_end + 4;
+        let end = start.checked_add(range.len()).ok_or(INVALID)?;
+        let data = body.get(start..end).ok_or(INVALID)?;
+        parts.push((range, data.to_vec()));
+
+        position = find(body, delimiter.as_bytes(), start + data.len()).ok_or(INVALID)?;
+    }
+}
+
+/// Cut each of `ranges` out of the fetched `pieces`
+pub(crate) fn extract_ranges(
+    ranges: &[Range<usize>],
+    pieces: &[(Range<usize>, Vec<u8>)],
+) -> Result<Vec<Vec<u8>>> {
+    ranges
+        .iter()
+        .map(|range| {
+            if range.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let (piece, data) = pieces
+                .iter()
+                .find(|(piece, _)| piece.start <= range.start && range.end <= piece.end)
+                .ok_or(Error::IoError(
+                    "Server response is missing a requested range",
+                ))?;
+            let start = range.start - piece.start;
+            data.get(start..start + range.len())
+                .map(<[u8]>::to_vec)
+                .ok_or(Error::IoError("Short read from server"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a `multipart/byteranges` body from ranges of `file`
+    fn multipart(file: &[u8], ranges: &[Range<usize>], boundary: &str) -> Vec<u8> {
+        let mut body = b"preamble\r\n".to_vec();
+        for range in ranges {
+            body.extend_from_slice(
+                format!(
+                    "--{boundary}\r\nContent-Type: application/octet-stream\r\n\
+                     content-range: bytes {}-{}/{}\r\n\r\n",
+                    range.start,
+                    range.end - 1,
+                    file.len()
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(&file[range.clone()]);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+        body
+    }
+
+    #[test]
+    fn test_coalesce_ranges() {
+        assert!(coalesce_ranges(&[], 10).is_empty());
+        assert_eq!(
+            coalesce_ranges(&[5..5, 3..4], 0),
+            [Range { start: 3, end: 4 }]
+        );
+        assert_eq!(
+            coalesce_ranges(&[40..50, 0..10, 12..20, 5..8, 100..101], 2),
+            [0..20, 40..50, 100..101]
+        );
+        // Adjacent ranges merge even without a gap allowance
+        assert_eq!(
+            coalesce_ranges(&[0..10, 10..20, 21..22], 0),
+            [0..20, 21..22]
+        );
+        assert_eq!(
+            coalesce_ranges(&[0..10, 2..3], 0),
+            [Range { start: 0, end: 10 }]
+        );
+        assert_eq!(
+            coalesce_ranges(&[usize::MAX - 1..usize::MAX, 0..1], usize::MAX),
+            [Range {
+                start: 0,
+                end: usize::MAX
+            }]
+        );
+    }
+
+    #[test]
+    fn test_range_headers() {
+        assert_eq!(range_header(&[0..10, 20..21]), "bytes=0-9,20-20");
+        assert_eq!(parse_content_range("bytes 0-99/1234"), Some(0..100));
+        assert_eq!(parse_content_range(" bytes 5-5/*"), Some(5..6));
+        assert_eq!(parse_content_range("bytes 9-5/10"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+        let max = format!("bytes 0-{}/*", usize::MAX);
+        assert_eq!(parse_content_range(&max), None);
+
+        assert_eq!(
+            multipart_boundary("multipart/byteranges; boundary=abc"),
+            Some("abc")
+        );
+        assert_eq!(
+            multipart_boundary("Multipart/ByteRanges;charset=x; Boundary=\"q r\""),
+            Some("q r")
+        );
+        assert_eq!(multipart_boundary("text/plain; boundary=abc"), None);
+    }
+
+    #[test]
+    fn test_parse_multipart_byteranges() {
+        // File bytes that contain the boundary are sized by Content-Range
+        let mut file: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        file[50..56].copy_from_slice(b"--sep\n");
+        let ranges = [0..10, 48..60, 199..200];
+        let body = multipart(&file, &ranges, "sep");
+
+        let parts = parse_multipart_byteranges(&body, "sep").unwrap();
+        assert_eq!(parts.len(), ranges.len());
+        for ((range, data), expected) in parts.iter().zip(&ranges) {
+            assert_eq!(range, expected);
+            assert_eq!(data, &file[expected.clone()]);
+        }
+
+        let wanted = [2..4, 50..52, 49..49, 199..200];
+        let extracted = extract_ranges(&wanted, &parts).unwrap();
+        for (range, data) in wanted.iter().zip(&extracted) {
+            assert_eq!(data, &file[range.clone()]);
+        }
+        assert!(extract_ranges(&[Range { start: 9, end: 11 }], &parts).is_err());
+    }
+
+    #[test]
+    fn test_parse_multipart_rejects_malformed() {
+        let file = [7u8; 32];
+        let body = multipart(&file, &[Range { start: 0, end: 16 }], "b");
+
+        assert!(parse_multipart_byteranges(&body, "other").is_err());
+        // Missing closing delimiter
+        let truncated = &body[..body.len() - 8];
+        assert!(parse_multipart_byteranges(truncated, "b").is_err());
+        // Part shorter than its Content-Range
+        let short = multipart(&file, &[Range { start: 0, end: 16 }], "b")
+            .iter()
+            .copied()
+            .filter(|&b| b != 7)
+            .collect::<Vec<u8>>();
+        assert!(parse_multipart_byteranges(&short, "b").is_err());
+        // Content-Range larger than the address space
+        let huge = format!(
+            "--b\r\nContent-Range: bytes 0-{}/*\r\n\r\nxx\r\n--b--",
+            usize::MAX - 1
+        );
+        assert!(parse_multipart_byteranges(huge.as_bytes(), "b").is_err());
+        let no_range = b"--b\r\nContent-Type: x\r\n\r\nxx\r\n--b--";
+        assert!(parse_multipart_byteranges(no_range, "b").is_err());
+    }
+}
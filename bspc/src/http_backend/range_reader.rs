@@ -0,0 +1,251 @@
+//! Sources of byte ranges for remote matrices
+//!
+//! [`crate::RemoteMatrix`] reads its file through the [`RangeReader`] trait,
+//! so the same lookup and caching code serves HTTP servers
+//! ([`crate::HttpReader`]), local files ([`FileReader`]) and in-memory
+//! buffers ([`MemoryReader`]). Other object stores plug in by implementing
+//! the trait.
+
+use binsparse_rs::{Error, Result};
+use std::future::Future;
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Future returned by [`RangeReader`] methods
+pub type ReadFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Outcome of [`RangeReader::read_range_if_modified`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConditionalRead {
+    /// The file still has the version tag that was given
+    NotModified,
+    /// The requested bytes and the version tag they belong to
+    Modified(Vec<u8>, Option<String>),
+}
+
+/// Random access to the bytes of one file
+///
+/// Futures are boxed so readers can be used as `Box<dyn RangeReader>`.
+pub trait RangeReader: Send + Sync {
+    /// Read exactly the bytes in `range`
+    fn read_range(&self, range: Range<usize>) -> ReadFuture<'_, Vec<u8>>;
+
+    /// Read several ranges, returned in the order given
+    ///
+    /// The default reads them one after another; readers that can batch
+    /// requests override it.
+    fn read_ranges(&self, ranges: Vec<Range<usize>>) -> ReadFuture<'_, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let mut data = Vec::with_capacity(ranges.len());
+            for range in ranges {
+                data.push(self.read_range(range).await?);
+            }
+            Ok(data)
+        })
+    }
+
+    /// Get the size of the file in bytes
+    fn size(&self) -> ReadFuture<'_, u64>;
+
+    /// Get a tag that changes whenever the file contents change
+    ///
+    /// Returns `None` when the source has no version identifier; blocks are
+    /// then never cached on disk, since they could not be told apart from
+    /// those of an older version.
+    fn etag(&self) -> ReadFuture<'_, Option<String>>;
+
+    /// Read `range` unless the file still has version `tag`
+    ///
+    /// Used to revalidate cached data. The default compares `tag` with
+    /// [`RangeReader::etag`] before reading; HTTP readers send a conditional
+    /// request instead. Without a `tag` the range is always read.
+    fn read_range_if_modified(
+        &self,
+        range: Range<usize>,
+        tag: Option<&str>,
+    ) -> ReadFuture<'_, ConditionalRead> {
+        let tag = tag.map(str::to_owned);
+        Box::pin(async move {
+            let current = self.etag().await?;
+            if current.is_some() && current == tag {
+                return Ok(ConditionalRead::NotModified);
+            }
+            Ok(ConditionalRead::Modified(
+                self.read_range(range).await?,
+                current,
+            ))
+        })
+    }
+
+    /// Get the key identifying the file in the disk cache
+    ///
+    /// Readers without a key are not cached on disk.
+    fn cache_key(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl<R: RangeReader + ?Sized> RangeReader for Box<R> {
+    fn read_range(&self, range: Range<usize>) -> ReadFuture<'_, Vec<u8>> {
+        (**self).read_range(range)
+    }
+
+    fn read_ranges(&self, ranges: Vec<Range<usize>>) -> ReadFuture<'_, Vec<Vec<u8>>> {
+        (**self).read_ranges(ranges)
+    }
+
+    fn size(&self) -> ReadFuture<'_, u64> {
+        (**self).size()
+    }
+
+    fn etag(&self) -> ReadFuture<'_, Option<String>> {
+        (**self).etag()
+    }
+
+    fn read_range_if_modified(
+        &self,
+        range: Range<usize>,
+        tag: Option<&str>,
+    ) -> ReadFuture<'_, ConditionalRead> {
+        (**self).read_range_if_modified(range, tag)
+    }
+
+    fn cache_key(&self) -> Option<&str> {
+        (**self).cache_key()
+    }
+}
+
+/// Version tag of a local file built from its modification time and length
+///
+/// Uses the quoted `mtime-length` form common to static file servers.
+pub(crate) fn file_etag(metadata: &std::fs::Metadata) -> Option<String> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "\"{:x}-{:x}\"",
+        modified.as_nanos(),
+        metadata.len()
+    ))
+}
+
+/// Reader for a file on the local filesystem
+pub struct FileReader {
+    path: PathBuf,
+    file: tokio::sync::Mutex<tokio::fs::File>,
+}
+
+impl FileReader {
+    /// Open the file at `path`
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = tokio::fs::File::open(&path)
+            .await
+            .map_err(|_| Error::IoError("Failed to open file"))?;
+
+        Ok(Self {
+            path,
+            file: tokio::sync::Mutex::new(file),
+        })
+    }
+
+    /// Get the path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn metadata(&self) -> Result<std::fs::Metadata> {
+        tokio::fs::metadata(&self.path)
+            .await
+            .map_err(|_| Error::IoError("Failed to read file metadata"))
+    }
+}
+
+/// Read `range` from `file`, failing on reads past the end
+async fn read_at(file: &mut tokio::fs::File, range: Range<usize>) -> Result<Vec<u8>> {
+    let mut data = vec![0u8; range.len()];
+    if data.is_empty() {
+        return Ok(data);
+    }
+
+    file.seek(SeekFrom::Start(range.start as u64))
+        .await
+        .map_err(|_| Error::IoError("Failed to seek in file"))?;
+    file.read_exact(&mut data)
+        .await
+        .map_err(|error| match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => Error::IoError("Read past the end of the file"),
+            _ => Error::IoError("Failed to read file"),
+        })?;
+    Ok(data)
+}
+
+impl RangeReader for FileReader {
+    fn read_range(&self, range: Range<usize>) -> ReadFuture<'_, Vec<u8>> {
+        Box::pin(async move { read_at(&mut *self.file.lock().await, range).await })
+    }
+
+    /// Reads every range under one lock of the file handle
+    fn read_ranges(&self, ranges: Vec<Range<usize>>) -> ReadFuture<'_, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let mut file = self.file.lock().await;
+            let mut data = Vec::with_capacity(ranges.len());
+            for range in ranges {
+                data.push(read_at(&mut file, range).await?);
+            }
+            Ok(data)
+        })
+    }
+
+    fn size(&self) -> ReadFuture<'_, u64> {
+        Box::pin(async move { Ok(self.metadata().await?.len()) })
+    }
+
+    fn etag(&self) -> ReadFuture<'_, Option<String>> {
+        Box::pin(async move { Ok(file_etag(&self.metadata().await?)) })
+    }
+}
+
+/// Reader for bytes held in memory
+#[derive(Debug, Clone)]
+pub struct MemoryReader {
+    bytes: Arc<[u8]>,
+}
+
+impl MemoryReader {
+    /// Wrap `bytes`
+    pub fn new(bytes: impl Into<Arc<[u8]>>) -> Self {
+        Self {
+            bytes: bytes.into(),
+        }
+    }
+
+    /// Get the wrapped bytes
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl RangeReader for MemoryReader {
+    fn read_range(&self, range: Range<usize>) -> ReadFuture<'_, Vec<u8>> {
+        let data = self
+            .bytes
+            .get(range)
+            .map(<[u8]>::to_vec)
+            .ok_or(Error::IoError("Read past the end of the buffer"));
+        Box::pin(async move { data })
+    }
+
+    fn size(&self) -> ReadFuture<'_, u64> {
+        let size = self.bytes.len() as u64;
+        Box::pin(async move { Ok(size) })
+    }
+
+    /// Buffers have no version; they are never cached on disk
+    fn etag(&self) -> ReadFuture<'_, Option<String>> {
+        Box::pin(async { Ok(None) })
+    }
+}
@@ -0,0 +1,784 @@
+//! HTTP requests with timeouts, retries and response checks
+//!
+//! Every request goes through [`HttpTransport::send`], which retries
+//! connection failures, timeouts, `429` and `5xx` responses with exponential
+//! backoff. Range responses are checked against what was asked for: a `200`
+//! means the server ignored the `Range` header, and bodies shorter than the
+//! requested span are reported as short reads rather than passed on. A `401`
+//! or `403` first refreshes the credentials (see `auth`) and is retried once.
+//! [`HttpReader`] exposes the transport as a [`RangeReader`].
+
+use super::auth::Auth;
+use super::range_plan::{self, RangePlan};
+use super::range_reader::{ConditionalRead, RangeReader, ReadFuture};
+use binsparse_rs::{Error, Result};
+use reqwest::header::{
+    HeaderMap, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE,
+    IF_NONE_MATCH, LAST_MODIFIED, RANGE,
+};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Timeout and retry settings for remote matrices
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpConfig {
+    /// Time allowed to establish a connection
+    pub connect_timeout: Duration,
+    /// Time allowed for a whole request, including reading the body
+    ///
+    /// Applied per request, so it also holds for a custom `reqwest::Client`;
+    /// the connect timeout only applies to the default client.
+    pub request_timeout: Duration,
+    /// Number of retries after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled for each further retry
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between retries
+    pub max_backoff: Duration,
+}
+
+impl HttpConfig {
+    /// Set the time allowed for a whole request
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Set the time allowed to establish a connection
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Set the number of retries after the first attempt
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the initial and maximum delay between retries
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Delay before retry number `attempt`, counting from zero
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1u32 << attempt.min(16))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(60),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Failure of a remote request
+///
+/// Public methods report these as [`binsparse_rs::Error`]; the most recent
+/// one is kept with its status code and available from
+/// [`crate::HttpMatrix::last_error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpError {
+    /// The server answered with an unexpected status code
+    Status(u16),
+    /// The request did not finish within the configured timeout
+    Timeout,
+    /// The connection could not be established or was dropped
+    Connection,
+    /// The server ignored the `Range` header and sent the whole file
+    RangeNotSupported,
+    /// The response held fewer bytes than requested
+    ShortRead {
+        /// Bytes requested
+        expected: usize,
+        /// Bytes received
+        actual: usize,
+    },
+    /// The response could not be understood
+    InvalidResponse(&'static str),
+}
+
+impl HttpError {
+    /// Get the HTTP status code, if the server sent one
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            HttpError::Status(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Check whether repeating the request may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            HttpError::Status(code) => *code == 429 || (500..600).contains(code),
+            HttpError::Timeout | HttpError::Connection => true,
+            _ => false,
+        }
+    }
+
+    fn from_reqwest(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            HttpError::Timeout
+        } else if let Some(status) = error.status() {
+            HttpError::Status(status.as_u16())
+        } else {
+            HttpError::Connection
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Status(code) => write!(f, "HTTP status {code}"),
+            HttpError::Timeout => write!(f, "HTTP request timed out"),
+            HttpError::Connection => write!(f, "HTTP connection failed"),
+            HttpError::RangeNotSupported => write!(f, "Server ignored the Range header"),
+            HttpError::ShortRead { expected, actual } => {
+                write!(f, "Short read: expected {expected} bytes, got {actual}")
+            }
+            HttpError::InvalidResponse(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<HttpError> for Error {
+    fn from(error: HttpError) -> Self {
+        match error {
+            HttpError::Status(code) => Error::IoError(status_message(code)),
+            HttpError::Timeout => Error::IoError("HTTP request timed out"),
+            HttpError::Connection => Error::IoError("HTTP connection failed"),
+            HttpError::RangeNotSupported => {
+                Error::IoError("Server ignored the Range header and sent the whole file")
+            }
+            HttpError::ShortRead { .. } => Error::IoError("Short read from server"),
+            HttpError::InvalidResponse(message) => Error::InvalidState(message),
+        }
+    }
+}
+
+/// Static description of a status code for [`binsparse_rs::Error`]
+fn status_message(code: u16) -> &'static str {
+    match code {
+        400 => "HTTP 400 Bad Request",
+        401 => "HTTP 401 Unauthorized",
+        403 => "HTTP 403 Forbidden",
+        404 => "HTTP 404 Not Found",
+        408 => "HTTP 408 Request Timeout",
+        416 => "HTTP 416 Range Not Satisfiable",
+        429 => "HTTP 429 Too Many Requests",
+        500 => "HTTP 500 Internal Server Error",
+        502 => "HTTP 502 Bad Gateway",
+        503 => "HTTP 503 Service Unavailable",
+        504 => "HTTP 504 Gateway Timeout",
+        _ if (400..500).contains(&code) => "HTTP client error",
+        _ if (500..600).contains(&code) => "HTTP server error",
+        _ => "Unexpected HTTP status",
+    }
+}
+
+/// Status, headers and body of a completed request
+struct Fetched {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl Fetched {
+    fn header(&self, name: reqwest::header::HeaderName) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+}
+
+/// Body of a multi-range response
+enum MultiRangeBody {
+    /// The requested ranges as `(range, data)` pieces, possibly merged
+    Ranges(Vec<(Range<usize>, Vec<u8>)>),
+    /// The whole file, from a server that ignored the `Range` header
+    Full(Vec<u8>),
+}
+
+/// Version tag of a response: its `ETag`, falling back to `Last-Modified`
+fn version_tag(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(ETAG)
+        .or_else(|| headers.get(LAST_MODIFIED))
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Add the precondition matching a tag from [`version_tag`]
+///
+/// Entity tags are quoted, so anything else is a `Last-Modified` date.
+fn if_modified(request: RequestBuilder, tag: &str) -> RequestBuilder {
+    if tag.starts_with('"') || tag.starts_with("W/") {
+        request.header(IF_NONE_MATCH, tag)
+    } else {
+        request.header(IF_MODIFIED_SINCE, tag)
+    }
+}
+
+/// Request sender for one remote file
+pub(crate) struct HttpTransport {
+    client: Client,
+    auth: Auth,
+    config: HttpConfig,
+    /// Cleared once the server answers a multi-range request with the full body
+    multi_range_supported: AtomicBool,
+    last_error: Mutex<Option<HttpError>>,
+}
+
+impl HttpTransport {
+    /// Create a transport, building a client with the configured connect
+    /// timeout unless one is given
+    pub(crate) fn new(client: Option<Client>, auth: Auth, config: HttpConfig) -> Result<Self> {
+        let client = match client {
+            Some(client) => client,
+            None => Client::builder()
+                .connect_timeout(config.connect_timeout)
+                .build()
+                .map_err(|_| Error::InvalidState("Failed to build HTTP client"))?,
+        };
+
+        Ok(Self {
+            client,
+            auth,
+            config,
+            multi_range_supported: AtomicBool::new(true),
+            last_error: Mutex::new(None),
+        })
+    }
+
+    /// Get the most recent request failure
+    pub(crate) fn last_error(&self) -> Option<HttpError> {
+        self.last_error.lock().unwrap().clone()
+    }
+
+    /// Remember a failure and convert it for the public API
+    fn record<T>(&self, result: std::result::Result<T, HttpError>) -> Result<T> {
+        result.map_err(|error| {
+            *self.last_error.lock().unwrap() = Some(error.clone());
+            error.into()
+        })
+    }
+
+    /// Fetch fresh credentials, if the matrix has credential providers
+    pub(crate) async fn refresh_credentials(&self) -> Result<()> {
+        if self.auth.can_refresh() {
+            self.auth.refresh(self.auth.generation()).await?;
+        }
+        Ok(())
+    }
+
+    /// Send a request, retrying transient failures with exponential backoff
+    ///
+    /// `decorate` adds request-specific headers on every attempt. A `401` or
+    /// `403` refreshes the credentials and is retried once; any other status
+    /// that is not retryable is returned to the caller.
+    async fn send(
+        &self,
+        method: Method,
+        decorate: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> std::result::Result<Fetched, HttpError> {
+        let mut attempt = 0;
+        let mut refreshed = false;
+        loop {
+            let (request, generation) = self.auth.request(&self.client, method.clone());
+            let request = decorate(request.timeout(self.config.request_timeout));
+            let result = match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    match response.bytes().await {
+                        Ok(_) if status.is_server_error() || status.as_u16() == 429 => {
+                            Err(HttpError::Status(status.as_u16()))
+                        }
+                        Ok(body) => Ok(Fetched {
+                            status,
+                            headers,
+                            body: body.to_vec(),
+                        }),
+                        Err(error) => Err(HttpError::from_reqwest(&error)),
+                    }
+                }
+                Err(error) => Err(HttpError::from_reqwest(&error)),
+            };
+
+            match result {
+                Ok(fetched)
+                    if matches!(
+                        fetched.status,
+                        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+                    ) && !refreshed
+                        && self.auth.can_refresh() =>
+                {
+                    // Report the original status when no fresh credentials can be had
+                    if self.auth.refresh(generation).await.is_err() {
+                        return Ok(fetched);
+                    }
+                    refreshed = true;
+                }
+                Err(error) if error.is_retryable() && attempt < self.config.max_retries => {
+                    tokio::time::sleep(self.config.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send a HEAD request for the file
+    pub(crate) async fn head(&self) -> Result<HeaderMap> {
+        let result = self
+            .send(Method::HEAD, |request| request)
+            .await
+            .and_then(|fetched| {
+                if fetched.status.is_success() {
+                    Ok(fetched.headers)
+                } else {
+                    Err(HttpError::Status(fetched.status.as_u16()))
+                }
+            });
+        self.record(result)
+    }
+
+    /// Check a single-range response and return exactly the requested bytes
+    fn range_body(
+        fetched: Fetched,
+        range: &Range<usize>,
+    ) -> std::result::Result<Vec<u8>, HttpError> {
+        match fetched.status {
+            StatusCode::PARTIAL_CONTENT => {
+                let served = fetched
+                    .header(CONTENT_RANGE)
+                    .and_then(range_plan::parse_content_range);
+                if served.is_some_and(|served| served.start != range.start) {
+                    return Err(HttpError::InvalidResponse(
+                        "Content-Range does not match the requested range",
+                    ));
+                }
+            }
+            // A full body starting at zero is still usable for the file's first bytes
+            StatusCode::OK if range.start == 0 => {}
+            StatusCode::OK => return Err(HttpError::RangeNotSupported),
+            status => return Err(HttpError::Status(status.as_u16())),
+        }
+
+        let mut body = fetched.body;
+        if body.len() < range.len() {
+            return Err(HttpError::ShortRead {
+                expected: range.len(),
+                actual: body.len(),
+            });
+        }
+        body.truncate(range.len());
+        Ok(body)
+    }
+
+    /// Fetch a byte range from the remote file
+    pub(crate) async fn fetch_range(&self, range: Range<usize>) -> Result<Vec<u8>> {
+        let result = self
+            .send(Method::GET, |request| {
+                request.header(
+                    RANGE,
+                    range_plan::range_header(std::slice::from_ref(&range)),
+                )
+            })
+            .await
+            .and_then(|fetched| Self::range_body(fetched, &range));
+        self.record(result)
+    }
+
+    /// Fetch a byte range unless the file still has version `tag`
+    ///
+    /// Sends `If-None-Match` for entity tags and `If-Modified-Since` for
+    /// dates; a `304` means the cached copy is current.
+    pub(crate) async fn fetch_range_if_modified(
+        &self,
+        range: Range<usize>,
+        tag: Option<&str>,
+    ) -> Result<ConditionalRead> {
+        let result = self
+            .send(Method::GET, |request| {
+                let request = request.header(
+                    RANGE,
+                    range_plan::range_header(std::slice::from_ref(&range)),
+                );
+                match tag {
+                    Some(tag) => if_modified(request, tag),
+                    None => request,
+                }
+            })
+            .await
+            .and_then(|fetched| {
+                if fetched.status == StatusCode::NOT_MODIFIED {
+                    return Ok(ConditionalRead::NotModified);
+                }
+                let tag = version_tag(&fetched.headers).map(str::to_owned);
+                Ok(ConditionalRead::Modified(
+                    Self::range_body(fetched, &range)?,
+                    tag,
+                ))
+            });
+        self.record(result)
+    }
+
+    /// Fetch several byte ranges, merging them according to `plan`
+    pub(crate) async fn fetch_ranges(
+        &self,
+        ranges: Vec<Range<usize>>,
+        plan: &RangePlan,
+    ) -> Result<Vec<Vec<u8>>> {
+        let planned = range_plan::coalesce_ranges(&ranges, plan.max_gap);
+
+        let group_len = if plan.multi_range {
+            plan.max_ranges_per_request.max(1)
+        } else {
+            1
+        };
+
+        let mut pieces = Vec::with_capacity(planned.len());
+        for group in planned.chunks(group_len) {
+            // An earlier group may have found that the server ignores multi-range requests
+            if group.len() == 1 || !self.multi_range_supported.load(Ordering::Relaxed) {
+                for range in group {
+                    let data = self.fetch_range(range.clone()).await?;
+                    pieces.push((range.clone(), data));
+                }
+                continue;
+            }
+
+            match self.fetch_multi_range(group).await? {
+                MultiRangeBody::Ranges(parts) => pieces.extend(parts),
+                // The whole file holds every remaining range as well
+                MultiRangeBody::Full(body) => {
+                    pieces.push((0..body.len(), body));
+                    break;
+                }
+            }
+        }
+
+        range_plan::extract_ranges(&ranges, &pieces)
+    }
+
+    /// Fetch several ranges with one multi-range request
+    ///
+    /// Handles `multipart/byteranges` responses, servers that merge the
+    /// ranges into a single part, and servers that ignore the `Range`
+    /// header and send the whole file; the latter disables multi-range
+    /// requests for this file.
+    async fn fetch_multi_range(&self, ranges: &[Range<usize>]) -> Result<MultiRangeBody> {
+        let result = self
+            .send(Method::GET, |request| {
+                request.header(RANGE, range_plan::range_header(ranges))
+            })
+            .await;
+        let fetched = self.record(result)?;
+
+        match fetched.status {
+            StatusCode::OK => {
+                self.multi_range_supported.store(false, Ordering::Relaxed);
+                return Ok(MultiRangeBody::Full(fetched.body));
+            }
+            StatusCode::PARTIAL_CONTENT => {}
+            status => return self.record(Err(HttpError::Status(status.as_u16()))),
+        }
+
+        if let Some(boundary) = fetched
+            .header(CONTENT_TYPE)
+            .and_then(range_plan::multipart_boundary)
+        {
+            return range_plan::parse_multipart_byteranges(&fetched.body, boundary)
+                .map(MultiRangeBody::Ranges);
+        }
+
+        let range = fetched
+            .header(CONTENT_RANGE)
+            .and_then(range_plan::parse_content_range)
+            .ok_or(Error::InvalidState("Missing Content-Range header"))?;
+        if fetched.body.len() < range.len() {
+            return self.record(Err(HttpError::ShortRead {
+                expected: range.len(),
+                actual: fetched.body.len(),
+            }));
+        }
+        Ok(MultiRangeBody::Ranges(vec![(range, fetched.body)]))
+    }
+}
+
+/// Reader for a file served over HTTP
+///
+/// Built by [`crate::HttpMatrixBuilder`]. The response to the first `HEAD`
+/// request is kept, so the size and version tag describe the version that
+/// was opened.
+pub struct HttpReader {
+    transport: HttpTransport,
+    range_plan: RangePlan,
+    cache_key: String,
+    head: OnceCell<HeaderMap>,
+}
+
+impl HttpReader {
+    /// Create a reader; `cache_key` identifies the file in the disk cache
+    pub(crate) fn new(transport: HttpTransport, cache_key: String, range_plan: RangePlan) -> Self {
+        Self {
+            transport,
+            range_plan,
+            cache_key,
+            head: OnceCell::new(),
+        }
+    }
+
+    /// Get the range request settings
+    pub fn range_plan(&self) -> &RangePlan {
+        &self.range_plan
+    }
+
+    /// Set how scattered reads are turned into range requests
+    pub fn set_range_plan(&mut self, range_plan: RangePlan) {
+        self.range_plan = range_plan;
+    }
+
+    /// Get the most recent request failure, with its status code
+    pub fn last_error(&self) -> Option<HttpError> {
+        self.transport.last_error()
+    }
+
+    /// Get the headers of the file, sending a `HEAD` request on first use
+    async fn head(&self) -> Result<&HeaderMap> {
+        self.head.get_or_try_init(|| self.transport.head()).await
+    }
+
+    /// Get a response header as text
+    async fn head_value(&self, name: reqwest::header::HeaderName) -> Result<Option<&str>> {
+        Ok(self
+            .head()
+            .await?
+            .get(name)
+            .and_then(|value| value.to_str().ok()))
+    }
+
+    /// Check if the server supports range requests
+    pub async fn supports_range_requests(&self) -> Result<bool> {
+        Ok(self.head_value(ACCEPT_RANGES).await? == Some("bytes"))
+    }
+}
+
+impl RangeReader for HttpReader {
+    fn read_range(&self, range: Range<usize>) -> ReadFuture<'_, Vec<u8>> {
+        Box::pin(self.transport.fetch_range(range))
+    }
+
+    /// Merges the ranges into few requests according to the range plan
+    fn read_ranges(&self, ranges: Vec<Range<usize>>) -> ReadFuture<'_, Vec<Vec<u8>>> {
+        Box::pin(self.transport.fetch_ranges(ranges, &self.range_plan))
+    }
+
+    fn size(&self) -> ReadFuture<'_, u64> {
+        Box::pin(async move {
+            self.head_value(CONTENT_LENGTH)
+                .await?
+                .ok_or(Error::InvalidState("No content-length header"))?
+                .parse::<u64>()
+                .map_err(|_| Error::InvalidState("Invalid content-length value"))
+        })
+    }
+
+    /// Uses the `ETag` header, falling back to `Last-Modified`
+    fn etag(&self) -> ReadFuture<'_, Option<String>> {
+        Box::pin(async move { Ok(version_tag(self.head().await?).map(str::to_owned)) })
+    }
+
+    /// Sends one conditional range request; no `HEAD` request is needed
+    fn read_range_if_modified(
+        &self,
+        range: Range<usize>,
+        tag: Option<&str>,
+    ) -> ReadFuture<'_, ConditionalRead> {
+        let tag = tag.map(str::to_owned);
+        Box::pin(async move {
+            self.transport
+                .fetch_range_if_modified(range, tag.as_deref())
+                .await
+        })
+    }
+
+    fn cache_key(&self) -> Option<&str> {
+        Some(&self.cache_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockResponse, MockServer};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    const FILE: &[u8] = b"0123456789abcdefghij";
+
+    fn transport(server: &MockServer, config: HttpConfig) -> HttpTransport {
+        let auth = Auth::new(server.url(), HeaderMap::new(), None, None, None, None);
+        HttpTransport::new(None, auth, config).unwrap()
+    }
+
+    /// Retry quickly so the tests stay fast
+    fn config() -> HttpConfig {
+        HttpConfig::default().with_backoff(Duration::from_millis(1), Duration::from_millis(2))
+    }
+
+    #[test]
+    fn test_backoff_and_retryable() {
+        let config = HttpConfig::default()
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(350));
+        assert_eq!(config.backoff(0), Duration::from_millis(100));
+        assert_eq!(config.backoff(1), Duration::from_millis(200));
+        assert_eq!(config.backoff(2), Duration::from_millis(350));
+        assert_eq!(config.backoff(40), Duration::from_millis(350));
+
+        assert!(HttpError::Status(503).is_retryable());
+        assert!(HttpError::Status(429).is_retryable());
+        assert!(HttpError::Timeout.is_retryable());
+        assert!(!HttpError::Status(404).is_retryable());
+        assert!(!HttpError::RangeNotSupported.is_retryable());
+        assert_eq!(HttpError::Status(404).status(), Some(404));
+
+        assert_eq!(status_message(404), "HTTP 404 Not Found");
+        assert_eq!(status_message(418), "HTTP client error");
+        assert_eq!(status_message(599), "HTTP server error");
+        assert_eq!(status_message(302), "Unexpected HTTP status");
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let seen = calls.clone();
+        let server = MockServer::start(move |request| {
+            if seen.fetch_add(1, Ordering::Relaxed) < 2 {
+                MockResponse::new(503, "busy")
+            } else {
+                request.serve_range(FILE)
+            }
+        })
+        .await;
+
+        let transport = transport(&server, config());
+        assert_eq!(transport.fetch_range(2..6).await.unwrap(), b"2345");
+        assert_eq!(server.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let server = MockServer::start(|_| MockResponse::new(503, "busy")).await;
+        let transport = transport(&server, config().with_max_retries(2));
+
+        assert!(transport.fetch_range(0..4).await.is_err());
+        assert_eq!(server.requests(), 3);
+        assert_eq!(transport.last_error(), Some(HttpError::Status(503)));
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let server = MockServer::start(|_| MockResponse::new(404, "missing")).await;
+        let transport = transport(&server, config());
+
+        assert!(transport.fetch_range(0..4).await.is_err());
+        assert_eq!(server.requests(), 1);
+        assert_eq!(transport.last_error(), Some(HttpError::Status(404)));
+    }
+
+    #[tokio::test]
+    async fn test_checks_range_responses() {
+        let server = MockServer::start(|request| match request.header("range") {
+            Some("bytes=0-3") | Some("bytes=10-13") => MockResponse::new(200, FILE),
+            Some("bytes=4-7") => {
+                MockResponse::new(206, "45").header("Content-Range", "bytes 4-7/20")
+            }
+            _ => MockResponse::new(206, "xxxx").header("Content-Range", "bytes 0-3/20"),
+        })
+        .await;
+        let transport = transport(&server, config());
+
+        // A full body still serves the first bytes of the file
+        assert_eq!(transport.fetch_range(0..4).await.unwrap(), b"0123");
+
+        assert!(transport.fetch_range(10..14).await.is_err());
+        assert_eq!(transport.last_error(), Some(HttpError::RangeNotSupported));
+
+        assert!(transport.fetch_range(4..8).await.is_err());
+        assert_eq!(
+            transport.last_error(),
+            Some(HttpError::ShortRead {
+                expected: 4,
+                actual: 2
+            })
+        );
+
+        assert!(transport.fetch_range(8..12).await.is_err());
+        assert!(matches!(
+            transport.last_error(),
+            Some(HttpError::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_full_body_serves_every_group() {
+        let server = MockServer::start(|request| match request.header("range") {
+            Some(spec) if spec.contains(',') => MockResponse::new(200, FILE),
+            _ => request.serve_range(FILE),
+        })
+        .await;
+        let transport = transport(&server, config());
+        let plan = RangePlan::default()
+            .with_max_gap(0)
+            .with_multi_range(true)
+            .with_max_ranges_per_request(2);
+        let ranges = vec![0..2, 5..7, 10..12, 15..17];
+        let expected: Vec<&[u8]> = vec![b"01", b"56", b"ab", b"fg"];
+
+        // The whole file from the first group also covers the second
+        let pieces = transport.fetch_ranges(ranges.clone(), &plan).await.unwrap();
+        assert_eq!(pieces, expected);
+        assert_eq!(server.requests(), 1);
+
+        // Later reads fall back to single-range requests
+        let pieces = transport.fetch_ranges(ranges, &plan).await.unwrap();
+        assert_eq!(pieces, expected);
+        assert_eq!(server.requests(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let server = MockServer::start(|request| {
+            request
+                .serve_range(FILE)
+                .delayed(Duration::from_millis(500))
+        })
+        .await;
+        let config = config()
+            .with_timeout(Duration::from_millis(50))
+            .with_max_retries(1);
+        let transport = transport(&server, config);
+
+        assert!(transport.fetch_range(0..4).await.is_err());
+        assert_eq!(transport.last_error(), Some(HttpError::Timeout));
+        assert_eq!(server.requests(), 2);
+    }
+}
@@ -48,38 +48,85 @@ pub use bspc_core::{
     BspcHeader,
     DataType,
     ErrorCategory,
+    IndexWidth,
     MatrixElement,
     MatrixFormat,
+    MatrixIndex,
     MatrixOperations,
+    Pattern,
     Result,
     // Core traits
     SparseMatrix,
 };
 
+// Half precision and complex element types
+pub use half::{bf16, f16};
+pub use num_complex::{Complex32, Complex64};
+
 // binsparse_rs imports are used by individual modules as needed
 
 // Implementation modules
+#[macro_use]
+mod dispatch;
 pub mod chunk_bloom_filter;
 pub mod chunked_backend;
+pub mod compression;
 pub mod http_backend;
 pub mod metadata;
 #[cfg(feature = "mmap")]
 pub mod mmap_backend;
+#[cfg(feature = "mmap")]
+pub mod normalize;
+#[cfg(feature = "http")]
+pub mod serve;
+#[cfg(feature = "mmap")]
+pub mod similarity;
+#[cfg(feature = "mmap")]
+pub mod slice;
+#[cfg(feature = "mmap")]
+pub mod spmv;
+#[cfg(feature = "mmap")]
+pub mod transform;
+
+#[cfg(test)]
+mod test_support;
 
 // Public exports
 pub use chunk_bloom_filter::ChunkBloomFilter;
 pub use chunked_backend::{ChunkConfig, ChunkedMatrix, ChunkedProcessor};
+pub use compression::{CompressionConfig, IndexCodec, ValueCodec};
 
 // Memory mapping features
 #[cfg(feature = "mmap")]
 pub use mmap_backend::{BspcFile, DynamicElement, DynamicMatrix, MmapMatrix, SubmatrixView};
+#[cfg(feature = "mmap")]
+pub use normalize::{normalize, Axis, Normalization};
+#[cfg(feature = "mmap")]
+pub use similarity::{most_similar_rows, most_similar_to_row, SimilarityMetric, SparseVector};
+#[cfg(feature = "mmap")]
+pub use slice::{slice, slice_file, Selection};
+#[cfg(feature = "mmap")]
+pub use spmv::{spmv, spmv_complex};
+#[cfg(feature = "mmap")]
+pub use transform::Transform;
 
 // HTTP backend features
 #[cfg(feature = "http")]
-pub use http_backend::HttpMatrix;
+pub use http_backend::{
+    coalesce_ranges, CacheConfig, CacheStats, ConditionalRead, FileReader, HttpConfig, HttpError,
+    HttpMatrix, HttpMatrixBuilder, HttpReader, MemoryReader, PrefetchConfig, RangePlan,
+    RangeReader, ReadFuture, RemoteMatrix, RowStream,
+};
+#[cfg(feature = "api")]
+pub use serve::{serve_api, ApiServer};
+#[cfg(feature = "http")]
+pub use serve::{serve_dir, StaticServer};
 
 // Metadata features
-pub use metadata::{MetadataBuilder, MetadataView};
+pub use metadata::{
+    Annotation, AnnotationKind, AnnotationTable, AttributeKind, AttributeValue, LabelEncoding,
+    MetadataBuilder, MetadataView,
+};
 
 // Note: MatrixOperations for binsparse-rs Matrix types would require
 // orphan rule compliance. Users should wrap Matrix in their own type
@@ -1,12 +1,23 @@
 //! Structured metadata support for BSPC files
 //!
 //! This module provides structured access to metadata stored in BSPC files,
-//! including fast O(1) label lookups for row and column labels.
+//! including fast O(1) label lookups for row and column labels and O(log n)
+//! reverse lookups through the optional sorted label index. Labels are stored
+//! either back to back with an offset table (the default) or padded to a fixed
+//! stride. Rows and columns can also carry typed annotation tables, and the
+//! file can carry free-form key/value attributes.
+
+mod annotations;
+mod attributes;
+
+pub use annotations::{Annotation, AnnotationColumn, AnnotationTable};
+pub use attributes::AttributeValue;
+pub use bspc_core::format::metadata::{AnnotationKind, AttributeKind};
 
 use binsparse_rs::{Error, Result};
 
 // Re-export metadata format definitions from bspc-core
-pub use bspc_core::format::metadata::{BspcMetadataHeader, LabelArrayHeader};
+pub use bspc_core::format::metadata::{BspcMetadataHeader, LabelArrayHeader, LabelEncoding};
 
 /// Fast label lookup array for matrix rows/columns
 pub struct LabelArray<'a> {
@@ -16,27 +27,71 @@ pub struct LabelArray<'a> {
 
 impl<'a> LabelArray<'a> {
     /// Create from bytes with validation
+    ///
+    /// Accepts every label encoding of the current metadata version.
     pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
-        let header = LabelArrayHeader::from_bytes(data)
+        Self::from_bytes_for_version(data, bspc_core::format::constants::metadata::VERSION)
+    }
+
+    /// Create from bytes stored in metadata of the given version
+    pub fn from_bytes_for_version(data: &'a [u8], version: u8) -> Result<Self> {
+        let header = LabelArrayHeader::from_bytes_for_version(data, version)
             .map_err(|_| Error::InvalidState("Invalid label array header"))?;
         Ok(Self { data, header })
     }
 
     /// Get label by index
-    pub fn get_label(&self, index: u32) -> Result<&[u8]> {
+    ///
+    /// Returns the exact label bytes; NUL padding of fixed-stride labels is removed.
+    pub fn get_label(&self, index: u32) -> Result<&'a [u8]> {
         if index >= self.header.count {
             return Err(Error::InvalidState("Label index out of bounds"));
         }
 
-        let start = bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE
-            + (index as usize * self.header.stride as usize);
-        let end = start + self.header.stride as usize;
+        let base = bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE;
+        let (start, end) = match self.encoding() {
+            LabelEncoding::Fixed(stride) => {
+                let start = base + index as usize * stride as usize;
+                (start, start + stride as usize)
+            }
+            LabelEncoding::Variable => {
+                let blob_start = base + self.header.total_size();
+                let offset = |i: u32| -> Result<usize> {
+                    let pos = base + i as usize * 8;
+                    let bytes = self
+                        .data
+                        .get(pos..pos + 8)
+                        .ok_or(Error::InvalidState("Label offsets extend beyond data"))?;
+                    Ok(u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+                };
+                let (start, end) = (offset(index)?, offset(index + 1)?);
+                if start > end {
+                    return Err(Error::InvalidState("Invalid label offsets"));
+                }
+                let beyond = || Error::InvalidState("Label extends beyond data");
+                (
+                    blob_start.checked_add(start).ok_or_else(beyond)?,
+                    blob_start.checked_add(end).ok_or_else(beyond)?,
+                )
+            }
+        };
 
         if end > self.data.len() {
             return Err(Error::InvalidState("Label extends beyond data"));
         }
 
-        Ok(&self.data[start..end])
+        let label = &self.data[start..end];
+        Ok(match self.encoding() {
+            LabelEncoding::Fixed(_) => trim_label(label),
+            LabelEncoding::Variable => label,
+        })
+    }
+
+    /// Copy all labels into owned byte vectors
+    pub fn to_vec(&self) -> Result<Vec<Vec<u8>>> {
+        (0..self.count())
+            .map(|i| self.get_label(i).map(<[u8]>::to_vec))
+            .collect()
     }
 
     /// Get label count
@@ -44,10 +99,15 @@ impl<'a> LabelArray<'a> {
         self.header.count
     }
 
-    /// Get label stride (size of each label in bytes)
+    /// Get label stride (size of each label in bytes), 0 for variable-length labels
     pub fn stride(&self) -> u32 {
         self.header.stride
     }
+
+    /// Get the label encoding
+    pub fn encoding(&self) -> LabelEncoding {
+        self.header.encoding()
+    }
 }
 
 /// High-level metadata view with efficient label access
@@ -65,148 +125,194 @@ impl<'a> MetadataView<'a> {
     }
 
     /// Get row labels array if present
-    pub fn row_labels(&self) -> Result<Option<LabelArray<'_>>> {
-        if self.header.row_labels_size == 0 {
-            return Ok(None);
-        }
+    pub fn row_labels(&self) -> Result<Option<LabelArray<'a>>> {
+        self.section(
+            self.header.row_labels_offset,
+            self.header.row_labels_size,
+            "Row labels extend beyond metadata",
+        )?
+        .map(|data| LabelArray::from_bytes_for_version(data, self.header.version))
+        .transpose()
+    }
 
-        let start = self.header.row_labels_offset as usize;
-        let end = start + self.header.row_labels_size as usize;
+    /// Get column labels array if present
+    pub fn col_labels(&self) -> Result<Option<LabelArray<'a>>> {
+        self.section(
+            self.header.col_labels_offset,
+            self.header.col_labels_size,
+            "Column labels extend beyond metadata",
+        )?
+        .map(|data| LabelArray::from_bytes_for_version(data, self.header.version))
+        .transpose()
+    }
 
-        if end > self.data.len() {
-            return Err(Error::InvalidState("Row labels extend beyond metadata"));
-        }
+    /// Get specific row label by index
+    pub fn row_label(&self, index: u32) -> Result<Option<&'a [u8]>> {
+        self.row_labels()?
+            .map(|labels| labels.get_label(index))
+            .transpose()
+    }
 
-        Ok(Some(LabelArray::from_bytes(&self.data[start..end])?))
+    /// Get specific column label by index
+    pub fn col_label(&self, index: u32) -> Result<Option<&'a [u8]>> {
+        self.col_labels()?
+            .map(|labels| labels.get_label(index))
+            .transpose()
     }
 
-    /// Get column labels array if present  
-    pub fn col_labels(&self) -> Result<Option<LabelArray<'_>>> {
-        if self.header.col_labels_size == 0 {
+    /// Find the index of a row label
+    ///
+    /// Labels are compared as returned by [`LabelArray::get_label`]. Uses the sorted
+    /// label index when present and falls back to a linear scan for files written
+    /// without it.
+    /// With duplicate labels the smallest index is returned.
+    pub fn row_index_of(&self, label: &[u8]) -> Result<Option<u32>> {
+        let Some(labels) = self.row_labels()? else {
             return Ok(None);
-        }
-
-        let start = self.header.col_labels_offset as usize;
-        let end = start + self.header.col_labels_size as usize;
-
-        if end > self.data.len() {
-            return Err(Error::InvalidState("Column labels extend beyond metadata"));
-        }
-
-        Ok(Some(LabelArray::from_bytes(&self.data[start..end])?))
+        };
+        let index = self.section(
+            self.header.row_index_offset,
+            self.header.row_index_size,
+            "Row label index extends beyond metadata",
+        )?;
+        find_label(&labels, index, label)
     }
 
-    /// Get specific row label by index
-    pub fn row_label(&self, index: u32) -> Result<Option<&[u8]>> {
-        if self.header.row_labels_size == 0 {
+    /// Find the index of a column label
+    ///
+    /// See [`MetadataView::row_index_of`].
+    pub fn col_index_of(&self, label: &[u8]) -> Result<Option<u32>> {
+        let Some(labels) = self.col_labels()? else {
             return Ok(None);
-        }
-
-        let labels_start = self.header.row_labels_offset as usize;
-        let labels_end = labels_start + self.header.row_labels_size as usize;
-
-        if labels_end > self.data.len() {
-            return Err(Error::InvalidState("Row labels extend beyond metadata"));
-        }
-
-        let labels_data = &self.data[labels_start..labels_end];
-
-        // Parse header manually to avoid lifetime issues
-        if labels_data.len() < bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE {
-            return Err(Error::InvalidState("Invalid label array header"));
-        }
-
-        let count = u32::from_le_bytes([
-            labels_data[0],
-            labels_data[1],
-            labels_data[2],
-            labels_data[3],
-        ]);
-        let stride = u32::from_le_bytes([
-            labels_data[4],
-            labels_data[5],
-            labels_data[6],
-            labels_data[7],
-        ]);
-
-        if index >= count {
-            return Err(Error::InvalidState("Label index out of bounds"));
-        }
-
-        let label_start = bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE
-            + (index as usize * stride as usize);
-        let label_end = label_start + stride as usize;
-
-        if label_end > labels_data.len() {
-            return Err(Error::InvalidState("Label extends beyond data"));
-        }
-
-        Ok(Some(&labels_data[label_start..label_end]))
+        };
+        let index = self.section(
+            self.header.col_index_offset,
+            self.header.col_index_size,
+            "Column label index extends beyond metadata",
+        )?;
+        find_label(&labels, index, label)
     }
 
-    /// Get specific column label by index
-    pub fn col_label(&self, index: u32) -> Result<Option<&[u8]>> {
-        if self.header.col_labels_size == 0 {
+    /// Get an optional section by offset and size
+    fn section(&self, offset: u64, size: u64, msg: &'static str) -> Result<Option<&'a [u8]>> {
+        if size == 0 {
             return Ok(None);
         }
 
-        let labels_start = self.header.col_labels_offset as usize;
-        let labels_end = labels_start + self.header.col_labels_size as usize;
-
-        if labels_end > self.data.len() {
-            return Err(Error::InvalidState("Column labels extend beyond metadata"));
-        }
-
-        let labels_data = &self.data[labels_start..labels_end];
+        let end = offset
+            .checked_add(size)
+            .filter(|&end| end <= self.data.len() as u64)
+            .ok_or(Error::InvalidState(msg))?;
 
-        // Parse header manually to avoid lifetime issues
-        if labels_data.len() < bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE {
-            return Err(Error::InvalidState("Invalid label array header"));
-        }
+        Ok(Some(&self.data[offset as usize..end as usize]))
+    }
 
-        let count = u32::from_le_bytes([
-            labels_data[0],
-            labels_data[1],
-            labels_data[2],
-            labels_data[3],
-        ]);
-        let stride = u32::from_le_bytes([
-            labels_data[4],
-            labels_data[5],
-            labels_data[6],
-            labels_data[7],
-        ]);
-
-        if index >= count {
-            return Err(Error::InvalidState("Label index out of bounds"));
-        }
+    /// Get the row annotation table if present
+    pub fn row_annotations(&self) -> Result<Option<AnnotationTable<'a>>> {
+        self.section(
+            self.header.row_annotations_offset,
+            self.header.row_annotations_size,
+            "Row annotations extend beyond metadata",
+        )?
+        .map(AnnotationTable::from_bytes)
+        .transpose()
+    }
 
-        let label_start = bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE
-            + (index as usize * stride as usize);
-        let label_end = label_start + stride as usize;
+    /// Get the column annotation table if present
+    pub fn col_annotations(&self) -> Result<Option<AnnotationTable<'a>>> {
+        self.section(
+            self.header.col_annotations_offset,
+            self.header.col_annotations_size,
+            "Column annotations extend beyond metadata",
+        )?
+        .map(AnnotationTable::from_bytes)
+        .transpose()
+    }
 
-        if label_end > labels_data.len() {
-            return Err(Error::InvalidState("Label extends beyond data"));
+    /// Get all file attributes in the order they were added
+    ///
+    /// Returns an empty list when the file has no attribute block.
+    pub fn attributes(&self) -> Result<Vec<(&'a str, AttributeValue)>> {
+        match self.section(
+            self.header.attributes_offset,
+            self.header.attributes_size,
+            "Attributes extend beyond metadata",
+        )? {
+            Some(block) => attributes::decode_attributes(block),
+            None => Ok(Vec::new()),
         }
+    }
 
-        Ok(Some(&labels_data[label_start..label_end]))
+    /// Get a single file attribute by key
+    pub fn attribute(&self, key: &str) -> Result<Option<AttributeValue>> {
+        Ok(self
+            .attributes()?
+            .into_iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value))
     }
 
     /// Get row labels array (compatibility method)
-    pub fn row_labels_array(&self) -> Result<Option<LabelArray<'_>>> {
+    pub fn row_labels_array(&self) -> Result<Option<LabelArray<'a>>> {
         self.row_labels()
     }
 
     /// Get column labels array (compatibility method)
-    pub fn col_labels_array(&self) -> Result<Option<LabelArray<'_>>> {
+    pub fn col_labels_array(&self) -> Result<Option<LabelArray<'a>>> {
         self.col_labels()
     }
 }
 
+/// Look up a label, by binary search over `index` when present
+fn find_label(labels: &LabelArray<'_>, index: Option<&[u8]>, label: &[u8]) -> Result<Option<u32>> {
+    let Some(index) = index else {
+        for i in 0..labels.count() {
+            if labels.get_label(i)? == label {
+                return Ok(Some(i));
+            }
+        }
+        return Ok(None);
+    };
+
+    if index.len() != labels.count() as usize * 4 {
+        return Err(Error::InvalidState("Label index doesn't match label count"));
+    }
+    let position = |k: usize| {
+        u32::from_le_bytes([
+            index[k * 4],
+            index[k * 4 + 1],
+            index[k * 4 + 2],
+            index[k * 4 + 3],
+        ])
+    };
+
+    // Lower bound: first sorted entry not less than the label
+    let (mut lo, mut hi) = (0, labels.count() as usize);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if labels.get_label(position(mid))? < label {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+
+    if lo < labels.count() as usize && labels.get_label(position(lo))? == label {
+        Ok(Some(position(lo)))
+    } else {
+        Ok(None)
+    }
+}
+
 /// Builder for creating metadata
 pub struct MetadataBuilder {
     row_labels: Option<Vec<Vec<u8>>>,
     col_labels: Option<Vec<Vec<u8>>>,
+    label_index: bool,
+    label_encoding: LabelEncoding,
+    row_annotations: Vec<(String, Annotation)>,
+    col_annotations: Vec<(String, Annotation)>,
+    attributes: Vec<(String, AttributeValue)>,
 }
 
 impl MetadataBuilder {
@@ -215,9 +321,28 @@ impl MetadataBuilder {
         Self {
             row_labels: None,
             col_labels: None,
+            label_index: true,
+            label_encoding: LabelEncoding::default(),
+            row_annotations: Vec::new(),
+            col_annotations: Vec::new(),
+            attributes: Vec::new(),
         }
     }
 
+    /// Enable or disable the sorted label index sections (enabled by default)
+    ///
+    /// The index stores 4 bytes per label and makes reverse lookups O(log n).
+    pub fn with_label_index(mut self, enabled: bool) -> Self {
+        self.label_index = enabled;
+        self
+    }
+
+    /// Set the label encoding (variable-length by default)
+    pub fn with_label_encoding(mut self, encoding: LabelEncoding) -> Self {
+        self.label_encoding = encoding;
+        self
+    }
+
     /// Add row labels
     pub fn with_row_labels(mut self, labels: Vec<Vec<u8>>) -> Self {
         self.row_labels = Some(labels);
@@ -230,87 +355,163 @@ impl MetadataBuilder {
         self
     }
 
-    /// Build metadata bytes
-    pub fn build(&self) -> Result<Vec<u8>> {
-        let mut result = Vec::new();
+    /// Add a named row annotation column with one entry per row
+    pub fn with_row_annotation(mut self, name: impl Into<String>, values: Annotation) -> Self {
+        self.row_annotations.push((name.into(), values));
+        self
+    }
 
-        // Calculate offsets
-        let header_size = bspc_core::format::constants::metadata::HEADER_SIZE;
-        let mut current_offset = header_size as u64;
+    /// Add a named column annotation column with one entry per column
+    pub fn with_col_annotation(mut self, name: impl Into<String>, values: Annotation) -> Self {
+        self.col_annotations.push((name.into(), values));
+        self
+    }
+
+    /// Set a file attribute, replacing any earlier value for the key
+    pub fn with_attribute(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<AttributeValue>,
+    ) -> Self {
+        let key = key.into();
+        let value = value.into();
+        match self.attributes.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.attributes.push((key, value)),
+        }
+        self
+    }
 
-        let (row_labels_offset, row_labels_size) = if let Some(ref labels) = self.row_labels {
-            let offset = current_offset;
-            let size = self.calculate_label_array_size(labels)?;
-            current_offset += size;
-            (offset, size)
+    /// Build metadata bytes
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let row_array = self.encode_label_array(self.row_labels.as_deref())?;
+        let col_array = self.encode_label_array(self.col_labels.as_deref())?;
+        let row_index = self.label_index_bytes(self.row_labels.as_deref());
+        let col_index = self.label_index_bytes(self.col_labels.as_deref());
+        let row_table =
+            Self::encode_annotations(&self.row_annotations, self.row_labels.as_deref())?;
+        let col_table =
+            Self::encode_annotations(&self.col_annotations, self.col_labels.as_deref())?;
+        let attributes = if self.attributes.is_empty() {
+            Vec::new()
         } else {
-            (0, 0)
+            attributes::encode_attributes(&self.attributes)?
         };
 
-        let (col_labels_offset, col_labels_size) = if let Some(ref labels) = self.col_labels {
-            let offset = current_offset;
-            let size = self.calculate_label_array_size(labels)?;
-            (offset, size)
-        } else {
-            (0, 0)
+        // Sections follow the header in order, each starting on an 8-byte
+        // boundary; absent sections have offset 0
+        let header_size = bspc_core::format::constants::metadata::HEADER_SIZE;
+        let mut current_offset = header_size as u64;
+        let mut place = |section: &[u8]| {
+            if section.is_empty() {
+                return (0, 0);
+            }
+            let offset = align_to_8(current_offset);
+            current_offset = offset + section.len() as u64;
+            (offset, section.len() as u64)
         };
 
-        // Create header manually since there are no builder methods
         let mut header = BspcMetadataHeader::new();
-        header.row_labels_offset = row_labels_offset;
-        header.row_labels_size = row_labels_size;
-        header.col_labels_offset = col_labels_offset;
-        header.col_labels_size = col_labels_size;
-
-        // Write header
+        (header.row_labels_offset, header.row_labels_size) = place(&row_array);
+        (header.col_labels_offset, header.col_labels_size) = place(&col_array);
+        (header.row_index_offset, header.row_index_size) = place(&row_index);
+        (header.col_index_offset, header.col_index_size) = place(&col_index);
+        (header.row_annotations_offset, header.row_annotations_size) = place(&row_table);
+        (header.col_annotations_offset, header.col_annotations_size) = place(&col_table);
+        (header.attributes_offset, header.attributes_size) = place(&attributes);
+
+        let sections = [
+            &row_array,
+            &col_array,
+            &row_index,
+            &col_index,
+            &row_table,
+            &col_table,
+            &attributes,
+        ];
+        let mut result = Vec::with_capacity(current_offset as usize);
         result.extend_from_slice(&header.to_bytes());
-
-        // Write row labels if present
-        if let Some(ref labels) = self.row_labels {
-            self.write_label_array(&mut result, labels)?;
-        }
-
-        // Write column labels if present
-        if let Some(ref labels) = self.col_labels {
-            self.write_label_array(&mut result, labels)?;
+        for section in sections.into_iter().filter(|s| !s.is_empty()) {
+            result.resize(align_to_8(result.len() as u64) as usize, 0);
+            result.extend_from_slice(section);
         }
 
         Ok(result)
     }
 
-    fn calculate_label_array_size(&self, labels: &[Vec<u8>]) -> Result<u64> {
-        if labels.is_empty() {
-            return Ok(0);
-        }
-
-        let max_len = labels.iter().map(|l| l.len()).max().unwrap_or(0);
-        let stride = max_len.next_power_of_two().max(4); // At least 4 bytes, power of 2
-        let total_size = bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE
-            + (labels.len() * stride);
-
-        Ok(total_size as u64)
+    /// Encode an annotation table (empty when there are no columns)
+    ///
+    /// Every column must have one entry per label when labels are present.
+    fn encode_annotations(
+        columns: &[(String, Annotation)],
+        labels: Option<&[Vec<u8>]>,
+    ) -> Result<Vec<u8>> {
+        let Some((_, first)) = columns.first() else {
+            return Ok(Vec::new());
+        };
+        let len = labels.map_or(first.len(), <[_]>::len);
+        annotations::encode_table(columns, len)
     }
 
-    fn write_label_array(&self, buf: &mut Vec<u8>, labels: &[Vec<u8>]) -> Result<()> {
-        if labels.is_empty() {
-            return Ok(());
-        }
+    /// Encode label positions sorted by label bytes as they will be read back
+    fn label_index_bytes(&self, labels: Option<&[Vec<u8>]>) -> Vec<u8> {
+        let Some(labels) = labels.filter(|labels| self.label_index && !labels.is_empty()) else {
+            return Vec::new();
+        };
 
-        let max_len = labels.iter().map(|l| l.len()).max().unwrap_or(0);
-        let stride = max_len.next_power_of_two().max(4);
+        let key = |pos: u32| match self.label_encoding {
+            LabelEncoding::Fixed(_) => trim_label(&labels[pos as usize]),
+            LabelEncoding::Variable => labels[pos as usize].as_slice(),
+        };
+        let mut order: Vec<u32> = (0..labels.len() as u32).collect();
+        order.sort_by(|&a, &b| key(a).cmp(key(b)).then(a.cmp(&b)));
 
-        // Write header
-        let header = LabelArrayHeader::new(labels.len() as u32, stride as u32);
-        buf.extend_from_slice(&header.to_bytes());
+        order.iter().flat_map(|pos| pos.to_le_bytes()).collect()
+    }
 
-        // Write labels with padding
-        for label in labels {
-            buf.extend_from_slice(label);
-            // Pad to stride
-            buf.resize(buf.len() + (stride - label.len()), 0);
+    /// Encode a label array section (empty when there are no labels)
+    fn encode_label_array(&self, labels: Option<&[Vec<u8>]>) -> Result<Vec<u8>> {
+        let Some(labels) = labels.filter(|labels| !labels.is_empty()) else {
+            return Ok(Vec::new());
+        };
+        let count = u32::try_from(labels.len())
+            .map_err(|_| Error::InvalidState("Too many labels for label array"))?;
+
+        let mut buf = Vec::new();
+        match self.label_encoding {
+            LabelEncoding::Variable => {
+                buf.extend_from_slice(&LabelArrayHeader::variable(count).to_bytes());
+
+                // Offset table, relative to the start of the blob
+                let mut offset = 0u64;
+                buf.extend_from_slice(&offset.to_le_bytes());
+                for label in labels {
+                    offset += label.len() as u64;
+                    buf.extend_from_slice(&offset.to_le_bytes());
+                }
+
+                for label in labels {
+                    buf.extend_from_slice(label);
+                }
+            }
+            LabelEncoding::Fixed(stride) => {
+                if stride == 0 || stride > bspc_core::format::constants::MAX_LABEL_STRIDE {
+                    return Err(Error::InvalidState("Invalid label stride"));
+                }
+                if labels.iter().any(|label| label.len() > stride as usize) {
+                    return Err(Error::InvalidState("Label longer than label stride"));
+                }
+
+                buf.extend_from_slice(&LabelArrayHeader::new(count, stride).to_bytes());
+                for label in labels {
+                    buf.extend_from_slice(label);
+                    // Pad to stride
+                    buf.resize(buf.len() + (stride as usize - label.len()), 0);
+                }
+            }
         }
 
-        Ok(())
+        Ok(buf)
     }
 }
 
@@ -324,3 +525,135 @@ impl Default for MetadataBuilder {
 pub fn align_to_8(value: u64) -> u64 {
     (value + 7) & !7
 }
+
+/// Strip the trailing NUL padding added to fixed-stride labels
+pub(crate) fn trim_label(label: &[u8]) -> &[u8] {
+    let end = label.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    &label[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(names: &[&str]) -> Vec<Vec<u8>> {
+        names.iter().map(|name| name.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_label_index_lookup() {
+        let bytes = MetadataBuilder::new()
+            .with_row_labels(labels(&["gene_c", "gene_a", "gene_b", "gene_a"]))
+            .with_col_labels(labels(&["cell_1", "cell_0"]))
+            .build()
+            .unwrap();
+        let view = MetadataView::new(&bytes).unwrap();
+
+        assert_eq!(view.row_index_of(b"gene_b").unwrap(), Some(2));
+        // Duplicates resolve to the smallest index
+        assert_eq!(view.row_index_of(b"gene_a").unwrap(), Some(1));
+        assert_eq!(view.row_index_of(b"gene_d").unwrap(), None);
+        assert_eq!(view.col_index_of(b"cell_0").unwrap(), Some(1));
+        assert_eq!(view.row_label(2).unwrap(), Some(&b"gene_b"[..]));
+    }
+
+    #[test]
+    fn test_lookup_without_label_index() {
+        let bytes = MetadataBuilder::new()
+            .with_label_index(false)
+            .with_row_labels(labels(&["b", "a", "a"]))
+            .build()
+            .unwrap();
+        let view = MetadataView::new(&bytes).unwrap();
+
+        assert_eq!(view.row_index_of(b"a").unwrap(), Some(1));
+        assert_eq!(view.row_index_of(b"c").unwrap(), None);
+        assert_eq!(view.col_index_of(b"a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_section_bounds_overflow() {
+        let mut bytes = MetadataBuilder::new()
+            .with_row_labels(labels(&["a", "b"]))
+            .build()
+            .unwrap();
+
+        // row_index_offset follows the magic, version and label sections
+        bytes[40..48].copy_from_slice(&u64::MAX.to_le_bytes());
+        let view = MetadataView::new(&bytes).unwrap();
+        assert!(view.row_index_of(b"a").is_err());
+    }
+
+    #[test]
+    fn test_label_offsets_overflow() {
+        let mut bytes = MetadataBuilder::new()
+            .with_row_labels(labels(&["a", "b"]))
+            .build()
+            .unwrap();
+
+        let header = BspcMetadataHeader::from_bytes(&bytes).unwrap();
+        let offsets = header.row_labels_offset as usize
+            + bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE;
+        bytes[offsets..offsets + 8].copy_from_slice(&(u64::MAX - 1).to_le_bytes());
+        bytes[offsets + 8..offsets + 16].copy_from_slice(&u64::MAX.to_le_bytes());
+        let view = MetadataView::new(&bytes).unwrap();
+        assert!(view.row_label(0).is_err());
+    }
+
+    #[test]
+    fn test_label_encodings_roundtrip() {
+        let names = labels(&["gene_c", "a", "gene_bb"]);
+        for encoding in [LabelEncoding::Variable, LabelEncoding::Fixed(8)] {
+            let bytes = MetadataBuilder::new()
+                .with_label_encoding(encoding)
+                .with_row_labels(names.clone())
+                .with_col_labels(labels(&["x"]))
+                .build()
+                .unwrap();
+            let view = MetadataView::new(&bytes).unwrap();
+
+            let rows = view.row_labels().unwrap().unwrap();
+            assert_eq!(rows.encoding(), encoding);
+            assert_eq!(rows.to_vec().unwrap(), names);
+            assert_eq!(view.row_index_of(b"gene_bb").unwrap(), Some(2));
+            assert_eq!(view.col_label(0).unwrap(), Some(&b"x"[..]));
+            assert!(view.row_label(3).is_err());
+        }
+    }
+
+    #[test]
+    fn test_sections_are_aligned() {
+        let bytes = MetadataBuilder::new()
+            .with_row_labels(labels(&["abc", "de"]))
+            .with_col_labels(labels(&["f"]))
+            .with_attribute("name", "x")
+            .build()
+            .unwrap();
+        let header = BspcMetadataHeader::from_bytes(&bytes).unwrap();
+        for offset in [
+            header.row_labels_offset,
+            header.col_labels_offset,
+            header.row_index_offset,
+            header.col_index_offset,
+            header.attributes_offset,
+        ] {
+            assert_ne!(offset, 0);
+            assert_eq!(offset % 8, 0);
+        }
+    }
+
+    #[test]
+    fn test_variable_labels_rejected_before_version_3() {
+        let mut bytes = MetadataBuilder::new()
+            .with_row_labels(labels(&["a", "b"]))
+            .build()
+            .unwrap();
+        let view = MetadataView::new(&bytes).unwrap();
+        assert_eq!(view.row_labels().unwrap().unwrap().stride(), 0);
+
+        // Version 2 metadata has no variable-length encoding
+        bytes[4] = 2;
+        let view = MetadataView::new(&bytes).unwrap();
+        assert!(view.row_labels().is_err());
+    }
+}
@@ -0,0 +1,580 @@
+//! Typed per-row and per-column annotation tables
+//!
+//! An annotation table is a small columnar table stored in the metadata section
+//! with one entry per row (or column) of the matrix. Columns are named and hold
+//! strings, categories, `i64` or `f64` values. Tables are read in place from the
+//! metadata bytes; filtering helpers return the matching row or column indices.
+
+use binsparse_rs::{Error, Result};
+use bspc_core::format::constants::metadata::{
+    ANNOTATION_COLUMN_HEADER_SIZE, ANNOTATION_TABLE_HEADER_SIZE,
+};
+use bspc_core::format::metadata::{AnnotationColumnHeader, AnnotationKind, AnnotationTableHeader};
+use std::collections::HashMap;
+
+/// Owned annotation values, one entry per row or column
+#[derive(Debug, Clone, PartialEq)]
+pub enum Annotation {
+    /// Free-form strings
+    String(Vec<String>),
+    /// Codes into a list of category names
+    Categorical {
+        /// Category names
+        categories: Vec<String>,
+        /// Category index of every entry
+        codes: Vec<u32>,
+    },
+    /// Signed integers
+    I64(Vec<i64>),
+    /// Floating point values
+    F64(Vec<f64>),
+}
+
+impl Annotation {
+    /// Build a categorical annotation, with categories in first-seen order
+    pub fn categorical<S: AsRef<str>>(values: &[S]) -> Self {
+        let mut categories = Vec::new();
+        let mut lookup: HashMap<&str, u32> = HashMap::new();
+        let codes = values
+            .iter()
+            .map(|value| {
+                let value = value.as_ref();
+                *lookup.entry(value).or_insert_with(|| {
+                    categories.push(value.to_string());
+                    categories.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        Annotation::Categorical { categories, codes }
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        match self {
+            Annotation::String(values) => values.len(),
+            Annotation::Categorical { codes, .. } => codes.len(),
+            Annotation::I64(values) => values.len(),
+            Annotation::F64(values) => values.len(),
+        }
+    }
+
+    /// Check if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Value type of the annotation
+    pub fn kind(&self) -> AnnotationKind {
+        match self {
+            Annotation::String(_) => AnnotationKind::String,
+            Annotation::Categorical { .. } => AnnotationKind::Categorical,
+            Annotation::I64(_) => AnnotationKind::I64,
+            Annotation::F64(_) => AnnotationKind::F64,
+        }
+    }
+
+    /// Encode the column data
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        match self {
+            Annotation::String(values) => encode_strings(&mut buf, values),
+            Annotation::Categorical { categories, codes } => {
+                if codes.iter().any(|&code| code as usize >= categories.len()) {
+                    return Err(Error::InvalidState("Category code out of range"));
+                }
+                let count = u32::try_from(categories.len())
+                    .map_err(|_| Error::InvalidState("Too many categories"))?;
+                buf.extend_from_slice(&count.to_le_bytes());
+                buf.extend_from_slice(&0u32.to_le_bytes());
+                encode_strings(&mut buf, categories);
+                buf.resize(buf.len().next_multiple_of(4), 0);
+                buf.extend(codes.iter().flat_map(|code| code.to_le_bytes()));
+            }
+            Annotation::I64(values) => buf.extend(values.iter().flat_map(|v| v.to_le_bytes())),
+            Annotation::F64(values) => buf.extend(values.iter().flat_map(|v| v.to_le_bytes())),
+        }
+        Ok(buf)
+    }
+}
+
+/// Write `len + 1` u64 offsets followed by the string bytes
+fn encode_strings(buf: &mut Vec<u8>, values: &[String]) {
+    let mut offset = 0u64;
+    buf.extend_from_slice(&offset.to_le_bytes());
+    for value in values {
+        offset += value.len() as u64;
+        buf.extend_from_slice(&offset.to_le_bytes());
+    }
+    for value in values {
+        buf.extend_from_slice(value.as_bytes());
+    }
+}
+
+/// Encode a table of named columns that all have `len` entries
+pub(crate) fn encode_table(columns: &[(String, Annotation)], len: usize) -> Result<Vec<u8>> {
+    if columns.iter().any(|(_, values)| values.len() != len) {
+        return Err(Error::InvalidState("Annotation length doesn't match axis"));
+    }
+    for (i, (name, _)) in columns.iter().enumerate() {
+        if columns[..i].iter().any(|(other, _)| other == name) {
+            return Err(Error::InvalidState("Duplicate annotation column name"));
+        }
+    }
+
+    let column_count = u32::try_from(columns.len())
+        .map_err(|_| Error::InvalidState("Too many annotation columns"))?;
+    let len = u32::try_from(len).map_err(|_| Error::InvalidState("Annotation too long"))?;
+
+    // Names follow the descriptors; each column's data starts 8-byte aligned
+    let mut offset = ANNOTATION_TABLE_HEADER_SIZE + columns.len() * ANNOTATION_COLUMN_HEADER_SIZE;
+    let mut descriptors = Vec::with_capacity(columns.len());
+    for (name, _) in columns {
+        let name_len = u32::try_from(name.len())
+            .map_err(|_| Error::InvalidState("Annotation name too long"))?;
+        descriptors.push((name_len, offset as u64));
+        offset += name.len();
+    }
+
+    let data_base = offset;
+    let mut data = Vec::new();
+    let mut placed = Vec::with_capacity(columns.len());
+    for (_, values) in columns {
+        let encoded = values.encode()?;
+        let start = offset.next_multiple_of(8);
+        data.resize(start - data_base, 0);
+        offset = start + encoded.len();
+        placed.push((start as u64, encoded.len() as u64));
+        data.extend_from_slice(&encoded);
+    }
+
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&AnnotationTableHeader::new(column_count, len).to_bytes());
+    for (((name_len, name_offset), (data_offset, data_size)), (_, values)) in
+        descriptors.iter().zip(&placed).zip(columns)
+    {
+        let header = AnnotationColumnHeader {
+            kind: values.kind(),
+            name_len: *name_len,
+            name_offset: *name_offset,
+            data_offset: *data_offset,
+            data_size: *data_size,
+        };
+        buf.extend_from_slice(&header.to_bytes());
+    }
+    for (name, _) in columns {
+        buf.extend_from_slice(name.as_bytes());
+    }
+    buf.extend_from_slice(&data);
+
+    Ok(buf)
+}
+
+/// Bounds-checked slice of `data`
+fn slice(data: &[u8], start: usize, len: usize) -> Result<&[u8]> {
+    start
+        .checked_add(len)
+        .and_then(|end| data.get(start..end))
+        .ok_or(Error::InvalidState("Annotation data extends beyond table"))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(slice(data, pos, 4)?.try_into().unwrap()))
+}
+
+fn read_u64(data: &[u8], pos: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(slice(data, pos, 8)?.try_into().unwrap()))
+}
+
+/// Read string `index` from an offsets + blob array of `len` strings
+fn read_string(data: &[u8], len: usize, index: usize) -> Result<&str> {
+    let blob_start = (len + 1) * 8;
+    let start = read_u64(data, index * 8)? as usize;
+    let end = read_u64(data, (index + 1) * 8)? as usize;
+    if start > end {
+        return Err(Error::InvalidState("Invalid annotation string offsets"));
+    }
+    let bytes = slice(data, blob_start + start, end - start)?;
+    std::str::from_utf8(bytes).map_err(|_| Error::InvalidState("Invalid UTF-8 in annotation"))
+}
+
+/// Annotation table read in place from metadata bytes
+pub struct AnnotationTable<'a> {
+    data: &'a [u8],
+    header: AnnotationTableHeader,
+}
+
+impl<'a> AnnotationTable<'a> {
+    /// Create from table bytes
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
+        let header = AnnotationTableHeader::from_bytes(data)
+            .map_err(|_| Error::InvalidState("Invalid annotation table header"))?;
+        Ok(Self { data, header })
+    }
+
+    /// Number of entries in every column
+    pub fn len(&self) -> usize {
+        self.header.len as usize
+    }
+
+    /// Check if the table has no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of annotation columns
+    pub fn column_count(&self) -> usize {
+        self.header.column_count as usize
+    }
+
+    /// Get a column by position
+    pub fn column_at(&self, index: usize) -> Result<AnnotationColumn<'a>> {
+        if index >= self.column_count() {
+            return Err(Error::InvalidState("Annotation column index out of bounds"));
+        }
+
+        let pos = ANNOTATION_TABLE_HEADER_SIZE + index * ANNOTATION_COLUMN_HEADER_SIZE;
+        let header = AnnotationColumnHeader::from_bytes(slice(
+            self.data,
+            pos,
+            ANNOTATION_COLUMN_HEADER_SIZE,
+        )?)
+        .map_err(|_| Error::InvalidState("Invalid annotation column header"))?;
+
+        let name = slice(
+            self.data,
+            header.name_offset as usize,
+            header.name_len as usize,
+        )?;
+        let name = std::str::from_utf8(name)
+            .map_err(|_| Error::InvalidState("Invalid UTF-8 in annotation name"))?;
+        let data = slice(
+            self.data,
+            header.data_offset as usize,
+            header.data_size as usize,
+        )?;
+
+        Ok(AnnotationColumn {
+            name,
+            kind: header.kind,
+            data,
+            len: self.len(),
+        })
+    }
+
+    /// Names of all columns, in storage order
+    pub fn column_names(&self) -> Result<Vec<&'a str>> {
+        (0..self.column_count())
+            .map(|i| self.column_at(i).map(|column| column.name()))
+            .collect()
+    }
+
+    /// Get a column by name
+    pub fn column(&self, name: &str) -> Result<Option<AnnotationColumn<'a>>> {
+        for i in 0..self.column_count() {
+            let column = self.column_at(i)?;
+            if column.name() == name {
+                return Ok(Some(column));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Get a column by name, failing if it is missing
+    fn require(&self, name: &str) -> Result<AnnotationColumn<'a>> {
+        self.column(name)?
+            .ok_or(Error::InvalidState("Annotation column not found"))
+    }
+
+    /// Indices whose `i64` value matches the predicate
+    pub fn filter_i64<F: Fn(i64) -> bool>(&self, name: &str, predicate: F) -> Result<Vec<usize>> {
+        let column = self.require(name)?;
+        let mut matches = Vec::new();
+        for i in 0..column.len() {
+            if predicate(column.get_i64(i)?) {
+                matches.push(i);
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Indices whose `f64` value matches the predicate
+    pub fn filter_f64<F: Fn(f64) -> bool>(&self, name: &str, predicate: F) -> Result<Vec<usize>> {
+        let column = self.require(name)?;
+        let mut matches = Vec::new();
+        for i in 0..column.len() {
+            if predicate(column.get_f64(i)?) {
+                matches.push(i);
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Indices whose string or category matches the predicate
+    ///
+    /// For categorical columns the predicate runs once per category.
+    pub fn filter_str<F: Fn(&str) -> bool>(&self, name: &str, predicate: F) -> Result<Vec<usize>> {
+        let column = self.require(name)?;
+        let mut matches = Vec::new();
+
+        if column.kind() == AnnotationKind::Categorical {
+            let keep = column
+                .categories()?
+                .into_iter()
+                .map(predicate)
+                .collect::<Vec<_>>();
+            for i in 0..column.len() {
+                if keep.get(column.code(i)? as usize).copied().unwrap_or(false) {
+                    matches.push(i);
+                }
+            }
+        } else {
+            for i in 0..column.len() {
+                if predicate(column.get_str(i)?) {
+                    matches.push(i);
+                }
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Indices whose string or category is one of `values`
+    pub fn filter_in(&self, name: &str, values: &[&str]) -> Result<Vec<usize>> {
+        self.filter_str(name, |value| values.contains(&value))
+    }
+}
+
+/// One annotation column read in place
+#[derive(Debug, Clone, Copy)]
+pub struct AnnotationColumn<'a> {
+    name: &'a str,
+    kind: AnnotationKind,
+    data: &'a [u8],
+    len: usize,
+}
+
+impl<'a> AnnotationColumn<'a> {
+    /// Column name
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Column value type
+    pub fn kind(&self) -> AnnotationKind {
+        self.kind
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the column has no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn check(&self, index: usize, kind: AnnotationKind) -> Result<()> {
+        if self.kind != kind {
+            return Err(Error::InvalidState(
+                "Annotation column has a different type",
+            ));
+        }
+        if index >= self.len {
+            return Err(Error::InvalidState("Annotation index out of bounds"));
+        }
+        Ok(())
+    }
+
+    /// Get an `i64` entry
+    pub fn get_i64(&self, index: usize) -> Result<i64> {
+        self.check(index, AnnotationKind::I64)?;
+        Ok(read_u64(self.data, index * 8)? as i64)
+    }
+
+    /// Get an `f64` entry
+    pub fn get_f64(&self, index: usize) -> Result<f64> {
+        self.check(index, AnnotationKind::F64)?;
+        Ok(f64::from_bits(read_u64(self.data, index * 8)?))
+    }
+
+    /// Get a string entry, or the category name of a categorical entry
+    pub fn get_str(&self, index: usize) -> Result<&'a str> {
+        if self.kind == AnnotationKind::Categorical {
+            let code = self.code(index)?;
+            return read_string(
+                self.category_names()?,
+                self.category_count()?,
+                code as usize,
+            );
+        }
+        self.check(index, AnnotationKind::String)?;
+        read_string(self.data, self.len, index)
+    }
+
+    /// Get the category code of a categorical entry
+    pub fn code(&self, index: usize) -> Result<u32> {
+        self.check(index, AnnotationKind::Categorical)?;
+        let count = self.category_count()?;
+        let blob_len = read_u64(self.data, 8 + count * 8)? as usize;
+        let codes_start = (8 + (count + 1) * 8 + blob_len).next_multiple_of(4);
+        read_u32(self.data, codes_start + index * 4)
+    }
+
+    /// Category names of a categorical column
+    pub fn categories(&self) -> Result<Vec<&'a str>> {
+        if self.kind != AnnotationKind::Categorical {
+            return Err(Error::InvalidState(
+                "Annotation column has a different type",
+            ));
+        }
+        let count = self.category_count()?;
+        (0..count)
+            .map(|i| read_string(self.category_names()?, count, i))
+            .collect()
+    }
+
+    fn category_count(&self) -> Result<usize> {
+        Ok(read_u32(self.data, 0)? as usize)
+    }
+
+    /// String array holding the category names
+    fn category_names(&self) -> Result<&'a [u8]> {
+        self.data
+            .get(8..)
+            .ok_or(Error::InvalidState("Annotation data extends beyond table"))
+    }
+
+    /// Copy the column into an owned [`Annotation`]
+    pub fn to_annotation(&self) -> Result<Annotation> {
+        Ok(match self.kind {
+            AnnotationKind::String => Annotation::String(
+                (0..self.len)
+                    .map(|i| self.get_str(i).map(str::to_string))
+                    .collect::<Result<_>>()?,
+            ),
+            AnnotationKind::Categorical => Annotation::Categorical {
+                categories: self.categories()?.into_iter().map(str::to_string).collect(),
+                codes: (0..self.len).map(|i| self.code(i)).collect::<Result<_>>()?,
+            },
+            AnnotationKind::I64 => Annotation::I64(
+                (0..self.len)
+                    .map(|i| self.get_i64(i))
+                    .collect::<Result<_>>()?,
+            ),
+            AnnotationKind::F64 => Annotation::F64(
+                (0..self.len)
+                    .map(|i| self.get_f64(i))
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{MetadataBuilder, MetadataView};
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_annotations_roundtrip() {
+        let cell_type = Annotation::categorical(&["T", "B", "T", "NK"]);
+        let bytes = MetadataBuilder::new()
+            .with_row_annotation("cell_type", cell_type.clone())
+            .with_row_annotation(
+                "barcode",
+                Annotation::String(strings(&["a", "", "ccc", "d"])),
+            )
+            .with_row_annotation("n_genes", Annotation::I64(vec![10, -2, 30, 0]))
+            .with_col_annotation("score", Annotation::F64(vec![0.5, 1.5]))
+            .build()
+            .unwrap();
+        let view = MetadataView::new(&bytes).unwrap();
+
+        let rows = view.row_annotations().unwrap().unwrap();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows.column_names().unwrap(),
+            ["cell_type", "barcode", "n_genes"]
+        );
+        let column = rows.column("cell_type").unwrap().unwrap();
+        assert_eq!(column.to_annotation().unwrap(), cell_type);
+        assert_eq!(column.categories().unwrap(), ["T", "B", "NK"]);
+        assert_eq!(column.get_str(3).unwrap(), "NK");
+        assert_eq!(
+            rows.column("barcode").unwrap().unwrap().get_str(2).unwrap(),
+            "ccc"
+        );
+        assert_eq!(
+            rows.column("n_genes").unwrap().unwrap().get_i64(1).unwrap(),
+            -2
+        );
+        assert!(rows.column("missing").unwrap().is_none());
+
+        let cols = view.col_annotations().unwrap().unwrap();
+        let score = cols.column("score").unwrap().unwrap();
+        assert_eq!(score.get_f64(1).unwrap(), 1.5);
+        assert!(score.get_i64(0).is_err());
+        assert!(score.get_f64(2).is_err());
+    }
+
+    #[test]
+    fn test_annotation_filters() {
+        let bytes = MetadataBuilder::new()
+            .with_row_annotation("kind", Annotation::categorical(&["x", "y", "x"]))
+            .with_row_annotation("name", Annotation::String(strings(&["ab", "b", "abc"])))
+            .with_row_annotation("count", Annotation::I64(vec![1, 5, 3]))
+            .with_row_annotation("weight", Annotation::F64(vec![0.1, 0.9, 0.5]))
+            .build()
+            .unwrap();
+        let view = MetadataView::new(&bytes).unwrap();
+        let table = view.row_annotations().unwrap().unwrap();
+
+        assert_eq!(table.filter_in("kind", &["x"]).unwrap(), [0, 2]);
+        assert_eq!(
+            table.filter_str("name", |s| s.starts_with("ab")).unwrap(),
+            [0, 2]
+        );
+        assert_eq!(table.filter_i64("count", |v| v > 2).unwrap(), [1, 2]);
+        assert_eq!(table.filter_f64("weight", |v| v < 0.6).unwrap(), [0, 2]);
+        assert!(table.filter_i64("missing", |_| true).is_err());
+    }
+
+    #[test]
+    fn test_annotation_validation() {
+        // Length must match the labels of the axis
+        let result = MetadataBuilder::new()
+            .with_row_labels(vec![b"a".to_vec(), b"b".to_vec()])
+            .with_row_annotation("n", Annotation::I64(vec![1]))
+            .build();
+        assert!(result.is_err());
+
+        let result = MetadataBuilder::new()
+            .with_col_annotation("n", Annotation::I64(vec![1]))
+            .with_col_annotation("n", Annotation::I64(vec![2]))
+            .build();
+        assert!(result.is_err());
+
+        let codes = Annotation::Categorical {
+            categories: strings(&["a"]),
+            codes: vec![1],
+        };
+        assert!(encode_table(&[("c".to_string(), codes)], 1).is_err());
+    }
+
+    #[test]
+    fn test_truncated_table() {
+        let table = encode_table(
+            &[("s".to_string(), Annotation::String(strings(&["hello"])))],
+            1,
+        )
+        .unwrap();
+        let truncated = AnnotationTable::from_bytes(&table[..table.len() - 2]).unwrap();
+        assert!(truncated.column_at(0).is_err());
+        assert!(truncated.column_at(1).is_err());
+    }
+}
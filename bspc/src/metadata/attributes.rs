@@ -0,0 +1,275 @@
+//! Free-form key/value file attributes
+//!
+//! Attributes record provenance such as the source URL, pipeline version,
+//! creation time or units. Keys are UTF-8 strings and values are typed scalars
+//! or JSON text.
+
+use binsparse_rs::{Error, Result};
+use bspc_core::format::constants::metadata::{
+    ATTRIBUTE_BLOCK_HEADER_SIZE, ATTRIBUTE_ENTRY_HEADER_SIZE,
+};
+use bspc_core::format::metadata::{AttributeEntryHeader, AttributeKind};
+use std::fmt;
+
+/// Value of a file attribute
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    /// UTF-8 string
+    String(String),
+    /// Signed integer
+    I64(i64),
+    /// Floating point number
+    F64(f64),
+    /// Boolean flag
+    Bool(bool),
+    /// JSON document stored as text
+    Json(String),
+}
+
+impl AttributeValue {
+    /// Value type
+    pub fn kind(&self) -> AttributeKind {
+        match self {
+            AttributeValue::String(_) => AttributeKind::String,
+            AttributeValue::I64(_) => AttributeKind::I64,
+            AttributeValue::F64(_) => AttributeKind::F64,
+            AttributeValue::Bool(_) => AttributeKind::Bool,
+            AttributeValue::Json(_) => AttributeKind::Json,
+        }
+    }
+
+    /// Parse a JSON value
+    #[cfg(feature = "serde")]
+    pub fn as_json(&self) -> Option<serde_json::Value> {
+        match self {
+            AttributeValue::Json(text) => serde_json::from_str(text).ok(),
+            _ => None,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            AttributeValue::String(value) | AttributeValue::Json(value) => {
+                value.as_bytes().to_vec()
+            }
+            AttributeValue::I64(value) => value.to_le_bytes().to_vec(),
+            AttributeValue::F64(value) => value.to_le_bytes().to_vec(),
+            AttributeValue::Bool(value) => vec![u8::from(*value)],
+        }
+    }
+
+    fn decode(kind: AttributeKind, bytes: &[u8]) -> Result<Self> {
+        let text = || {
+            std::str::from_utf8(bytes)
+                .map(str::to_string)
+                .map_err(|_| Error::InvalidState("Invalid UTF-8 in attribute value"))
+        };
+        let fixed = |len: usize| -> Result<[u8; 8]> {
+            if bytes.len() != len {
+                return Err(Error::InvalidState("Invalid attribute value size"));
+            }
+            let mut buf = [0u8; 8];
+            buf[..len].copy_from_slice(bytes);
+            Ok(buf)
+        };
+
+        Ok(match kind {
+            AttributeKind::String => AttributeValue::String(text()?),
+            AttributeKind::Json => AttributeValue::Json(text()?),
+            AttributeKind::I64 => AttributeValue::I64(i64::from_le_bytes(fixed(8)?)),
+            AttributeKind::F64 => AttributeValue::F64(f64::from_le_bytes(fixed(8)?)),
+            AttributeKind::Bool => AttributeValue::Bool(fixed(1)?[0] != 0),
+        })
+    }
+}
+
+impl fmt::Display for AttributeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeValue::String(value) | AttributeValue::Json(value) => write!(f, "{value}"),
+            AttributeValue::I64(value) => write!(f, "{value}"),
+            AttributeValue::F64(value) => write!(f, "{value}"),
+            AttributeValue::Bool(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::String(value.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::I64(value)
+    }
+}
+
+impl From<f64> for AttributeValue {
+    fn from(value: f64) -> Self {
+        AttributeValue::F64(value)
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        AttributeValue::Bool(value)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Value> for AttributeValue {
+    fn from(value: serde_json::Value) -> Self {
+        AttributeValue::Json(value.to_string())
+    }
+}
+
+/// Encode the attribute block (empty when there are no attributes)
+pub(crate) fn encode_attributes(attributes: &[(String, AttributeValue)]) -> Result<Vec<u8>> {
+    if attributes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let count =
+        u32::try_from(attributes.len()).map_err(|_| Error::InvalidState("Too many attributes"))?;
+
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&count.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+
+    for (key, value) in attributes {
+        let encoded = value.encode();
+        let header = AttributeEntryHeader {
+            kind: value.kind(),
+            key_len: u32::try_from(key.len())
+                .map_err(|_| Error::InvalidState("Attribute key too long"))?,
+            value_len: encoded.len() as u64,
+        };
+        let start = buf.len();
+        buf.extend_from_slice(&header.to_bytes());
+        buf.extend_from_slice(key.as_bytes());
+        buf.extend_from_slice(&encoded);
+        buf.resize(start + header.entry_size(), 0);
+    }
+
+    Ok(buf)
+}
+
+/// Decode every attribute in a block, in storage order
+pub(crate) fn decode_attributes(data: &[u8]) -> Result<Vec<(&str, AttributeValue)>> {
+    if data.len() < ATTRIBUTE_BLOCK_HEADER_SIZE {
+        return Err(Error::InvalidState("Invalid attribute block header"));
+    }
+    let count = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+
+    // Every entry takes at least a header, so a corrupt count can't over-allocate
+    let max_entries = (data.len() - ATTRIBUTE_BLOCK_HEADER_SIZE) / ATTRIBUTE_ENTRY_HEADER_SIZE;
+    let mut attributes = Vec::with_capacity((count as usize).min(max_entries));
+    let mut pos = ATTRIBUTE_BLOCK_HEADER_SIZE;
+    for _ in 0..count {
+        let header = AttributeEntryHeader::from_bytes(&data[pos.min(data.len())..])
+            .map_err(|_| Error::InvalidState("Invalid attribute entry header"))?;
+
+        let key_start = pos + ATTRIBUTE_ENTRY_HEADER_SIZE;
+        let (value_start, value_end) = key_start
+            .checked_add(header.key_len as usize)
+            .and_then(|value_start| {
+                let value_len = usize::try_from(header.value_len).ok()?;
+                Some((value_start, value_start.checked_add(value_len)?))
+            })
+            .filter(|&(_, value_end)| value_end <= data.len())
+            .ok_or(Error::InvalidState("Attribute extends beyond block"))?;
+
+        let key = std::str::from_utf8(&data[key_start..value_start])
+            .map_err(|_| Error::InvalidState("Invalid UTF-8 in attribute key"))?;
+        let value = AttributeValue::decode(header.kind, &data[value_start..value_end])?;
+        attributes.push((key, value));
+        // Entries are padded to 8 bytes; value_end is in bounds so this can't overflow
+        pos = value_end.next_multiple_of(8);
+    }
+
+    Ok(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{MetadataBuilder, MetadataView};
+
+    #[test]
+    fn test_attributes_roundtrip() {
+        let bytes = MetadataBuilder::new()
+            .with_attribute("source", "https://example.org/data")
+            .with_attribute("version", 3i64)
+            .with_attribute("scale", 0.25)
+            .with_attribute("normalized", true)
+            .with_attribute("version", 4i64)
+            .build()
+            .unwrap();
+        let view = MetadataView::new(&bytes).unwrap();
+
+        let attributes = view.attributes().unwrap();
+        assert_eq!(
+            attributes,
+            [
+                ("source", AttributeValue::from("https://example.org/data")),
+                ("version", AttributeValue::I64(4)),
+                ("scale", AttributeValue::F64(0.25)),
+                ("normalized", AttributeValue::Bool(true)),
+            ]
+        );
+        assert_eq!(
+            view.attribute("scale").unwrap(),
+            Some(AttributeValue::F64(0.25))
+        );
+        assert_eq!(view.attribute("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_no_attributes() {
+        let bytes = MetadataBuilder::new().build().unwrap();
+        let view = MetadataView::new(&bytes).unwrap();
+        assert!(view.attributes().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_attribute() {
+        let value = AttributeValue::from(serde_json::json!({ "units": "counts" }));
+        let block = encode_attributes(&[("info".to_string(), value.clone())]).unwrap();
+        let decoded = decode_attributes(&block).unwrap();
+        assert_eq!(decoded, [("info", value)]);
+        assert_eq!(
+            decoded[0].1.as_json().unwrap()["units"],
+            serde_json::json!("counts")
+        );
+    }
+
+    #[test]
+    fn test_corrupt_attribute_lengths() {
+        let block = encode_attributes(&[("k".to_string(), AttributeValue::I64(7))]).unwrap();
+
+        // Entry header starts after the block header: kind, pad, key_len, value_len
+        let key_len = ATTRIBUTE_BLOCK_HEADER_SIZE + 4;
+        let value_len = ATTRIBUTE_BLOCK_HEADER_SIZE + 8;
+
+        let mut corrupt = block.clone();
+        corrupt[value_len..value_len + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(decode_attributes(&corrupt).is_err());
+
+        let mut corrupt = block.clone();
+        corrupt[key_len..key_len + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode_attributes(&corrupt).is_err());
+
+        // A huge count fails on the missing entries instead of allocating
+        let mut corrupt = block;
+        corrupt[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decode_attributes(&corrupt).is_err());
+    }
+}
@@ -5,19 +5,26 @@
 //!
 //! # Architecture
 //!
-//! The module is split into three main components:
+//! The module is split into six main components:
 //! - `mmap_core`: Core memory mapping types and traits
+//! - `blocks`: Block-wise decoding of compressed files
 //! - `matrix_operations`: Matrix operations, views, and iterators
 //! - `file_io`: File I/O operations and streaming writers
+//! - `top_k`: Top-k queries over rows and columns
+//! - `combine`: Vertical and horizontal concatenation of files
 
 // Declare submodules
+pub(crate) mod blocks;
+pub(crate) mod combine;
 pub(crate) mod file_io;
 pub(crate) mod matrix_operations;
 pub(crate) mod mmap_core;
+pub(crate) mod top_k;
 
 // Re-export main public types
 pub use file_io::BspcFile;
 pub use matrix_operations::{
-    DynamicElement, DynamicMatrix, DynamicMatrixRowIterator, SubmatrixView,
+    DynamicElement, DynamicMatrix, DynamicMatrixRowIterator, DynamicTopKRows, SubmatrixView,
 };
 pub use mmap_core::{MatrixElement, MmapMatrix};
+pub use top_k::TopKRows;
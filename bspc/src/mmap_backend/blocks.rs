@@ -0,0 +1,227 @@
+//! Block-wise decoding for compressed .bspc files
+//!
+//! Compressed files cannot be viewed as typed slices, so the matrix keeps the
+//! block table and decodes on demand. Point and row-range queries decode only
+//! the blocks whose row span overlaps the query; whole-array accessors decode
+//! every block once and keep the result.
+
+use super::mmap_core::MatrixElement;
+use crate::compression::{decode_indices, decode_values};
+use binsparse_rs::{Error, Result};
+use bspc_core::{BlockTableEntry, BspcHeader, IndexCodec, MatrixIndex, ValueCodec};
+use rayon::prelude::*;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Block table and lazily decoded arrays of a compressed matrix
+pub(crate) struct BlockStore<T: MatrixElement, I: MatrixIndex> {
+    entries: Vec<BlockTableEntry>,
+    block_len: usize,
+    nnz: usize,
+    index_codec: IndexCodec,
+    value_codec: ValueCodec,
+    values: OnceLock<Vec<T>>,
+    row_indices: OnceLock<Vec<I>>,
+    col_indices: OnceLock<Vec<I>>,
+}
+
+impl<T: MatrixElement, I: MatrixIndex> BlockStore<T, I> {
+    /// Parse and validate the block table of a mapped file
+    pub(crate) fn new(header: &BspcHeader, data: &[u8]) -> Result<Self> {
+        // A matrix without elements has no blocks and so no block table
+        let (offset, size) = match header.block_table_region() {
+            Some(region) => region,
+            None if header.nnz == 0 => (0, 0),
+            None => return Err(Error::InvalidState("File has no block table")),
+        };
+        let index_codec = header
+            .index_codec()
+            .ok_or(Error::InvalidState("Unsupported index codec"))?;
+        let value_codec = header
+            .value_codec()
+            .ok_or(Error::InvalidState("Unsupported value codec"))?;
+        if header.block_size_log2 > 30 {
+            return Err(Error::InvalidState("Block size too large"));
+        }
+
+        let start = offset as usize;
+        let end = start
+            .checked_add(size as usize)
+            .filter(|&end| end <= data.len())
+            .ok_or(Error::InvalidState("Block table extends beyond file"))?;
+        if (end - start) % BlockTableEntry::SIZE != 0 {
+            return Err(Error::InvalidState("Invalid block table size"));
+        }
+
+        let entries = data[start..end]
+            .chunks_exact(BlockTableEntry::SIZE)
+            .map(BlockTableEntry::from_bytes)
+            .collect::<core::result::Result<Vec<_>, _>>()
+            .map_err(|_| Error::InvalidState("Invalid block table"))?;
+
+        let block_len = header.block_len();
+        let nnz = header.nnz as usize;
+        if entries.len() != nnz.div_ceil(block_len) {
+            return Err(Error::InvalidState("Block count doesn't match nnz"));
+        }
+
+        let in_file = |offset: u64, size: u64| {
+            offset
+                .checked_add(size)
+                .is_some_and(|end| end <= data.len() as u64)
+        };
+        let mut prev_row = 0;
+        for entry in &entries {
+            if !in_file(entry.values_offset, entry.values_size)
+                || !in_file(entry.rows_offset, entry.rows_size)
+                || !in_file(entry.cols_offset, entry.cols_size)
+            {
+                return Err(Error::InvalidState("Block extends beyond file"));
+            }
+            if entry.first_row < prev_row || entry.last_row < entry.first_row {
+                return Err(Error::InvalidState("Block rows are not sorted"));
+            }
+            prev_row = entry.last_row;
+        }
+
+        Ok(Self {
+            entries,
+            block_len,
+            nnz,
+            index_codec,
+            value_codec,
+            values: OnceLock::new(),
+            row_indices: OnceLock::new(),
+            col_indices: OnceLock::new(),
+        })
+    }
+
+    /// Number of elements in a block
+    fn block_count(&self, block: usize) -> usize {
+        (self.nnz - block * self.block_len).min(self.block_len)
+    }
+
+    fn decode_rows(&self, data: &[u8], block: usize) -> Result<Vec<I>> {
+        let entry = &self.entries[block];
+        let bytes = &data[entry.rows_offset as usize..][..entry.rows_size as usize];
+        decode_indices(bytes, self.block_count(block), self.index_codec)
+    }
+
+    fn decode_cols(&self, data: &[u8], block: usize) -> Result<Vec<I>> {
+        let entry = &self.entries[block];
+        let bytes = &data[entry.cols_offset as usize..][..entry.cols_size as usize];
+        decode_indices(bytes, self.block_count(block), self.index_codec)
+    }
+
+    fn decode_values(&self, data: &[u8], block: usize) -> Result<Vec<T>> {
+        let entry = &self.entries[block];
+        let bytes = &data[entry.values_offset as usize..][..entry.values_size as usize];
+        decode_values(bytes, self.block_count(block), self.value_codec)
+    }
+
+    /// Blocks whose row span overlaps `start..end`
+    fn blocks_for_rows(&self, start: usize, end: usize) -> Range<usize> {
+        let first = self
+            .entries
+            .partition_point(|entry| (entry.last_row as usize) < start);
+        let last = self
+            .entries
+            .partition_point(|entry| (entry.first_row as usize) < end);
+        first..last.max(first)
+    }
+
+    /// Look up one element, decoding only the blocks that hold its row
+    pub(crate) fn get_element(&self, data: &[u8], row: usize, col: usize) -> Result<Option<T>> {
+        for block in self.blocks_for_rows(row, row + 1) {
+            let rows = self.decode_rows(data, block)?;
+            let cols = self.decode_cols(data, block)?;
+            let key = |i: usize| (rows[i].to_usize(), cols[i].to_usize());
+
+            // Binary search on the (row, col) key within the block
+            let (mut left, mut right) = (0, rows.len());
+            while left < right {
+                let mid = left + (right - left) / 2;
+                if key(mid) < (row, col) {
+                    left = mid + 1;
+                } else {
+                    right = mid;
+                }
+            }
+            if left < rows.len() && key(left) == (row, col) {
+                return Ok(Some(self.decode_values(data, block)?[left]));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Collect the elements of rows `start..end`, decoding only overlapping blocks
+    pub(crate) fn row_range(
+        &self,
+        data: &[u8],
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<(usize, usize, T)>> {
+        let mut elements = Vec::new();
+        for block in self.blocks_for_rows(start, end) {
+            let rows = self.decode_rows(data, block)?;
+            let cols = self.decode_cols(data, block)?;
+            let values = self.decode_values(data, block)?;
+            elements.extend(
+                rows.iter()
+                    .zip(&cols)
+                    .zip(values)
+                    .map(|((row, col), value)| (row.to_usize(), col.to_usize(), value))
+                    .filter(|(row, _, _)| (start..end).contains(row)),
+            );
+        }
+        Ok(elements)
+    }
+
+    /// Decode every block in parallel and concatenate the results
+    ///
+    /// The block table was validated on open, but the encoded data is only
+    /// checked here, so a corrupt block fails the whole array.
+    fn decode_all<V: Send>(
+        &self,
+        decode: impl Fn(usize) -> Result<Vec<V>> + Send + Sync,
+    ) -> Result<Vec<V>> {
+        let blocks = (0..self.entries.len())
+            .into_par_iter()
+            .map(decode)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(blocks.into_iter().flatten().collect())
+    }
+
+    /// All values, decoded on first use
+    pub(crate) fn values(&self, data: &[u8]) -> Result<&[T]> {
+        cached(&self.values, || {
+            self.decode_all(|block| self.decode_values(data, block))
+        })
+    }
+
+    /// All row indices, decoded on first use
+    pub(crate) fn row_indices(&self, data: &[u8]) -> Result<&[I]> {
+        cached(&self.row_indices, || {
+            self.decode_all(|block| self.decode_rows(data, block))
+        })
+    }
+
+    /// All column indices, decoded on first use
+    pub(crate) fn col_indices(&self, data: &[u8]) -> Result<&[I]> {
+        cached(&self.col_indices, || {
+            self.decode_all(|block| self.decode_cols(data, block))
+        })
+    }
+}
+
+/// Get the array in `cell`, decoding it on first use
+///
+/// Failures are not cached. Threads racing on the first use may each decode,
+/// but only one result is kept.
+fn cached<V>(cell: &OnceLock<Vec<V>>, decode: impl FnOnce() -> Result<Vec<V>>) -> Result<&[V]> {
+    if let Some(array) = cell.get() {
+        return Ok(array);
+    }
+    let array = decode()?;
+    Ok(cell.get_or_init(|| array))
+}
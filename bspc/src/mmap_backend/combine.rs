@@ -0,0 +1,354 @@
+//! Vertical and horizontal concatenation of .bspc files
+//!
+//! This module combines several memory-mapped inputs into a single file. Inputs
+//! are read through their mappings and streamed section by section through the
+//! file writer, so no input is ever loaded fully into memory.
+
+use super::file_io::{write_streaming, BspcFile};
+use super::mmap_core::{MatrixElement, MmapMatrix};
+use binsparse_rs::{Error, Result};
+use bspc_core::DataType;
+use std::path::Path;
+
+/// Accessor for one dimension of an input
+type DimFn<T> = fn(&MmapMatrix<T>) -> usize;
+
+/// Stacking direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StackAxis {
+    /// Inputs share columns; rows are appended
+    Vertical,
+    /// Inputs share rows; columns are appended
+    Horizontal,
+}
+
+impl BspcFile {
+    /// Stack matrices vertically, appending the rows of each input in order
+    ///
+    /// All inputs must have the same data type and number of columns. Row labels
+    /// are concatenated; column labels are taken from the inputs and must agree.
+    pub async fn vstack<P: AsRef<Path>, Q: AsRef<Path>>(inputs: &[P], dst: Q) -> Result<()> {
+        stack_files(inputs, dst.as_ref(), StackAxis::Vertical).await
+    }
+
+    /// Stack matrices horizontally, appending the columns of each input in order
+    ///
+    /// All inputs must have the same data type and number of rows. Column labels
+    /// are concatenated; row labels are taken from the inputs and must agree.
+    pub async fn hstack<P: AsRef<Path>, Q: AsRef<Path>>(inputs: &[P], dst: Q) -> Result<()> {
+        stack_files(inputs, dst.as_ref(), StackAxis::Horizontal).await
+    }
+}
+
+/// Resolve the input data type and dispatch to the typed implementation
+async fn stack_files<P: AsRef<Path>>(inputs: &[P], dst: &Path, axis: StackAxis) -> Result<()> {
+    let first = inputs
+        .first()
+        .ok_or(Error::InvalidState("No input files to stack"))?;
+    let data_type = DataType::from_u8(BspcFile::open(first)?.header.data_type)
+        .ok_or(Error::InvalidState("Unsupported data type"))?;
+
+    dispatch_data_type!(data_type, |T| stack_typed::<T, P>(inputs, dst, axis).await)
+}
+
+async fn stack_typed<T: MatrixElement, P: AsRef<Path>>(
+    inputs: &[P],
+    dst: &Path,
+    axis: StackAxis,
+) -> Result<()> {
+    let matrices = inputs
+        .iter()
+        .map(|path| {
+            let matrix = MmapMatrix::<T>::from_file(path)?;
+            if matrix.header.data_type != T::data_type() as u8 {
+                return Err(Error::InvalidState("Input data types don't match"));
+            }
+            Ok(matrix)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Check the shared dimension and compute offsets along the stacked one
+    let (shared, stacked): (DimFn<T>, DimFn<T>) = match axis {
+        StackAxis::Vertical => (MmapMatrix::ncols, MmapMatrix::nrows),
+        StackAxis::Horizontal => (MmapMatrix::nrows, MmapMatrix::ncols),
+    };
+    let shared_dim = shared(&matrices[0]);
+    if matrices.iter().any(|m| shared(m) != shared_dim) {
+        return Err(Error::InvalidState("Input dimensions don't agree"));
+    }
+
+    let mut offsets = Vec::with_capacity(matrices.len());
+    let mut stacked_dim = 0usize;
+    for matrix in &matrices {
+        offsets.push(stacked_dim);
+        stacked_dim = stacked_dim
+            .checked_add(stacked(matrix))
+            .ok_or(Error::InvalidState("Stacked dimension would overflow"))?;
+    }
+
+    let metadata = merge_metadata(&matrices, axis)?;
+    let chunk_size = matrices[0].chunk_bloom_filter().chunk_size();
+    let arrays = matrices
+        .iter()
+        .map(|matrix| {
+            Ok((
+                matrix.values()?,
+                matrix.row_indices()?,
+                matrix.col_indices()?,
+            ))
+        })
+        .collect::<Result<Vec<Arrays<'_, T>>>>()?;
+    let arrays = &arrays[..];
+    let offsets = &offsets[..];
+
+    match axis {
+        StackAxis::Vertical => {
+            write_streaming(
+                dst,
+                stacked_dim,
+                shared_dim,
+                move || {
+                    arrays.iter().zip(offsets).flat_map(|(&arrays, &offset)| {
+                        let (values, row_indices, col_indices) = arrays;
+                        (0..values.len()).map(move |i| {
+                            (
+                                row_indices[i] as usize + offset,
+                                col_indices[i] as usize,
+                                values[i],
+                            )
+                        })
+                    })
+                },
+                chunk_size,
+                metadata.as_deref(),
+            )
+            .await
+        }
+        StackAxis::Horizontal => {
+            write_streaming(
+                dst,
+                shared_dim,
+                stacked_dim,
+                move || hstack_elements(arrays, offsets),
+                chunk_size,
+                metadata.as_deref(),
+            )
+            .await
+        }
+    }
+}
+
+/// Values, row indices and column indices of one input
+type Arrays<'a, T> = (&'a [T], &'a [u32], &'a [u32]);
+
+/// Merge sorted inputs row by row, offsetting the columns of each input
+fn hstack_elements<'a, T: MatrixElement>(
+    arrays: &'a [Arrays<'a, T>],
+    offsets: &'a [usize],
+) -> impl Iterator<Item = (usize, usize, T)> + 'a {
+    let mut cursors = vec![0usize; arrays.len()];
+    let mut current: Option<(u32, usize)> = None;
+
+    std::iter::from_fn(move || loop {
+        if let Some((row, input)) = current {
+            let (values, row_indices, col_indices) = arrays[input];
+            let pos = cursors[input];
+            if row_indices.get(pos) == Some(&row) {
+                cursors[input] += 1;
+                return Some((
+                    row as usize,
+                    col_indices[pos] as usize + offsets[input],
+                    values[pos],
+                ));
+            }
+
+            // This input has no more elements in the row; move to the next one
+            current = (input + 1 < arrays.len()).then_some((row, input + 1));
+            continue;
+        }
+
+        // Start the smallest row still pending in any input
+        let row = arrays
+            .iter()
+            .zip(&cursors)
+            .filter_map(|((_, row_indices, _), &pos)| row_indices.get(pos).copied())
+            .min()?;
+        current = Some((row, 0));
+    })
+}
+
+/// Read the labels of one axis of an input, if present
+fn axis_labels<T: MatrixElement>(
+    matrix: &MmapMatrix<T>,
+    rows: bool,
+) -> Result<Option<Vec<Vec<u8>>>> {
+    let Some(view) = matrix.metadata_view()? else {
+        return Ok(None);
+    };
+    let labels = if rows {
+        view.row_labels()?
+    } else {
+        view.col_labels()?
+    };
+    labels.map(|labels| labels.to_vec()).transpose()
+}
+
+/// Build the output metadata from the labels of every input
+fn merge_metadata<T: MatrixElement>(
+    matrices: &[MmapMatrix<T>],
+    axis: StackAxis,
+) -> Result<Option<Vec<u8>>> {
+    let stacked_rows = axis == StackAxis::Vertical;
+
+    // Labels along the stacked axis are concatenated and must be present on all inputs
+    let stacked = matrices
+        .iter()
+        .map(|m| axis_labels(m, stacked_rows))
+        .collect::<Result<Vec<_>>>()?;
+    let stacked_labels = if stacked.iter().all(Option::is_none) {
+        None
+    } else if stacked.iter().all(Option::is_some) {
+        Some(stacked.into_iter().flatten().flatten().collect::<Vec<_>>())
+    } else {
+        return Err(Error::InvalidState("Labels missing from some inputs"));
+    };
+
+    // Labels along the shared axis must agree wherever they are present
+    let mut shared_labels: Option<Vec<Vec<u8>>> = None;
+    for matrix in matrices {
+        if let Some(labels) = axis_labels(matrix, !stacked_rows)? {
+            match &shared_labels {
+                Some(existing) if *existing != labels => {
+                    return Err(Error::InvalidState("Shared labels differ between inputs"));
+                }
+                Some(_) => {}
+                None => shared_labels = Some(labels),
+            }
+        }
+    }
+
+    let (row_labels, col_labels) = if stacked_rows {
+        (stacked_labels, shared_labels)
+    } else {
+        (shared_labels, stacked_labels)
+    };
+    if row_labels.is_none() && col_labels.is_none() {
+        return Ok(None);
+    }
+
+    let mut builder = crate::metadata::MetadataBuilder::new();
+    if let Some(labels) = row_labels {
+        builder = builder.with_row_labels(labels);
+    }
+    if let Some(labels) = col_labels {
+        builder = builder.with_col_labels(labels);
+    }
+    Ok(Some(builder.build()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{coo, write_labelled, TempDir};
+
+    fn labels(matrix: &MmapMatrix<impl MatrixElement>, rows: bool) -> Option<Vec<Vec<u8>>> {
+        axis_labels(matrix, rows).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_vstack() {
+        let dir = TempDir::new();
+        let a = write_labelled(
+            &dir,
+            "a.bspc",
+            (2, 3),
+            &[(0, 1, 1u16), (1, 2, 2)],
+            &[b"a0", b"a1"],
+            &[b"x", b"y", b"z"],
+        )
+        .await;
+        let b = write_labelled(&dir, "b.bspc", (1, 3), &[(0, 0, 3u16)], &[b"b0"], &[]).await;
+        let out = dir.file("out.bspc");
+
+        BspcFile::vstack(&[a, b], &out).await.unwrap();
+
+        let result: MmapMatrix<u16> = MmapMatrix::from_file(&out).unwrap();
+        assert_eq!((result.nrows(), result.ncols()), (3, 3));
+        assert_eq!(coo(&result), vec![(0, 1, 1), (1, 2, 2), (2, 0, 3)]);
+        assert_eq!(
+            labels(&result, true),
+            Some(vec![b"a0".to_vec(), b"a1".to_vec(), b"b0".to_vec()])
+        );
+        assert_eq!(
+            labels(&result, false),
+            Some(vec![b"x".to_vec(), b"y".to_vec(), b"z".to_vec()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hstack_interleaves_rows() {
+        let dir = TempDir::new();
+        let a = write_labelled(
+            &dir,
+            "a.bspc",
+            (3, 2),
+            &[(0, 0, 1.0f32), (2, 1, 2.0)],
+            &[],
+            &[b"a0", b"a1"],
+        )
+        .await;
+        let b = write_labelled(
+            &dir,
+            "b.bspc",
+            (3, 1),
+            &[(0, 0, 3.0f32), (1, 0, 4.0)],
+            &[],
+            &[b"b0"],
+        )
+        .await;
+        let out = dir.file("out.bspc");
+
+        BspcFile::hstack(&[a, b], &out).await.unwrap();
+
+        let result: MmapMatrix<f32> = MmapMatrix::from_file(&out).unwrap();
+        assert_eq!((result.nrows(), result.ncols()), (3, 3));
+        assert_eq!(
+            coo(&result),
+            vec![(0, 0, 1.0), (0, 2, 3.0), (1, 2, 4.0), (2, 1, 2.0)]
+        );
+        assert_eq!(
+            labels(&result, false),
+            Some(vec![b"a0".to_vec(), b"a1".to_vec(), b"b0".to_vec()])
+        );
+        assert_eq!(labels(&result, true), None);
+
+        // The output is readable through the data type dispatch
+        let dynamic = BspcFile::read_dynamic_matrix(&out).unwrap();
+        assert_eq!(dynamic.data_type(), DataType::F32);
+        assert_eq!(dynamic.nnz(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_stack_rejects_mismatched_inputs() {
+        let dir = TempDir::new();
+        let a = write_labelled(&dir, "a.bspc", (2, 3), &[(0, 0, 1i8)], &[], &[]).await;
+        let narrow = write_labelled(&dir, "narrow.bspc", (2, 2), &[(0, 0, 1i8)], &[], &[]).await;
+        let other_type =
+            write_labelled(&dir, "other.bspc", (2, 3), &[(0, 0, 1i16)], &[], &[]).await;
+        let labelled = write_labelled(
+            &dir,
+            "labelled.bspc",
+            (2, 3),
+            &[(0, 0, 1i8)],
+            &[b"r0", b"r1"],
+            &[],
+        )
+        .await;
+        let out = dir.file("out.bspc");
+
+        assert!(BspcFile::vstack(&[&a, &narrow], &out).await.is_err());
+        assert!(BspcFile::vstack(&[&a, &other_type], &out).await.is_err());
+        assert!(BspcFile::vstack(&[&a, &labelled], &out).await.is_err());
+        assert!(BspcFile::vstack::<&Path, _>(&[], &out).await.is_err());
+    }
+}
@@ -5,8 +5,12 @@
 use super::matrix_operations::DynamicMatrix;
 use super::mmap_core::{MatrixElement, MmapMatrix};
 use binsparse_rs::{Error, Result};
-use bspc_core::{BspcHeader, DataType, MatrixFormat};
-use std::{fs::File, io::Read, path::Path};
+use bspc_core::{BlockTableEntry, BspcHeader, DataType, IndexWidth, MatrixFormat, MatrixIndex};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
 
 /// Helper struct for file layout calculations
 #[derive(Debug, Clone)]
@@ -20,9 +24,10 @@ struct FileLayout {
 }
 
 impl FileLayout {
-    fn calculate<T: MatrixElement>(nnz: usize) -> Result<Self> {
+    fn calculate<T: MatrixElement>(nnz: usize, index_width: IndexWidth) -> Result<Self> {
         let header_size = BspcHeader::SIZE as u64;
         let alignment = std::mem::align_of::<T>() as u64;
+        let index_size = index_width.size_bytes() as u64;
 
         let values_offset = header_size.div_ceil(alignment) * alignment;
         let values_size = (nnz as u64)
@@ -30,11 +35,14 @@ impl FileLayout {
             .ok_or(Error::InvalidState(
                 "Values size calculation would overflow",
             ))?;
-        let indices_0_offset = (values_offset + values_size).div_ceil(4) * 4;
-        let indices_0_size = (nnz as u64).checked_mul(4).ok_or(Error::InvalidState(
-            "Indices size calculation would overflow",
-        ))?;
-        let indices_1_offset = (indices_0_offset + indices_0_size).div_ceil(4) * 4;
+        let indices_0_offset = (values_offset + values_size).div_ceil(index_size) * index_size;
+        let indices_0_size = (nnz as u64)
+            .checked_mul(index_size)
+            .ok_or(Error::InvalidState(
+                "Indices size calculation would overflow",
+            ))?;
+        let indices_1_offset =
+            (indices_0_offset + indices_0_size).div_ceil(index_size) * index_size;
         let indices_1_size = indices_0_size;
 
         Ok(Self {
@@ -46,6 +54,38 @@ impl FileLayout {
             indices_1_size,
         })
     }
+
+    /// Offset of the bloom filter, directly after the column indices
+    fn bloom_filter_offset(&self) -> u64 {
+        self.indices_1_offset + self.indices_1_size
+    }
+
+    /// Header of an uncompressed COO file with this layout
+    fn coo_header<T: MatrixElement>(
+        &self,
+        nrows: usize,
+        ncols: usize,
+        nnz: usize,
+        index_width: IndexWidth,
+        bloom_filter_size: usize,
+    ) -> BspcHeader {
+        let mut header = BspcHeader::new();
+        header.nrows = nrows as u64;
+        header.ncols = ncols as u64;
+        header.nnz = nnz as u64;
+        header.format_type = MatrixFormat::Coo as u8;
+        header.data_type = T::data_type() as u8;
+        header.set_index_width(index_width);
+        header.values_offset = self.values_offset;
+        header.values_size = self.values_size;
+        header.indices_0_offset = self.indices_0_offset;
+        header.indices_0_size = self.indices_0_size;
+        header.indices_1_offset = self.indices_1_offset;
+        header.indices_1_size = self.indices_1_size;
+        header.bloom_filter_offset = self.bloom_filter_offset();
+        header.bloom_filter_size = bloom_filter_size as u64;
+        header
+    }
 }
 
 /// Helper for creating bloom filter
@@ -71,6 +111,393 @@ fn create_bloom_filter(
     bloom_filter
 }
 
+/// Write zero bytes to advance a writer to an aligned offset
+fn write_padding<W: Write>(writer: &mut W, target_offset: u64, current_pos: u64) -> Result<()> {
+    if target_offset > current_pos {
+        let padding = vec![0u8; (target_offset - current_pos) as usize];
+        writer
+            .write_all(&padding)
+            .map_err(|_| Error::IoError("Failed to write padding"))?;
+    }
+    Ok(())
+}
+
+/// Convert an index to its on-disk form without truncating
+fn index_to<I: MatrixIndex>(index: usize) -> Result<I> {
+    I::from_usize(index).ok_or(Error::InvalidState("Index does not fit in index width"))
+}
+
+/// Write one index with the given on-disk width
+fn write_index<W: Write>(writer: &mut W, index: usize, width: IndexWidth) -> Result<()> {
+    let written = match width {
+        IndexWidth::U32 => writer.write_all(&index_to::<u32>(index)?.to_le_bytes()),
+        IndexWidth::U64 => writer.write_all(&index_to::<u64>(index)?.to_le_bytes()),
+    };
+    written.map_err(|_| Error::IoError("Failed to write indices"))
+}
+
+/// Serialize a bloom filter with sizes stored at the given index width
+fn serialize_bloom_filter(
+    bloom_filter: &crate::chunk_bloom_filter::ChunkBloomFilter,
+    width: IndexWidth,
+) -> Result<Vec<u8>> {
+    match width {
+        IndexWidth::U32 => bloom_filter.serialize_with::<u32>(),
+        IndexWidth::U64 => bloom_filter.serialize_with::<u64>(),
+    }
+    .map_err(Error::InvalidState)
+}
+
+/// One block of a compressed file, encoded and ready to write
+struct EncodedBlock {
+    values: Vec<u8>,
+    rows: Vec<u8>,
+    cols: Vec<u8>,
+    first_row: u64,
+    last_row: u64,
+}
+
+/// Write a matrix as independently encoded blocks
+///
+/// Each array section holds its encoded blocks back to back. The block table
+/// follows the bloom filter and records where every block lives and which rows
+/// it spans, so readers decode only the blocks a query touches. Elements must
+/// be sorted by `(row, col)`.
+async fn write_compressed<T: MatrixElement>(
+    nrows: usize,
+    ncols: usize,
+    sparse_elements: &[(usize, usize, T)],
+    config: &crate::chunked_backend::ChunkConfig,
+    compression: crate::compression::CompressionConfig,
+    path: &Path,
+) -> Result<()> {
+    use crate::compression::{encode_indices, encode_values};
+    use rayon::prelude::*;
+
+    if compression.block_size_log2 > 30 {
+        return Err(Error::InvalidState("Block size too large"));
+    }
+    if sparse_elements
+        .iter()
+        .any(|&(row, col, _)| row >= nrows || col >= ncols)
+    {
+        return Err(Error::InvalidState(
+            "Element index exceeds matrix dimensions",
+        ));
+    }
+    // Readers binary search the block table by row and each block by position
+    if sparse_elements
+        .windows(2)
+        .any(|pair| (pair[0].0, pair[0].1) > (pair[1].0, pair[1].1))
+    {
+        return Err(Error::InvalidState(
+            "Elements are not sorted by row and column",
+        ));
+    }
+
+    let index_width = IndexWidth::for_dims(nrows as u64, ncols as u64);
+    let (blocks, bloom_filter_data) = rayon::join(
+        || {
+            sparse_elements
+                .par_chunks(compression.block_len())
+                .map(|block| {
+                    let rows: Vec<u64> = block.iter().map(|&(row, _, _)| row as u64).collect();
+                    let cols: Vec<u64> = block.iter().map(|&(_, col, _)| col as u64).collect();
+                    let values: Vec<T> = block.iter().map(|&(_, _, value)| value).collect();
+                    Ok(EncodedBlock {
+                        values: encode_values(&values, compression.value_codec, compression.level)?,
+                        rows: encode_indices(&rows, index_width, compression.index_codec)?,
+                        cols: encode_indices(&cols, index_width, compression.index_codec)?,
+                        first_row: rows[0],
+                        last_row: rows[rows.len() - 1],
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        },
+        || {
+            serialize_bloom_filter(
+                &create_bloom_filter(sparse_elements, nrows, config),
+                index_width,
+            )
+        },
+    );
+    let (blocks, bloom_filter_data) = (blocks?, bloom_filter_data?);
+
+    // Lay out the sections and the table entry of every block
+    let section_size =
+        |part: fn(&EncodedBlock) -> &[u8]| blocks.iter().map(|b| part(b).len() as u64).sum::<u64>();
+    let sections: [fn(&EncodedBlock) -> &[u8]; 3] = [
+        |block| &block.values,
+        |block| &block.rows,
+        |block| &block.cols,
+    ];
+    let values_offset = crate::metadata::align_to_8(BspcHeader::SIZE as u64);
+    let values_size = section_size(sections[0]);
+    let indices_0_offset = crate::metadata::align_to_8(values_offset + values_size);
+    let indices_0_size = section_size(sections[1]);
+    let indices_1_offset = crate::metadata::align_to_8(indices_0_offset + indices_0_size);
+    let indices_1_size = section_size(sections[2]);
+    let bloom_filter_offset = crate::metadata::align_to_8(indices_1_offset + indices_1_size);
+    let block_table_offset =
+        crate::metadata::align_to_8(bloom_filter_offset + bloom_filter_data.len() as u64);
+
+    let mut entries = Vec::with_capacity(blocks.len());
+    let (mut values_pos, mut rows_pos, mut cols_pos) =
+        (values_offset, indices_0_offset, indices_1_offset);
+    for block in &blocks {
+        entries.push(BlockTableEntry {
+            first_row: block.first_row,
+            last_row: block.last_row,
+            values_offset: values_pos,
+            values_size: block.values.len() as u64,
+            rows_offset: rows_pos,
+            rows_size: block.rows.len() as u64,
+            cols_offset: cols_pos,
+            cols_size: block.cols.len() as u64,
+        });
+        values_pos += block.values.len() as u64;
+        rows_pos += block.rows.len() as u64;
+        cols_pos += block.cols.len() as u64;
+    }
+
+    let mut header = BspcHeader::new();
+    header.nrows = nrows as u64;
+    header.ncols = ncols as u64;
+    header.nnz = sparse_elements.len() as u64;
+    header.format_type = MatrixFormat::Coo as u8;
+    header.data_type = T::data_type() as u8;
+    header.set_index_width(index_width);
+    header.set_compression(
+        compression.index_codec,
+        compression.value_codec,
+        compression.block_size_log2,
+    );
+    header.values_offset = values_offset;
+    header.values_size = values_size;
+    header.indices_0_offset = indices_0_offset;
+    header.indices_0_size = indices_0_size;
+    header.indices_1_offset = indices_1_offset;
+    header.indices_1_size = indices_1_size;
+    header.bloom_filter_offset = bloom_filter_offset;
+    header.bloom_filter_size = bloom_filter_data.len() as u64;
+    header.block_table_offset = block_table_offset;
+    header.block_table_size = (entries.len() * BlockTableEntry::SIZE) as u64;
+
+    let mut writer = StreamWriter::create(path).await?;
+    writer.start(&header, values_offset)?;
+
+    let regions = [
+        (values_offset, values_size),
+        (indices_0_offset, indices_0_size),
+        (indices_1_offset, indices_1_size),
+    ];
+    let mut position = values_offset;
+    for ((offset, size), part) in regions.into_iter().zip(sections) {
+        write_padding(&mut writer.buffer, offset, position)?;
+        for block in &blocks {
+            writer.write_bytes(part(block)).await?;
+        }
+        position = offset + size;
+    }
+
+    write_padding(&mut writer.buffer, bloom_filter_offset, position)?;
+    writer.write_bytes(&bloom_filter_data).await?;
+    write_padding(
+        &mut writer.buffer,
+        block_table_offset,
+        bloom_filter_offset + bloom_filter_data.len() as u64,
+    )?;
+    for entry in &entries {
+        writer.write_bytes(&entry.to_bytes()).await?;
+    }
+    writer.finish().await
+}
+
+/// Bytes of a streamed section buffered before each write
+const STREAM_BUFFER_BYTES: usize = 1 << 20;
+
+/// Buffered writer for sections produced one element at a time
+struct StreamWriter {
+    file: tokio::fs::File,
+    buffer: Vec<u8>,
+}
+
+impl StreamWriter {
+    async fn create(path: &Path) -> Result<Self> {
+        let file = tokio::fs::File::create(path)
+            .await
+            .map_err(|_| Error::IoError("Failed to create file"))?;
+        Ok(Self {
+            file,
+            buffer: Vec::with_capacity(STREAM_BUFFER_BYTES),
+        })
+    }
+
+    /// Buffer the header and pad up to the first array section
+    fn start(&mut self, header: &BspcHeader, values_offset: u64) -> Result<()> {
+        let header_bytes = header.to_bytes();
+        self.buffer.extend_from_slice(&header_bytes);
+        write_padding(&mut self.buffer, values_offset, header_bytes.len() as u64)
+    }
+
+    /// Write a serialized chunk, bypassing the buffer when it would not fit
+    async fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        if self.buffer.len() + bytes.len() <= STREAM_BUFFER_BYTES {
+            self.buffer.extend_from_slice(bytes);
+            return Ok(());
+        }
+        self.write_buffer().await?;
+        self.file
+            .write_all(bytes)
+            .await
+            .map_err(|_| Error::IoError("Failed to write file"))
+    }
+
+    /// Write out the buffer once it is full
+    async fn write_if_full(&mut self) -> Result<()> {
+        if self.buffer.len() >= STREAM_BUFFER_BYTES {
+            self.write_buffer().await?;
+        }
+        Ok(())
+    }
+
+    async fn write_buffer(&mut self) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        self.file
+            .write_all(&self.buffer)
+            .await
+            .map_err(|_| Error::IoError("Failed to write file"))?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        self.write_buffer().await?;
+        self.file
+            .flush()
+            .await
+            .map_err(|_| Error::IoError("Failed to flush file"))
+    }
+}
+
+/// Write a matrix whose sorted COO elements come from a re-startable source
+///
+/// `elements` is invoked once per file section (bloom filter and element
+/// count, values, row indices, column indices), so the matrix is never held
+/// in memory. Every invocation must yield the same elements in the same order.
+pub(crate) async fn write_streaming<T, I, F, P>(
+    path: P,
+    nrows: usize,
+    ncols: usize,
+    elements: F,
+    chunk_size: usize,
+    metadata: Option<&[u8]>,
+) -> Result<()>
+where
+    T: MatrixElement,
+    I: Iterator<Item = (usize, usize, T)>,
+    F: Fn() -> I,
+    P: AsRef<Path>,
+{
+    let index_width = IndexWidth::for_dims(nrows as u64, ncols as u64);
+
+    // Pass 1: element count and bloom filter over the distinct rows
+    let mut bloom_filter = crate::chunk_bloom_filter::ChunkBloomFilter::new(nrows, chunk_size);
+    let mut prev_row = None;
+    let mut nnz = 0usize;
+    for (row, col, _) in elements() {
+        if row >= nrows || col >= ncols {
+            return Err(Error::InvalidState(
+                "Element index exceeds matrix dimensions",
+            ));
+        }
+        if prev_row != Some(row) {
+            bloom_filter.insert(row);
+            prev_row = Some(row);
+        }
+        nnz += 1;
+    }
+    let bloom_filter_data = serialize_bloom_filter(&bloom_filter, index_width)?;
+    let layout = FileLayout::calculate::<T>(nnz, index_width)?;
+
+    let bloom_filter_end = layout.bloom_filter_offset() + bloom_filter_data.len() as u64;
+    let metadata_offset = crate::metadata::align_to_8(bloom_filter_end);
+
+    let mut header =
+        layout.coo_header::<T>(nrows, ncols, nnz, index_width, bloom_filter_data.len());
+    if let Some(metadata) = metadata {
+        header.set_metadata_region(metadata_offset, metadata.len() as u64);
+    }
+
+    let mut writer = StreamWriter::create(path.as_ref()).await?;
+    writer.start(&header, layout.values_offset)?;
+
+    // Pass 2: values
+    for (_, _, value) in elements() {
+        writer.buffer.extend_from_slice(&value.to_le_bytes());
+        writer.write_if_full().await?;
+    }
+
+    // Pass 3: row indices
+    write_padding(
+        &mut writer.buffer,
+        layout.indices_0_offset,
+        layout.values_offset + layout.values_size,
+    )?;
+    for (row, _, _) in elements() {
+        write_index(&mut writer.buffer, row, index_width)?;
+        writer.write_if_full().await?;
+    }
+
+    // Pass 4: column indices
+    write_padding(
+        &mut writer.buffer,
+        layout.indices_1_offset,
+        layout.indices_0_offset + layout.indices_0_size,
+    )?;
+    for (_, col, _) in elements() {
+        write_index(&mut writer.buffer, col, index_width)?;
+        writer.write_if_full().await?;
+    }
+
+    writer.buffer.extend_from_slice(&bloom_filter_data);
+    if let Some(metadata) = metadata {
+        write_padding(&mut writer.buffer, metadata_offset, bloom_filter_end)?;
+        writer.buffer.extend_from_slice(metadata);
+    }
+
+    writer.finish().await
+}
+
+/// Write bytes at an offset, zero-filling any gap, and sync them to disk
+fn write_at(file: &mut File, offset: u64, bytes: &[u8]) -> Result<()> {
+    use std::io::{Seek, SeekFrom};
+
+    let end = file
+        .seek(SeekFrom::End(0))
+        .map_err(|_| Error::IoError("Failed to seek in file"))?;
+    if offset > end {
+        file.write_all(&vec![0u8; (offset - end) as usize])
+            .map_err(|_| Error::IoError("Failed to write padding"))?;
+    }
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|_| Error::IoError("Failed to seek in file"))?;
+    file.write_all(bytes)
+        .map_err(|_| Error::IoError("Failed to write to file"))?;
+    file.sync_data()
+        .map_err(|_| Error::IoError("Failed to sync file"))
+}
+
+/// Overwrite the header in a single write once everything it points at is on disk
+fn write_header(file: &mut File, header: &BspcHeader) -> Result<()> {
+    write_at(file, 0, &header.to_bytes_array())
+}
+
 /// File handle for .bspc files
 pub struct BspcFile {
     pub header: BspcHeader,
@@ -96,6 +523,96 @@ impl BspcFile {
         })
     }
 
+    /// Read the raw metadata section, if the file has one
+    pub fn read_metadata(&self) -> Result<Option<Vec<u8>>> {
+        use std::io::{Seek, SeekFrom};
+
+        let Some((offset, size)) = self.header.metadata_region() else {
+            return Ok(None);
+        };
+
+        let mut file = File::open(&self.path).map_err(|_| Error::IoError("Failed to open file"))?;
+        file.seek(SeekFrom::Start(offset))
+            .map_err(|_| Error::IoError("Failed to seek to metadata"))?;
+        let mut metadata = vec![0u8; size as usize];
+        file.read_exact(&mut metadata)
+            .map_err(|_| Error::IoError("Failed to read metadata"))?;
+        Ok(Some(metadata))
+    }
+
+    /// Replace the metadata of an existing file without rewriting the matrix
+    ///
+    /// The new block is appended at an aligned offset after everything already
+    /// in the file, then the header is patched to point at it. A crash before the
+    /// header patch leaves the old metadata in effect. The old block is left in
+    /// place; use [`BspcFile::set_metadata_compact`] to reclaim it.
+    ///
+    /// Existing memory maps of the file keep seeing the old metadata and should be
+    /// reopened.
+    pub fn set_metadata<P: AsRef<Path>>(
+        path: P,
+        builder: crate::metadata::MetadataBuilder,
+    ) -> Result<()> {
+        let metadata = builder.build()?;
+        Self::replace_metadata(path.as_ref(), &metadata, false)
+    }
+
+    /// Replace the metadata of an existing file and reclaim the old block
+    ///
+    /// The new block is first appended and published as in
+    /// [`BspcFile::set_metadata`]. It is then copied to directly after the data
+    /// sections, the header is patched again and the file is truncated, so the
+    /// header never points at a partially written block.
+    pub fn set_metadata_compact<P: AsRef<Path>>(
+        path: P,
+        builder: crate::metadata::MetadataBuilder,
+    ) -> Result<()> {
+        let metadata = builder.build()?;
+        Self::replace_metadata(path.as_ref(), &metadata, true)
+    }
+
+    /// Append a metadata block, publish it, and optionally move it to the end of the data
+    fn replace_metadata(path: &Path, metadata: &[u8], compact: bool) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|_| Error::IoError("Failed to open file"))?;
+        let mut header_bytes = [0u8; BspcHeader::SIZE];
+        file.read_exact(&mut header_bytes)
+            .map_err(|_| Error::IoError("Failed to read header"))?;
+        let mut header = BspcHeader::from_bytes(&header_bytes)
+            .map_err(|_| Error::InvalidState("Invalid BSPC header format"))?;
+
+        let file_len = file
+            .metadata()
+            .map_err(|_| Error::IoError("Failed to read file size"))?
+            .len();
+        let size = metadata.len() as u64;
+        let compact_offset = crate::metadata::align_to_8(header.data_end());
+
+        // The appended copy must not overlap the compacted location, which is
+        // overwritten while the appended copy is live
+        let mut offset = crate::metadata::align_to_8(file_len);
+        if compact {
+            offset = offset.max(crate::metadata::align_to_8(compact_offset + size));
+        }
+
+        write_at(&mut file, offset, metadata)?;
+        header.set_metadata_region(offset, size);
+        write_header(&mut file, &header)?;
+
+        if compact && offset != compact_offset {
+            write_at(&mut file, compact_offset, metadata)?;
+            header.set_metadata_region(compact_offset, size);
+            write_header(&mut file, &header)?;
+            file.set_len(compact_offset + size)
+                .map_err(|_| Error::IoError("Failed to truncate file"))?;
+        }
+
+        Ok(())
+    }
+
     /// Create a new .bspc file
     pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
         Ok(Self {
@@ -105,11 +622,24 @@ impl BspcFile {
     }
 
     /// Read matrix with bloom filter optimization
+    ///
+    /// Only files with 32-bit indices can be read as a [`DynamicMatrix`].
     #[cfg(feature = "mmap")]
     pub fn read_matrix_with_bloom_filter<P: AsRef<Path>>(
         path: P,
         config: crate::chunked_backend::ChunkConfig,
     ) -> Result<crate::chunked_backend::ChunkedMatrix<DynamicMatrix>> {
+        Ok(crate::chunked_backend::ChunkedMatrix::new(
+            Self::read_dynamic_matrix(path)?,
+            config,
+        ))
+    }
+
+    /// Memory-map a matrix of whatever element type the file holds
+    ///
+    /// Only files with 32-bit indices can be read as a [`DynamicMatrix`].
+    #[cfg(feature = "mmap")]
+    pub fn read_dynamic_matrix<P: AsRef<Path>>(path: P) -> Result<DynamicMatrix> {
         let path_ref = path.as_ref();
         let mut file = File::open(path_ref).map_err(|_| Error::IoError("Failed to open file"))?;
         let mut header_bytes = [0u8; BspcHeader::SIZE];
@@ -118,20 +648,18 @@ impl BspcFile {
 
         let header = BspcHeader::from_bytes(&header_bytes)
             .map_err(|_| Error::InvalidState("Invalid BSPC header format"))?;
+        if header.index_width() != Some(IndexWidth::U32) {
+            return Err(Error::InvalidState(
+                "64-bit index files must be opened as MmapMatrix<T, u64>",
+            ));
+        }
 
-        let dynamic_matrix = match DataType::from_u8(header.data_type).unwrap_or(DataType::F64) {
-            DataType::F32 => DynamicMatrix::F32(MmapMatrix::from_file(path_ref)?),
-            DataType::F64 => DynamicMatrix::F64(MmapMatrix::from_file(path_ref)?),
-            DataType::I32 => DynamicMatrix::I32(MmapMatrix::from_file(path_ref)?),
-            DataType::I64 => DynamicMatrix::I64(MmapMatrix::from_file(path_ref)?),
-            DataType::U32 => DynamicMatrix::U32(MmapMatrix::from_file(path_ref)?),
-            DataType::U64 => DynamicMatrix::U64(MmapMatrix::from_file(path_ref)?),
-        };
+        let data_type = DataType::from_u8(header.data_type).unwrap_or(DataType::F64);
+        let dynamic_matrix = dispatch_data_type!(dynamic data_type, |T| {
+            MmapMatrix::<T>::from_file(path_ref)?
+        });
 
-        Ok(crate::chunked_backend::ChunkedMatrix::new(
-            dynamic_matrix,
-            config,
-        ))
+        Ok(dynamic_matrix)
     }
 
     /// Write sparse matrix using high-performance async I/O
@@ -141,24 +669,28 @@ impl BspcFile {
     /// - Async I/O operations with tokio
     /// - Efficient bloom filter generation
     /// - Zero-copy operations where possible
+    ///
+    /// When `config` carries a compression setting the arrays are written as
+    /// encoded blocks instead.
     pub async fn write_sparse_matrix<T: MatrixElement + Send + Sync, P: AsRef<std::path::Path>>(
         nrows: usize,
         ncols: usize,
         sparse_elements: &[(usize, usize, T)],
         config: crate::chunked_backend::ChunkConfig,
         filename: P,
-    ) -> Result<()>
-    where
-        T: 'static,
-    {
-        use tokio::fs::File as AsyncFile;
-        use tokio::io::AsyncWriteExt;
-
+    ) -> Result<()> {
         let path = filename.as_ref();
         let nnz = sparse_elements.len();
 
-        // Calculate layout immediately (no async needed for this simple calculation)
-        let layout = FileLayout::calculate::<T>(nnz)?;
+        if let Some(&compression) = config.compression() {
+            return write_compressed(nrows, ncols, sparse_elements, &config, compression, path)
+                .await;
+        }
+
+        // Calculate layout immediately (no async needed for this simple calculation);
+        // indices are widened to u64 only when a dimension needs it
+        let index_width = IndexWidth::for_dims(nrows as u64, ncols as u64);
+        let layout = FileLayout::calculate::<T>(nnz, index_width)?;
 
         // Process data directly with rayon (no spawn_blocking overhead) and bloom filter in parallel
         let (buffers, bloom_filter_data) = {
@@ -174,7 +706,8 @@ impl BspcFile {
             } else {
                 // For smaller datasets: Use larger chunks to reduce overhead
                 nnz.div_ceil(num_threads)
-            };
+            }
+            .max(1);
 
             // Run data processing and bloom filter creation in parallel using rayon::join
             rayon::join(
@@ -186,7 +719,7 @@ impl BspcFile {
                             // Pre-allocate exact buffers for maximum efficiency
                             let chunk_len = chunk.len();
                             let values_capacity = chunk_len * T::size_bytes();
-                            let indices_capacity = chunk_len * 4;
+                            let indices_capacity = chunk_len * index_width.size_bytes();
 
                             // COPY: Allocating new vectors for serialized data
                             // ZERO-COPY: Could use memory mapping or pre-allocated shared buffers
@@ -201,153 +734,81 @@ impl BspcFile {
                                 values_chunk.extend_from_slice(&value.to_le_bytes());
                                 // COPY: Converting row indices to bytes and copying
                                 // ZERO-COPY: Impossible - need endianness conversion to bytes
-                                row_chunk.extend_from_slice(&(row as u32).to_le_bytes());
+                                write_index(&mut row_chunk, row, index_width)?;
                                 // COPY: Converting column indices to bytes and copying
                                 // ZERO-COPY: Impossible - need endianness conversion to bytes
-                                col_chunk.extend_from_slice(&(col as u32).to_le_bytes());
+                                write_index(&mut col_chunk, col, index_width)?;
                             }
 
-                            (values_chunk, row_chunk, col_chunk)
+                            Ok((values_chunk, row_chunk, col_chunk))
                         })
                         // COPY: Collecting all chunks into a single vector
                         // ZERO-COPY: Could stream chunks directly to file without collecting
-                        .collect::<Vec<_>>()
+                        .collect::<Result<Vec<_>>>()
                 },
                 || {
                     // COPY: Create bloom filter in parallel (involves copying row data)
                     // ZERO-COPY: Could work directly with row indices without intermediate collections
-                    create_bloom_filter(sparse_elements, nrows, &config).serialize()
+                    serialize_bloom_filter(
+                        &create_bloom_filter(sparse_elements, nrows, &config),
+                        index_width,
+                    )
                 },
             )
         };
+        let (buffers, bloom_filter_data) = (buffers?, bloom_filter_data?);
 
-        // Create file and header
-        let mut file = AsyncFile::create(path)
-            .await
-            .map_err(|_| Error::IoError("Failed to create file"))?;
-        let bloom_filter_offset = layout.indices_1_offset + layout.indices_1_size;
-
-        let mut header = BspcHeader::new();
-        header.nrows = nrows as u64;
-        header.ncols = ncols as u64;
-        header.nnz = nnz as u64;
-        header.format_type = MatrixFormat::Coo as u8;
-        header.data_type = T::data_type() as u8;
-        header.values_offset = layout.values_offset;
-        header.values_size = layout.values_size;
-        header.indices_0_offset = layout.indices_0_offset;
-        header.indices_0_size = layout.indices_0_size;
-        header.indices_1_offset = layout.indices_1_offset;
-        header.indices_1_size = layout.indices_1_size;
-        header.bloom_filter_offset = bloom_filter_offset;
-        header.bloom_filter_size = bloom_filter_data.len() as u64;
-        header.pointers_offset = 0;
-        header.pointers_size = 0;
-
-        // Write header with padding
-        // COPY: Converting header struct to bytes
-        // ZERO-COPY: Could write header directly as bytes using unsafe transmute
-        let header_bytes = header.to_bytes();
-        let padding_size = layout.values_offset as usize - header_bytes.len();
-        // COPY: Writing header bytes to file
-        // ZERO-COPY: Unavoidable for file I/O
-        file.write_all(&header_bytes)
-            .await
-            .map_err(|_| Error::IoError("Failed to write header"))?;
-        if padding_size > 0 {
-            // COPY: Creating padding buffer and writing to file
-            // ZERO-COPY: Could use write_zeros() system call if available
-            let padding = vec![0u8; padding_size];
-            file.write_all(&padding)
-                .await
-                .map_err(|_| Error::IoError("Failed to write padding"))?;
-        }
-
-        // Helper for async padding
-        async fn write_padding_async(
-            file: &mut AsyncFile,
-            target_offset: u64,
-            current_pos: u64,
-        ) -> Result<()> {
-            if target_offset > current_pos {
-                // COPY: Creating padding buffer for alignment
-                // ZERO-COPY: Could use write_zeros() system call if available
-                let padding = vec![0u8; (target_offset - current_pos) as usize];
-                file.write_all(&padding)
-                    .await
-                    .map_err(|_| Error::IoError("Failed to write padding"))?;
-            }
-            Ok(())
-        }
+        let header =
+            layout.coo_header::<T>(nrows, ncols, nnz, index_width, bloom_filter_data.len());
+        let mut writer = StreamWriter::create(path).await?;
+        writer.start(&header, layout.values_offset)?;
 
         // Write values
         for (values_chunk, _, _) in &buffers {
-            // COPY: Writing serialized values data to file
-            // ZERO-COPY: Unavoidable for file I/O
-            file.write_all(values_chunk)
-                .await
-                .map_err(|_| Error::IoError("Failed to write values"))?;
+            writer.write_bytes(values_chunk).await?;
         }
 
         // Write row indices with padding
-        write_padding_async(
-            &mut file,
+        write_padding(
+            &mut writer.buffer,
             layout.indices_0_offset,
             layout.values_offset + layout.values_size,
-        )
-        .await?;
+        )?;
         for (_, row_chunk, _) in &buffers {
-            // COPY: Writing serialized row indices to file
-            // ZERO-COPY: Unavoidable for file I/O
-            file.write_all(row_chunk)
-                .await
-                .map_err(|_| Error::IoError("Failed to write row indices"))?;
+            writer.write_bytes(row_chunk).await?;
         }
 
         // Write column indices with padding
-        write_padding_async(
-            &mut file,
+        write_padding(
+            &mut writer.buffer,
             layout.indices_1_offset,
             layout.indices_0_offset + layout.indices_0_size,
-        )
-        .await?;
+        )?;
         for (_, _, col_chunk) in &buffers {
-            // COPY: Writing serialized column indices to file
-            // ZERO-COPY: Unavoidable for file I/O
-            file.write_all(col_chunk)
-                .await
-                .map_err(|_| Error::IoError("Failed to write column indices"))?;
+            writer.write_bytes(col_chunk).await?;
         }
 
         // Write bloom filter
-        // COPY: Writing serialized bloom filter data to file
-        // ZERO-COPY: Unavoidable for file I/O
-        file.write_all(&bloom_filter_data)
-            .await
-            .map_err(|_| Error::IoError("Failed to write bloom filter"))?;
-        file.flush()
-            .await
-            .map_err(|_| Error::IoError("Failed to flush file"))?;
-
-        Ok(())
+        writer.write_bytes(&bloom_filter_data).await?;
+        writer.finish().await
     }
 
     /// Write sparse matrix with structured metadata (labels)
     ///
-    /// Uses the same high-performance async method internally for optimal performance
+    /// Uses the same high-performance async method internally for optimal performance.
+    /// A `label_stride` of 0 stores labels with the variable-length encoding; any
+    /// other value pads every label to that stride and rejects longer labels.
+    #[allow(clippy::too_many_arguments)]
     pub async fn write_sparse_matrix_with_labels<T: MatrixElement + Send + Sync, P: AsRef<Path>>(
         nrows: usize,
         ncols: usize,
         sparse_elements: &[(usize, usize, T)],
         row_labels: &[&[u8]],
         col_labels: &[&[u8]],
-        _label_stride: u32,
+        label_stride: u32,
         config: crate::chunked_backend::ChunkConfig,
         filename: P,
-    ) -> Result<()>
-    where
-        T: 'static,
-    {
+    ) -> Result<()> {
         let path = filename.as_ref();
 
         // If no labels, just use the fast path directly
@@ -356,25 +817,14 @@ impl BspcFile {
                 .await;
         }
 
-        // First, write the matrix data using the high-performance async method to a temporary file
-        let temp_path = path.with_extension("tmp");
-        Self::write_sparse_matrix(nrows, ncols, sparse_elements, config.clone(), &temp_path)
-            .await?;
-
-        // Now we need to read the header to get the bloom filter location and update it with metadata
-        let mut temp_file =
-            File::open(&temp_path).map_err(|_| Error::IoError("Failed to open temp file"))?;
-        let mut header_bytes = [0u8; BspcHeader::SIZE];
-        use std::io::Read;
-        temp_file
-            .read_exact(&mut header_bytes)
-            .map_err(|_| Error::IoError("Failed to read header"))?;
+        Self::write_sparse_matrix(nrows, ncols, sparse_elements, config, path).await?;
 
-        let mut header = BspcHeader::from_bytes(&header_bytes)
-            .map_err(|_| Error::InvalidState("Invalid BSPC header format"))?;
-
-        // Build structured metadata
-        let mut builder = crate::metadata::MetadataBuilder::new();
+        // Build structured metadata; a zero stride selects variable-length labels
+        let encoding = match label_stride {
+            0 => crate::metadata::LabelEncoding::Variable,
+            stride => crate::metadata::LabelEncoding::Fixed(stride),
+        };
+        let mut builder = crate::metadata::MetadataBuilder::new().with_label_encoding(encoding);
 
         if !row_labels.is_empty() {
             // COPY: Converting label byte slices to owned vectors for metadata
@@ -390,83 +840,374 @@ impl BspcFile {
                 builder.with_col_labels(col_labels.iter().map(|&label| label.to_vec()).collect());
         }
 
-        let metadata = builder.build()?;
+        // Append the metadata after the bloom filter and patch the header
+        Self::set_metadata(path, builder)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunked_backend::ChunkConfig;
+    use crate::metadata::MetadataBuilder;
+    use crate::mmap_backend::DynamicElement;
+    use crate::test_support::TempDir;
+
+    const ELEMENTS: [(usize, usize, f64); 4] = [(0, 1, 1.0), (1, 0, 2.0), (2, 3, 3.0), (3, 2, 4.0)];
+
+    async fn write_matrix(dir: &TempDir, name: &str) -> std::path::PathBuf {
+        let path = dir.file(name);
+        BspcFile::write_sparse_matrix(4, 4, &ELEMENTS, ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        path
+    }
+
+    fn labels(prefix: &str, n: usize) -> Vec<Vec<u8>> {
+        (0..n)
+            .map(|i| format!("{prefix}{i}").into_bytes())
+            .collect()
+    }
+
+    fn file_len(path: &Path) -> u64 {
+        std::fs::metadata(path).unwrap().len()
+    }
+
+    fn assert_matrix(path: &Path, row_label: &[u8]) {
+        let matrix = MmapMatrix::<f64>::from_file(path).unwrap();
+        for &(row, col, value) in &ELEMENTS {
+            let mut entries = matrix.row_view(row).unwrap();
+            assert_eq!(entries.find(|&(c, _)| c == col), Some((col, &value)));
+        }
+        assert_eq!(matrix.row_label(3).unwrap(), Some(row_label));
+    }
 
-        // Calculate where to append metadata after the bloom filter
-        let metadata_start =
-            crate::metadata::align_to_8(header.bloom_filter_offset + header.bloom_filter_size);
-        let metadata_size = metadata.len() as u64;
+    #[tokio::test]
+    async fn test_set_metadata_replaces_labels() {
+        let dir = TempDir::new();
+        let path = write_matrix(&dir, "m.bspc").await;
+        let data_len = file_len(&path);
 
-        // Update header with metadata location
-        header.set_metadata_region(metadata_start, metadata_size);
+        BspcFile::set_metadata(
+            &path,
+            MetadataBuilder::new().with_row_labels(labels("r", 4)),
+        )
+        .unwrap();
+        assert_matrix(&path, b"r3");
+
+        BspcFile::set_metadata(
+            &path,
+            MetadataBuilder::new().with_row_labels(labels("row_", 4)),
+        )
+        .unwrap();
+        assert_matrix(&path, b"row_3");
+
+        // Each rewrite appends a new block
+        let matrix = MmapMatrix::<f64>::from_file(&path).unwrap();
+        let (offset, _) = matrix.header.metadata_region().unwrap();
+        assert!(offset > data_len);
+    }
 
-        // Copy the temp file to final location, updating the header and appending metadata
-        use tokio::fs::File as AsyncFile;
-        use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+    #[tokio::test]
+    async fn test_set_metadata_compact() {
+        let dir = TempDir::new();
+        let path = write_matrix(&dir, "m.bspc").await;
+
+        for prefix in ["a_long_label_prefix_", "b"] {
+            BspcFile::set_metadata(
+                &path,
+                MetadataBuilder::new().with_row_labels(labels(prefix, 4)),
+            )
+            .unwrap();
+        }
+        BspcFile::set_metadata_compact(
+            &path,
+            MetadataBuilder::new().with_row_labels(labels("c", 4)),
+        )
+        .unwrap();
+        assert_matrix(&path, b"c3");
 
-        let mut temp_file = AsyncFile::open(&temp_path)
+        // The block now directly follows the data and nothing follows it
+        let matrix = MmapMatrix::<f64>::from_file(&path).unwrap();
+        let header = &matrix.header;
+        let (offset, size) = header.metadata_region().unwrap();
+        assert_eq!(offset, crate::metadata::align_to_8(header.data_end()));
+        assert_eq!(file_len(&path), offset + size);
+    }
+
+    #[tokio::test]
+    async fn test_set_metadata_compact_larger_block() {
+        let dir = TempDir::new();
+        let path = write_matrix(&dir, "m.bspc").await;
+        BspcFile::set_metadata(
+            &path,
+            MetadataBuilder::new().with_row_labels(labels("r", 4)),
+        )
+        .unwrap();
+
+        // The new block is larger than the space left by the old one
+        BspcFile::set_metadata_compact(
+            &path,
+            MetadataBuilder::new().with_row_labels(labels("a_much_longer_row_label_", 4)),
+        )
+        .unwrap();
+        assert_matrix(&path, b"a_much_longer_row_label_3");
+    }
+
+    /// Write `values` on the diagonal and read them back as a `DynamicMatrix`
+    async fn roundtrip_dtype<T>(values: [T; 3])
+    where
+        T: MatrixElement + Into<DynamicElement> + PartialEq + std::fmt::Debug,
+    {
+        use bspc_core::SparseMatrix;
+
+        let dir = TempDir::new();
+        let path = dir.file("m.bspc");
+        let elements: Vec<_> = values.iter().enumerate().map(|(i, &v)| (i, i, v)).collect();
+        BspcFile::write_sparse_matrix(3, 3, &elements, ChunkConfig::default(), &path)
             .await
-            .map_err(|_| Error::IoError("Failed to open temp file"))?;
-        let mut final_file = AsyncFile::create(path)
+            .unwrap();
+
+        let typed = MmapMatrix::<T>::from_file(&path).unwrap();
+        assert_eq!(typed.data_type(), T::data_type());
+        for (i, &value) in values.iter().enumerate() {
+            assert_eq!(typed.get_value(i, i).unwrap(), Some(value));
+            assert_eq!(typed.get_value(i, (i + 1) % 3).unwrap(), None);
+        }
+
+        let dynamic = BspcFile::read_dynamic_matrix(&path).unwrap();
+        assert_eq!(dynamic.data_type(), T::data_type());
+        for (i, &value) in values.iter().enumerate() {
+            assert_eq!(
+                SparseMatrix::get_element(&dynamic, i, i),
+                Some(value.into())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dtype_roundtrip() {
+        roundtrip_dtype([1.5f32, -2.0, 3.25]).await;
+        roundtrip_dtype([1.5f64, -2.0, 1e300]).await;
+        roundtrip_dtype([i32::MIN, -1, i32::MAX]).await;
+        roundtrip_dtype([i64::MIN, -1, i64::MAX]).await;
+        roundtrip_dtype([0u32, 7, u32::MAX]).await;
+        roundtrip_dtype([0u64, 7, u64::MAX]).await;
+        roundtrip_dtype([i8::MIN, 0, i8::MAX]).await;
+        roundtrip_dtype([i16::MIN, 0, i16::MAX]).await;
+        roundtrip_dtype([0u8, 1, u8::MAX]).await;
+        roundtrip_dtype([0u16, 1, u16::MAX]).await;
+        roundtrip_dtype([half::f16::from_f32(0.5), half::f16::MAX, half::f16::ZERO]).await;
+        roundtrip_dtype([half::bf16::from_f32(-0.5), half::bf16::MAX, half::bf16::ONE]).await;
+        roundtrip_dtype([
+            num_complex::Complex32::new(1.0, -1.0),
+            num_complex::Complex32::new(0.0, 2.5),
+            num_complex::Complex32::new(-3.0, 0.0),
+        ])
+        .await;
+        roundtrip_dtype([
+            num_complex::Complex64::new(1.0, -1.0),
+            num_complex::Complex64::new(0.0, 2.5),
+            num_complex::Complex64::new(-3.0, 0.0),
+        ])
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_pattern_roundtrip() {
+        roundtrip_dtype([bspc_core::Pattern; 3]).await;
+
+        // Pattern matrices store no values array
+        let dir = TempDir::new();
+        let path = dir.file("p.bspc");
+        let elements = [(0, 1, bspc_core::Pattern), (2, 0, bspc_core::Pattern)];
+        BspcFile::write_sparse_matrix(3, 3, &elements, ChunkConfig::default(), &path)
             .await
-            .map_err(|_| Error::IoError("Failed to create final file"))?;
+            .unwrap();
+        let matrix = MmapMatrix::<bspc_core::Pattern>::from_file(&path).unwrap();
+        assert_eq!(matrix.header.values_size, 0);
+        assert!(matches!(
+            matrix.get_element(2, 0).unwrap(),
+            Some(binsparse_rs::array::ArrayValue::BInt8(1))
+        ));
+    }
+
+    #[test]
+    fn test_bool_array_value_is_pattern() {
+        use binsparse_rs::array::ArrayValue;
+        assert_eq!(
+            DynamicElement::from_array_value(ArrayValue::BInt8(0)),
+            DynamicElement::Pattern
+        );
+        assert_eq!(
+            DynamicElement::from_array_value(ArrayValue::BInt8(1)),
+            DynamicElement::Pattern
+        );
+    }
 
-        // Write updated header
-        final_file
-            .write_all(&header.to_bytes())
+    #[tokio::test]
+    async fn test_complex_array_value_accessors_fail() {
+        use bspc_core::SparseMatrix;
+
+        let dir = TempDir::new();
+        let path = dir.file("c.bspc");
+        let value = num_complex::Complex64::new(1.0, -2.0);
+        BspcFile::write_sparse_matrix(2, 2, &[(1, 0, value)], ChunkConfig::default(), &path)
             .await
-            .map_err(|_| Error::IoError("Failed to write updated header"))?;
+            .unwrap();
+
+        let matrix = MmapMatrix::<num_complex::Complex64>::from_file(&path).unwrap();
+        assert_eq!(matrix.get_value(1, 0).unwrap(), Some(value));
+        assert!(matrix.get_element(1, 0).is_err());
+        assert!(matrix.get_row(1).is_err());
+        assert!(matrix.get_col(0).is_err());
+
+        let dynamic = BspcFile::read_dynamic_matrix(&path).unwrap();
+        assert!(dynamic.get_element(1, 0).is_err());
+        assert!(dynamic.row_view(1).is_err());
+        assert_eq!(
+            SparseMatrix::get_element(&dynamic, 1, 0),
+            Some(DynamicElement::C128(value))
+        );
+    }
 
-        // Copy the rest of the file (skipping original header)
-        temp_file
-            .seek(std::io::SeekFrom::Start(BspcHeader::SIZE as u64))
+    #[tokio::test]
+    async fn test_u64_index_roundtrip() {
+        let dir = TempDir::new();
+        let path = dir.file("wide.bspc");
+        let ncols = 1usize << 33;
+        let elements = [(0, 5, 1.0f64), (1, ncols - 1, 2.0), (3, 1 << 32, 3.0)];
+        BspcFile::write_sparse_matrix(4, ncols, &elements, ChunkConfig::default(), &path)
             .await
-            .map_err(|_| Error::IoError("Failed to seek in temp file"))?;
+            .unwrap();
 
-        let mut buffer = vec![0u8; 1024 * 1024]; // 1MB buffer for copying
-        loop {
-            let n = temp_file
-                .read(&mut buffer)
-                .await
-                .map_err(|_| Error::IoError("Failed to read from temp file"))?;
-            if n == 0 {
-                break;
-            }
-            final_file
-                .write_all(&buffer[..n])
-                .await
-                .map_err(|_| Error::IoError("Failed to write to final file"))?;
+        let matrix = MmapMatrix::<f64, u64>::from_file(&path).unwrap();
+        assert_eq!(matrix.header.index_width(), Some(IndexWidth::U64));
+        assert_eq!(matrix.header.version, BspcHeader::INDEX_WIDTH_VERSION);
+        assert_eq!(matrix.ncols(), ncols);
+        for &(row, col, value) in &elements {
+            assert_eq!(matrix.get_value(row, col).unwrap(), Some(value));
         }
+        assert_eq!(matrix.get_value(2, 5).unwrap(), None);
 
-        // Ensure we're at the right position for metadata
-        let current_pos = final_file
-            .stream_position()
-            .await
-            .map_err(|_| Error::IoError("Failed to get file position"))?;
-        if metadata_start > current_pos {
-            let padding = vec![0u8; (metadata_start - current_pos) as usize];
-            final_file
-                .write_all(&padding)
+        assert!(MmapMatrix::<f64>::from_file(&path).is_err());
+        assert!(BspcFile::read_dynamic_matrix(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_u32_files_keep_base_version() {
+        let dir = TempDir::new();
+        let path = write_matrix(&dir, "m.bspc").await;
+        let matrix = MmapMatrix::<f64>::from_file(&path).unwrap();
+        assert_eq!(matrix.header.version, BspcHeader::BASE_VERSION);
+        assert_eq!(matrix.header.index_width(), Some(IndexWidth::U32));
+    }
+
+    fn codec_configs() -> Vec<crate::compression::CompressionConfig> {
+        use crate::compression::{CompressionConfig, IndexCodec, ValueCodec};
+
+        let mut value_codecs = vec![ValueCodec::Raw, ValueCodec::Dictionary];
+        if cfg!(feature = "zstd") {
+            value_codecs.push(ValueCodec::Zstd);
+        }
+        if cfg!(feature = "lz4") {
+            value_codecs.push(ValueCodec::Lz4);
+        }
+        [IndexCodec::Raw, IndexCodec::DeltaBitPack]
+            .into_iter()
+            .flat_map(|index_codec| {
+                value_codecs.iter().map(move |&value_codec| {
+                    CompressionConfig::new(index_codec, value_codec).with_block_size_log2(2)
+                })
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_compressed_roundtrip() {
+        let dir = TempDir::new();
+        let mut elements: Vec<(usize, usize, f64)> = (0..50)
+            .map(|i| (i / 3, (i * 7) % 40, (i % 4) as f64 - 1.5))
+            .collect();
+        elements.sort_by_key(|&(row, col, _)| (row, col));
+
+        for compression in codec_configs() {
+            let path = dir.file("compressed.bspc");
+            let config = ChunkConfig::default()
+                .with_chunk_size(4)
+                .with_compression(compression);
+            BspcFile::write_sparse_matrix(20, 40, &elements, config, &path)
                 .await
-                .map_err(|_| Error::IoError("Failed to write metadata padding"))?;
+                .unwrap();
+
+            let matrix = MmapMatrix::<f64>::from_file(&path).unwrap();
+            assert!(matrix.is_compressed());
+            assert_eq!(matrix.header.version, BspcHeader::COMPRESSION_VERSION);
+            assert_eq!(matrix.header.index_codec(), Some(compression.index_codec));
+            assert_eq!(matrix.header.value_codec(), Some(compression.value_codec));
+            for &(row, col, value) in &elements {
+                assert_eq!(matrix.get_value(row, col).unwrap(), Some(value));
+            }
+            assert_eq!(matrix.get_value(19, 0).unwrap(), None);
         }
+    }
 
-        // Append metadata
-        final_file
-            .write_all(&metadata)
+    #[tokio::test]
+    async fn test_compressed_empty_matrix() {
+        let dir = TempDir::new();
+        let path = dir.file("empty.bspc");
+        let config = ChunkConfig::default().with_compression(Default::default());
+        BspcFile::write_sparse_matrix::<f64, _>(5, 5, &[], config, &path)
             .await
-            .map_err(|_| Error::IoError("Failed to write metadata"))?;
+            .unwrap();
 
-        final_file
-            .flush()
-            .await
-            .map_err(|_| Error::IoError("Failed to flush file"))?;
+        let matrix = MmapMatrix::<f64>::from_file(&path).unwrap();
+        assert!(matrix.header.is_compressed());
+        assert!(matrix.header.block_table_region().is_none());
+        assert!(matrix.is_compressed());
+        assert_eq!(matrix.get_value(0, 0).unwrap(), None);
+        assert_eq!(matrix.row_view(4).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_rejects_unsorted_elements() {
+        let dir = TempDir::new();
+        let path = dir.file("unsorted.bspc");
+        let config = ChunkConfig::default().with_compression(Default::default());
+        let elements = [(1, 0, 1.0), (0, 1, 2.0)];
 
-        // Clean up temp file
-        tokio::fs::remove_file(&temp_path)
+        let result = BspcFile::write_sparse_matrix(2, 2, &elements, config, &path).await;
+        assert!(matches!(
+            result,
+            Err(Error::InvalidState(
+                "Elements are not sorted by row and column"
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_block_fails_without_panicking() {
+        let dir = TempDir::new();
+        let path = dir.file("corrupt.bspc");
+        let config = ChunkConfig::default().with_compression(Default::default());
+        BspcFile::write_sparse_matrix(4, 4, &ELEMENTS, config, &path)
             .await
-            .map_err(|_| Error::IoError("Failed to remove temp file"))?;
+            .unwrap();
 
-        Ok(())
+        // Give the first column block a bit width no valid block can have
+        let mut bytes = std::fs::read(&path).unwrap();
+        let header = MmapMatrix::<f64>::from_file(&path).unwrap().header;
+        let (offset, _) = header.block_table_region().unwrap();
+        let entry = BlockTableEntry::from_bytes(&bytes[offset as usize..]).unwrap();
+        bytes[entry.cols_offset as usize + 8] = u8::MAX;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let matrix = MmapMatrix::<f64>::from_file(&path).unwrap();
+        assert!(matrix.col_indices().is_err());
+        assert!(matrix.get_value(0, 1).is_err());
+        assert!(matrix.row_view(0).is_err());
+        assert!(matrix.values().is_ok());
     }
 }
@@ -2,40 +2,31 @@
 //!
 //! This module provides matrix operations, views, and iterators for working with sparse matrices.
 
-use super::mmap_core::{MatrixElement, MmapMatrix};
+use super::mmap_core::{ensure_real, MatrixElement, MmapMatrix};
 use binsparse_rs::{array::ArrayValue, Error, Result};
-use bspc_core::{DataType, MatrixFormat, SparseMatrix};
+use bspc_core::{DataType, MatrixFormat, MatrixIndex, Pattern, SparseMatrix};
+use half::{bf16, f16};
+use num_complex::{Complex32, Complex64};
 use std::collections::HashMap;
 
 /// Macro to generate repetitive method implementations for DynamicMatrix
 macro_rules! impl_dynamic_method {
     ($method:ident -> $return_type:ty) => {
         pub fn $method(&self) -> $return_type {
-            match self {
-                DynamicMatrix::F32(m) => m.$method(),
-                DynamicMatrix::F64(m) => m.$method(),
-                DynamicMatrix::I32(m) => m.$method(),
-                DynamicMatrix::I64(m) => m.$method(),
-                DynamicMatrix::U32(m) => m.$method(),
-                DynamicMatrix::U64(m) => m.$method(),
-            }
+            dispatch_data_type!(match self, |m| m.$method())
         }
     };
 
     ($method:ident($($param:ident: $param_type:ty),*) -> $return_type:ty) => {
         pub fn $method(&self, $($param: $param_type),*) -> $return_type {
-            match self {
-                DynamicMatrix::F32(m) => m.$method($($param),*),
-                DynamicMatrix::F64(m) => m.$method($($param),*),
-                DynamicMatrix::I32(m) => m.$method($($param),*),
-                DynamicMatrix::I64(m) => m.$method($($param),*),
-                DynamicMatrix::U32(m) => m.$method($($param),*),
-                DynamicMatrix::U64(m) => m.$method($($param),*),
-            }
+            dispatch_data_type!(match self, |m| m.$method($($param),*))
         }
     };
 }
 
+/// Boxed per-row top-k iterator returned by [`DynamicMatrix::top_k_per_row`]
+pub type DynamicTopKRows<'a> = Box<dyn Iterator<Item = (usize, Vec<(usize, ArrayValue)>)> + 'a>;
+
 /// Dynamic matrix that can hold any element type
 /// Uses efficient enum dispatch for runtime type handling
 #[cfg(feature = "mmap")]
@@ -46,6 +37,15 @@ pub enum DynamicMatrix {
     I64(MmapMatrix<i64>),
     U32(MmapMatrix<u32>),
     U64(MmapMatrix<u64>),
+    Pattern(MmapMatrix<Pattern>),
+    I8(MmapMatrix<i8>),
+    I16(MmapMatrix<i16>),
+    U8(MmapMatrix<u8>),
+    U16(MmapMatrix<u16>),
+    F16(MmapMatrix<f16>),
+    BF16(MmapMatrix<bf16>),
+    C64(MmapMatrix<Complex32>),
+    C128(MmapMatrix<Complex64>),
 }
 
 #[cfg(feature = "mmap")]
@@ -67,17 +67,26 @@ impl DynamicMatrix {
     impl_dynamic_method!(get_col_range(start_col: usize, end_col: usize) -> Result<Vec<(usize, usize, ArrayValue)>>);
     impl_dynamic_method!(get_row_with_col_range(row: usize, start_col: usize, end_col: usize) -> Result<Vec<(usize, ArrayValue)>>);
 
+    // Top-k queries
+    impl_dynamic_method!(top_k_row(row: usize, k: usize) -> Result<Vec<(usize, ArrayValue)>>);
+    impl_dynamic_method!(top_k_col(col: usize, k: usize) -> Result<Vec<(usize, ArrayValue)>>);
+
     // Metadata methods
     impl_dynamic_method!(metadata_bytes -> Option<&[u8]>);
     impl_dynamic_method!(metadata_view -> Result<Option<crate::metadata::MetadataView<'_>>>);
     impl_dynamic_method!(row_label(row_idx: u32) -> Result<Option<&[u8]>>);
     impl_dynamic_method!(col_label(col_idx: u32) -> Result<Option<&[u8]>>);
+    impl_dynamic_method!(row_index_of(label: &[u8]) -> Result<Option<usize>>);
+    impl_dynamic_method!(col_index_of(label: &[u8]) -> Result<Option<usize>>);
 
     /// Get row view iterator with zero-copy access
+    ///
+    /// Complex matrices are rejected, see [`MatrixElement::to_array_value`].
     pub fn row_view(
         &self,
         row: usize,
     ) -> Result<Box<dyn Iterator<Item = (usize, ArrayValue)> + '_>> {
+        ensure_real(self.data_type())?;
         macro_rules! row_view_impl {
             ($matrix:expr) => {{
                 let iter = $matrix
@@ -87,21 +96,17 @@ impl DynamicMatrix {
             }};
         }
 
-        match self {
-            DynamicMatrix::F32(m) => row_view_impl!(m),
-            DynamicMatrix::F64(m) => row_view_impl!(m),
-            DynamicMatrix::I32(m) => row_view_impl!(m),
-            DynamicMatrix::I64(m) => row_view_impl!(m),
-            DynamicMatrix::U32(m) => row_view_impl!(m),
-            DynamicMatrix::U64(m) => row_view_impl!(m),
-        }
+        dispatch_data_type!(match self, |m| row_view_impl!(m))
     }
 
     /// Get column view iterator with zero-copy access
+    ///
+    /// Complex matrices are rejected, see [`MatrixElement::to_array_value`].
     pub fn col_view(
         &self,
         col: usize,
     ) -> Result<Box<dyn Iterator<Item = (usize, ArrayValue)> + '_>> {
+        ensure_real(self.data_type())?;
         macro_rules! col_view_impl {
             ($matrix:expr) => {{
                 let iter = $matrix
@@ -111,22 +116,18 @@ impl DynamicMatrix {
             }};
         }
 
-        match self {
-            DynamicMatrix::F32(m) => col_view_impl!(m),
-            DynamicMatrix::F64(m) => col_view_impl!(m),
-            DynamicMatrix::I32(m) => col_view_impl!(m),
-            DynamicMatrix::I64(m) => col_view_impl!(m),
-            DynamicMatrix::U32(m) => col_view_impl!(m),
-            DynamicMatrix::U64(m) => col_view_impl!(m),
-        }
+        dispatch_data_type!(match self, |m| col_view_impl!(m))
     }
 
     /// Get efficient row range iterator that processes multiple rows in a single pass
+    ///
+    /// Complex matrices are rejected, see [`MatrixElement::to_array_value`].
     pub fn row_range_view(
         &self,
         start_row: usize,
         end_row: usize,
     ) -> Result<Box<dyn Iterator<Item = (usize, usize, ArrayValue)> + '_>> {
+        ensure_real(self.data_type())?;
         macro_rules! row_range_view_impl {
             ($matrix:expr) => {{
                 let iter = $matrix
@@ -136,14 +137,18 @@ impl DynamicMatrix {
             }};
         }
 
-        match self {
-            DynamicMatrix::F32(m) => row_range_view_impl!(m),
-            DynamicMatrix::F64(m) => row_range_view_impl!(m),
-            DynamicMatrix::I32(m) => row_range_view_impl!(m),
-            DynamicMatrix::I64(m) => row_range_view_impl!(m),
-            DynamicMatrix::U32(m) => row_range_view_impl!(m),
-            DynamicMatrix::U64(m) => row_range_view_impl!(m),
+        dispatch_data_type!(match self, |m| row_range_view_impl!(m))
+    }
+
+    /// Stream the `k` largest entries of every row, see [`MmapMatrix::top_k_per_row`]
+    pub fn top_k_per_row(&self, k: usize) -> Result<DynamicTopKRows<'_>> {
+        macro_rules! top_k_per_row_impl {
+            ($matrix:expr) => {
+                Ok(Box::new($matrix.top_k_per_row(k)?))
+            };
         }
+
+        dispatch_data_type!(match self, |m| top_k_per_row_impl!(m))
     }
 
     /// Get iterator over all rows in the matrix
@@ -196,6 +201,15 @@ pub enum DynamicElement {
     I64(i64),
     U32(u32),
     U64(u64),
+    Pattern,
+    I8(i8),
+    I16(i16),
+    U8(u8),
+    U16(u16),
+    F16(f16),
+    BF16(bf16),
+    C64(Complex32),
+    C128(Complex64),
 }
 
 // Implement bspc_core::MatrixElement for DynamicElement
@@ -216,6 +230,23 @@ impl bspc_core::MatrixElement for DynamicElement {
             DynamicElement::I64(v) => v as f64,
             DynamicElement::U32(v) => v as f64,
             DynamicElement::U64(v) => v as f64,
+            DynamicElement::Pattern => 1.0,
+            DynamicElement::I8(v) => v as f64,
+            DynamicElement::I16(v) => v as f64,
+            DynamicElement::U8(v) => v as f64,
+            DynamicElement::U16(v) => v as f64,
+            DynamicElement::F16(v) => v.to_f64(),
+            DynamicElement::BF16(v) => v.to_f64(),
+            DynamicElement::C64(v) => v.to_f64(),
+            DynamicElement::C128(v) => v.to_f64(),
+        }
+    }
+
+    fn to_complex(self) -> Complex64 {
+        match self {
+            DynamicElement::C64(v) => v.to_complex(),
+            DynamicElement::C128(v) => v,
+            other => Complex64::new(other.to_f64(), 0.0),
         }
     }
 }
@@ -230,6 +261,15 @@ impl MatrixElement for DynamicElement {
             DynamicElement::I64(v) => ArrayValue::Int64(v),
             DynamicElement::U32(v) => ArrayValue::UInt32(v),
             DynamicElement::U64(v) => ArrayValue::UInt64(v),
+            DynamicElement::Pattern => Pattern.to_array_value(),
+            DynamicElement::I8(v) => ArrayValue::Int8(v),
+            DynamicElement::I16(v) => ArrayValue::Int16(v),
+            DynamicElement::U8(v) => ArrayValue::UInt8(v),
+            DynamicElement::U16(v) => ArrayValue::UInt16(v),
+            DynamicElement::F16(v) => v.to_array_value(),
+            DynamicElement::BF16(v) => v.to_array_value(),
+            DynamicElement::C64(v) => v.to_array_value(),
+            DynamicElement::C128(v) => v.to_array_value(),
         }
     }
 
@@ -255,12 +295,24 @@ impl MatrixElement for DynamicElement {
             DynamicElement::I64(v) => v.to_le_bytes().to_vec(),
             DynamicElement::U32(v) => v.to_le_bytes().to_vec(),
             DynamicElement::U64(v) => v.to_le_bytes().to_vec(),
+            DynamicElement::Pattern => Vec::new(),
+            DynamicElement::I8(v) => v.to_le_bytes().to_vec(),
+            DynamicElement::I16(v) => v.to_le_bytes().to_vec(),
+            DynamicElement::U8(v) => v.to_le_bytes().to_vec(),
+            DynamicElement::U16(v) => v.to_le_bytes().to_vec(),
+            DynamicElement::F16(v) => v.to_le_bytes().to_vec(),
+            DynamicElement::BF16(v) => v.to_le_bytes().to_vec(),
+            DynamicElement::C64(v) => MatrixElement::to_le_bytes(v),
+            DynamicElement::C128(v) => MatrixElement::to_le_bytes(v),
         }
     }
 }
 
 impl DynamicElement {
     /// Convert from ArrayValue
+    ///
+    /// `ArrayValue` has no half precision or complex variants, so those values
+    /// come back as `F32`/`F64`; convert from the typed value to keep them.
     pub fn from_array_value(value: ArrayValue) -> Self {
         match value {
             ArrayValue::Float32(v) => DynamicElement::F32(v),
@@ -269,26 +321,58 @@ impl DynamicElement {
             ArrayValue::Int64(v) => DynamicElement::I64(v),
             ArrayValue::UInt32(v) => DynamicElement::U32(v),
             ArrayValue::UInt64(v) => DynamicElement::U64(v),
-            // Convert other types to appropriate equivalents
-            ArrayValue::UInt8(v) => DynamicElement::U32(v as u32),
-            ArrayValue::UInt16(v) => DynamicElement::U32(v as u32),
-            ArrayValue::Int8(v) => DynamicElement::I32(v as i32),
-            ArrayValue::Int16(v) => DynamicElement::I32(v as i32),
-            ArrayValue::BInt8(v) => DynamicElement::I32(v as i32),
+            ArrayValue::UInt8(v) => DynamicElement::U8(v),
+            ArrayValue::UInt16(v) => DynamicElement::U16(v),
+            ArrayValue::Int8(v) => DynamicElement::I8(v),
+            ArrayValue::Int16(v) => DynamicElement::I16(v),
+            // Booleans are only stored by pattern matrices
+            ArrayValue::BInt8(_) => DynamicElement::Pattern,
         }
     }
 }
 
+/// Implement `From<T>` for every element type with a `DynamicElement` payload
+macro_rules! impl_dynamic_element_from {
+    ($($variant:ident($ty:ty)),*) => {
+        $(impl From<$ty> for DynamicElement {
+            fn from(value: $ty) -> Self {
+                DynamicElement::$variant(value)
+            }
+        })*
+    };
+}
+
+impl_dynamic_element_from!(
+    F32(f32),
+    F64(f64),
+    I32(i32),
+    I64(i64),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    U8(u8),
+    U16(u16),
+    F16(f16),
+    BF16(bf16),
+    C64(Complex32),
+    C128(Complex64)
+);
+
+impl From<Pattern> for DynamicElement {
+    fn from(_: Pattern) -> Self {
+        DynamicElement::Pattern
+    }
+}
+
 // Implement SparseMatrix for DynamicMatrix using DynamicElement as the element type
 #[cfg(feature = "mmap")]
 impl SparseMatrix for DynamicMatrix {
     type Element = DynamicElement;
 
     fn get_element(&self, row: usize, col: usize) -> Option<Self::Element> {
-        self.get_element(row, col)
-            .ok()
-            .flatten()
-            .map(DynamicElement::from_array_value)
+        // Convert from the typed value so the element keeps the stored data type
+        dispatch_data_type!(match self, |m| m.get_value(row, col).ok().flatten().map(Into::into))
     }
 
     fn dimensions(&self) -> (usize, usize) {
@@ -306,8 +390,8 @@ pub struct SubmatrixView<T: MatrixElement> {
 }
 
 impl<T: MatrixElement> SubmatrixView<T> {
-    pub fn new(
-        matrix: &MmapMatrix<T>,
+    pub fn new<I: MatrixIndex>(
+        matrix: &MmapMatrix<T, I>,
         rows: std::ops::Range<usize>,
         cols: std::ops::Range<usize>,
     ) -> Result<Self> {
@@ -325,13 +409,13 @@ impl<T: MatrixElement> SubmatrixView<T> {
         }
 
         let mut elements = HashMap::new();
-        let values = matrix.values();
-        let row_indices = matrix.row_indices();
-        let col_indices = matrix.col_indices();
+        let values = matrix.values()?;
+        let row_indices = matrix.row_indices()?;
+        let col_indices = matrix.col_indices()?;
 
         for i in 0..values.len() {
-            let file_row = row_indices[i] as usize;
-            let file_col = col_indices[i] as usize;
+            let file_row = row_indices[i].to_usize();
+            let file_col = col_indices[i].to_usize();
 
             // Validate file indices
             if file_row >= matrix.nrows() {
@@ -367,31 +451,40 @@ impl<T: MatrixElement> SubmatrixView<T> {
     }
 }
 
+/// Range of element positions holding a row in sorted COO `row_indices`
+pub(crate) fn row_span<I: MatrixIndex>(row_indices: &[I], row: usize) -> std::ops::Range<usize> {
+    let start = row_indices.partition_point(|&r| r.to_usize() < row);
+    let end = start + row_indices[start..].partition_point(|&r| r.to_usize() <= row);
+    start..end
+}
+
 // Add view methods to MmapMatrix
 #[cfg(feature = "mmap")]
-impl<T: MatrixElement> MmapMatrix<T> {
+impl<T: MatrixElement, I: MatrixIndex> MmapMatrix<T, I> {
     /// Binary search for element position in sorted COO format
-    fn find_element_index(&self, row: usize, col: usize) -> Option<usize> {
-        let values = self.values();
-        let row_indices = self.row_indices();
-        let col_indices = self.col_indices();
+    fn find_element_index(&self, row: usize, col: usize) -> Result<Option<usize>> {
+        let values = self.values()?;
+        let row_indices = self.row_indices()?;
+        let col_indices = self.col_indices()?;
         let len = values.len();
 
         // Verify data integrity
         if len != row_indices.len() || len != col_indices.len() {
-            return None;
+            return Ok(None);
         }
 
-        // Binary search using combined key
-        let target = (row as u64) << 32 | (col as u64);
+        // Binary search on the (row, col) key
+        let target = (row, col);
         let mut left = 0;
         let mut right = len;
 
         while left < right {
             let mid = left + (right - left) / 2;
             let current = unsafe {
-                ((*row_indices.get_unchecked(mid) as u64) << 32)
-                    | (*col_indices.get_unchecked(mid) as u64)
+                (
+                    row_indices.get_unchecked(mid).to_usize(),
+                    col_indices.get_unchecked(mid).to_usize(),
+                )
             };
 
             if current < target {
@@ -403,16 +496,16 @@ impl<T: MatrixElement> MmapMatrix<T> {
 
         // Check if found
         if left < len {
-            let found_row = unsafe { *row_indices.get_unchecked(left) } as usize;
-            let found_col = unsafe { *col_indices.get_unchecked(left) } as usize;
-            (found_row == row && found_col == col).then_some(left)
+            let found_row = unsafe { row_indices.get_unchecked(left) }.to_usize();
+            let found_col = unsafe { col_indices.get_unchecked(left) }.to_usize();
+            Ok((found_row == row && found_col == col).then_some(left))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    /// Get element at specific position with optimized bounds checking
-    pub fn get_element(&self, row: usize, col: usize) -> Result<Option<ArrayValue>> {
+    /// Get the typed value at a position with optimized bounds checking
+    pub fn get_value(&self, row: usize, col: usize) -> Result<Option<T>> {
         // Bounds check
         if row >= self.nrows() || col >= self.ncols() {
             return Err(Error::InvalidState("Index out of bounds"));
@@ -423,10 +516,25 @@ impl<T: MatrixElement> MmapMatrix<T> {
             return Ok(None);
         }
 
+        // Compressed files decode only the blocks holding the row
+        if let Some(blocks) = &self.blocks {
+            return blocks.get_element(&self._mmap, row, col);
+        }
+
         // Find and return element
-        Ok(self
-            .find_element_index(row, col)
-            .map(|idx| unsafe { self.values().get_unchecked(idx).to_array_value() }))
+        let Some(idx) = self.find_element_index(row, col)? else {
+            return Ok(None);
+        };
+        Ok(Some(unsafe { *self.values()?.get_unchecked(idx) }))
+    }
+
+    /// Get element at specific position with optimized bounds checking
+    ///
+    /// See [`MatrixElement::to_array_value`] for how each data type is reported;
+    /// complex matrices are rejected, use [`MmapMatrix::get_value`] for them.
+    pub fn get_element(&self, row: usize, col: usize) -> Result<Option<ArrayValue>> {
+        ensure_real(T::data_type())?;
+        Ok(self.get_value(row, col)?.map(T::to_array_value))
     }
 
     /// Get row view with zero-copy iterator
@@ -435,13 +543,13 @@ impl<T: MatrixElement> MmapMatrix<T> {
             return Err(Error::InvalidState("Row index out of bounds"));
         }
 
-        let values = self.values();
-        let row_indices = self.row_indices();
-        let col_indices = self.col_indices();
+        let values = self.values()?;
+        let row_indices = self.row_indices()?;
+        let col_indices = self.col_indices()?;
 
         Ok((0..values.len()).filter_map(move |i| {
-            let file_row = row_indices[i] as usize;
-            let file_col = col_indices[i] as usize;
+            let file_row = row_indices[i].to_usize();
+            let file_col = col_indices[i].to_usize();
 
             if file_row == row && file_col < self.ncols() {
                 Some((file_col, &values[i]))
@@ -466,13 +574,13 @@ impl<T: MatrixElement> MmapMatrix<T> {
             return Err(Error::InvalidState("Column index out of bounds"));
         }
 
-        let values = self.values();
-        let row_indices = self.row_indices();
-        let col_indices = self.col_indices();
+        let values = self.values()?;
+        let row_indices = self.row_indices()?;
+        let col_indices = self.col_indices()?;
 
         Ok((0..values.len()).filter_map(move |i| {
-            let file_row = row_indices[i] as usize;
-            let file_col = col_indices[i] as usize;
+            let file_row = row_indices[i].to_usize();
+            let file_col = col_indices[i].to_usize();
 
             if file_col == col && file_row < self.nrows() {
                 Some((file_row, &values[i]))
@@ -494,9 +602,9 @@ impl<T: MatrixElement> MmapMatrix<T> {
             return Err(Error::InvalidState("Invalid row range"));
         }
 
-        let values = self.values();
-        let row_indices = self.row_indices();
-        let col_indices = self.col_indices();
+        let values = self.values()?;
+        let row_indices = self.row_indices()?;
+        let col_indices = self.col_indices()?;
 
         // Use chunk bloom filter for efficient filtering
         let relevant_chunks = self
@@ -527,8 +635,8 @@ impl<T: MatrixElement> MmapMatrix<T> {
 
         // Filter elements that fall within relevant chunks
         Ok(Box::new((0..values.len()).filter_map(move |i| {
-            let file_row = row_indices[i] as usize;
-            let file_col = col_indices[i] as usize;
+            let file_row = row_indices[i].to_usize();
+            let file_col = col_indices[i].to_usize();
 
             // Check if row is in any relevant chunk range
             let in_relevant_chunk = relevant_row_ranges
@@ -549,18 +657,18 @@ impl<T: MatrixElement> MmapMatrix<T> {
     }
 
     /// Get a range of rows efficiently
+    ///
+    /// Complex matrices are rejected, see [`MatrixElement::to_array_value`].
     pub fn get_row_range(
         &self,
         start_row: usize,
         end_row: usize,
     ) -> Result<Vec<(usize, usize, ArrayValue)>> {
+        ensure_real(T::data_type())?;
         if start_row >= self.nrows() || end_row > self.nrows() || start_row >= end_row {
             return Err(Error::InvalidState("Invalid row range"));
         }
 
-        let values = self.values();
-        let row_indices = self.row_indices();
-        let col_indices = self.col_indices();
         let mut results = Vec::new();
 
         // Use chunk bloom filter to check if we should scan
@@ -570,9 +678,22 @@ impl<T: MatrixElement> MmapMatrix<T> {
             .is_empty();
 
         if should_scan {
+            // Compressed files decode only the blocks overlapping the range
+            if let Some(blocks) = &self.blocks {
+                return Ok(blocks
+                    .row_range(&self._mmap, start_row, end_row)?
+                    .into_iter()
+                    .filter(|&(_, col, _)| col < self.ncols())
+                    .map(|(row, col, value)| (row, col, value.to_array_value()))
+                    .collect());
+            }
+
+            let values = self.values()?;
+            let row_indices = self.row_indices()?;
+            let col_indices = self.col_indices()?;
             for i in 0..values.len() {
-                let file_row = row_indices[i] as usize;
-                let file_col = col_indices[i] as usize;
+                let file_row = row_indices[i].to_usize();
+                let file_col = col_indices[i].to_usize();
 
                 if file_row >= start_row && file_row < end_row && file_col < self.ncols() {
                     results.push((file_row, file_col, values[i].to_array_value()));
@@ -599,6 +720,7 @@ impl<T: MatrixElement> MmapMatrix<T> {
         start_col: usize,
         end_col: usize,
     ) -> Result<Vec<(usize, ArrayValue)>> {
+        ensure_real(T::data_type())?;
         if row >= self.nrows() {
             return Err(Error::InvalidState("Row index out of bounds"));
         }
@@ -620,23 +742,26 @@ impl<T: MatrixElement> MmapMatrix<T> {
     }
 
     /// Get a column range efficiently
+    ///
+    /// Complex matrices are rejected, see [`MatrixElement::to_array_value`].
     pub fn get_col_range(
         &self,
         start_col: usize,
         end_col: usize,
     ) -> Result<Vec<(usize, usize, ArrayValue)>> {
+        ensure_real(T::data_type())?;
         if start_col >= self.ncols() || end_col > self.ncols() || start_col >= end_col {
             return Err(Error::InvalidState("Invalid column range"));
         }
 
-        let values = self.values();
-        let row_indices = self.row_indices();
-        let col_indices = self.col_indices();
+        let values = self.values()?;
+        let row_indices = self.row_indices()?;
+        let col_indices = self.col_indices()?;
         let mut results = Vec::new();
 
         for i in 0..values.len() {
-            let file_row = row_indices[i] as usize;
-            let file_col = col_indices[i] as usize;
+            let file_row = row_indices[i].to_usize();
+            let file_col = col_indices[i].to_usize();
 
             if file_row < self.nrows() && file_col >= start_col && file_col < end_col {
                 results.push((file_row, file_col, values[i].to_array_value()));
@@ -684,126 +809,63 @@ impl<T: MatrixElement> MmapMatrix<T> {
 
     /// Get row label by index
     pub fn row_label(&self, row_idx: u32) -> Result<Option<&[u8]>> {
-        let Some(metadata_bytes) = self.metadata_bytes() else {
-            return Ok(None);
-        };
-
-        let header = crate::metadata::BspcMetadataHeader::from_bytes(metadata_bytes)
-            .map_err(|_| Error::InvalidState("Invalid metadata header"))?;
-        if header.row_labels_size == 0 {
-            return Ok(None);
-        }
-
-        let start = header.row_labels_offset as usize;
-        let end = start + header.row_labels_size as usize;
-        if end > metadata_bytes.len() {
-            return Err(Error::InvalidState("Row labels extend beyond metadata"));
-        }
-
-        let labels_data = &metadata_bytes[start..end];
-        let labels_array = crate::metadata::LabelArray::from_bytes(labels_data)?;
-        if row_idx >= labels_array.count() {
-            return Err(Error::InvalidState("Row index out of bounds"));
+        match self.metadata_view()? {
+            Some(view) => view.row_label(row_idx),
+            None => Ok(None),
         }
-
-        let label_start = bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE
-            + (row_idx as usize * labels_array.stride() as usize);
-        let label_end = label_start + labels_array.stride() as usize;
-        if label_end > labels_data.len() {
-            return Err(Error::InvalidState("Row label extends beyond data"));
-        }
-
-        Ok(Some(&labels_data[label_start..label_end]))
     }
 
     /// Get column label by index
     pub fn col_label(&self, col_idx: u32) -> Result<Option<&[u8]>> {
-        let Some(metadata_bytes) = self.metadata_bytes() else {
-            return Ok(None);
-        };
-
-        let header = crate::metadata::BspcMetadataHeader::from_bytes(metadata_bytes)
-            .map_err(|_| Error::InvalidState("Invalid metadata header"))?;
-        if header.col_labels_size == 0 {
-            return Ok(None);
-        }
-
-        let start = header.col_labels_offset as usize;
-        let end = start + header.col_labels_size as usize;
-        if end > metadata_bytes.len() {
-            return Err(Error::InvalidState("Column labels extend beyond metadata"));
+        match self.metadata_view()? {
+            Some(view) => view.col_label(col_idx),
+            None => Ok(None),
         }
+    }
 
-        let labels_data = &metadata_bytes[start..end];
-        let labels_array = crate::metadata::LabelArray::from_bytes(labels_data)?;
-        if col_idx >= labels_array.count() {
-            return Err(Error::InvalidState("Column index out of bounds"));
+    /// Find the row with the given label
+    ///
+    /// Binary searches the sorted label index in the mapping when the file has
+    /// one, otherwise scans the row labels.
+    pub fn row_index_of(&self, label: &[u8]) -> Result<Option<usize>> {
+        match self.metadata_view()? {
+            Some(view) => Ok(view.row_index_of(label)?.map(|i| i as usize)),
+            None => Ok(None),
         }
+    }
 
-        let label_start = bspc_core::format::constants::metadata::LABEL_ARRAY_HEADER_SIZE
-            + (col_idx as usize * labels_array.stride() as usize);
-        let label_end = label_start + labels_array.stride() as usize;
-        if label_end > labels_data.len() {
-            return Err(Error::InvalidState("Column label extends beyond data"));
+    /// Find the column with the given label
+    ///
+    /// Binary searches the sorted label index in the mapping when the file has
+    /// one, otherwise scans the column labels.
+    pub fn col_index_of(&self, label: &[u8]) -> Result<Option<usize>> {
+        match self.metadata_view()? {
+            Some(view) => Ok(view.col_index_of(label)?.map(|i| i as usize)),
+            None => Ok(None),
         }
-
-        Ok(Some(&labels_data[label_start..label_end]))
     }
 }
 
 // Implement SparseMatrix for MmapMatrix
 #[cfg(feature = "mmap")]
-impl<T: MatrixElement + bspc_core::MatrixElement> SparseMatrix for MmapMatrix<T> {
+impl<T: MatrixElement + bspc_core::MatrixElement, I: MatrixIndex> SparseMatrix
+    for MmapMatrix<T, I>
+{
     type Element = T;
 
     fn get_element(&self, row: usize, col: usize) -> Option<Self::Element> {
-        // Use the typed get_element_typed method if available, otherwise convert from ArrayValue
-        match self.get_element(row, col) {
-            Ok(Some(array_value)) => {
-                // Convert ArrayValue to T - this is a simplified conversion
-                // In practice, you'd want a more robust conversion based on the actual types
-                match array_value {
-                    ArrayValue::Float32(val)
-                        if core::any::TypeId::of::<T>() == core::any::TypeId::of::<f32>() =>
-                    {
-                        // SAFETY: We've checked the type matches
-                        unsafe { Some(*((&val as *const f32) as *const T)) }
-                    }
-                    ArrayValue::Float64(val)
-                        if core::any::TypeId::of::<T>() == core::any::TypeId::of::<f64>() =>
-                    {
-                        // SAFETY: We've checked the type matches
-                        unsafe { Some(*((&val as *const f64) as *const T)) }
-                    }
-                    ArrayValue::Int32(val)
-                        if core::any::TypeId::of::<T>() == core::any::TypeId::of::<i32>() =>
-                    {
-                        // SAFETY: We've checked the type matches
-                        unsafe { Some(*((&val as *const i32) as *const T)) }
-                    }
-                    ArrayValue::Int64(val)
-                        if core::any::TypeId::of::<T>() == core::any::TypeId::of::<i64>() =>
-                    {
-                        // SAFETY: We've checked the type matches
-                        unsafe { Some(*((&val as *const i64) as *const T)) }
-                    }
-                    ArrayValue::UInt32(val)
-                        if core::any::TypeId::of::<T>() == core::any::TypeId::of::<u32>() =>
-                    {
-                        // SAFETY: We've checked the type matches
-                        unsafe { Some(*((&val as *const u32) as *const T)) }
-                    }
-                    ArrayValue::UInt64(val)
-                        if core::any::TypeId::of::<T>() == core::any::TypeId::of::<u64>() =>
-                    {
-                        // SAFETY: We've checked the type matches
-                        unsafe { Some(*((&val as *const u64) as *const T)) }
-                    }
-                    _ => None, // Type mismatch or unsupported conversion
-                }
-            }
-            _ => None,
+        if row >= self.nrows() || col >= self.ncols() {
+            return None;
         }
+        if !self.chunk_bloom_filter.may_contain_row(row) {
+            return None;
+        }
+        if let Some(blocks) = &self.blocks {
+            return blocks.get_element(&self._mmap, row, col).ok().flatten();
+        }
+
+        let idx = self.find_element_index(row, col).ok().flatten()?;
+        self.values().ok().map(|values| values[idx])
     }
 
     fn dimensions(&self) -> (usize, usize) {
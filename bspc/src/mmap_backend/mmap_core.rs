@@ -3,41 +3,50 @@
 //! This module contains the fundamental types for working with memory-mapped sparse matrices.
 
 use binsparse_rs::{Error, Result};
-use bspc_core::{BspcHeader, DataType, MatrixFormat};
+use bspc_core::{BspcHeader, DataType, IndexWidth, MatrixFormat, MatrixIndex, Pattern};
 #[cfg(feature = "mmap")]
 use memmap2::{Mmap, MmapOptions};
-use std::{fs::File, path::Path};
+use num_complex::{Complex32, Complex64};
+use std::{cmp::Ordering, fs::File, path::Path};
 
 /// Macro for safe array accessors
+///
+/// Compressed files have no mapped arrays; their accessors decode every block
+/// on first use, and fail if a block is corrupt.
 macro_rules! safe_array_accessor {
     ($name:ident, $field:ident, $len_field:ident, $type:ty) => {
-        pub(crate) fn $name(&self) -> &[$type] {
+        pub(crate) fn $name(&self) -> Result<&[$type]> {
+            if let Some(blocks) = &self.blocks {
+                return blocks.$name(&self._mmap);
+            }
             // SAFETY: Pointers and lengths validated during construction, mmap keeps memory alive
-            unsafe { std::slice::from_raw_parts(self.$field, self.$len_field) }
+            Ok(unsafe { std::slice::from_raw_parts(self.$field, self.$len_field) })
         }
     };
 }
 
-/// Safely validate array size for u32 indices with overflow protection
-pub(crate) fn validate_u32_array_size(byte_len: usize) -> Result<usize> {
-    const U32_SIZE: usize = 4;
+/// Safely validate array size for indices of type `I` with overflow protection
+pub(crate) fn validate_index_array_size<I: MatrixIndex>(byte_len: usize) -> Result<usize> {
+    let index_size = I::WIDTH.size_bytes();
 
     // Check alignment
-    if byte_len % U32_SIZE != 0 {
-        return Err(Error::InvalidState("Array size not aligned to u32 size"));
+    if byte_len % index_size != 0 {
+        return Err(Error::InvalidState("Array size not aligned to index size"));
     }
 
     // Use checked division
-    let count = byte_len / U32_SIZE;
+    let count = byte_len / index_size;
 
     // Check if array would be too large for safe indexing
     if count > isize::MAX as usize {
-        return Err(Error::InvalidState("u32 array too large for safe indexing"));
+        return Err(Error::InvalidState(
+            "Index array too large for safe indexing",
+        ));
     }
 
     // Verify we can multiply back without overflow
-    count.checked_mul(U32_SIZE).ok_or(Error::InvalidState(
-        "u32 array size calculation would overflow",
+    count.checked_mul(index_size).ok_or(Error::InvalidState(
+        "Index array size calculation would overflow",
     ))?;
 
     Ok(count)
@@ -72,12 +81,12 @@ where
     Ok(unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const T, len) })
 }
 
-/// Helper function for u32 slices with additional validation
-fn create_u32_slice(bytes: &[u8]) -> Result<&[u32]> {
-    let len = validate_u32_array_size(bytes.len())?;
-    let slice = create_typed_slice::<u32>(bytes)?;
+/// Helper function for index slices with additional validation
+fn create_index_slice<I: MatrixIndex>(bytes: &[u8]) -> Result<&[I]> {
+    let len = validate_index_array_size::<I>(bytes.len())?;
+    let slice = create_typed_slice::<I>(bytes)?;
     if slice.len() != len {
-        return Err(Error::InvalidState("U32 slice length mismatch"));
+        return Err(Error::InvalidState("Index slice length mismatch"));
     }
     Ok(slice)
 }
@@ -88,19 +97,38 @@ fn create_u32_slice(bytes: &[u8]) -> Result<&[u32]> {
 /// and byte serialization that builds on top of bspc_core::MatrixElement.
 pub trait MatrixElement: bspc_core::MatrixElement + Send + Sync + 'static {
     /// Convert to ArrayValue for binsparse_rs compatibility
+    ///
+    /// `ArrayValue` has no half precision variants, so `f16` and `bf16` widen
+    /// to `Float32`; the stored type is available from the matrix `data_type()`.
+    /// It has no complex variants either, so accessors returning `ArrayValue`
+    /// reject complex matrices (see [`ensure_real`]).
     fn to_array_value(self) -> binsparse_rs::array::ArrayValue;
     /// Read from bytes in little-endian format
     fn from_le_bytes(bytes: &[u8]) -> Result<Self>;
     /// Write to bytes in little-endian format
     fn to_le_bytes(self) -> Vec<u8>;
+    /// Order two values, as used by top-k queries
+    ///
+    /// Primitive types compare in their own type, so 64-bit integers beyond
+    /// 2^53 stay distinct. Other types compare their `f64` values.
+    fn value_cmp(&self, other: &Self) -> Ordering {
+        self.to_f64().total_cmp(&other.to_f64())
+    }
 }
 
 /// Macro to implement mmap-specific MatrixElement for primitive types
+///
+/// `$cmp` orders two values: `Ord::cmp` for integers, `total_cmp` for floats.
+/// Types without a matching `ArrayValue` variant pass a conversion to a wider one.
 macro_rules! impl_mmap_matrix_element {
-    ($type:ty, $array_variant:ident) => {
+    ($type:ty, $array_variant:ident, $cmp:path) => {
+        impl_mmap_matrix_element!($type, $array_variant, $cmp, |value: $type| value);
+    };
+
+    ($type:ty, $array_variant:ident, $cmp:path, $convert:expr) => {
         impl MatrixElement for $type {
             fn to_array_value(self) -> binsparse_rs::array::ArrayValue {
-                binsparse_rs::array::ArrayValue::$array_variant(self)
+                binsparse_rs::array::ArrayValue::$array_variant(($convert)(self))
             }
 
             fn from_le_bytes(bytes: &[u8]) -> Result<Self> {
@@ -124,31 +152,119 @@ macro_rules! impl_mmap_matrix_element {
             fn to_le_bytes(self) -> Vec<u8> {
                 self.to_le_bytes().to_vec()
             }
+
+            fn value_cmp(&self, other: &Self) -> Ordering {
+                $cmp(self, other)
+            }
         }
     };
 }
 
 // Implement mmap-specific MatrixElement for standard types
-impl_mmap_matrix_element!(f32, Float32);
-impl_mmap_matrix_element!(f64, Float64);
-impl_mmap_matrix_element!(i32, Int32);
-impl_mmap_matrix_element!(i64, Int64);
-impl_mmap_matrix_element!(u32, UInt32);
-impl_mmap_matrix_element!(u64, UInt64);
+impl_mmap_matrix_element!(f32, Float32, f32::total_cmp);
+impl_mmap_matrix_element!(f64, Float64, f64::total_cmp);
+impl_mmap_matrix_element!(i32, Int32, Ord::cmp);
+impl_mmap_matrix_element!(i64, Int64, Ord::cmp);
+impl_mmap_matrix_element!(u32, UInt32, Ord::cmp);
+impl_mmap_matrix_element!(u64, UInt64, Ord::cmp);
+impl_mmap_matrix_element!(i8, Int8, Ord::cmp);
+impl_mmap_matrix_element!(i16, Int16, Ord::cmp);
+impl_mmap_matrix_element!(u8, UInt8, Ord::cmp);
+impl_mmap_matrix_element!(u16, UInt16, Ord::cmp);
+impl_mmap_matrix_element!(half::f16, Float32, half::f16::total_cmp, half::f16::to_f32);
+impl_mmap_matrix_element!(
+    half::bf16,
+    Float32,
+    half::bf16::total_cmp,
+    half::bf16::to_f32
+);
+
+/// Macro to implement mmap-specific MatrixElement for complex types
+///
+/// Values are stored as interleaved little-endian real/imaginary pairs, which
+/// matches the `repr(C)` layout of `Complex`. `ArrayValue` has no complex
+/// variant, so `to_array_value` holds only the real part and is never exposed
+/// by the public accessors.
+macro_rules! impl_mmap_complex_element {
+    ($type:ty, $part:ty, $array_variant:ident) => {
+        impl MatrixElement for $type {
+            fn to_array_value(self) -> binsparse_rs::array::ArrayValue {
+                binsparse_rs::array::ArrayValue::$array_variant(self.re)
+            }
+
+            fn from_le_bytes(bytes: &[u8]) -> Result<Self> {
+                const PART: usize = std::mem::size_of::<$part>();
+                if bytes.len() < 2 * PART {
+                    return Err(Error::ConversionError(concat!(
+                        "Insufficient bytes for ",
+                        stringify!($type)
+                    )));
+                }
+                let re = <$part as MatrixElement>::from_le_bytes(&bytes[..PART])?;
+                let im = <$part as MatrixElement>::from_le_bytes(&bytes[PART..2 * PART])?;
+                Ok(<$type>::new(re, im))
+            }
+
+            fn to_le_bytes(self) -> Vec<u8> {
+                let mut bytes = self.re.to_le_bytes().to_vec();
+                bytes.extend_from_slice(&self.im.to_le_bytes());
+                bytes
+            }
+        }
+    };
+}
+
+impl_mmap_complex_element!(Complex32, f32, Float32);
+impl_mmap_complex_element!(Complex64, f64, Float64);
+
+// Pattern elements have no bytes on disk and read back as boolean true
+impl MatrixElement for Pattern {
+    fn to_array_value(self) -> binsparse_rs::array::ArrayValue {
+        binsparse_rs::array::ArrayValue::BInt8(1)
+    }
+
+    fn from_le_bytes(_bytes: &[u8]) -> Result<Self> {
+        Ok(Pattern)
+    }
+
+    fn to_le_bytes(self) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+/// Reject complex data types in operations that only handle real values
+///
+/// Complex values have no ordering and no `ArrayValue` variant. Rather than
+/// silently using the real part, top-k queries, similarity search,
+/// normalisation and the `ArrayValue` accessors fail for complex matrices;
+/// use the typed accessors, `to_complex` or [`crate::spmv_complex`] instead.
+pub(crate) fn ensure_real(data_type: DataType) -> Result<()> {
+    if data_type.is_complex() {
+        return Err(Error::InvalidState(
+            "Operation is not supported for complex matrices",
+        ));
+    }
+    Ok(())
+}
 
 /// Memory-mapped matrix container that owns the memory mapping
 /// and provides access to arrays using raw pointers with proper lifetime management
+///
+/// `I` is the stored index type and must match the index width in the file
+/// header; it defaults to `u32`. Uncompressed files are accessed in place;
+/// compressed files keep their block table and decode blocks on demand.
 #[cfg(feature = "mmap")]
-pub struct MmapMatrix<T: MatrixElement> {
+pub struct MmapMatrix<T: MatrixElement, I: MatrixIndex = u32> {
     pub(crate) _mmap: Mmap, // Keep the mmap alive
     pub header: BspcHeader,
     pub(crate) values: *const T,
     pub(crate) values_len: usize,
-    pub(crate) row_indices: *const u32,
+    pub(crate) row_indices: *const I,
     pub(crate) row_indices_len: usize,
-    pub(crate) col_indices: *const u32,
+    pub(crate) col_indices: *const I,
     pub(crate) col_indices_len: usize,
     pub(crate) chunk_bloom_filter: crate::chunk_bloom_filter::ChunkBloomFilter,
+    pub(crate) blocks: Option<super::blocks::BlockStore<T, I>>,
     pub(crate) _phantom: std::marker::PhantomData<T>,
 }
 
@@ -158,7 +274,7 @@ pub struct MmapMatrix<T: MatrixElement> {
 // 3. The data is immutable once created
 // 4. T: MatrixElement already requires Send + Sync
 #[cfg(feature = "mmap")]
-unsafe impl<T: MatrixElement> Send for MmapMatrix<T> {}
+unsafe impl<T: MatrixElement, I: MatrixIndex> Send for MmapMatrix<T, I> {}
 
 // SAFETY: MmapMatrix is safe to share between threads (Sync) because:
 // 1. All access methods are read-only
@@ -167,10 +283,10 @@ unsafe impl<T: MatrixElement> Send for MmapMatrix<T> {}
 // 4. No interior mutability is used
 // 5. T: MatrixElement already requires Send + Sync
 #[cfg(feature = "mmap")]
-unsafe impl<T: MatrixElement> Sync for MmapMatrix<T> {}
+unsafe impl<T: MatrixElement, I: MatrixIndex> Sync for MmapMatrix<T, I> {}
 
 #[cfg(feature = "mmap")]
-impl<T: MatrixElement> MmapMatrix<T> {
+impl<T: MatrixElement, I: MatrixIndex> MmapMatrix<T, I> {
     /// Load a matrix from a .bspc file using memory mapping
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let file = File::open(&path).map_err(|_| Error::IoError("Failed to open file"))?;
@@ -189,6 +305,9 @@ impl<T: MatrixElement> MmapMatrix<T> {
 
         let header = BspcHeader::from_bytes(&mmap[0..BspcHeader::SIZE])
             .map_err(|_| Error::InvalidState("Invalid BSPC header format"))?;
+        if header.index_width() != Some(I::WIDTH) {
+            return Err(Error::InvalidState("Index width doesn't match file"));
+        }
 
         // Validate and calculate offsets first (before creating slices)
         let values_start = header.values_offset as usize;
@@ -223,7 +342,9 @@ impl<T: MatrixElement> MmapMatrix<T> {
                 let end = start + size as usize;
                 (end <= mmap.len()).then(|| &mmap[start..end])
             })
-            .and_then(|data| crate::chunk_bloom_filter::ChunkBloomFilter::deserialize(data).ok());
+            .and_then(|data| {
+                crate::chunk_bloom_filter::ChunkBloomFilter::deserialize_with::<I>(data).ok()
+            });
 
         // Create the struct with mmap moved (bloom filter will be set later)
         let mut result = Self {
@@ -239,9 +360,19 @@ impl<T: MatrixElement> MmapMatrix<T> {
                 header.nrows as usize,
                 100_000,
             ), // temporary, will be set properly
+            blocks: None,
             _phantom: std::marker::PhantomData,
         };
 
+        if header.is_compressed() {
+            result.blocks = Some(super::blocks::BlockStore::new(&header, &result._mmap)?);
+            result.chunk_bloom_filter = match loaded_bloom_filter {
+                Some(bloom_filter) => bloom_filter,
+                None => Self::build_bloom_filter(header.nrows as usize, result.row_indices()?),
+            };
+            return Ok(result);
+        }
+
         // Now create slices from the owned mmap
         let values_bytes = &result._mmap[values_start..values_end];
         let row_indices_bytes = &result._mmap[row_indices_start..row_indices_end];
@@ -251,21 +382,33 @@ impl<T: MatrixElement> MmapMatrix<T> {
         if (values_bytes.as_ptr() as usize) % std::mem::align_of::<T>() != 0 {
             return Err(Error::InvalidState("Values array not properly aligned"));
         }
-        if (row_indices_bytes.as_ptr() as usize) % std::mem::align_of::<u32>() != 0 {
+        if (row_indices_bytes.as_ptr() as usize) % std::mem::align_of::<I>() != 0 {
             return Err(Error::InvalidState(
                 "Row indices array not properly aligned",
             ));
         }
-        if (col_indices_bytes.as_ptr() as usize) % std::mem::align_of::<u32>() != 0 {
+        if (col_indices_bytes.as_ptr() as usize) % std::mem::align_of::<I>() != 0 {
             return Err(Error::InvalidState(
                 "Column indices array not properly aligned",
             ));
         }
 
-        // Create typed slices
-        let values = create_typed_slice::<T>(values_bytes)?;
-        let row_indices = create_u32_slice(row_indices_bytes)?;
-        let col_indices = create_u32_slice(col_indices_bytes)?;
+        // Create typed slices; pattern matrices have no values array, and their
+        // zero-sized elements need no backing memory
+        let values = if std::mem::size_of::<T>() == 0 {
+            // SAFETY: A dangling, aligned pointer is valid for any number of
+            // zero-sized elements
+            unsafe {
+                std::slice::from_raw_parts(
+                    std::ptr::NonNull::<T>::dangling().as_ptr(),
+                    header.nnz as usize,
+                )
+            }
+        } else {
+            create_typed_slice::<T>(values_bytes)?
+        };
+        let row_indices = create_index_slice::<I>(row_indices_bytes)?;
+        let col_indices = create_index_slice::<I>(col_indices_bytes)?;
 
         // Validate array consistency
         if values.len() != row_indices.len() || values.len() != col_indices.len() {
@@ -287,27 +430,33 @@ impl<T: MatrixElement> MmapMatrix<T> {
         result.chunk_bloom_filter = if let Some(bloom_filter) = loaded_bloom_filter {
             bloom_filter
         } else {
-            // Create bloom filter from the matrix data
-            let mut bloom_filter =
-                crate::chunk_bloom_filter::ChunkBloomFilter::new(header.nrows as usize, 100_000);
-
-            // Collect unique rows efficiently
-            let mut unique_rows = Vec::new();
-            let mut prev_row = None;
-            for &row in row_indices {
-                if prev_row != Some(row) {
-                    unique_rows.push(row as usize);
-                    prev_row = Some(row);
-                }
-            }
-
-            bloom_filter.bulk_insert_sorted(&unique_rows);
-            bloom_filter
+            Self::build_bloom_filter(header.nrows as usize, row_indices)
         };
 
         Ok(result)
     }
 
+    /// Create bloom filter from the matrix data
+    fn build_bloom_filter(
+        nrows: usize,
+        row_indices: &[I],
+    ) -> crate::chunk_bloom_filter::ChunkBloomFilter {
+        let mut bloom_filter = crate::chunk_bloom_filter::ChunkBloomFilter::new(nrows, 100_000);
+
+        // Collect unique rows efficiently
+        let mut unique_rows = Vec::new();
+        let mut prev_row = None;
+        for &row in row_indices {
+            if prev_row != Some(row) {
+                unique_rows.push(row.to_usize());
+                prev_row = Some(row);
+            }
+        }
+
+        bloom_filter.bulk_insert_sorted(&unique_rows);
+        bloom_filter
+    }
+
     // Matrix dimensions - direct header access
     pub fn nrows(&self) -> usize {
         self.header.nrows as usize
@@ -320,8 +469,8 @@ impl<T: MatrixElement> MmapMatrix<T> {
     }
 
     safe_array_accessor!(values, values, values_len, T);
-    safe_array_accessor!(row_indices, row_indices, row_indices_len, u32);
-    safe_array_accessor!(col_indices, col_indices, col_indices_len, u32);
+    safe_array_accessor!(row_indices, row_indices, row_indices_len, I);
+    safe_array_accessor!(col_indices, col_indices, col_indices_len, I);
 
     // Simple accessors
     pub fn chunk_bloom_filter(&self) -> &crate::chunk_bloom_filter::ChunkBloomFilter {
@@ -333,4 +482,10 @@ impl<T: MatrixElement> MmapMatrix<T> {
     pub fn data_type(&self) -> DataType {
         DataType::from_u8(self.header.data_type).unwrap_or(DataType::F64)
     }
+    pub fn index_width(&self) -> IndexWidth {
+        I::WIDTH
+    }
+    pub fn is_compressed(&self) -> bool {
+        self.blocks.is_some()
+    }
 }
@@ -0,0 +1,314 @@
+//! Top-k queries for memory-mapped sparse matrices
+//!
+//! This module finds the largest stored entries of a row or column. Values are
+//! compared in their stored type (see [`MatrixElement::value_cmp`]) and ties
+//! are broken by the smaller index so results are deterministic. Complex
+//! values have no ordering, so complex matrices are rejected.
+
+use super::matrix_operations::row_span;
+use super::mmap_core::{ensure_real, MatrixElement, MmapMatrix};
+use binsparse_rs::{array::ArrayValue, Error, Result};
+use bspc_core::MatrixIndex;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+/// Order entries by descending value, then ascending index
+fn rank<T: MatrixElement>(a: &(usize, T), b: &(usize, T)) -> Ordering {
+    b.1.value_cmp(&a.1).then_with(|| a.0.cmp(&b.0))
+}
+
+/// Keep the `k` highest ranked entries, sorted by rank
+fn select_top_k<T: MatrixElement>(
+    mut entries: Vec<(usize, T)>,
+    k: usize,
+) -> Vec<(usize, ArrayValue)> {
+    if k == 0 {
+        return Vec::new();
+    }
+    if entries.len() > k {
+        entries.select_nth_unstable_by(k - 1, rank);
+        entries.truncate(k);
+    }
+    entries.sort_unstable_by(rank);
+
+    entries
+        .into_iter()
+        .map(|(index, value)| (index, value.to_array_value()))
+        .collect()
+}
+
+/// Rows evaluated in parallel per batch by [`TopKRows`]
+const TOP_K_BATCH_ROWS: usize = 4096;
+
+#[cfg(feature = "mmap")]
+impl<T: MatrixElement, I: MatrixIndex> MmapMatrix<T, I> {
+    /// Get the `k` largest entries of a row as `(col, value)` pairs
+    ///
+    /// Entries are sorted by descending value; equal values are ordered by column.
+    pub fn top_k_row(&self, row: usize, k: usize) -> Result<Vec<(usize, ArrayValue)>> {
+        ensure_real(T::data_type())?;
+        if row >= self.nrows() {
+            return Err(Error::InvalidState("Row index out of bounds"));
+        }
+
+        let values = self.values()?;
+        let row_indices = self.row_indices()?;
+        let col_indices = self.col_indices()?;
+        Ok(self.row_top_k(values, row_indices, col_indices, row, k))
+    }
+
+    /// Get the `k` largest entries of a column as `(row, value)` pairs
+    ///
+    /// Entries are sorted by descending value; equal values are ordered by row.
+    pub fn top_k_col(&self, col: usize, k: usize) -> Result<Vec<(usize, ArrayValue)>> {
+        ensure_real(T::data_type())?;
+        if col >= self.ncols() {
+            return Err(Error::InvalidState("Column index out of bounds"));
+        }
+
+        let values = self.values()?;
+        let row_indices = self.row_indices()?;
+        let col_indices = self.col_indices()?;
+        let entries = (0..values.len())
+            .into_par_iter()
+            .filter(|&i| col_indices[i].to_usize() == col)
+            .map(|i| (row_indices[i].to_usize(), values[i]))
+            .collect();
+
+        Ok(select_top_k(entries, k))
+    }
+
+    /// Stream the `k` largest entries of every row as `(row, entries)` pairs
+    ///
+    /// Rows are evaluated in parallel a batch at a time, so memory use is
+    /// bounded by the batch rather than the matrix. Every row is yielded in
+    /// order; rows without stored values have no entries.
+    pub fn top_k_per_row(&self, k: usize) -> Result<TopKRows<'_, T, I>> {
+        ensure_real(T::data_type())?;
+        Ok(TopKRows {
+            matrix: self,
+            values: self.values()?,
+            row_indices: self.row_indices()?,
+            col_indices: self.col_indices()?,
+            k,
+            next_row: 0,
+            batch: Vec::new().into_iter(),
+        })
+    }
+
+    /// Top-k entries of an in-bounds row, given the decoded arrays
+    fn row_top_k(
+        &self,
+        values: &[T],
+        row_indices: &[I],
+        col_indices: &[I],
+        row: usize,
+        k: usize,
+    ) -> Vec<(usize, ArrayValue)> {
+        if !self.chunk_bloom_filter.may_contain_row(row) {
+            return Vec::new();
+        }
+
+        let entries = row_span(row_indices, row)
+            .map(|i| (col_indices[i].to_usize(), values[i]))
+            .collect();
+
+        select_top_k(entries, k)
+    }
+}
+
+/// Iterator over the top-k entries of every row, see [`MmapMatrix::top_k_per_row`]
+#[cfg(feature = "mmap")]
+pub struct TopKRows<'a, T: MatrixElement, I: MatrixIndex = u32> {
+    matrix: &'a MmapMatrix<T, I>,
+    values: &'a [T],
+    row_indices: &'a [I],
+    col_indices: &'a [I],
+    k: usize,
+    next_row: usize,
+    batch: std::vec::IntoIter<(usize, Vec<(usize, ArrayValue)>)>,
+}
+
+#[cfg(feature = "mmap")]
+impl<T: MatrixElement, I: MatrixIndex> Iterator for TopKRows<'_, T, I> {
+    type Item = (usize, Vec<(usize, ArrayValue)>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.batch.next() {
+            return Some(item);
+        }
+
+        let start = self.next_row;
+        let end = (start + TOP_K_BATCH_ROWS).min(self.matrix.nrows());
+        if start >= end {
+            return None;
+        }
+        self.next_row = end;
+
+        let (matrix, k) = (self.matrix, self.k);
+        let (values, row_indices, col_indices) = (self.values, self.row_indices, self.col_indices);
+        self.batch = (start..end)
+            .into_par_iter()
+            .map(|row| {
+                let entries = matrix.row_top_k(values, row_indices, col_indices, row, k);
+                (row, entries)
+            })
+            .collect::<Vec<_>>()
+            .into_iter();
+        self.batch.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<T: MatrixElement, I: MatrixIndex> ExactSizeIterator for TopKRows<'_, T, I> {
+    fn len(&self) -> usize {
+        self.batch.len() + self.matrix.nrows().saturating_sub(self.next_row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunked_backend::ChunkConfig;
+    use crate::mmap_backend::{BspcFile, DynamicElement};
+    use crate::test_support::TempDir;
+
+    fn as_i32(entries: Vec<(usize, ArrayValue)>) -> Vec<(usize, DynamicElement)> {
+        entries
+            .into_iter()
+            .map(|(index, value)| (index, DynamicElement::from_array_value(value)))
+            .collect()
+    }
+
+    async fn write_matrix(
+        dir: &TempDir,
+        nrows: usize,
+        elements: &[(usize, usize, i32)],
+    ) -> MmapMatrix<i32> {
+        let path = dir.file("matrix.bspc");
+        BspcFile::write_sparse_matrix(nrows, 5, elements, ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        MmapMatrix::from_file(&path).unwrap()
+    }
+
+    const ELEMENTS: [(usize, usize, i32); 7] = [
+        (0, 0, 3),
+        (0, 1, 7),
+        (0, 2, 3),
+        (0, 4, -1),
+        (1, 1, 2),
+        (3, 1, 9),
+        (3, 2, 3),
+    ];
+
+    #[tokio::test]
+    async fn test_top_k_row_breaks_ties_by_column() {
+        let dir = TempDir::new();
+        let matrix = write_matrix(&dir, 4, &ELEMENTS).await;
+
+        let top = as_i32(matrix.top_k_row(0, 3).unwrap());
+        assert_eq!(
+            top,
+            vec![
+                (1, DynamicElement::I32(7)),
+                (0, DynamicElement::I32(3)),
+                (2, DynamicElement::I32(3)),
+            ]
+        );
+        assert!(matrix.top_k_row(2, 3).unwrap().is_empty());
+        assert!(matrix.top_k_row(0, 0).unwrap().is_empty());
+        assert!(matrix.top_k_row(4, 1).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_top_k_col() {
+        let dir = TempDir::new();
+        let matrix = write_matrix(&dir, 4, &ELEMENTS).await;
+
+        let top = as_i32(matrix.top_k_col(1, 2).unwrap());
+        assert_eq!(
+            top,
+            vec![(3, DynamicElement::I32(9)), (0, DynamicElement::I32(7))]
+        );
+        assert!(matrix.top_k_col(3, 2).unwrap().is_empty());
+        assert!(matrix.top_k_col(5, 1).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_top_k_per_row_streams_every_row() {
+        let dir = TempDir::new();
+        // Spans more than one batch
+        let nrows = TOP_K_BATCH_ROWS + 10;
+        let elements: Vec<_> = (0..nrows)
+            .step_by(3)
+            .flat_map(|row| [(row, 0, row as i32), (row, 3, 1)])
+            .collect();
+        let matrix = write_matrix(&dir, nrows, &elements).await;
+
+        let rows = matrix.top_k_per_row(1).unwrap();
+        assert_eq!(rows.len(), nrows);
+
+        let mut seen = 0;
+        for (row, top) in rows {
+            assert_eq!(row, seen);
+            seen += 1;
+
+            let expected = match row {
+                0 => vec![(3, DynamicElement::I32(1))],
+                _ if row % 3 == 0 => vec![(0, DynamicElement::I32(row as i32))],
+                _ => Vec::new(),
+            };
+            assert_eq!(as_i32(top), expected);
+        }
+        assert_eq!(seen, nrows);
+    }
+
+    #[tokio::test]
+    async fn test_top_k_keeps_large_integers_apart() {
+        // Neighbouring integers above 2^53 share one f64 value
+        let dir = TempDir::new();
+        let path = dir.file("i64.bspc");
+        let big = 1i64 << 53;
+        let elements = [(0, 0, big), (0, 1, big + 1), (1, 0, -big - 1), (1, 1, -big)];
+        BspcFile::write_sparse_matrix(2, 2, &elements, ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        let matrix = MmapMatrix::<i64>::from_file(&path).unwrap();
+        let indices = |entries: Vec<(usize, ArrayValue)>| -> Vec<usize> {
+            entries.into_iter().map(|(index, _)| index).collect()
+        };
+
+        assert_eq!(indices(matrix.top_k_row(0, 2).unwrap()), vec![1, 0]);
+        assert_eq!(indices(matrix.top_k_row(1, 1).unwrap()), vec![1]);
+        assert_eq!(indices(matrix.top_k_col(0, 2).unwrap()), vec![0, 1]);
+
+        let path = dir.file("u64.bspc");
+        let elements = [(0, 0, u64::MAX - 1), (0, 1, u64::MAX)];
+        BspcFile::write_sparse_matrix(1, 2, &elements, ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        let matrix = MmapMatrix::<u64>::from_file(&path).unwrap();
+        assert_eq!(indices(matrix.top_k_row(0, 1).unwrap()), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_top_k_rejects_complex() {
+        let dir = TempDir::new();
+        let path = dir.file("complex.bspc");
+        let elements = [(0, 0, num_complex::Complex64::new(1.0, 2.0))];
+        BspcFile::write_sparse_matrix(1, 1, &elements, ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        let matrix = MmapMatrix::<num_complex::Complex64>::from_file(&path).unwrap();
+
+        assert!(matrix.top_k_row(0, 1).is_err());
+        assert!(matrix.top_k_col(0, 1).is_err());
+        assert!(matrix.top_k_per_row(1).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! Normalisation kernels for count matrices
+//!
+//! This module provides the normalisation steps commonly applied to single-cell
+//! count matrices. Each kernel computes its size factors in one pass over the
+//! source matrix and writes an `f32` matrix in a second pass, keeping the source
+//! labels and chunk bloom filter layout. Complex matrices are rejected rather
+//! than normalised by their real part.
+
+use crate::chunked_backend::ChunkConfig;
+use crate::mmap_backend::mmap_core::ensure_real;
+use crate::mmap_backend::{MatrixElement, MmapMatrix};
+use crate::transform::Transform;
+use binsparse_rs::Result;
+use std::path::Path;
+
+/// Matrix axis along which size factors are computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// One factor per row
+    Row,
+    /// One factor per column
+    Col,
+}
+
+/// Built-in normalisation methods
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    /// Counts per million: scale each row (or column) to sum to 1e6
+    Cpm(Axis),
+    /// Natural log of one plus each value
+    Log1p,
+    /// Term frequency times inverse document frequency, with rows as documents
+    ///
+    /// `tf = value / row_sum` and `idf = ln(1 + nrows / df)` where `df` is the
+    /// number of rows with a stored value in the column.
+    TfIdf,
+    /// Divide each column by its standard deviation (implicit zeros included)
+    ///
+    /// Values are not centred so the output keeps the sparsity of the input.
+    ScaleColumns,
+}
+
+/// Per-row or per-column factors computed in the first pass
+#[derive(Debug, Clone, Default)]
+pub struct SizeFactors {
+    /// Multiplier applied to every value of a row (empty when unused)
+    pub row: Vec<f64>,
+    /// Multiplier applied to every value of a column (empty when unused)
+    pub col: Vec<f64>,
+}
+
+impl SizeFactors {
+    /// Compute the factors required by a normalisation method in one pass
+    ///
+    /// Complex matrices are rejected.
+    pub fn compute<T: MatrixElement>(
+        matrix: &MmapMatrix<T>,
+        method: Normalization,
+    ) -> Result<Self> {
+        ensure_real(T::data_type())?;
+        let values = matrix.values()?;
+        let row_indices = matrix.row_indices()?;
+        let col_indices = matrix.col_indices()?;
+
+        Ok(match method {
+            Normalization::Log1p => Self::default(),
+            Normalization::Cpm(Axis::Row) => {
+                let mut sums = vec![0.0; matrix.nrows()];
+                for (&row, &value) in row_indices.iter().zip(values) {
+                    sums[row as usize] += value.to_f64();
+                }
+                Self {
+                    row: sums.into_iter().map(|s| safe_recip(s) * 1e6).collect(),
+                    col: Vec::new(),
+                }
+            }
+            Normalization::Cpm(Axis::Col) => {
+                let mut sums = vec![0.0; matrix.ncols()];
+                for (&col, &value) in col_indices.iter().zip(values) {
+                    sums[col as usize] += value.to_f64();
+                }
+                Self {
+                    row: Vec::new(),
+                    col: sums.into_iter().map(|s| safe_recip(s) * 1e6).collect(),
+                }
+            }
+            Normalization::TfIdf => {
+                let mut row_sums = vec![0.0; matrix.nrows()];
+                let mut doc_freq = vec![0u64; matrix.ncols()];
+                for ((&row, &col), &value) in row_indices.iter().zip(col_indices).zip(values) {
+                    row_sums[row as usize] += value.to_f64();
+                    doc_freq[col as usize] += 1;
+                }
+
+                let ndocs = matrix.nrows() as f64;
+                Self {
+                    row: row_sums.into_iter().map(safe_recip).collect(),
+                    col: doc_freq
+                        .into_iter()
+                        .map(|df| {
+                            if df == 0 {
+                                0.0
+                            } else {
+                                (1.0 + ndocs / df as f64).ln()
+                            }
+                        })
+                        .collect(),
+                }
+            }
+            Normalization::ScaleColumns => {
+                let mut sums = vec![0.0; matrix.ncols()];
+                let mut sums_sq = vec![0.0; matrix.ncols()];
+                for (&col, &value) in col_indices.iter().zip(values) {
+                    let v = value.to_f64();
+                    sums[col as usize] += v;
+                    sums_sq[col as usize] += v * v;
+                }
+
+                // Without rows there are no values to scale
+                if matrix.nrows() == 0 {
+                    return Ok(Self {
+                        row: Vec::new(),
+                        col: vec![0.0; matrix.ncols()],
+                    });
+                }
+
+                let n = matrix.nrows() as f64;
+                Self {
+                    row: Vec::new(),
+                    col: sums
+                        .into_iter()
+                        .zip(sums_sq)
+                        .map(|(sum, sum_sq)| {
+                            let mean = sum / n;
+                            let variance = (sum_sq / n - mean * mean).max(0.0);
+                            safe_recip(variance.sqrt())
+                        })
+                        .collect(),
+                }
+            }
+        })
+    }
+}
+
+/// Reciprocal that maps zero (empty rows/columns) to zero
+fn safe_recip(value: f64) -> f64 {
+    if value == 0.0 {
+        0.0
+    } else {
+        1.0 / value
+    }
+}
+
+/// Normalise a matrix and write the `f32` result to a new .bspc file
+///
+/// Labels are carried over from the source metadata and the output uses the
+/// same bloom filter chunk size as the source. Normalisation never drops a
+/// stored element, so the rebuilt filter marks exactly the chunks the source
+/// filter does.
+pub async fn normalize<T: MatrixElement, P: AsRef<Path>>(
+    matrix: &MmapMatrix<T>,
+    method: Normalization,
+    path: P,
+) -> Result<()> {
+    let factors = SizeFactors::compute(matrix, method)?;
+    let config = ChunkConfig::default().with_chunk_size(matrix.chunk_bloom_filter().chunk_size());
+    let transform = Transform::new(matrix);
+
+    match method {
+        Normalization::Log1p => {
+            transform
+                .map(|v| v.to_f64().ln_1p() as f32)
+                .write_to(path, config)
+                .await
+        }
+        Normalization::Cpm(Axis::Row) => {
+            transform
+                .map_indexed(move |row, _, v| (v.to_f64() * factors.row[row]) as f32)
+                .write_to(path, config)
+                .await
+        }
+        Normalization::Cpm(Axis::Col) | Normalization::ScaleColumns => {
+            transform
+                .map_indexed(move |_, col, v| (v.to_f64() * factors.col[col]) as f32)
+                .write_to(path, config)
+                .await
+        }
+        Normalization::TfIdf => {
+            transform
+                .map_indexed(move |row, col, v| {
+                    (v.to_f64() * factors.row[row] * factors.col[col]) as f32
+                })
+                .write_to(path, config)
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmap_backend::BspcFile;
+    use crate::test_support::TempDir;
+
+    async fn write_counts(
+        dir: &TempDir,
+        nrows: usize,
+        elements: &[(usize, usize, u32)],
+    ) -> MmapMatrix<u32> {
+        let path = dir.file("counts.bspc");
+        BspcFile::write_sparse_matrix(
+            nrows,
+            2,
+            elements,
+            ChunkConfig::default().with_chunk_size(2),
+            &path,
+        )
+        .await
+        .unwrap();
+        MmapMatrix::from_file(&path).unwrap()
+    }
+
+    async fn normalized(
+        dir: &TempDir,
+        counts: &MmapMatrix<u32>,
+        method: Normalization,
+    ) -> MmapMatrix<f32> {
+        let out = dir.file("normalized.bspc");
+        normalize(counts, method, &out).await.unwrap();
+        MmapMatrix::from_file(&out).unwrap()
+    }
+
+    const COUNTS: [(usize, usize, u32); 4] = [(0, 0, 1), (0, 1, 3), (2, 0, 2), (2, 1, 2)];
+
+    fn assert_close(actual: &[f32], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (f64::from(*a) - e).abs() < 1e-3 * e.abs().max(1.0),
+                "{a} != {e}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cpm_rows() {
+        let dir = TempDir::new();
+        let counts = write_counts(&dir, 3, &COUNTS).await;
+        let result = normalized(&dir, &counts, Normalization::Cpm(Axis::Row)).await;
+        assert_close(
+            result.values().unwrap(),
+            &[250_000.0, 750_000.0, 500_000.0, 500_000.0],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cpm_cols() {
+        let dir = TempDir::new();
+        let counts = write_counts(&dir, 3, &COUNTS).await;
+        let result = normalized(&dir, &counts, Normalization::Cpm(Axis::Col)).await;
+        assert_close(
+            result.values().unwrap(),
+            &[1e6 / 3.0, 600_000.0, 2e6 / 3.0, 400_000.0],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log1p_keeps_structure_and_bloom_filter() {
+        let dir = TempDir::new();
+        let counts = write_counts(&dir, 3, &COUNTS).await;
+        let result = normalized(&dir, &counts, Normalization::Log1p).await;
+
+        let expected: Vec<f64> = COUNTS
+            .iter()
+            .map(|&(_, _, v)| f64::from(v).ln_1p())
+            .collect();
+        assert_close(result.values().unwrap(), &expected);
+        assert_eq!(result.row_indices().unwrap(), counts.row_indices().unwrap());
+        assert_eq!(result.col_indices().unwrap(), counts.col_indices().unwrap());
+        assert_eq!(
+            result.chunk_bloom_filter().serialize(),
+            counts.chunk_bloom_filter().serialize()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tf_idf() {
+        let dir = TempDir::new();
+        let counts = write_counts(&dir, 3, &[(0, 0, 1), (0, 1, 3), (2, 1, 2)]).await;
+        let result = normalized(&dir, &counts, Normalization::TfIdf).await;
+
+        let idf0 = (1.0f64 + 3.0).ln();
+        let idf1 = (1.0f64 + 1.5).ln();
+        assert_close(result.values().unwrap(), &[0.25 * idf0, 0.75 * idf1, idf1]);
+    }
+
+    #[tokio::test]
+    async fn test_scale_columns() {
+        let dir = TempDir::new();
+        let counts = write_counts(&dir, 3, &COUNTS).await;
+        let result = normalized(&dir, &counts, Normalization::ScaleColumns).await;
+
+        // Column 0 is [1, 0, 2] and column 1 is [3, 0, 2]
+        let std0 = (5.0f64 / 3.0 - 1.0).sqrt();
+        let std1 = (13.0f64 / 3.0 - 25.0 / 9.0).sqrt();
+        assert_close(
+            result.values().unwrap(),
+            &[1.0 / std0, 3.0 / std1, 2.0 / std0, 2.0 / std1],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scale_columns_without_rows() {
+        let dir = TempDir::new();
+        let counts = write_counts(&dir, 0, &[]).await;
+
+        let factors = SizeFactors::compute(&counts, Normalization::ScaleColumns).unwrap();
+        assert_eq!(factors.col, vec![0.0, 0.0]);
+
+        let result = normalized(&dir, &counts, Normalization::ScaleColumns).await;
+        assert_eq!((result.nrows(), result.ncols(), result.nnz()), (0, 2, 0));
+    }
+
+    #[tokio::test]
+    async fn test_complex_matrix_rejected() {
+        let dir = TempDir::new();
+        let path = dir.file("complex.bspc");
+        let elements = [(0, 0, num_complex::Complex64::new(3.0, 4.0))];
+        BspcFile::write_sparse_matrix(1, 1, &elements, ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        let matrix = MmapMatrix::<num_complex::Complex64>::from_file(&path).unwrap();
+
+        assert!(SizeFactors::compute(&matrix, Normalization::Cpm(Axis::Row)).is_err());
+        let out = dir.file("normalized.bspc");
+        assert!(normalize(&matrix, Normalization::Log1p, &out)
+            .await
+            .is_err());
+        assert!(!out.exists());
+    }
+}
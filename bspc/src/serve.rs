@@ -0,0 +1,18 @@
+//! Local HTTP servers for BSPC files
+//!
+//! [`serve_dir`] and [`StaticServer`] serve a directory with range request
+//! support (`static_files`), which is enough for [`crate::HttpMatrix`] to read
+//! files from localhost in tests or to share them on a local network.
+//! [`serve_api`] and [`ApiServer`] instead answer row, column and element
+//! queries as JSON or Arrow (`api`, behind the "api" feature). The servers run
+//! on a minimal HTTP/1.1 implementation (`http`) on top of tokio.
+//! Only available when the "http" feature is enabled.
+
+#[cfg(feature = "api")]
+mod api;
+mod http;
+mod static_files;
+
+#[cfg(feature = "api")]
+pub use api::{serve_api, ApiServer};
+pub use static_files::{serve_dir, StaticServer};
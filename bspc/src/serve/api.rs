@@ -0,0 +1,932 @@
+//! JSON and Arrow query API over memory-mapped BSPC files
+//!
+//! Every file is served under its file stem as `{name}`:
+//!
+//! | Endpoint | Result |
+//! |----------|--------|
+//! | `GET /matrices` | Names and shapes of the served matrices |
+//! | `GET /m/{name}/info` | Shape, types, labels, annotations and attributes |
+//! | `GET /m/{name}/row/{idx-or-label}` | Elements of one row |
+//! | `GET /m/{name}/col/{idx-or-label}` | Elements of one column |
+//! | `GET /m/{name}/element?row=..&col=..` | One element, if stored |
+//! | `GET /m/{name}/slice?rows=a:b&cols=c:d` | Elements in a block of rows and columns |
+//!
+//! Rows and columns are looked up by label first, then as a numeric index.
+//! Element lists are paginated with `offset` and `limit`; until the last page
+//! responses carry `next_offset`, also sent as the `X-Next-Offset` header.
+//! Element lists are JSON unless `format=arrow` is given or the `Accept`
+//! header asks for `application/vnd.apache.arrow.stream`, in which case the
+//! page is sent as an Arrow IPC stream holding one record batch.
+//!
+//! Complex matrices are listed and described by `info`, but their element
+//! queries answer 400: elements are sent as real values only.
+
+use super::http::{self, percent_decode, Request, Response};
+use crate::mmap_backend::{BspcFile, DynamicMatrix};
+use crate::normalize::Axis;
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray, UInt64Array};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType as ArrowType, Field, Schema};
+use binsparse_rs::{array::ArrayValue, Error, Result};
+use bspc_core::DataType;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// Content type of Arrow IPC streams
+const ARROW_STREAM: &str = "application/vnd.apache.arrow.stream";
+
+/// Content type of JSON responses
+const JSON: &str = "application/json";
+
+/// Elements per page when no `limit` is given
+const DEFAULT_LIMIT: usize = 1000;
+
+/// Largest accepted `limit`
+const MAX_LIMIT: usize = 100_000;
+
+/// Serve the query API for the files in `paths` on `addr` until the task is cancelled
+///
+/// Directories in `paths` contribute the `.bspc` files directly inside them.
+pub async fn serve_api<P: AsRef<Path>>(paths: &[P], addr: SocketAddr) -> Result<()> {
+    let api = Api::load(paths)?;
+    let listener = bind(addr).await?;
+    run(listener, api).await;
+    Ok(())
+}
+
+/// Query API server running in the background
+///
+/// The server stops when this handle is dropped.
+pub struct ApiServer {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl ApiServer {
+    /// Start serving the query API for the files in `paths` on `addr`
+    ///
+    /// Port `0` picks a free port; see [`ApiServer::local_addr`].
+    pub async fn start<P: AsRef<Path>>(paths: &[P], addr: SocketAddr) -> Result<Self> {
+        let api = Api::load(paths)?;
+        let listener = bind(addr).await?;
+        let addr = listener
+            .local_addr()
+            .map_err(|_| Error::IoError("Failed to read server address"))?;
+
+        Ok(Self {
+            addr,
+            task: tokio::spawn(run(listener, api)),
+        })
+    }
+
+    /// Get the address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Get the URL of an endpoint, such as `matrices`
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}/{}", self.addr, path.trim_start_matches('/'))
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    TcpListener::bind(addr)
+        .await
+        .map_err(|_| Error::IoError("Failed to bind server address"))
+}
+
+/// Answer requests on `listener`; queries run on the blocking thread pool
+async fn run(listener: TcpListener, api: Api) {
+    let api = Arc::new(api);
+    http::serve(listener, move |request| {
+        let api = Arc::clone(&api);
+        async move {
+            tokio::task::spawn_blocking(move || api.respond(&request))
+                .await
+                .unwrap_or_else(|_| Response::text(500, "Query failed"))
+        }
+    })
+    .await
+}
+
+/// Failure answered with a JSON error body
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        Self::new(400, format!("{error:?}"))
+    }
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+fn json_response(status: u16, value: &Value) -> Response {
+    Response::new(status).bytes(JSON, serde_json::to_vec(value).unwrap_or_default())
+}
+
+/// Requested page of an element list
+struct Pagination {
+    offset: usize,
+    limit: usize,
+}
+
+impl Pagination {
+    fn from_request(request: &Request) -> ApiResult<Self> {
+        let param = |name, default| match request.query_param(name) {
+            Some(value) => value
+                .parse::<usize>()
+                .map_err(|_| ApiError::new(400, format!("Invalid {name}"))),
+            None => Ok(default),
+        };
+
+        Ok(Self {
+            offset: param("offset", 0)?,
+            limit: param("limit", DEFAULT_LIMIT)?.clamp(1, MAX_LIMIT),
+        })
+    }
+
+    /// Take this page from `items`, and the offset of the next page if any
+    fn apply<T>(&self, items: impl Iterator<Item = T>) -> (Vec<T>, Option<usize>) {
+        let mut page: Vec<T> = items.skip(self.offset).take(self.limit + 1).collect();
+        if page.len() > self.limit {
+            page.truncate(self.limit);
+            (page, Some(self.offset + self.limit))
+        } else {
+            (page, None)
+        }
+    }
+}
+
+/// Name and values of a label column
+type LabelColumn = (&'static str, Vec<Option<String>>);
+
+/// One page of matrix elements
+struct Page {
+    /// Index columns, such as `row` and `col`
+    indices: Vec<(&'static str, Vec<u64>)>,
+    /// Label columns, for axes that have labels
+    labels: Vec<LabelColumn>,
+    values: Vec<ArrayValue>,
+    data_type: DataType,
+    offset: usize,
+    next_offset: Option<usize>,
+    /// Length of the whole list, when known without scanning it
+    total: Option<usize>,
+}
+
+/// Format a label for output
+fn label_text(label: &[u8]) -> String {
+    String::from_utf8_lossy(label).into_owned()
+}
+
+/// Labels of `indices` along `axis`, or `None` when the axis has no labels
+fn axis_labels(
+    matrix: &DynamicMatrix,
+    axis: Axis,
+    indices: &[u64],
+) -> Result<Option<Vec<Option<String>>>> {
+    let Some(view) = matrix.metadata_view()? else {
+        return Ok(None);
+    };
+    let labels = match axis {
+        Axis::Row => view.row_labels()?,
+        Axis::Col => view.col_labels()?,
+    };
+
+    Ok(labels.map(|labels| {
+        indices
+            .iter()
+            .map(|&i| labels.get_label(i as u32).ok().map(label_text))
+            .collect()
+    }))
+}
+
+/// Resolve a row or column given as a label or an index
+fn resolve(matrix: &DynamicMatrix, axis: Axis, key: &str) -> ApiResult<usize> {
+    let (by_label, len) = match axis {
+        Axis::Row => (matrix.row_index_of(key.as_bytes())?, matrix.nrows()),
+        Axis::Col => (matrix.col_index_of(key.as_bytes())?, matrix.ncols()),
+    };
+
+    match by_label.or_else(|| key.parse::<usize>().ok()) {
+        Some(index) if index < len => Ok(index),
+        Some(_) => Err(ApiError::new(404, "Index out of bounds")),
+        None => Err(ApiError::new(404, format!("Unknown label {key:?}"))),
+    }
+}
+
+/// Parse an optional `start:end` parameter, defaulting to `0..len`
+fn range_param(request: &Request, name: &str, len: usize) -> ApiResult<Range<usize>> {
+    let Some(value) = request.query_param(name) else {
+        return Ok(0..len);
+    };
+    let range = crate::http_backend::parse_range(&value)
+        .map_err(|_| ApiError::new(400, format!("Invalid {name}, use start:end")))?;
+    if range.end > len {
+        return Err(ApiError::new(400, format!("{name} out of bounds")));
+    }
+    Ok(range)
+}
+
+fn value_json(value: &ArrayValue) -> Value {
+    match *value {
+        ArrayValue::Float32(v) => json!(v),
+        ArrayValue::Float64(v) => json!(v),
+        ArrayValue::Int8(v) => json!(v),
+        ArrayValue::Int16(v) => json!(v),
+        ArrayValue::Int32(v) => json!(v),
+        ArrayValue::Int64(v) => json!(v),
+        ArrayValue::UInt8(v) => json!(v),
+        ArrayValue::UInt16(v) => json!(v),
+        ArrayValue::UInt32(v) => json!(v),
+        ArrayValue::UInt64(v) => json!(v),
+        ArrayValue::BInt8(v) => json!(v),
+    }
+}
+
+/// Widen an integer value; `None` for floating point values
+fn value_int(value: &ArrayValue) -> Option<i128> {
+    match *value {
+        ArrayValue::Int8(v) => Some(v.into()),
+        ArrayValue::Int16(v) => Some(v.into()),
+        ArrayValue::Int32(v) => Some(v.into()),
+        ArrayValue::Int64(v) => Some(v.into()),
+        ArrayValue::UInt8(v) => Some(v.into()),
+        ArrayValue::UInt16(v) => Some(v.into()),
+        ArrayValue::UInt32(v) => Some(v.into()),
+        ArrayValue::UInt64(v) => Some(v.into()),
+        ArrayValue::BInt8(v) => Some(v.into()),
+        ArrayValue::Float32(_) | ArrayValue::Float64(_) => None,
+    }
+}
+
+fn value_f64(value: &ArrayValue) -> f64 {
+    match *value {
+        ArrayValue::Float32(v) => v.into(),
+        ArrayValue::Float64(v) => v,
+        _ => value_int(value).unwrap_or_default() as f64,
+    }
+}
+
+/// Arrow column holding `values` of a matrix with `data_type`
+///
+/// Integers keep their signedness at 64 bits and floating point values
+/// become `Float64`. Complex matrices never get here, as their element
+/// queries are rejected.
+fn value_column(values: &[ArrayValue], data_type: DataType) -> (ArrowType, ArrayRef) {
+    match data_type {
+        DataType::I8 | DataType::I16 | DataType::I32 | DataType::I64 => (
+            ArrowType::Int64,
+            Arc::new(Int64Array::from_iter_values(
+                values
+                    .iter()
+                    .map(|v| value_int(v).unwrap_or_default() as i64),
+            )),
+        ),
+        DataType::U8 | DataType::U16 | DataType::U32 | DataType::U64 | DataType::Pattern => (
+            ArrowType::UInt64,
+            Arc::new(UInt64Array::from_iter_values(
+                values
+                    .iter()
+                    .map(|v| value_int(v).unwrap_or_default() as u64),
+            )),
+        ),
+        _ => (
+            ArrowType::Float64,
+            Arc::new(Float64Array::from_iter_values(values.iter().map(value_f64))),
+        ),
+    }
+}
+
+impl Page {
+    /// Send the page as JSON or Arrow, as the request asks
+    fn render(self, request: &Request, context: Map<String, Value>) -> ApiResult<Response> {
+        let arrow = request.query_param("format").as_deref() == Some("arrow")
+            || request
+                .header("accept")
+                .is_some_and(|accept| accept.contains(ARROW_STREAM));
+
+        let mut headers = Vec::new();
+        if let Some(total) = self.total {
+            headers.push(("X-Total-Count", total.to_string()));
+        }
+        if let Some(next_offset) = self.next_offset {
+            headers.push(("X-Next-Offset", next_offset.to_string()));
+        }
+
+        let response = if arrow {
+            Response::new(200).bytes(ARROW_STREAM, self.into_arrow(context)?)
+        } else {
+            json_response(200, &self.into_json(context))
+        };
+        Ok(headers
+            .into_iter()
+            .fold(response, |response, (name, value)| {
+                response.header(name, value)
+            }))
+    }
+
+    fn into_json(self, mut context: Map<String, Value>) -> Value {
+        let elements: Vec<Value> = self
+            .values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let mut element = Map::new();
+                for (name, indices) in &self.indices {
+                    element.insert((*name).into(), json!(indices[i]));
+                }
+                for (name, labels) in &self.labels {
+                    element.insert((*name).into(), json!(labels[i]));
+                }
+                element.insert("value".into(), value_json(value));
+                Value::Object(element)
+            })
+            .collect();
+
+        context.insert("offset".into(), json!(self.offset));
+        context.insert("total".into(), json!(self.total));
+        context.insert("next_offset".into(), json!(self.next_offset));
+        context.insert("elements".into(), Value::Array(elements));
+        Value::Object(context)
+    }
+
+    /// Encode as an Arrow IPC stream; `context` becomes schema metadata
+    fn into_arrow(self, context: Map<String, Value>) -> ApiResult<Vec<u8>> {
+        let mut fields = Vec::new();
+        let mut columns: Vec<ArrayRef> = Vec::new();
+        for (name, indices) in self.indices {
+            fields.push(Field::new(name, ArrowType::UInt64, false));
+            columns.push(Arc::new(UInt64Array::from(indices)));
+        }
+        for (name, labels) in self.labels {
+            fields.push(Field::new(name, ArrowType::Utf8, true));
+            columns.push(Arc::new(StringArray::from(labels)));
+        }
+        let (value_type, values) = value_column(&self.values, self.data_type);
+        fields.push(Field::new("value", value_type, false));
+        columns.push(values);
+
+        let metadata: HashMap<String, String> = context
+            .into_iter()
+            .map(|(key, value)| match value {
+                Value::String(text) => (key, text),
+                value => (key, value.to_string()),
+            })
+            .collect();
+        let schema = Arc::new(Schema::new(fields).with_metadata(metadata));
+
+        let encode = || -> std::result::Result<Vec<u8>, arrow_schema::ArrowError> {
+            let batch = RecordBatch::try_new(Arc::clone(&schema), columns)?;
+            let mut writer = StreamWriter::try_new(Vec::new(), &schema)?;
+            writer.write(&batch)?;
+            writer.into_inner()
+        };
+        encode().map_err(|_| ApiError::new(500, "Failed to encode Arrow response"))
+    }
+}
+
+/// The served matrices, by name
+struct Api {
+    matrices: BTreeMap<String, DynamicMatrix>,
+}
+
+impl Api {
+    /// Memory-map the `.bspc` files in `paths`
+    fn load<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        for path in paths.iter().map(AsRef::as_ref) {
+            if path.is_dir() {
+                let entries = std::fs::read_dir(path)
+                    .map_err(|_| Error::IoError("Failed to read served directory"))?;
+                let mut found: Vec<PathBuf> = entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "bspc"))
+                    .collect();
+                found.sort();
+                files.extend(found);
+            } else {
+                files.push(path.to_path_buf());
+            }
+        }
+
+        let mut matrices = BTreeMap::new();
+        for file in files {
+            let name = file
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .ok_or(Error::InvalidState("Served file has no name"))?;
+            let matrix = BspcFile::read_dynamic_matrix(&file)?;
+            if matrices.insert(name, matrix).is_some() {
+                return Err(Error::InvalidState("Two served files have the same name"));
+            }
+        }
+        if matrices.is_empty() {
+            return Err(Error::InvalidState("No .bspc files to serve"));
+        }
+
+        Ok(Self { matrices })
+    }
+
+    fn respond(&self, request: &Request) -> Response {
+        self.route(request)
+            .unwrap_or_else(|error| json_response(error.status, &json!({ "error": error.message })))
+    }
+
+    fn route(&self, request: &Request) -> ApiResult<Response> {
+        if request.method != "GET" && request.method != "HEAD" {
+            return Err(ApiError::new(405, "Method not allowed"));
+        }
+
+        let segments = request
+            .path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                percent_decode(segment, false).ok_or_else(|| ApiError::new(400, "Invalid path"))
+            })
+            .collect::<ApiResult<Vec<String>>>()?;
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        match segments.as_slice() {
+            ["matrices"] => Ok(json_response(200, &self.list())),
+            ["m", name, rest @ ..] => {
+                let matrix = self
+                    .matrices
+                    .get(*name)
+                    .ok_or_else(|| ApiError::new(404, format!("Unknown matrix {name:?}")))?;
+                match rest {
+                    ["info"] => Ok(json_response(200, &info(name, matrix)?)),
+                    ["row", key] => line(request, name, matrix, Axis::Row, key),
+                    ["col", key] => line(request, name, matrix, Axis::Col, key),
+                    ["element"] => element(request, name, matrix),
+                    ["slice"] => slice(request, name, matrix),
+                    _ => Err(ApiError::new(404, "Not found")),
+                }
+            }
+            _ => Err(ApiError::new(404, "Not found")),
+        }
+    }
+
+    fn list(&self) -> Value {
+        let matrices: Vec<Value> = self
+            .matrices
+            .iter()
+            .map(|(name, matrix)| {
+                json!({
+                    "name": name,
+                    "nrows": matrix.nrows(),
+                    "ncols": matrix.ncols(),
+                    "nnz": matrix.nnz(),
+                    "data_type": matrix.data_type().to_string(),
+                })
+            })
+            .collect();
+        json!({ "matrices": matrices })
+    }
+}
+
+/// Shape, types, labels, annotations and attributes of a matrix
+fn info(name: &str, matrix: &DynamicMatrix) -> ApiResult<Value> {
+    let mut info = json!({
+        "name": name,
+        "nrows": matrix.nrows(),
+        "ncols": matrix.ncols(),
+        "nnz": matrix.nnz(),
+        "format": matrix.format().to_string(),
+        "data_type": matrix.data_type().to_string(),
+        "row_labels": null,
+        "col_labels": null,
+        "row_annotations": [],
+        "col_annotations": [],
+        "attributes": {},
+    });
+
+    let Some(view) = matrix.metadata_view()? else {
+        return Ok(info);
+    };
+    info["row_labels"] = json!(view.row_labels()?.map(|labels| labels.count()));
+    info["col_labels"] = json!(view.col_labels()?.map(|labels| labels.count()));
+    if let Some(table) = view.row_annotations()? {
+        info["row_annotations"] = json!(table.column_names()?);
+    }
+    if let Some(table) = view.col_annotations()? {
+        info["col_annotations"] = json!(table.column_names()?);
+    }
+
+    let attributes: Map<String, Value> = view
+        .attributes()?
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                crate::metadata::AttributeValue::String(text) => json!(text),
+                crate::metadata::AttributeValue::I64(v) => json!(v),
+                crate::metadata::AttributeValue::F64(v) => json!(v),
+                crate::metadata::AttributeValue::Bool(v) => json!(v),
+                crate::metadata::AttributeValue::Json(text) => {
+                    serde_json::from_str(&text).unwrap_or(Value::String(text))
+                }
+            };
+            (key.to_owned(), value)
+        })
+        .collect();
+    info["attributes"] = Value::Object(attributes);
+    Ok(info)
+}
+
+/// Elements of one row or column
+fn line(
+    request: &Request,
+    name: &str,
+    matrix: &DynamicMatrix,
+    axis: Axis,
+    key: &str,
+) -> ApiResult<Response> {
+    let index = resolve(matrix, axis, key)?;
+    let (elements, axis_name, other, other_axis) = match axis {
+        Axis::Row => (matrix.get_row(index)?, "row", "col", Axis::Col),
+        Axis::Col => (matrix.get_col(index)?, "col", "row", Axis::Row),
+    };
+
+    let total = elements.len();
+    let pagination = Pagination::from_request(request)?;
+    let (elements, next_offset) = pagination.apply(elements.into_iter());
+    let (indices, values): (Vec<u64>, Vec<ArrayValue>) = elements
+        .into_iter()
+        .map(|(i, value)| (i as u64, value))
+        .unzip();
+
+    let mut context = Map::new();
+    context.insert("matrix".into(), json!(name));
+    context.insert(axis_name.into(), json!(index));
+    if let Some(labels) = axis_labels(matrix, axis, &[index as u64])? {
+        context.insert(format!("{axis_name}_label"), json!(labels[0]));
+    }
+
+    let mut labels = Vec::new();
+    if let Some(other_labels) = axis_labels(matrix, other_axis, &indices)? {
+        labels.push((
+            match other_axis {
+                Axis::Row => "row_label",
+                Axis::Col => "col_label",
+            },
+            other_labels,
+        ));
+    }
+
+    Page {
+        indices: vec![(other, indices)],
+        labels,
+        values,
+        data_type: matrix.data_type(),
+        offset: pagination.offset,
+        next_offset,
+        total: Some(total),
+    }
+    .render(request, context)
+}
+
+/// Label columns for the `row` and `col` indices of a page
+fn element_labels(matrix: &DynamicMatrix, rows: &[u64], cols: &[u64]) -> Result<Vec<LabelColumn>> {
+    let mut labels = Vec::new();
+    if let Some(row_labels) = axis_labels(matrix, Axis::Row, rows)? {
+        labels.push(("row_label", row_labels));
+    }
+    if let Some(col_labels) = axis_labels(matrix, Axis::Col, cols)? {
+        labels.push(("col_label", col_labels));
+    }
+    Ok(labels)
+}
+
+/// One element, as a page of zero or one elements
+fn element(request: &Request, name: &str, matrix: &DynamicMatrix) -> ApiResult<Response> {
+    let key = |param| {
+        request
+            .query_param(param)
+            .ok_or_else(|| ApiError::new(400, format!("Missing {param} parameter")))
+    };
+    let row = resolve(matrix, Axis::Row, &key("row")?)?;
+    let col = resolve(matrix, Axis::Col, &key("col")?)?;
+    let values: Vec<ArrayValue> = matrix.get_element(row, col)?.into_iter().collect();
+
+    let (rows, cols) = (
+        vec![row as u64; values.len()],
+        vec![col as u64; values.len()],
+    );
+    let mut context = Map::new();
+    context.insert("matrix".into(), json!(name));
+
+    Page {
+        labels: element_labels(matrix, &rows, &cols)?,
+        indices: vec![("row", rows), ("col", cols)],
+        total: Some(values.len()),
+        values,
+        data_type: matrix.data_type(),
+        offset: 0,
+        next_offset: None,
+    }
+    .render(request, context)
+}
+
+/// Elements in a block of rows and columns
+///
+/// The block is scanned lazily, so the response has no total.
+fn slice(request: &Request, name: &str, matrix: &DynamicMatrix) -> ApiResult<Response> {
+    let rows = range_param(request, "rows", matrix.nrows())?;
+    let cols = range_param(request, "cols", matrix.ncols())?;
+    let pagination = Pagination::from_request(request)?;
+
+    let elements = matrix
+        .row_range_view(rows.start, rows.end)?
+        .filter(|(_, col, _)| cols.contains(col));
+    let (elements, next_offset) = pagination.apply(elements);
+
+    let mut row_indices = Vec::with_capacity(elements.len());
+    let mut col_indices = Vec::with_capacity(elements.len());
+    let mut values = Vec::with_capacity(elements.len());
+    for (row, col, value) in elements {
+        row_indices.push(row as u64);
+        col_indices.push(col as u64);
+        values.push(value);
+    }
+
+    let mut context = Map::new();
+    context.insert("matrix".into(), json!(name));
+    context.insert("rows".into(), json!(format!("{}:{}", rows.start, rows.end)));
+    context.insert("cols".into(), json!(format!("{}:{}", cols.start, cols.end)));
+
+    Page {
+        labels: element_labels(matrix, &row_indices, &col_indices)?,
+        indices: vec![("row", row_indices), ("col", col_indices)],
+        values,
+        data_type: matrix.data_type(),
+        offset: pagination.offset,
+        next_offset,
+        total: None,
+    }
+    .render(request, context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunked_backend::ChunkConfig;
+    use crate::test_support::{send_request, RawResponse, TempDir};
+    use arrow_array::Array;
+    use arrow_ipc::reader::StreamReader;
+
+    /// Serve a labelled 3 x 3 `small` matrix and an unlabelled 1 x 10 `wide` one
+    async fn start() -> (TempDir, ApiServer) {
+        let dir = TempDir::new();
+        let elements = [(0, 0, 1.0f64), (1, 2, 2.0), (2, 1, 3.0)];
+        let rows: [&[u8]; 3] = [b"r0", b"r1", b"r2"];
+        let cols: [&[u8]; 3] = [b"c0", b"c1", b"c2"];
+        BspcFile::write_sparse_matrix_with_labels(
+            3,
+            3,
+            &elements,
+            &rows,
+            &cols,
+            0,
+            ChunkConfig::default(),
+            dir.file("small.bspc"),
+        )
+        .await
+        .unwrap();
+
+        let wide: Vec<(usize, usize, i32)> = (0..10).map(|col| (0, col, col as i32 - 5)).collect();
+        BspcFile::write_sparse_matrix(1, 10, &wide, ChunkConfig::default(), dir.file("wide.bspc"))
+            .await
+            .unwrap();
+
+        let server = ApiServer::start(&[dir.file("")], "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        (dir, server)
+    }
+
+    async fn get(server: &ApiServer, target: &str) -> RawResponse {
+        send_request(server.local_addr(), &format!("GET {target} HTTP/1.1\r\n")).await
+    }
+
+    async fn get_json(server: &ApiServer, target: &str) -> Value {
+        let response = get(server, target).await;
+        assert_eq!(response.status(), 200, "{target}");
+        assert_eq!(response.header("content-type"), Some(JSON));
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    async fn get_error(server: &ApiServer, target: &str) -> u16 {
+        let response = get(server, target).await;
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert!(body["error"].is_string(), "{target}");
+        response.status()
+    }
+
+    #[tokio::test]
+    async fn test_list_and_info() {
+        let (_dir, server) = start().await;
+
+        let list = get_json(&server, "/matrices").await;
+        let names: Vec<&str> = list["matrices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["small", "wide"]);
+        assert_eq!(list["matrices"][1]["nnz"], 10);
+
+        let info = get_json(&server, "/m/small/info").await;
+        assert_eq!(
+            (info["nrows"].clone(), info["ncols"].clone()),
+            (json!(3), json!(3))
+        );
+        assert_eq!(info["row_labels"], 3);
+        assert_eq!(info["col_labels"], 3);
+
+        let info = get_json(&server, "/m/wide/info").await;
+        assert_eq!(info["row_labels"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_row_col_and_element_queries() {
+        let (_dir, server) = start().await;
+
+        let row = get_json(&server, "/m/small/row/r1").await;
+        assert_eq!(row["row"], 1);
+        assert_eq!(row["row_label"], "r1");
+        assert_eq!(row["total"], 1);
+        assert_eq!(
+            row["elements"],
+            json!([{ "col": 2, "col_label": "c2", "value": 2.0 }])
+        );
+        assert_eq!(get_json(&server, "/m/small/row/1").await, row);
+
+        let col = get_json(&server, "/m/small/col/c1").await;
+        assert_eq!(col["elements"][0]["row"], 2);
+        assert_eq!(col["elements"][0]["row_label"], "r2");
+
+        let element = get_json(&server, "/m/small/element?row=r2&col=1").await;
+        assert_eq!(element["elements"][0]["value"], 3.0);
+        assert_eq!(element["elements"][0]["col_label"], "c1");
+        let missing = get_json(&server, "/m/small/element?row=0&col=1").await;
+        assert_eq!(missing["elements"], json!([]));
+
+        let slice = get_json(&server, "/m/small/slice?rows=0:2&cols=0:3").await;
+        assert_eq!(slice["elements"].as_array().unwrap().len(), 2);
+        assert_eq!(slice["total"], Value::Null);
+        let slice = get_json(&server, "/m/small/slice?cols=1:2").await;
+        assert_eq!(slice["elements"][0]["row"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_pagination() {
+        let (_dir, server) = start().await;
+
+        let response = get(&server, "/m/wide/row/0?limit=4&offset=4").await;
+        assert_eq!(response.header("x-next-offset"), Some("8"));
+        assert_eq!(response.header("x-total-count"), Some("10"));
+        let page: Value = serde_json::from_slice(&response.body).unwrap();
+        let cols: Vec<u64> = page["elements"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["col"].as_u64().unwrap())
+            .collect();
+        assert_eq!(cols, [4, 5, 6, 7]);
+        assert_eq!(page["next_offset"], 8);
+
+        let response = get(&server, "/m/wide/row/0?limit=4&offset=8").await;
+        assert_eq!(response.header("x-next-offset"), None);
+        let page: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(page["elements"].as_array().unwrap().len(), 2);
+        assert_eq!(page["next_offset"], Value::Null);
+
+        let page = get_json(&server, "/m/wide/slice?limit=3&offset=9").await;
+        assert_eq!(page["elements"][0]["value"], 4);
+        assert_eq!(page["next_offset"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_arrow_responses() {
+        let (_dir, server) = start().await;
+
+        let response = get(&server, "/m/small/row/r1?format=arrow").await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.header("content-type"), Some(ARROW_STREAM));
+        let mut reader = StreamReader::try_new(response.body.as_slice(), None).unwrap();
+        let schema = reader.schema();
+        assert_eq!(schema.metadata()["row_label"], "r1");
+        let batch = reader.next().unwrap().unwrap();
+        assert!(reader.next().is_none());
+        let cols = batch.column_by_name("col").unwrap();
+        let cols = cols.as_any().downcast_ref::<UInt64Array>().unwrap();
+        assert_eq!(cols.values(), &[2]);
+        let labels = batch.column_by_name("col_label").unwrap();
+        let labels = labels.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(labels.value(0), "c2");
+        let values = batch.column_by_name("value").unwrap();
+        let values = values.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(values.values(), &[2.0]);
+
+        // Integer matrices keep integer values, and Accept selects Arrow too
+        let response = send_request(
+            server.local_addr(),
+            &format!("GET /m/wide/row/0?limit=3 HTTP/1.1\r\nAccept: {ARROW_STREAM}\r\n"),
+        )
+        .await;
+        let mut reader = StreamReader::try_new(response.body.as_slice(), None).unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        let values = batch.column_by_name("value").unwrap();
+        let values = values.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(values.values(), &[-5, -4, -3]);
+        assert!(batch.column_by_name("col_label").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let (_dir, server) = start().await;
+
+        assert_eq!(get_error(&server, "/m/none/info").await, 404);
+        assert_eq!(get_error(&server, "/m/small/row/r9").await, 404);
+        assert_eq!(get_error(&server, "/m/small/row/3").await, 404);
+        assert_eq!(get_error(&server, "/m/small/element?row=0").await, 400);
+        assert_eq!(get_error(&server, "/m/small/row/0?limit=x").await, 400);
+        assert_eq!(get_error(&server, "/m/small/slice?rows=0:9").await, 400);
+        assert_eq!(get_error(&server, "/m/small/slice?rows=abc").await, 400);
+        assert_eq!(get_error(&server, "/m/small/unknown").await, 404);
+        assert_eq!(get_error(&server, "/").await, 404);
+
+        let response = send_request(server.local_addr(), "DELETE /matrices HTTP/1.1\r\n").await;
+        assert_eq!(response.status(), 405);
+    }
+
+    #[tokio::test]
+    async fn test_complex_matrices_reject_element_queries() {
+        let dir = TempDir::new();
+        let elements = [(0, 1, num_complex::Complex64::new(1.0, -2.0))];
+        BspcFile::write_sparse_matrix(2, 2, &elements, ChunkConfig::default(), dir.file("z.bspc"))
+            .await
+            .unwrap();
+        let server = ApiServer::start(&[dir.file("")], "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        let info = get_json(&server, "/m/z/info").await;
+        assert_eq!(info["nnz"], 1);
+        for target in [
+            "/m/z/row/0",
+            "/m/z/col/1",
+            "/m/z/element?row=0&col=1",
+            "/m/z/slice",
+            "/m/z/row/0?format=arrow",
+        ] {
+            assert_eq!(get_error(&server, target).await, 400, "{target}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load_rejects_empty_and_clashing_paths() {
+        let empty = TempDir::new();
+        assert!(Api::load(&[empty.file("")]).is_err());
+
+        let (dir, _server) = start().await;
+        let other = TempDir::new();
+        std::fs::copy(dir.file("wide.bspc"), other.file("wide.bspc")).unwrap();
+        assert!(Api::load(&[dir.file(""), other.file("wide.bspc")]).is_err());
+        assert_eq!(
+            Api::load(&[other.file("wide.bspc")])
+                .unwrap()
+                .matrices
+                .len(),
+            1
+        );
+    }
+}
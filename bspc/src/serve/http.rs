@@ -0,0 +1,386 @@
+//! Minimal HTTP/1.1 server plumbing
+//!
+//! Just enough of HTTP/1.1 for the servers in this module: requests without
+//! bodies, persistent connections and responses of known length. Each
+//! connection is handled on its own task.
+
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind, SeekFrom};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest request line and headers accepted, in bytes
+const MAX_HEAD_BYTES: u64 = 64 * 1024;
+
+/// A parsed request
+pub(crate) struct Request {
+    /// Request method, such as `GET`
+    pub(crate) method: String,
+    /// Path of the request target, still percent-encoded
+    pub(crate) path: String,
+    /// Query string of the request target, still percent-encoded
+    ///
+    /// Only the query API reads it.
+    #[cfg_attr(not(feature = "api"), allow(dead_code))]
+    query: Option<String>,
+    /// Headers with lowercase names
+    headers: Vec<(String, String)>,
+    keep_alive: bool,
+}
+
+impl Request {
+    /// Get the first header called `name`, given in lowercase
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Get the decoded value of the first query parameter called `name`
+    #[cfg_attr(not(feature = "api"), allow(dead_code))]
+    pub(crate) fn query_param(&self, name: &str) -> Option<String> {
+        self.query.as_deref()?.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key, true)? == name).then(|| percent_decode(value, true))?
+        })
+    }
+}
+
+/// Body of a response
+pub(crate) enum Body {
+    /// Bytes held in memory
+    Bytes(Vec<u8>),
+    /// The next `len` bytes of an open file
+    File { file: tokio::fs::File, len: u64 },
+    /// Ranges of an open file interleaved with in-memory bytes
+    Parts {
+        file: tokio::fs::File,
+        parts: Vec<Part>,
+    },
+}
+
+/// One piece of a [`Body::Parts`] body
+pub(crate) enum Part {
+    /// Bytes held in memory
+    Bytes(Vec<u8>),
+    /// A byte range of the file
+    Range(Range<u64>),
+}
+
+/// A response to be written
+pub(crate) struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Body,
+}
+
+impl Response {
+    /// Create an empty response
+    pub(crate) fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Body::Bytes(Vec::new()),
+        }
+    }
+
+    /// Create a plain text response, used for errors
+    pub(crate) fn text(status: u16, message: &str) -> Self {
+        Self::new(status).bytes(
+            "text/plain; charset=utf-8",
+            format!("{message}\n").into_bytes(),
+        )
+    }
+
+    /// Add a header
+    pub(crate) fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Set an in-memory body
+    pub(crate) fn bytes(self, content_type: &str, body: Vec<u8>) -> Self {
+        let mut response = self.header("Content-Type", content_type);
+        response.body = Body::Bytes(body);
+        response
+    }
+
+    /// Set a body read from the current position of `file`
+    pub(crate) fn file(self, content_type: &str, file: tokio::fs::File, len: u64) -> Self {
+        let mut response = self.header("Content-Type", content_type);
+        response.body = Body::File { file, len };
+        response
+    }
+
+    /// Set a body assembled from `parts`, with ranges read from `file`
+    pub(crate) fn parts(self, content_type: &str, file: tokio::fs::File, parts: Vec<Part>) -> Self {
+        let mut response = self.header("Content-Type", content_type);
+        response.body = Body::Parts { file, parts };
+        response
+    }
+
+    fn body_len(&self) -> u64 {
+        match &self.body {
+            Body::Bytes(bytes) => bytes.len() as u64,
+            Body::File { len, .. } => *len,
+            Body::Parts { parts, .. } => parts
+                .iter()
+                .map(|part| match part {
+                    Part::Bytes(bytes) => bytes.len() as u64,
+                    Part::Range(range) => range.end - range.start,
+                })
+                .sum(),
+        }
+    }
+}
+
+/// Reason phrase for the status codes the servers send
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        416 => "Range Not Satisfiable",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+/// Decode `%XX` escapes; `+` is decoded as a space when `plus_as_space` is set
+pub(crate) fn percent_decode(text: &str, plus_as_space: bool) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Read one line of the request head into `line`, counting against `budget`
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+    budget: &mut u64,
+) -> std::io::Result<usize> {
+    line.clear();
+    let read = (&mut *reader).take(*budget).read_until(b'\n', line).await?;
+    *budget -= read as u64;
+    if read > 0 && line.last() != Some(&b'\n') {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            "request head too large",
+        ));
+    }
+    while matches!(line.last(), Some(b'\n' | b'\r')) {
+        line.pop();
+    }
+    Ok(read)
+}
+
+/// Read the next request, or `None` once the client closes the connection
+async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Request>> {
+    let invalid = |message| IoError::new(ErrorKind::InvalidData, message);
+    let mut budget = MAX_HEAD_BYTES;
+    let mut line = Vec::new();
+
+    // Clients may send empty lines between requests
+    loop {
+        if read_line(reader, &mut line, &mut budget).await? == 0 {
+            return Ok(None);
+        }
+        if !line.is_empty() {
+            break;
+        }
+    }
+
+    let request_line =
+        String::from_utf8(line.clone()).map_err(|_| invalid("invalid request line"))?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid("invalid request line"));
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_owned())),
+        None => (target, None),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        if read_line(reader, &mut line, &mut budget).await? == 0 {
+            return Err(invalid("connection closed in request head"));
+        }
+        if line.is_empty() {
+            break;
+        }
+        let text = std::str::from_utf8(&line).map_err(|_| invalid("invalid header"))?;
+        let (name, value) = text
+            .split_once(':')
+            .ok_or_else(|| invalid("invalid header"))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+    }
+
+    let mut request = Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        query,
+        headers,
+        keep_alive: false,
+    };
+    let connection = request.header("connection").map(str::to_ascii_lowercase);
+    request.keep_alive = match version {
+        "HTTP/1.1" => connection.as_deref() != Some("close"),
+        "HTTP/1.0" => connection.as_deref() == Some("keep-alive"),
+        _ => return Err(invalid("unsupported HTTP version")),
+    };
+
+    // Request bodies are not used; skip them to reach the next request
+    if request.header("transfer-encoding").is_some() {
+        return Err(invalid("chunked request bodies are not supported"));
+    }
+    if let Some(length) = request.header("content-length") {
+        let length = length
+            .parse::<u64>()
+            .map_err(|_| invalid("invalid content-length"))?;
+        tokio::io::copy(&mut (&mut *reader).take(length), &mut tokio::io::sink()).await?;
+    }
+
+    Ok(Some(request))
+}
+
+/// Write `response`, leaving out the body for `HEAD` requests
+async fn write_response<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    response: Response,
+    head_only: bool,
+    keep_alive: bool,
+) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        response.status,
+        reason(response.status)
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if response.status != 304 {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body_len()));
+    }
+    if !keep_alive {
+        head.push_str("Connection: close\r\n");
+    }
+    head.push_str("\r\n");
+    writer.write_all(head.as_bytes()).await?;
+
+    if !head_only {
+        match response.body {
+            Body::Bytes(bytes) => writer.write_all(&bytes).await?,
+            Body::File { file, len } => copy_file(file, len, writer).await?,
+            Body::Parts { mut file, parts } => {
+                for part in parts {
+                    match part {
+                        Part::Bytes(bytes) => writer.write_all(&bytes).await?,
+                        Part::Range(range) => {
+                            file.seek(SeekFrom::Start(range.start)).await?;
+                            copy_file(&mut file, range.end - range.start, writer).await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    writer.flush().await
+}
+
+/// Copy the next `len` bytes of `file` to `writer`
+async fn copy_file<F, W>(file: F, len: u64, writer: &mut W) -> std::io::Result<()>
+where
+    F: tokio::io::AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let copied = tokio::io::copy(&mut file.take(len), writer).await?;
+    // The length is already sent; a shrunk file cannot be recovered from
+    if copied < len {
+        return Err(IoError::new(ErrorKind::UnexpectedEof, "file shrank"));
+    }
+    Ok(())
+}
+
+/// Answer the requests on one connection until either side closes it
+async fn handle_connection<H, Fut>(stream: TcpStream, handler: &H) -> std::io::Result<()>
+where
+    H: Fn(Request) -> Fut,
+    Fut: Future<Output = Response>,
+{
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+
+    loop {
+        let request = match read_request(&mut reader).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(error) if error.kind() == ErrorKind::InvalidData => {
+                let response = Response::text(400, &error.to_string());
+                return write_response(&mut write, response, false, false).await;
+            }
+            Err(error) => return Err(error),
+        };
+
+        let head_only = request.method == "HEAD";
+        let keep_alive = request.keep_alive;
+        let response = handler(request).await;
+        write_response(&mut write, response, head_only, keep_alive).await?;
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+/// Accept connections on `listener` forever, answering requests with `handler`
+pub(crate) async fn serve<H, Fut>(listener: TcpListener, handler: H)
+where
+    H: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Response> + Send,
+{
+    let handler = Arc::new(handler);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            // Running out of file descriptors is transient; back off briefly
+            Err(_) => {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                continue;
+            }
+        };
+
+        let handler = Arc::clone(&handler);
+        tokio::spawn(async move {
+            // A failed connection only affects its own client
+            let _ = handle_connection(stream, &*handler).await;
+        });
+    }
+}
@@ -0,0 +1,485 @@
+//! Static file server with range request support
+//!
+//! Serves the files under one directory the way object stores do: `HEAD`,
+//! single and multi-range `GET`, `ETag` validators and `Accept-Ranges`, so
+//! [`crate::HttpMatrix`] can be exercised against localhost.
+
+use super::http::{self, percent_decode, Part, Request, Response};
+use crate::http_backend::file_etag;
+use binsparse_rs::{Error, Result};
+use std::io::SeekFrom;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncSeekExt;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// Content type of served files
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Most ranges answered in one multi-range response
+///
+/// Requests with more ranges get the whole file, as RFC 9110 §14.2 allows.
+const MAX_RANGES: usize = 64;
+
+/// Serve the files under `dir` on `addr` until the task is cancelled
+///
+/// Directory listings are not served, and request paths cannot leave `dir`.
+pub async fn serve_dir<P: AsRef<Path>>(dir: P, addr: SocketAddr) -> Result<()> {
+    let (listener, root) = bind(dir.as_ref(), addr).await?;
+    run(listener, root).await;
+    Ok(())
+}
+
+/// Static file server running in the background
+///
+/// The server stops when this handle is dropped.
+///
+/// ```rust,no_run
+/// use bspc::{HttpMatrix, StaticServer};
+///
+/// async fn example() -> Result<(), binsparse_rs::Error> {
+///     let server = StaticServer::start("data", "127.0.0.1:0".parse().unwrap()).await?;
+///     let matrix = HttpMatrix::new(&server.url("matrix.bspc")).await?;
+///     println!("{} x {}", matrix.nrows(), matrix.ncols());
+///     Ok(())
+/// }
+/// ```
+pub struct StaticServer {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl StaticServer {
+    /// Start serving the files under `dir` on `addr`
+    ///
+    /// Port `0` picks a free port; see [`StaticServer::local_addr`].
+    pub async fn start<P: AsRef<Path>>(dir: P, addr: SocketAddr) -> Result<Self> {
+        let (listener, root) = bind(dir.as_ref(), addr).await?;
+        let addr = listener
+            .local_addr()
+            .map_err(|_| Error::IoError("Failed to read server address"))?;
+
+        Ok(Self {
+            addr,
+            task: tokio::spawn(run(listener, root)),
+        })
+    }
+
+    /// Get the address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Get the URL of the file at `path` relative to the served directory
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}/{}", self.addr, path.trim_start_matches('/'))
+    }
+}
+
+impl Drop for StaticServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Bind `addr` and resolve the served directory
+async fn bind(dir: &Path, addr: SocketAddr) -> Result<(TcpListener, PathBuf)> {
+    let root = tokio::fs::canonicalize(dir)
+        .await
+        .map_err(|_| Error::IoError("Failed to open served directory"))?;
+    if !root.is_dir() {
+        return Err(Error::InvalidState("Served path is not a directory"));
+    }
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|_| Error::IoError("Failed to bind server address"))?;
+    Ok((listener, root))
+}
+
+async fn run(listener: TcpListener, root: PathBuf) {
+    let root = Arc::new(root);
+    http::serve(listener, move |request| {
+        let root = Arc::clone(&root);
+        async move { respond(&root, &request).await }
+    })
+    .await
+}
+
+/// Map a request path to a file under `root`
+///
+/// Paths with `..` or other non-plain components are rejected.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(path, false)?;
+    let mut resolved = root.to_path_buf();
+    for part in decoded.split('/').filter(|part| !part.is_empty()) {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) => resolved.push(name),
+            (Some(Component::CurDir), None) => {}
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+/// Check an `If-None-Match` value against `etag`, using weak comparison
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let strip = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|tag| strip(tag) == strip(etag))
+}
+
+/// Parse a `Range` header against a file of `len` bytes
+///
+/// Returns `None` for a malformed header, which is then ignored, and also for
+/// more than [`MAX_RANGES`] ranges or overlapping ones, so that a short header
+/// cannot ask for many copies of the file. Otherwise returns the satisfiable
+/// ranges, which may be none.
+fn parse_ranges(value: &str, len: u64) -> Option<Vec<Range<u64>>> {
+    let specs = value.trim().strip_prefix("bytes=")?;
+    if specs.split(',').count() > MAX_RANGES {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        let (start, end) = spec.trim().split_once('-')?;
+        let range = if start.is_empty() {
+            // Suffix range: the last `end` bytes
+            let suffix = end.parse::<u64>().ok()?;
+            len.saturating_sub(suffix)..len
+        } else {
+            let start = start.parse::<u64>().ok()?;
+            let end = match end {
+                "" => len,
+                end => {
+                    let last = end.parse::<u64>().ok()?;
+                    if last < start {
+                        return None;
+                    }
+                    last.saturating_add(1).min(len)
+                }
+            };
+            start..end
+        };
+        if range.start < len && !range.is_empty() {
+            ranges.push(range);
+        }
+    }
+
+    let mut sorted = ranges.clone();
+    sorted.sort_by_key(|range| range.start);
+    if sorted.windows(2).any(|pair| pair[1].start < pair[0].end) {
+        return None;
+    }
+    Some(ranges)
+}
+
+/// Lay out a `multipart/byteranges` body and pick its boundary
+///
+/// The parts reference `ranges` of the file, which are streamed when the
+/// response is written.
+fn multipart_parts(ranges: &[Range<u64>], len: u64) -> (Vec<Part>, String) {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let boundary = format!("bspc-{nanos:x}");
+
+    let mut parts = Vec::with_capacity(ranges.len() * 2 + 1);
+    let mut separator = String::new();
+    for range in ranges {
+        separator.push_str(&format!(
+            "--{boundary}\r\nContent-Type: {OCTET_STREAM}\r\nContent-Range: bytes {}-{}/{len}\r\n\r\n",
+            range.start,
+            range.end - 1
+        ));
+        parts.push(Part::Bytes(std::mem::take(&mut separator).into_bytes()));
+        parts.push(Part::Range(range.clone()));
+        separator.push_str("\r\n");
+    }
+    separator.push_str(&format!("--{boundary}--\r\n"));
+    parts.push(Part::Bytes(separator.into_bytes()));
+    (parts, boundary)
+}
+
+/// Answer one request for a file under `root`
+async fn respond(root: &Path, request: &Request) -> Response {
+    if request.method != "GET" && request.method != "HEAD" {
+        return Response::text(405, "Method not allowed").header("Allow", "GET, HEAD");
+    }
+
+    let Some(path) = resolve(root, &request.path) else {
+        return Response::text(404, "Not found");
+    };
+    let Ok(mut file) = tokio::fs::File::open(&path).await else {
+        return Response::text(404, "Not found");
+    };
+    let metadata = match file.metadata().await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return Response::text(404, "Not found"),
+    };
+    let len = metadata.len();
+    let etag = file_etag(&metadata);
+
+    let base = |status| {
+        let response = Response::new(status).header("Accept-Ranges", "bytes");
+        match &etag {
+            Some(etag) => response.header("ETag", etag.clone()),
+            None => response,
+        }
+    };
+
+    if let (Some(etag), Some(if_none_match)) = (&etag, request.header("if-none-match")) {
+        if etag_matches(if_none_match, etag) {
+            return base(304);
+        }
+    }
+
+    // A stale If-Range asks for the whole current file instead
+    let range_current = match request.header("if-range") {
+        Some(tag) => etag.as_deref() == Some(tag.trim()),
+        None => true,
+    };
+    let ranges = request
+        .header("range")
+        .filter(|_| range_current)
+        .and_then(|value| parse_ranges(value, len));
+
+    match ranges.as_deref() {
+        None => base(200).file(OCTET_STREAM, file, len),
+        Some([]) => base(416).header("Content-Range", format!("bytes */{len}")),
+        Some([range]) => {
+            if file.seek(SeekFrom::Start(range.start)).await.is_err() {
+                return Response::text(500, "Failed to read file");
+            }
+            base(206)
+                .header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{len}", range.start, range.end - 1),
+                )
+                .file(OCTET_STREAM, file, range.end - range.start)
+        }
+        Some(ranges) => {
+            let (parts, boundary) = multipart_parts(ranges, len);
+            base(206).parts(
+                &format!("multipart/byteranges; boundary={boundary}"),
+                file,
+                parts,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunked_backend::ChunkConfig;
+    use crate::mmap_backend::BspcFile;
+    use crate::test_support::send_request;
+    use crate::test_support::TempDir;
+    use crate::HttpMatrix;
+    use binsparse_rs::array::ArrayValue;
+
+    #[test]
+    fn test_parse_ranges() {
+        let single = |start, end| Some(vec![Range { start, end }]);
+        assert_eq!(parse_ranges("bytes=0-9", 100), single(0, 10));
+        assert_eq!(parse_ranges("bytes=90-", 100), single(90, 100));
+        assert_eq!(parse_ranges("bytes=-10", 100), single(90, 100));
+        assert_eq!(parse_ranges("bytes=95-200", 100), single(95, 100));
+        assert_eq!(
+            parse_ranges("bytes=50-59, 0-9", 100),
+            Some(vec![50..60, 0..10])
+        );
+        assert_eq!(parse_ranges("bytes=100-", 100), Some(vec![]));
+        assert_eq!(parse_ranges("bytes=300-", 100), Some(vec![]));
+        assert_eq!(parse_ranges("bytes=300-400, 0-0", 100), single(0, 1));
+        assert_eq!(parse_ranges("bytes=9-0", 100), None);
+        assert_eq!(parse_ranges("items=0-9", 100), None);
+        assert_eq!(parse_ranges("bytes=a-b", 100), None);
+    }
+
+    #[test]
+    fn test_parse_ranges_rejects_overlapping_and_many_ranges() {
+        assert_eq!(parse_ranges("bytes=0-,0-,0-", 100), None);
+        assert_eq!(parse_ranges("bytes=50-59,0-50", 100), None);
+        assert_eq!(
+            parse_ranges("bytes=0-9,10-19", 100),
+            Some(vec![0..10, 10..20])
+        );
+
+        let many = |count: usize| {
+            let specs: Vec<String> = (0..count).map(|i| format!("{i}-{i}")).collect();
+            format!("bytes={}", specs.join(","))
+        };
+        assert_eq!(
+            parse_ranges(&many(MAX_RANGES), 1000).unwrap().len(),
+            MAX_RANGES
+        );
+        assert_eq!(parse_ranges(&many(MAX_RANGES + 1), 1000), None);
+    }
+
+    #[test]
+    fn test_resolve_stays_under_root() {
+        let root = Path::new("/srv/data");
+        assert_eq!(
+            resolve(root, "/a/./b%20c.bspc"),
+            Some(PathBuf::from("/srv/data/a/b c.bspc"))
+        );
+        assert_eq!(resolve(root, "/../etc/passwd"), None);
+        assert_eq!(resolve(root, "/a/%2e%2e/%2e%2e/etc"), None);
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("\"abc\"", "\"abc\""));
+        assert!(etag_matches("\"x\", W/\"abc\"", "\"abc\""));
+        assert!(etag_matches("*", "\"abc\""));
+        assert!(!etag_matches("\"abd\"", "\"abc\""));
+    }
+
+    async fn serve_bytes(data: &[u8]) -> (TempDir, StaticServer) {
+        let dir = TempDir::new();
+        std::fs::write(dir.file("data.bin"), data).unwrap();
+        let server = StaticServer::start(dir.file(""), "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        (dir, server)
+    }
+
+    #[tokio::test]
+    async fn test_range_responses() {
+        let data: Vec<u8> = (0..=255).collect();
+        let (_dir, server) = serve_bytes(&data).await;
+        let addr = server.local_addr();
+
+        let response = send_request(addr, "GET /data.bin HTTP/1.1\r\n").await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body, data);
+        let etag = response.header("etag").unwrap().to_owned();
+
+        let response = send_request(addr, "GET /data.bin HTTP/1.1\r\nRange: bytes=10-19\r\n").await;
+        assert_eq!(response.status(), 206);
+        assert_eq!(response.header("content-range"), Some("bytes 10-19/256"));
+        assert_eq!(response.body, &data[10..20]);
+
+        let response = send_request(addr, "HEAD /data.bin HTTP/1.1\r\n").await;
+        assert_eq!(response.header("content-length"), Some("256"));
+        assert!(response.body.is_empty());
+
+        let response = send_request(addr, "GET /data.bin HTTP/1.1\r\nRange: bytes=300-\r\n").await;
+        assert_eq!(response.status(), 416);
+        assert_eq!(response.header("content-range"), Some("bytes */256"));
+
+        let response = send_request(
+            addr,
+            &format!("GET /data.bin HTTP/1.1\r\nIf-None-Match: {etag}\r\n"),
+        )
+        .await;
+        assert_eq!(response.status(), 304);
+        assert!(response.body.is_empty());
+
+        // A stale If-Range gets the whole file
+        let response = send_request(
+            addr,
+            "GET /data.bin HTTP/1.1\r\nRange: bytes=0-9\r\nIf-Range: \"stale\"\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body.len(), 256);
+
+        let response = send_request(addr, "GET /../data.bin HTTP/1.1\r\n").await;
+        assert_eq!(response.status(), 404);
+        let response = send_request(addr, "POST /data.bin HTTP/1.1\r\n").await;
+        assert_eq!(response.status(), 405);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_response() {
+        let data: Vec<u8> = (0..=255).collect();
+        let (_dir, server) = serve_bytes(&data).await;
+
+        let response = send_request(
+            server.local_addr(),
+            "GET /data.bin HTTP/1.1\r\nRange: bytes=200-203,0-3\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), 206);
+        let body = &response.body;
+        assert_eq!(
+            response.header("content-length"),
+            Some(body.len().to_string().as_str())
+        );
+        let boundary = response
+            .header("content-type")
+            .unwrap()
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+
+        let mut expected = Vec::new();
+        for (start, end) in [(200, 203), (0, 3)] {
+            expected.extend_from_slice(
+                format!(
+                    "--{boundary}\r\nContent-Type: {OCTET_STREAM}\r\nContent-Range: bytes {start}-{end}/256\r\n\r\n"
+                )
+                .as_bytes(),
+            );
+            expected.extend_from_slice(&data[start..=end]);
+            expected.extend_from_slice(b"\r\n");
+        }
+        expected.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+        assert_eq!(*body, expected);
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_ranges_get_whole_file() {
+        let data = vec![7u8; 1000];
+        let (_dir, server) = serve_bytes(&data).await;
+
+        let ranges = vec!["0-"; 1000].join(",");
+        let response = send_request(
+            server.local_addr(),
+            &format!("GET /data.bin HTTP/1.1\r\nRange: bytes={ranges}\r\n"),
+        )
+        .await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body, data);
+    }
+
+    #[tokio::test]
+    async fn test_http_matrix_round_trip() {
+        let dir = TempDir::new();
+        let elements: Vec<(usize, usize, f64)> = (0..50)
+            .flat_map(|row| [(row, row % 3, row as f64), (row, 10 + row % 4, 0.5)])
+            .collect();
+        let config = ChunkConfig::default().with_chunk_size(8);
+        BspcFile::write_sparse_matrix(50, 20, &elements, config, dir.file("matrix.bspc"))
+            .await
+            .unwrap();
+
+        let server = StaticServer::start(dir.file(""), "127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let matrix = HttpMatrix::new(&server.url("matrix.bspc")).await.unwrap();
+        assert_eq!((matrix.nrows(), matrix.ncols()), (50, 20));
+        assert_eq!(matrix.nnz(), elements.len());
+        assert!(matrix.supports_range_requests().await.unwrap());
+
+        for row in [0, 17, 49] {
+            let got = matrix.get_row(row).await.unwrap();
+            assert_eq!(got.len(), 2);
+            assert_eq!(got[0].0, row % 3);
+            assert!(matches!(got[0].1, ArrayValue::Float64(v) if v == row as f64));
+        }
+        let rows = matrix.get_rows(&[45, 2, 30]).await.unwrap();
+        assert_eq!(rows.iter().map(Vec::len).sum::<usize>(), 6);
+        assert!(matrix.get_element(3, 5).await.unwrap().is_none());
+        assert!(HttpMatrix::new(&server.url("missing.bspc")).await.is_err());
+    }
+}
@@ -0,0 +1,356 @@
+//! Row similarity search for memory-mapped sparse matrices
+//!
+//! This module scores every row of a matrix against a sparse query vector using
+//! cosine, dot-product or Jaccard similarity and returns the top-k rows. Rows are
+//! scanned in parallel, one bloom filter chunk per task. Complex matrices are
+//! rejected rather than compared by their real part.
+
+use crate::mmap_backend::matrix_operations::row_span;
+use crate::mmap_backend::mmap_core::ensure_real;
+use crate::mmap_backend::{MatrixElement, MmapMatrix};
+use binsparse_rs::{Error, Result};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Similarity measure between a query and a matrix row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimilarityMetric {
+    /// Dot product divided by the product of L2 norms
+    Cosine,
+    /// Plain dot product
+    Dot,
+    /// Shared stored columns divided by the union of stored columns (values ignored)
+    Jaccard,
+}
+
+impl SimilarityMetric {
+    /// Combine per-row accumulators into a score
+    fn score(self, acc: &RowAccumulator, query: &SparseVector, query_norm: f64) -> f64 {
+        match self {
+            SimilarityMetric::Dot => acc.dot,
+            SimilarityMetric::Cosine => {
+                let denom = acc.norm_sq.sqrt() * query_norm;
+                if denom == 0.0 {
+                    0.0
+                } else {
+                    acc.dot / denom
+                }
+            }
+            SimilarityMetric::Jaccard => {
+                let union = acc.nnz + query.nnz() - acc.shared;
+                acc.shared as f64 / union as f64
+            }
+        }
+    }
+}
+
+/// Sparse query vector with sorted, unique indices
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseVector {
+    indices: Vec<usize>,
+    values: Vec<f64>,
+}
+
+impl SparseVector {
+    /// Create from parallel index/value lists
+    ///
+    /// Entries are sorted by index; duplicate indices are rejected.
+    pub fn new(indices: Vec<usize>, values: Vec<f64>) -> Result<Self> {
+        if indices.len() != values.len() {
+            return Err(Error::InvalidState("Index and value lengths don't match"));
+        }
+
+        let mut entries: Vec<(usize, f64)> = indices.into_iter().zip(values).collect();
+        entries.sort_unstable_by_key(|&(index, _)| index);
+        if entries.windows(2).any(|w| w[0].0 == w[1].0) {
+            return Err(Error::InvalidState("Duplicate index in sparse vector"));
+        }
+
+        let (indices, values) = entries.into_iter().unzip();
+        Ok(Self { indices, values })
+    }
+
+    /// Copy a matrix row into a sparse vector
+    pub fn from_row<T: MatrixElement>(matrix: &MmapMatrix<T>, row: usize) -> Result<Self> {
+        ensure_real(T::data_type())?;
+        if row >= matrix.nrows() {
+            return Err(Error::InvalidState("Row index out of bounds"));
+        }
+
+        let values = matrix.values()?;
+        let col_indices = matrix.col_indices()?;
+        let span = row_span(matrix.row_indices()?, row);
+
+        Ok(Self {
+            indices: span.clone().map(|i| col_indices[i] as usize).collect(),
+            values: span.map(|i| values[i].to_f64()).collect(),
+        })
+    }
+
+    /// Column indices of stored entries
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    /// Values of stored entries
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Number of stored entries
+    pub fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// L2 norm of the stored values
+    pub fn norm(&self) -> f64 {
+        self.values.iter().map(|v| v * v).sum::<f64>().sqrt()
+    }
+}
+
+/// Running totals for one matrix row
+#[derive(Default)]
+struct RowAccumulator {
+    dot: f64,
+    norm_sq: f64,
+    nnz: usize,
+    shared: usize,
+}
+
+/// Sort by descending score, then ascending row, and keep the first `k`
+fn keep_top_k(mut scores: Vec<(usize, f64)>, k: usize) -> Vec<(usize, f64)> {
+    let rank = |a: &(usize, f64), b: &(usize, f64)| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0));
+
+    if k == 0 {
+        return Vec::new();
+    }
+    if scores.len() > k {
+        scores.select_nth_unstable_by(k - 1, rank);
+        scores.truncate(k);
+    }
+    scores.sort_unstable_by(rank);
+    scores
+}
+
+/// Find the `k` rows most similar to a query vector
+///
+/// Returns `(row, score)` pairs sorted by descending score with ties broken by
+/// row index. Rows sharing no stored column with the query are never returned.
+///
+/// Row norms for [`SimilarityMetric::Cosine`] are recomputed from the stored
+/// values on every call, since the file format has no statistics section to
+/// store them in. A bloom filter with a chunk size of zero is rejected, as are
+/// complex matrices.
+pub fn most_similar_rows<T: MatrixElement>(
+    matrix: &MmapMatrix<T>,
+    query: &SparseVector,
+    metric: SimilarityMetric,
+    k: usize,
+) -> Result<Vec<(usize, f64)>> {
+    ensure_real(T::data_type())?;
+    if query
+        .indices
+        .last()
+        .is_some_and(|&col| col >= matrix.ncols())
+    {
+        return Err(Error::InvalidState("Query index exceeds matrix columns"));
+    }
+
+    let lookup: HashMap<usize, f64> = query
+        .indices
+        .iter()
+        .copied()
+        .zip(query.values.iter().copied())
+        .collect();
+    let query_norm = query.norm();
+
+    let values = matrix.values()?;
+    let row_indices = matrix.row_indices()?;
+    let col_indices = matrix.col_indices()?;
+    let nrows = matrix.nrows();
+    let chunk_size = matrix.chunk_bloom_filter().chunk_size();
+    if chunk_size == 0 {
+        return Err(Error::InvalidState("Bloom filter chunk size is zero"));
+    }
+
+    let candidates = (0..nrows.div_ceil(chunk_size))
+        .into_par_iter()
+        .flat_map_iter(|chunk| {
+            let chunk_start = chunk * chunk_size;
+            let chunk_end = (chunk_start + chunk_size).min(nrows);
+
+            // Skip chunks the bloom filter proves empty before touching indices
+            if matrix
+                .chunk_bloom_filter()
+                .may_contain_range(chunk_start, chunk_end)
+                .is_empty()
+            {
+                return Vec::new();
+            }
+
+            let start = row_indices.partition_point(|&r| (r as usize) < chunk_start);
+            let end = row_indices.partition_point(|&r| (r as usize) < chunk_end);
+
+            let mut scores = Vec::new();
+            let mut i = start;
+            while i < end {
+                let row = row_indices[i];
+                let mut acc = RowAccumulator::default();
+
+                while i < end && row_indices[i] == row {
+                    let value = values[i].to_f64();
+                    acc.norm_sq += value * value;
+                    acc.nnz += 1;
+                    if let Some(q) = lookup.get(&(col_indices[i] as usize)) {
+                        acc.dot += value * q;
+                        acc.shared += 1;
+                    }
+                    i += 1;
+                }
+
+                if acc.shared > 0 {
+                    scores.push((row as usize, metric.score(&acc, query, query_norm)));
+                }
+            }
+
+            keep_top_k(scores, k)
+        })
+        .collect();
+
+    Ok(keep_top_k(candidates, k))
+}
+
+/// Find the `k` rows most similar to an existing row, excluding the row itself
+pub fn most_similar_to_row<T: MatrixElement>(
+    matrix: &MmapMatrix<T>,
+    row: usize,
+    metric: SimilarityMetric,
+    k: usize,
+) -> Result<Vec<(usize, f64)>> {
+    let query = SparseVector::from_row(matrix, row)?;
+    let mut results = most_similar_rows(matrix, &query, metric, k.saturating_add(1))?;
+    results.retain(|&(r, _)| r != row);
+    results.truncate(k);
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunked_backend::ChunkConfig;
+    use crate::mmap_backend::BspcFile;
+    use crate::test_support::TempDir;
+
+    /// 4x4 matrix: rows 0 and 1 are parallel, row 2 is orthogonal to both, row 3 is empty
+    const ELEMENTS: [(usize, usize, f64); 6] = [
+        (0, 0, 1.0),
+        (0, 1, 2.0),
+        (1, 0, 2.0),
+        (1, 1, 4.0),
+        (2, 2, 5.0),
+        (2, 3, 1.0),
+    ];
+
+    async fn write_matrix(dir: &TempDir) -> std::path::PathBuf {
+        let path = dir.file("matrix.bspc");
+        BspcFile::write_sparse_matrix(
+            4,
+            4,
+            &ELEMENTS,
+            ChunkConfig::default().with_chunk_size(2),
+            &path,
+        )
+        .await
+        .unwrap();
+        path
+    }
+
+    fn assert_scores(actual: &[(usize, f64)], expected: &[(usize, f64)]) {
+        assert_eq!(actual.len(), expected.len());
+        for (&(row, score), &(expected_row, expected_score)) in actual.iter().zip(expected) {
+            assert_eq!(row, expected_row);
+            assert!(
+                (score - expected_score).abs() < 1e-9,
+                "{score} != {expected_score}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sparse_vector_rejects_duplicates() {
+        let vector = SparseVector::new(vec![3, 1], vec![1.0, 2.0]).unwrap();
+        assert_eq!(vector.indices(), &[1, 3]);
+        assert_eq!(vector.values(), &[2.0, 1.0]);
+
+        assert!(SparseVector::new(vec![1, 1], vec![1.0, 2.0]).is_err());
+        assert!(SparseVector::new(vec![1], vec![]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let dir = TempDir::new();
+        let matrix: MmapMatrix<f64> = MmapMatrix::from_file(write_matrix(&dir).await).unwrap();
+        let query = SparseVector::new(vec![0, 1, 2], vec![1.0, 2.0, 0.0]).unwrap();
+
+        let cosine = most_similar_rows(&matrix, &query, SimilarityMetric::Cosine, 3).unwrap();
+        assert_scores(&cosine, &[(0, 1.0), (1, 1.0), (2, 0.0)]);
+
+        let dot = most_similar_rows(&matrix, &query, SimilarityMetric::Dot, 2).unwrap();
+        assert_scores(&dot, &[(1, 10.0), (0, 5.0)]);
+
+        let jaccard = most_similar_rows(&matrix, &query, SimilarityMetric::Jaccard, 3).unwrap();
+        assert_scores(&jaccard, &[(0, 2.0 / 3.0), (1, 2.0 / 3.0), (2, 1.0 / 4.0)]);
+
+        let out_of_bounds = SparseVector::new(vec![4], vec![1.0]).unwrap();
+        assert!(most_similar_rows(&matrix, &out_of_bounds, SimilarityMetric::Dot, 1).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_most_similar_to_row_excludes_row() {
+        let dir = TempDir::new();
+        let matrix: MmapMatrix<f64> = MmapMatrix::from_file(write_matrix(&dir).await).unwrap();
+
+        let results = most_similar_to_row(&matrix, 1, SimilarityMetric::Cosine, 2).unwrap();
+        assert_scores(&results, &[(0, 1.0)]);
+        assert!(most_similar_to_row(&matrix, 3, SimilarityMetric::Cosine, 2)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_zero_chunk_size_bloom_filter_is_rebuilt() {
+        let dir = TempDir::new();
+        let path = write_matrix(&dir).await;
+
+        // Zero the chunk size stored at the start of the bloom filter section
+        let matrix: MmapMatrix<f64> = MmapMatrix::from_file(&path).unwrap();
+        let (offset, _) = matrix.header.chunk_bloom_filter_region().unwrap();
+        drop(matrix);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[offset as usize..offset as usize + 4].fill(0);
+        std::fs::write(&path, bytes).unwrap();
+
+        let matrix: MmapMatrix<f64> = MmapMatrix::from_file(&path).unwrap();
+        assert_ne!(matrix.chunk_bloom_filter().chunk_size(), 0);
+
+        let query = SparseVector::new(vec![2], vec![1.0]).unwrap();
+        let results = most_similar_rows(&matrix, &query, SimilarityMetric::Dot, 1).unwrap();
+        assert_scores(&results, &[(2, 5.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_complex_matrix_rejected() {
+        let dir = TempDir::new();
+        let path = dir.file("complex.bspc");
+        let elements = [(0, 0, num_complex::Complex32::new(0.0, 1.0))];
+        BspcFile::write_sparse_matrix(1, 1, &elements, ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        let matrix = MmapMatrix::<num_complex::Complex32>::from_file(&path).unwrap();
+
+        let query = SparseVector::new(vec![0], vec![1.0]).unwrap();
+        assert!(SparseVector::from_row(&matrix, 0).is_err());
+        assert!(most_similar_rows(&matrix, &query, SimilarityMetric::Dot, 1).is_err());
+        assert!(most_similar_to_row(&matrix, 0, SimilarityMetric::Cosine, 1).is_err());
+    }
+}
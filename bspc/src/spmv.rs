@@ -0,0 +1,161 @@
+//! Sparse matrix-vector products for memory-mapped matrices
+//!
+//! This module computes `y = A x` for a dense vector `x`. Real products
+//! accumulate in `f64` through `to_f64`; complex products accumulate in
+//! `Complex64` through `to_complex`, so complex matrices keep their imaginary
+//! part. Rows are processed in parallel.
+
+use crate::mmap_backend::matrix_operations::row_span;
+use crate::mmap_backend::{MatrixElement, MmapMatrix};
+use binsparse_rs::{Error, Result};
+use bspc_core::MatrixIndex;
+use num_complex::Complex64;
+use rayon::prelude::*;
+use std::ops::{Add, Mul};
+
+/// Multiply a matrix by a dense real vector
+///
+/// Complex matrices are rejected because their imaginary parts would be
+/// dropped; use [`spmv_complex`] for them.
+pub fn spmv<T: MatrixElement, I: MatrixIndex>(
+    matrix: &MmapMatrix<T, I>,
+    x: &[f64],
+) -> Result<Vec<f64>> {
+    if T::data_type().is_complex() {
+        return Err(Error::InvalidState("Complex matrices require spmv_complex"));
+    }
+    multiply(matrix, x, T::to_f64)
+}
+
+/// Multiply a matrix by a dense complex vector
+///
+/// Real matrices are promoted to complex values with a zero imaginary part.
+pub fn spmv_complex<T: MatrixElement, I: MatrixIndex>(
+    matrix: &MmapMatrix<T, I>,
+    x: &[Complex64],
+) -> Result<Vec<Complex64>> {
+    multiply(matrix, x, T::to_complex)
+}
+
+/// Compute `y = A x` row by row, converting each stored value with `convert`
+fn multiply<T, I, V>(matrix: &MmapMatrix<T, I>, x: &[V], convert: fn(T) -> V) -> Result<Vec<V>>
+where
+    T: MatrixElement,
+    I: MatrixIndex,
+    V: Copy + Default + Send + Sync + Add<Output = V> + Mul<Output = V>,
+{
+    if x.len() != matrix.ncols() {
+        return Err(Error::InvalidState(
+            "Vector length doesn't match column count",
+        ));
+    }
+
+    let values = matrix.values()?;
+    let row_indices = matrix.row_indices()?;
+    let col_indices = matrix.col_indices()?;
+
+    Ok((0..matrix.nrows())
+        .into_par_iter()
+        .map(|row| {
+            if !matrix.chunk_bloom_filter().may_contain_row(row) {
+                return V::default();
+            }
+            row_span(row_indices, row).fold(V::default(), |acc, i| {
+                acc + convert(values[i]) * x[col_indices[i].to_usize()]
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunked_backend::ChunkConfig;
+    use crate::mmap_backend::BspcFile;
+    use crate::test_support::TempDir;
+    use bspc_core::BspcHeader;
+    use std::path::PathBuf;
+
+    async fn write_matrix<T: MatrixElement>(
+        dir: &TempDir,
+        nrows: usize,
+        ncols: usize,
+        elements: &[(usize, usize, T)],
+    ) -> PathBuf {
+        let path = dir.file("matrix.bspc");
+        BspcFile::write_sparse_matrix(nrows, ncols, elements, ChunkConfig::default(), &path)
+            .await
+            .unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_spmv_real() {
+        // [[1, 0, 2], [0, 0, 0], [3, 4, 0]]
+        let dir = TempDir::new();
+        let elements = [(0, 0, 1.0), (0, 2, 2.0), (2, 0, 3.0), (2, 1, 4.0)];
+        let path = write_matrix(&dir, 3, 3, &elements).await;
+        let matrix = MmapMatrix::<f64>::from_file(&path).unwrap();
+
+        let y = spmv(&matrix, &[1.0, 2.0, 3.0]).unwrap();
+        assert_eq!(y, vec![7.0, 0.0, 11.0]);
+    }
+
+    #[tokio::test]
+    async fn test_spmv_complex_keeps_imaginary_part() {
+        // [[1 + 2i, 0], [0, -1i]]
+        let dir = TempDir::new();
+        let elements = [
+            (0, 0, Complex64::new(1.0, 2.0)),
+            (1, 1, Complex64::new(0.0, -1.0)),
+        ];
+        let path = write_matrix(&dir, 2, 2, &elements).await;
+        let matrix = MmapMatrix::<Complex64>::from_file(&path).unwrap();
+
+        let x = [Complex64::new(2.0, 0.0), Complex64::new(3.0, 1.0)];
+        let y = spmv_complex(&matrix, &x).unwrap();
+        assert_eq!(y, vec![Complex64::new(2.0, 4.0), Complex64::new(1.0, -3.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_spmv_rejects_complex_matrix() {
+        let dir = TempDir::new();
+        let path = write_matrix(&dir, 1, 1, &[(0, 0, Complex64::new(1.0, 1.0))]).await;
+        let matrix = MmapMatrix::<Complex64>::from_file(&path).unwrap();
+
+        assert!(spmv(&matrix, &[1.0]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spmv_rejects_vector_length_mismatch() {
+        let dir = TempDir::new();
+        let path = write_matrix(&dir, 2, 3, &[(0, 0, 1.0f64)]).await;
+        let matrix = MmapMatrix::<f64>::from_file(&path).unwrap();
+
+        assert!(matches!(
+            spmv(&matrix, &[1.0, 2.0]),
+            Err(Error::InvalidState(
+                "Vector length doesn't match column count"
+            ))
+        ));
+        assert!(spmv_complex(&matrix, &[Complex64::new(1.0, 0.0); 4]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spmv_u64_indices() {
+        // Only a huge dimension selects 64-bit indices, so shrink the column
+        // count in the header afterwards to keep the dense vector small
+        let dir = TempDir::new();
+        let elements = [(0, 1, 2.0f64), (2, 0, 5.0), (2, 1, 1.0)];
+        let path = write_matrix(&dir, 3, 1 << 33, &elements).await;
+        let mut bytes = std::fs::read(&path).unwrap();
+        let mut header = BspcHeader::from_bytes(&bytes).unwrap();
+        header.ncols = 2;
+        bytes[..BspcHeader::SIZE].copy_from_slice(&header.to_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let matrix = MmapMatrix::<f64, u64>::from_file(&path).unwrap();
+
+        let y = spmv(&matrix, &[3.0, 4.0]).unwrap();
+        assert_eq!(y, vec![8.0, 0.0, 19.0]);
+    }
+}
@@ -0,0 +1,295 @@
+//! Shared helpers for unit tests
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Scratch directory removed when dropped
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create a fresh directory under the system temp dir
+    pub(crate) fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "bspc-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).expect("create temp dir");
+        Self { path }
+    }
+
+    /// Path of a file inside the directory
+    pub(crate) fn file(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Write a labelled matrix to `name` in `dir`
+#[cfg(feature = "mmap")]
+pub(crate) async fn write_labelled<T: crate::mmap_backend::MatrixElement>(
+    dir: &TempDir,
+    name: &str,
+    (nrows, ncols): (usize, usize),
+    elements: &[(usize, usize, T)],
+    row_labels: &[&[u8]],
+    col_labels: &[&[u8]],
+) -> PathBuf {
+    let path = dir.file(name);
+    crate::mmap_backend::BspcFile::write_sparse_matrix_with_labels(
+        nrows,
+        ncols,
+        elements,
+        row_labels,
+        col_labels,
+        0,
+        crate::chunked_backend::ChunkConfig::default(),
+        &path,
+    )
+    .await
+    .unwrap();
+    path
+}
+
+/// Stored elements of a matrix as `(row, col, value)` triples
+#[cfg(feature = "mmap")]
+pub(crate) fn coo<T: crate::mmap_backend::MatrixElement>(
+    matrix: &crate::mmap_backend::MmapMatrix<T>,
+) -> Vec<(usize, usize, T)> {
+    let values = matrix.values().unwrap();
+    let row_indices = matrix.row_indices().unwrap();
+    let col_indices = matrix.col_indices().unwrap();
+    (0..values.len())
+        .map(|i| (row_indices[i] as usize, col_indices[i] as usize, values[i]))
+        .collect()
+}
+
+/// Request received by a [`MockServer`]
+#[cfg(feature = "http")]
+pub(crate) struct MockRequest {
+    pub(crate) method: String,
+    pub(crate) target: String,
+    headers: Vec<(String, String)>,
+}
+
+#[cfg(feature = "http")]
+impl MockRequest {
+    /// Get a header value, matching the name case-insensitively
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Answer a single `Range` request from `file`, or send it whole
+    pub(crate) fn serve_range(&self, file: &[u8]) -> MockResponse {
+        let Some(spec) = self.header("range").and_then(|v| v.strip_prefix("bytes=")) else {
+            return MockResponse::new(200, file.to_vec());
+        };
+        let (start, end) = spec.split_once('-').unwrap();
+        let start: usize = start.parse().unwrap();
+        let end = end
+            .parse::<usize>()
+            .map_or(file.len(), |end| end + 1)
+            .min(file.len());
+        MockResponse::new(206, file[start..end].to_vec()).header(
+            "Content-Range",
+            &format!("bytes {start}-{}/{}", end - 1, file.len()),
+        )
+    }
+}
+
+/// Scripted response of a [`MockServer`]
+#[cfg(feature = "http")]
+pub(crate) struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    delay: std::time::Duration,
+}
+
+#[cfg(feature = "http")]
+impl MockResponse {
+    pub(crate) fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            delay: std::time::Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Wait before answering
+    pub(crate) fn delayed(mut self, delay: std::time::Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// Handler deciding the response to each request
+#[cfg(feature = "http")]
+type Respond = dyn Fn(&MockRequest) -> MockResponse + Send + Sync;
+
+/// HTTP/1.1 server on localhost answering one request per connection
+#[cfg(feature = "http")]
+pub(crate) struct MockServer {
+    url: String,
+    requests: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(feature = "http")]
+impl MockServer {
+    /// Start a server answering with `respond`
+    pub(crate) async fn start(
+        respond: impl Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+    ) -> Self {
+        use std::sync::atomic::Ordering;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/matrix.bspc", listener.local_addr().unwrap());
+        let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let respond: std::sync::Arc<Respond> = std::sync::Arc::new(respond);
+
+        let counter = requests.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let respond = respond.clone();
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    let mut data = Vec::new();
+                    let mut buffer = [0u8; 1024];
+                    while !data.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => data.extend_from_slice(&buffer[..n]),
+                        }
+                    }
+                    counter.fetch_add(1, Ordering::Relaxed);
+
+                    let text = String::from_utf8_lossy(&data);
+                    let mut lines = text.split("\r\n");
+                    let mut request_line = lines.next().unwrap_or_default().split(' ');
+                    let request = MockRequest {
+                        method: request_line.next().unwrap_or_default().to_owned(),
+                        target: request_line.next().unwrap_or_default().to_owned(),
+                        headers: lines
+                            .filter_map(|line| line.split_once(':'))
+                            .map(|(n, v)| (n.trim().to_owned(), v.trim().to_owned()))
+                            .collect(),
+                    };
+
+                    let response = respond(&request);
+                    tokio::time::sleep(response.delay).await;
+                    let mut head = format!(
+                        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        response.status,
+                        response.body.len()
+                    );
+                    for (name, value) in &response.headers {
+                        head.push_str(&format!("{name}: {value}\r\n"));
+                    }
+                    head.push_str("\r\n");
+                    let _ = stream.write_all(head.as_bytes()).await;
+                    if request.method != "HEAD" {
+                        let _ = stream.write_all(&response.body).await;
+                    }
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+
+        Self {
+            url,
+            requests,
+            task,
+        }
+    }
+
+    /// URL of the served file
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Number of requests received so far
+    pub(crate) fn requests(&self) -> usize {
+        self.requests.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "http")]
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Response read by [`send_request`]
+#[cfg(feature = "http")]
+pub(crate) struct RawResponse {
+    head: String,
+    pub(crate) body: Vec<u8>,
+}
+
+#[cfg(feature = "http")]
+impl RawResponse {
+    pub(crate) fn status(&self) -> u16 {
+        self.head
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .unwrap()
+    }
+
+    /// Get a header value, matching the name case-insensitively
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (n, value) = line.split_once(':')?;
+            n.eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+}
+
+/// Send a request line and headers to `addr` and read the whole response
+///
+/// `head` ends each line with `\r\n`; the request asks the server to close
+/// the connection so the response ends at end of stream.
+#[cfg(feature = "http")]
+pub(crate) async fn send_request(addr: std::net::SocketAddr, head: &str) -> RawResponse {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(format!("{head}Connection: close\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap();
+    RawResponse {
+        head: String::from_utf8(response[..split].to_vec()).unwrap(),
+        body: response[split + 4..].to_vec(),
+    }
+}
@@ -0,0 +1,430 @@
+//! Element-wise transform pipelines for .bspc files
+//!
+//! This module provides a pipeline that reads a memory-mapped matrix, applies
+//! map/filter closures and row/column masks, and streams the derived matrix to
+//! a new .bspc file. Row and column labels are carried over from the source
+//! metadata and re-indexed when rows or columns are dropped.
+
+use crate::chunked_backend::ChunkConfig;
+use crate::metadata::MetadataBuilder;
+use crate::mmap_backend::file_io::write_streaming;
+use crate::mmap_backend::{BspcFile, MatrixElement, MmapMatrix};
+use binsparse_rs::{Error, Result};
+use rayon::prelude::*;
+use std::path::Path;
+
+/// Marker for rows/columns removed by a mask
+const DROPPED: u32 = u32::MAX;
+
+/// Composed per-element stage: `(row, col, value) -> Some(new_value)` or `None` to drop
+type Stage<'a, T, U> = Box<dyn Fn(usize, usize, T) -> Option<U> + Send + Sync + 'a>;
+
+/// Old -> new index map of a mask, `None` when the axis is not masked
+type IndexMap = Option<Vec<u32>>;
+
+/// Streaming transform over a memory-mapped matrix
+///
+/// Closures are composed in the order they are added and evaluated once per
+/// stored element. Row and column indices passed to closures are always the
+/// source indices; masks are applied before any closure runs.
+///
+/// ```rust,no_run
+/// use bspc::{ChunkConfig, MmapMatrix, Transform};
+///
+/// async fn example() -> binsparse_rs::Result<()> {
+///     let counts: MmapMatrix<u32> = MmapMatrix::from_file("counts.bspc")?;
+///     Transform::new(&counts)
+///         .map(|v| (v as f32).ln_1p())
+///         .filter_value(|v| v > 0.5)
+///         .write_to("log_counts.bspc", ChunkConfig::default())
+///         .await
+/// }
+/// ```
+pub struct Transform<'a, T: MatrixElement, U: MatrixElement = T> {
+    matrix: &'a MmapMatrix<T>,
+    stage: Stage<'a, T, U>,
+    row_mask: Option<Vec<bool>>,
+    col_mask: Option<Vec<bool>>,
+}
+
+impl<'a, T: MatrixElement> Transform<'a, T, T> {
+    /// Create an identity pipeline over a matrix
+    pub fn new(matrix: &'a MmapMatrix<T>) -> Self {
+        Self {
+            matrix,
+            stage: Box::new(|_, _, value| Some(value)),
+            row_mask: None,
+            col_mask: None,
+        }
+    }
+}
+
+impl<'a, T: MatrixElement, U: MatrixElement> Transform<'a, T, U> {
+    /// Apply a function to every value, possibly changing the element type
+    pub fn map<V, F>(self, f: F) -> Transform<'a, T, V>
+    where
+        V: MatrixElement,
+        F: Fn(U) -> V + Send + Sync + 'a,
+    {
+        let prev = self.stage;
+        Transform {
+            matrix: self.matrix,
+            stage: Box::new(move |row, col, value| prev(row, col, value).map(&f)),
+            row_mask: self.row_mask,
+            col_mask: self.col_mask,
+        }
+    }
+
+    /// Apply a function that also receives the source row and column
+    pub fn map_indexed<V, F>(self, f: F) -> Transform<'a, T, V>
+    where
+        V: MatrixElement,
+        F: Fn(usize, usize, U) -> V + Send + Sync + 'a,
+    {
+        let prev = self.stage;
+        Transform {
+            matrix: self.matrix,
+            stage: Box::new(move |row, col, value| prev(row, col, value).map(|v| f(row, col, v))),
+            row_mask: self.row_mask,
+            col_mask: self.col_mask,
+        }
+    }
+
+    /// Keep only elements for which the predicate returns true
+    pub fn filter<F>(self, f: F) -> Self
+    where
+        F: Fn(usize, usize, U) -> bool + Send + Sync + 'a,
+    {
+        let prev = self.stage;
+        Self {
+            matrix: self.matrix,
+            stage: Box::new(move |row, col, value| {
+                prev(row, col, value).filter(|&v| f(row, col, v))
+            }),
+            row_mask: self.row_mask,
+            col_mask: self.col_mask,
+        }
+    }
+
+    /// Keep only elements whose current value satisfies the predicate
+    pub fn filter_value<F>(self, f: F) -> Self
+    where
+        F: Fn(U) -> bool + Send + Sync + 'a,
+    {
+        self.filter(move |_, _, value| f(value))
+    }
+
+    /// Keep only elements whose source row satisfies the predicate
+    ///
+    /// Unlike [`Transform::row_mask`], this does not remove the row from the output.
+    pub fn filter_row<F>(self, f: F) -> Self
+    where
+        F: Fn(usize) -> bool + Send + Sync + 'a,
+    {
+        self.filter(move |row, _, _| f(row))
+    }
+
+    /// Keep only elements whose source column satisfies the predicate
+    ///
+    /// Unlike [`Transform::col_mask`], this does not remove the column from the output.
+    pub fn filter_col<F>(self, f: F) -> Self
+    where
+        F: Fn(usize) -> bool + Send + Sync + 'a,
+    {
+        self.filter(move |_, col, _| f(col))
+    }
+
+    /// Drop rows where `mask[row]` is false and re-index the remaining rows
+    pub fn row_mask(mut self, mask: Vec<bool>) -> Self {
+        self.row_mask = Some(mask);
+        self
+    }
+
+    /// Drop columns where `mask[col]` is false and re-index the remaining columns
+    pub fn col_mask(mut self, mask: Vec<bool>) -> Self {
+        self.col_mask = Some(mask);
+        self
+    }
+
+    /// Dimensions of the output matrix after masks are applied
+    pub fn output_dimensions(&self) -> Result<(usize, usize)> {
+        let nrows = match &self.row_mask {
+            Some(mask) => count_kept(mask, self.matrix.nrows(), "Row mask length mismatch")?,
+            None => self.matrix.nrows(),
+        };
+        let ncols = match &self.col_mask {
+            Some(mask) => count_kept(mask, self.matrix.ncols(), "Column mask length mismatch")?,
+            None => self.matrix.ncols(),
+        };
+        Ok((nrows, ncols))
+    }
+
+    /// Build the old -> new index maps of the row and column masks
+    fn index_maps(&self) -> Result<(IndexMap, IndexMap)> {
+        // Validate masks before building index maps
+        self.output_dimensions()?;
+
+        Ok((
+            self.row_mask.as_deref().map(remap_from_mask),
+            self.col_mask.as_deref().map(remap_from_mask),
+        ))
+    }
+
+    /// Evaluate the pipeline for the source element at position `i`
+    fn output_element<'s>(
+        &'s self,
+        row_map: Option<&'s [u32]>,
+        col_map: Option<&'s [u32]>,
+    ) -> Result<impl Fn(usize) -> Option<(usize, usize, U)> + Sync + 's> {
+        let values = self.matrix.values()?;
+        let row_indices = self.matrix.row_indices()?;
+        let col_indices = self.matrix.col_indices()?;
+        let stage = &self.stage;
+
+        Ok(move |i| {
+            let row = row_indices[i] as usize;
+            let col = col_indices[i] as usize;
+
+            let new_row = match row_map {
+                Some(map) => *map.get(row).filter(|&&r| r != DROPPED)? as usize,
+                None => row,
+            };
+            let new_col = match col_map {
+                Some(map) => *map.get(col).filter(|&&c| c != DROPPED)? as usize,
+                None => col,
+            };
+
+            stage(row, col, values[i]).map(|value| (new_row, new_col, value))
+        })
+    }
+
+    /// Evaluate the pipeline and return output elements in sorted COO order
+    pub fn elements(&self) -> Result<Vec<(usize, usize, U)>> {
+        let (row_map, col_map) = self.index_maps()?;
+        let output_element = self.output_element(row_map.as_deref(), col_map.as_deref())?;
+
+        // Masks are monotone, so the source ordering is preserved in the output
+        Ok((0..self.matrix.nnz())
+            .into_par_iter()
+            .filter_map(output_element)
+            .collect())
+    }
+
+    /// Evaluate the pipeline and write the result to a new .bspc file
+    ///
+    /// The output is streamed to disk section by section, so memory use does
+    /// not grow with the number of elements. The pipeline is evaluated once
+    /// per section, which requires closures to give the same result every
+    /// time they see the same element. Compressed output (see
+    /// [`ChunkConfig::with_compression`]) is encoded from the collected
+    /// [`Transform::elements`] instead.
+    ///
+    /// Row and column labels present in the source metadata are carried over,
+    /// keeping only the labels of rows and columns that survive the masks.
+    pub async fn write_to<P: AsRef<Path>>(self, path: P, config: ChunkConfig) -> Result<()> {
+        let (nrows, ncols) = self.output_dimensions()?;
+
+        let (row_labels, col_labels) = match self.matrix.metadata_view()? {
+            Some(view) => (
+                kept_labels(view.row_labels()?, self.row_mask.as_deref())?,
+                kept_labels(view.col_labels()?, self.col_mask.as_deref())?,
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+
+        if config.compression().is_some() {
+            let elements = self.elements()?;
+            let row_label_refs: Vec<&[u8]> = row_labels.iter().map(Vec::as_slice).collect();
+            let col_label_refs: Vec<&[u8]> = col_labels.iter().map(Vec::as_slice).collect();
+
+            return BspcFile::write_sparse_matrix_with_labels(
+                nrows,
+                ncols,
+                &elements,
+                &row_label_refs,
+                &col_label_refs,
+                0,
+                config,
+                path,
+            )
+            .await;
+        }
+
+        let metadata = if row_labels.is_empty() && col_labels.is_empty() {
+            None
+        } else {
+            let mut builder = MetadataBuilder::new();
+            if !row_labels.is_empty() {
+                builder = builder.with_row_labels(row_labels);
+            }
+            if !col_labels.is_empty() {
+                builder = builder.with_col_labels(col_labels);
+            }
+            Some(builder.build()?)
+        };
+
+        let (row_map, col_map) = self.index_maps()?;
+        let output_element = self.output_element(row_map.as_deref(), col_map.as_deref())?;
+        let nnz = self.matrix.nnz();
+
+        write_streaming(
+            path,
+            nrows,
+            ncols,
+            || (0..nnz).filter_map(&output_element),
+            config.chunk_size(),
+            metadata.as_deref(),
+        )
+        .await
+    }
+}
+
+/// Count kept entries in a mask, checking it covers the whole axis
+fn count_kept(mask: &[bool], len: usize, msg: &'static str) -> Result<usize> {
+    if mask.len() != len {
+        return Err(Error::InvalidState(msg));
+    }
+    Ok(mask.iter().filter(|&&keep| keep).count())
+}
+
+/// Build a dense old -> new index map from a keep mask
+fn remap_from_mask(mask: &[bool]) -> Vec<u32> {
+    let mut next = 0u32;
+    mask.iter()
+        .map(|&keep| {
+            if keep {
+                next += 1;
+                next - 1
+            } else {
+                DROPPED
+            }
+        })
+        .collect()
+}
+
+/// Copy out the labels of rows/columns kept by an optional mask
+fn kept_labels(
+    labels: Option<crate::metadata::LabelArray<'_>>,
+    mask: Option<&[bool]>,
+) -> Result<Vec<Vec<u8>>> {
+    let Some(labels) = labels else {
+        return Ok(Vec::new());
+    };
+
+    if let Some(mask) = mask {
+        if mask.len() != labels.count() as usize {
+            return Err(Error::InvalidState("Label count doesn't match mask length"));
+        }
+    }
+
+    (0..labels.count())
+        .filter(|&i| mask.is_none_or(|m| m[i as usize]))
+        .map(|i| labels.get_label(i).map(<[u8]>::to_vec))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{coo, write_labelled, TempDir};
+
+    /// 4x3 matrix with row/column labels
+    async fn write_source(dir: &TempDir) -> MmapMatrix<f32> {
+        let elements = vec![
+            (0, 0, 1.0f32),
+            (0, 2, 2.0),
+            (1, 1, 3.0),
+            (2, 0, 4.0),
+            (2, 2, 5.0),
+            (3, 1, 6.0),
+        ];
+        let rows: [&[u8]; 4] = [b"r0", b"r1", b"r2", b"r3"];
+        let cols: [&[u8]; 3] = [b"c0", b"c1", b"c2"];
+        let path = write_labelled(dir, "source.bspc", (4, 3), &elements, &rows, &cols).await;
+        MmapMatrix::from_file(&path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_identity_round_trip() {
+        let dir = TempDir::new();
+        let source = write_source(&dir).await;
+        let out = dir.file("out.bspc");
+
+        Transform::new(&source)
+            .write_to(&out, ChunkConfig::default())
+            .await
+            .unwrap();
+
+        let result: MmapMatrix<f32> = MmapMatrix::from_file(&out).unwrap();
+        assert_eq!((result.nrows(), result.ncols()), (4, 3));
+        assert_eq!(coo(&result), coo(&source));
+    }
+
+    #[tokio::test]
+    async fn test_map_and_filter() {
+        let dir = TempDir::new();
+        let source = write_source(&dir).await;
+        let out = dir.file("out.bspc");
+
+        let transform = Transform::new(&source)
+            .map(|v| f64::from(v) * 2.0)
+            .filter_value(|v| v > 4.0)
+            .filter_col(|col| col != 1);
+        let expected = transform.elements().unwrap();
+        assert_eq!(expected, vec![(2, 0, 8.0), (2, 2, 10.0)]);
+
+        transform
+            .write_to(&out, ChunkConfig::default())
+            .await
+            .unwrap();
+        let result: MmapMatrix<f64> = MmapMatrix::from_file(&out).unwrap();
+        assert_eq!(coo(&result), expected);
+    }
+
+    #[tokio::test]
+    async fn test_masks_reindex_and_keep_labels() {
+        let dir = TempDir::new();
+        let source = write_source(&dir).await;
+        let out = dir.file("out.bspc");
+
+        let transform = Transform::new(&source)
+            .row_mask(vec![true, false, true, true])
+            .col_mask(vec![true, true, false]);
+        assert_eq!(transform.output_dimensions().unwrap(), (3, 2));
+        let expected = transform.elements().unwrap();
+        assert_eq!(expected, vec![(0, 0, 1.0), (1, 0, 4.0), (2, 1, 6.0)]);
+
+        transform
+            .write_to(&out, ChunkConfig::default().with_chunk_size(2))
+            .await
+            .unwrap();
+        let result: MmapMatrix<f32> = MmapMatrix::from_file(&out).unwrap();
+        assert_eq!((result.nrows(), result.ncols()), (3, 2));
+        assert_eq!(coo(&result), expected);
+
+        let view = result.metadata_view().unwrap().unwrap();
+        let rows = view.row_labels().unwrap().unwrap();
+        let cols = view.col_labels().unwrap().unwrap();
+        let row_labels: Vec<&[u8]> = (0..rows.count())
+            .map(|i| rows.get_label(i).unwrap())
+            .collect();
+        let col_labels: Vec<&[u8]> = (0..cols.count())
+            .map(|i| cols.get_label(i).unwrap())
+            .collect();
+        assert_eq!(row_labels, [b"r0", b"r2", b"r3"]);
+        assert_eq!(col_labels, [b"c0", b"c1"]);
+    }
+
+    #[tokio::test]
+    async fn test_mask_length_mismatch() {
+        let dir = TempDir::new();
+        let source = write_source(&dir).await;
+
+        let transform = Transform::new(&source).row_mask(vec![true; 3]);
+        assert!(transform.output_dimensions().is_err());
+        assert!(transform
+            .write_to(dir.file("out.bspc"), ChunkConfig::default())
+            .await
+            .is_err());
+    }
+}